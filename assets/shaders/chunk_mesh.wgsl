@@ -0,0 +1,156 @@
+// Meshes a Chunk on the GPU, one invocation per voxel.
+// Follows the same rules as `create_chunk_mesh_data` in `voxel/util.rs`, so both give the same faces.
+// Only the order differs, since faces get their spot in the buffers from an atomic counter.
+
+const CHUNK_SIZE: i32 = 32;
+// The Chunk plus one voxel of its neighbours on every side.
+const PADDED_SIZE: i32 = 34;
+// Marks voxels in Chunks that aren't loaded.
+const UNLOADED: u32 = 255u;
+// Words per packed ChunkVertex.
+const VERTEX_WORDS: u32 = 2u;
+// Has to match the steps in `voxel/vertex.rs`.
+const POSITION_STEPS: f32 = 64.0;
+const UV_STEPS: f32 = 64.0;
+
+// Has to match `BlockInfo` flags in `gpu_mesher.rs`.
+const VISIBLE: u32 = 1u;
+const OPAQUE: u32 = 2u;
+const TRANSLUCENT: u32 = 4u;
+const FLUID: u32 = 8u;
+
+struct Params {
+    // Only blocks in this RenderLayer get meshed.
+    layer: u32,
+    // How many faces fit in the vertex and index buffers.
+    max_faces: u32,
+}
+
+struct Counter {
+    faces: atomic<u32>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+// Block in the lowest 8 bits, then sunlight and block light, 4 bits each.
+@group(0) @binding(1)
+var<storage, read> voxels: array<u32>;
+// Flags, RenderLayer and the top, side and bottom tiles (8 bits each) of every block.
+@group(0) @binding(2)
+var<storage, read> blocks: array<vec4<u32>>;
+@group(0) @binding(3)
+// Packed like `ChunkVertex`, two words per vertex.
+var<storage, read_write> vertices: array<u32>;
+@group(0) @binding(4)
+var<storage, read_write> indices: array<u32>;
+@group(0) @binding(5)
+var<storage, read_write> counter: Counter;
+
+// Above, under, right, left, behind, in front. Same order as `FACES`.
+var<private> NORMALS: array<vec3<i32>, 6> = array<vec3<i32>, 6>(
+    vec3<i32>(0, 1, 0),
+    vec3<i32>(0, -1, 0),
+    vec3<i32>(1, 0, 0),
+    vec3<i32>(-1, 0, 0),
+    vec3<i32>(0, 0, 1),
+    vec3<i32>(0, 0, -1),
+);
+
+var<private> CORNERS: array<vec3<f32>, 24> = array<vec3<f32>, 24>(
+    vec3<f32>(-0.5, 0.5, -0.5), vec3<f32>(0.5, 0.5, -0.5), vec3<f32>(0.5, 0.5, 0.5), vec3<f32>(-0.5, 0.5, 0.5),
+    vec3<f32>(-0.5, -0.5, -0.5), vec3<f32>(0.5, -0.5, -0.5), vec3<f32>(0.5, -0.5, 0.5), vec3<f32>(-0.5, -0.5, 0.5),
+    vec3<f32>(0.5, -0.5, -0.5), vec3<f32>(0.5, -0.5, 0.5), vec3<f32>(0.5, 0.5, 0.5), vec3<f32>(0.5, 0.5, -0.5),
+    vec3<f32>(-0.5, -0.5, -0.5), vec3<f32>(-0.5, -0.5, 0.5), vec3<f32>(-0.5, 0.5, 0.5), vec3<f32>(-0.5, 0.5, -0.5),
+    vec3<f32>(-0.5, -0.5, 0.5), vec3<f32>(-0.5, 0.5, 0.5), vec3<f32>(0.5, 0.5, 0.5), vec3<f32>(0.5, -0.5, 0.5),
+    vec3<f32>(-0.5, -0.5, -0.5), vec3<f32>(-0.5, 0.5, -0.5), vec3<f32>(0.5, 0.5, -0.5), vec3<f32>(0.5, -0.5, -0.5),
+);
+
+// Faces pointing the positive way wind one way, the others the other way.
+var<private> FACE_INDICES: array<u32, 12> = array<u32, 12>(
+    0u, 3u, 1u, 1u, 3u, 2u,
+    0u, 1u, 3u, 1u, 2u, 3u,
+);
+
+fn voxel(pos: vec3<i32>) -> u32 {
+    let padded = pos + 1;
+    return voxels[padded.x + PADDED_SIZE * (padded.z + PADDED_SIZE * padded.y)];
+}
+
+// Same as `face_uv` in `voxel/util.rs`.
+fn face_uv(normal: vec3<i32>, corner: vec3<f32>) -> vec2<f32> {
+    if normal.y == 0 && normal.x != 0 {
+        return vec2<f32>(corner.z + 0.5, 0.5 - corner.y);
+    } else if normal.y == 0 {
+        return vec2<f32>(corner.x + 0.5, 0.5 - corner.y);
+    }
+    return vec2<f32>(corner.x + 0.5, corner.z + 0.5);
+}
+
+fn face_tile(tiles: u32, normal: vec3<i32>) -> u32 {
+    if normal.y == 1 {
+        return tiles & 0xFFu;
+    }
+    if normal.y == -1 {
+        return (tiles >> 16u) & 0xFFu;
+    }
+    return (tiles >> 8u) & 0xFFu;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let pos = vec3<i32>(id);
+    if any(pos >= vec3<i32>(CHUNK_SIZE)) {
+        return;
+    }
+
+    let block = voxel(pos) & 0xFFu;
+    let info = blocks[block];
+    // Fluids have sloped surfaces, those are left to the CPU
+    if (info.x & VISIBLE) == 0u || (info.x & FLUID) != 0u || info.y != params.layer {
+        return;
+    }
+
+    // Relative to the lowest corner of the Chunk
+    let center = vec3<f32>(pos) + 0.5;
+
+    for (var face = 0; face < 6; face++) {
+        let normal = NORMALS[face];
+        let neighbour = voxel(pos + normal);
+        let neighbour_block = neighbour & 0xFFu;
+        // Don't draw faces towards Chunks that aren't loaded
+        if neighbour_block == UNLOADED {
+            continue;
+        }
+        // Same as `Block::hides_face_of`
+        let hidden = (blocks[neighbour_block].x & OPAQUE) != 0u
+            || (neighbour_block == block && (info.x & TRANSLUCENT) != 0u);
+        if hidden {
+            continue;
+        }
+
+        let slot = atomicAdd(&counter.faces, 1u);
+        // Still counted, so the CPU can tell the buffers were too small
+        if slot >= params.max_faces {
+            continue;
+        }
+
+        // Sunlight and block light, already where `ChunkVertex` wants them
+        let light = ((neighbour >> 8u) & 0xFFu) << 14u;
+        let tile = face_tile(info.z, normal);
+
+        for (var corner = 0; corner < 4; corner++) {
+            let offset = CORNERS[face * 4 + corner];
+            let position = vec3<u32>(round((center + offset) * vec3<f32>(1.0, POSITION_STEPS, 1.0)));
+            let uv = vec2<u32>(round(face_uv(normal, offset) * UV_STEPS));
+            let base = (slot * 4u + u32(corner)) * VERTEX_WORDS;
+
+            vertices[base] = position.x | position.z << 6u | position.y << 12u | u32(face) << 24u | tile << 27u;
+            vertices[base + 1u] = uv.x | uv.y << 7u | light;
+        }
+
+        let pattern = u32(face % 2) * 6u;
+        for (var i = 0u; i < 6u; i++) {
+            indices[slot * 6u + i] = slot * 4u + FACE_INDICES[pattern + i];
+        }
+    }
+}
@@ -1,17 +1,27 @@
 struct Camera {
+    view_pos: vec4<f32>,
     view_proj: mat4x4<f32>,
 }
 
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+const LIGHT_DIRECTIONAL: u32 = 2u;
+
 struct Light {
     position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
     color: vec3<f32>,
+    intensity: f32,
+    cos_inner: f32,
+    cos_outer: f32,
+    casts_shadows: u32,
 }
 
-@group(1) @binding(0)
-var<uniform> light: Light;
+@group(1) @binding(1)
+var<storage, read> lights: array<Light>;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -22,19 +32,28 @@ struct VertexOutput {
     @location(0) color: vec3<f32>,
 }
 
+// Draws a small cube at every light. One instance per light.
 @vertex
 fn vs_main(
     model: VertexInput,
+    @builtin(instance_index) instance: u32,
 ) -> VertexOutput {
+    let light = lights[instance];
+
     let scale = 0.25;
     var out: VertexOutput;
     out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + light.position, 1.0);
     out.color = light.color;
 
+    // Directional lights don't have a position, so put them outside of the screen
+    if light.kind == LIGHT_DIRECTIONAL {
+        out.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+    }
+
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return vec4<f32>(in.color, 1.0);
-}
\ No newline at end of file
+}
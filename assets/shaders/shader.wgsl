@@ -7,17 +7,150 @@ struct Camera {
 @group(1) @binding(0)
 var<uniform> camera: Camera;
 
+// Has to match LightKind
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_SPOT: u32 = 1u;
+const LIGHT_DIRECTIONAL: u32 = 2u;
+
 struct Light {
     position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
     color: vec3<f32>,
+    intensity: f32,
+    cos_inner: f32,
+    cos_outer: f32,
+    casts_shadows: u32,
+}
+
+struct LightGlobals {
+    view: mat4x4<f32>,
+    // Follows the time of day, bright during the day and dark blue at night
+    ambient: vec4<f32>,
+    // Clusters on each axis, and the amount of lights
+    clusters: vec4<u32>,
+    // Width, height, near and far plane
+    screen: vec4<f32>,
+    // Seconds since the scene started
+    time: vec4<f32>,
 }
+
 @group(2) @binding(0)
-var <uniform> light: Light;
+var<uniform> light_globals: LightGlobals;
+@group(2) @binding(1)
+var<storage, read> lights: array<Light>;
+// Offset into light_indices and the amount of lights for every cluster
+@group(2) @binding(2)
+var<storage, read> clusters: array<vec2<u32>>;
+@group(2) @binding(3)
+var<storage, read> light_indices: array<u32>;
+
+// Has to match FogMode
+const FOG_OFF: u32 = 0u;
+const FOG_LINEAR: u32 = 1u;
+const FOG_EXPONENTIAL: u32 = 2u;
+const FOG_EXPONENTIAL_SQUARED: u32 = 3u;
+
+struct Fog {
+    // The colour of the sky at the horizon
+    color: vec4<f32>,
+    start: f32,
+    end: f32,
+    density: f32,
+    mode: u32,
+}
+@group(2) @binding(4)
+var<uniform> fog: Fog;
+
+// Has to match CASCADES
+const SHADOW_CASCADES: u32 = 3u;
+
+struct Shadows {
+    view_proj: array<mat4x4<f32>, 3>,
+    // How far from the camera each cascade reaches
+    splits: vec4<f32>,
+    // How big a texel of each cascade is in the world
+    texel_sizes: vec4<f32>,
+}
+
+@group(3) @binding(0)
+var<uniform> shadows: Shadows;
+@group(3) @binding(1)
+var shadow_map: texture_depth_2d_array;
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+}
+
+// Chunks have the light shining on every face baked in.
+// Every vertex is packed into two u32s, see `ChunkVertex` for what goes where.
+// Not called `packed`, GLSL keeps that name for itself.
+struct ChunkVertexInput {
+    @location(0) words: vec2<u32>,
+}
+
+// Same order as `FACES` in `voxel::util`
+var<private> FACE_NORMALS: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, -1.0, 0.0),
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(-1.0, 0.0, 0.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+);
+
+// Has to match `FLUID_FLOW_SPEED`
+const FLUID_FLOW_SPEED: f32 = 1.0;
+const FLOW_DIRECTIONS: f32 = 64.0;
+const FLOW_SPEED_LEVELS: f32 = 15.0;
+
+struct ChunkVertex {
+    // Relative to the lowest corner of the chunk
+    position: vec3<f32>,
+    tex_coords: vec2<f32>,
+    normal: vec3<f32>,
+    // Sunlight and block light, 0.0 to 1.0
+    light: vec2<f32>,
+    // How fast the texture scrolls, in tiles per second
+    flow: vec2<f32>,
+}
+
+fn unpack_chunk_vertex(words: vec2<u32>) -> ChunkVertex {
+    var vertex: ChunkVertex;
+    vertex.position = vec3<f32>(
+        f32(words.x & 0x3Fu),
+        f32((words.x >> 12u) & 0xFFFu) / 64.0,
+        f32((words.x >> 6u) & 0x3Fu),
+    );
+    vertex.normal = FACE_NORMALS[(words.x >> 24u) & 0x7u];
+
+    // Stay half a pixel inside the tile so filtering doesn't bleed the neighbours in
+    let tile = words.x >> 27u;
+    let uv = vec2<f32>(f32(words.y & 0x7Fu), f32((words.y >> 7u) & 0x7Fu)) / 64.0;
+    let inset = 0.5 / TILE_SIZE;
+    let inside = inset + uv * (1.0 - 2.0 * inset);
+    let tile_xy = vec2<f32>(f32(tile % u32(ATLAS_TILES.x)), f32(tile / u32(ATLAS_TILES.x)));
+    vertex.tex_coords = (tile_xy + inside) / ATLAS_TILES;
+
+    vertex.light = vec2<f32>(f32((words.y >> 14u) & 0xFu), f32((words.y >> 18u) & 0xFu)) / 15.0;
+
+    let speed_level = words.y >> 28u;
+    if speed_level == 0u {
+        vertex.flow = vec2<f32>(0.0);
+    } else {
+        let angle = f32((words.y >> 22u) & 0x3Fu) / FLOW_DIRECTIONS * 6.28318530718;
+        let speed = FLUID_FLOW_SPEED * exp2((f32(speed_level) - FLOW_SPEED_LEVELS) / 2.0);
+        vertex.flow = vec2<f32>(cos(angle), sin(angle)) * speed;
+    }
+
+    return vertex;
 }
 
 struct InstanceInput {
@@ -28,6 +161,8 @@ struct InstanceInput {
     @location(9) normal_matrix_0: vec3<f32>,
     @location(10) normal_matrix_1: vec3<f32>,
     @location(11) normal_matrix_2: vec3<f32>,
+    // 12 and 13 are the SkinInput
+    @location(14) tint: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -35,13 +170,54 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) voxel_light: vec2<f32>,
+    @location(4) flow: vec2<f32>,
+    // Zero when there's nothing to normal map with
+    @location(5) world_tangent: vec3<f32>,
+    @location(6) world_bitangent: vec3<f32>,
+    // Multiplies the diffuse colour, see `Instance::tint`
+    @location(7) tint: vec4<f32>,
 };
 
+// Which joints a vertex moves with, see `SkinVertex`
+struct SkinInput {
+    @location(12) joints: vec4<u32>,
+    @location(13) weights: vec4<f32>,
+}
+
+// Only bound for skinned models, see `SkinBuffer`
+@group(1) @binding(1)
+var<storage, read> joint_matrices: array<mat4x4<f32>>;
+
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput
 ) -> VertexOutput {
+    return model_vertex(model, instance, mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    ));
+}
+
+// Models with a Skeleton. Every vertex gets moved by its joints before the instance moves it.
+@vertex
+fn vs_skinned(
+    model: VertexInput,
+    instance: InstanceInput,
+    skin_input: SkinInput,
+) -> VertexOutput {
+    let skin = joint_matrices[skin_input.joints.x] * skin_input.weights.x
+        + joint_matrices[skin_input.joints.y] * skin_input.weights.y
+        + joint_matrices[skin_input.joints.z] * skin_input.weights.z
+        + joint_matrices[skin_input.joints.w] * skin_input.weights.w;
+    return model_vertex(model, instance, skin);
+}
+
+// `skin` moves the vertex around in the model, before the instance puts the model in the world.
+fn model_vertex(model: VertexInput, instance: InstanceInput, skin: mat4x4<f32>) -> VertexOutput {
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
         instance.model_matrix_1,
@@ -49,50 +225,423 @@ fn vs_main(
         instance.model_matrix_3,
     );
 
+    // Joints aren't expected to stretch things unevenly, so the skin can turn normals as it is
     let normal_matrix = mat3x3<f32>(
         instance.normal_matrix_0,
         instance.normal_matrix_1,
         instance.normal_matrix_2,
-    );
+    ) * mat3x3<f32>(skin[0].xyz, skin[1].xyz, skin[2].xyz);
 
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
     out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
+
+    var world_position: vec4<f32> = model_matrix * skin * vec4<f32>(model.position, 1.0);
+
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+
+    // Models aren't part of the voxel light, so they're always in full sunlight
+    out.voxel_light = vec2<f32>(1.0, 0.0);
+    out.flow = vec2<f32>(0.0);
+    out.tint = instance.tint;
+
+    return out;
+}
+
+@vertex
+fn vs_chunk(
+    model: ChunkVertexInput,
+    instance: InstanceInput
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
 
-    var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+
+    let vertex = unpack_chunk_vertex(model.words);
+
+    var out: VertexOutput;
+    out.tex_coords = vertex.tex_coords;
+    out.world_normal = normal_matrix * vertex.normal;
+    // The blocks don't have normal maps
+    out.world_tangent = vec3<f32>(0.0);
+    out.world_bitangent = vec3<f32>(0.0);
+
+    // The instance moves the vertex from its chunk into the world
+    var world_position: vec4<f32> = model_matrix * vec4<f32>(vertex.position, 1.0);
 
     out.world_position = world_position.xyz;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * world_position;
+    out.voxel_light = vertex.light;
+    out.flow = vertex.flow;
+    out.tint = instance.tint;
 
     return out;
 }
 
+// Every level of light is a bit dimmer than the last, instead of going down in a straight line.
+fn light_curve(level: f32) -> f32 {
+    return pow(0.8, (1.0 - level) * 15.0);
+}
+
+// Finds the cluster a fragment is in. Has to match `Lights::cluster_bounds`.
+fn cluster_index(frag_coord: vec2<f32>, depth: f32) -> u32 {
+    let size = light_globals.clusters.xyz;
+    let z_near = light_globals.screen.z;
+    let z_far = light_globals.screen.w;
+
+    let tile = vec2<u32>(frag_coord / light_globals.screen.xy * vec2<f32>(size.xy));
+    let slice = u32(max(log(depth / z_near) / log(z_far / z_near) * f32(size.z), 0.0));
+
+    let x = min(tile.x, size.x - 1u);
+    let y = min(tile.y, size.y - 1u);
+    let z = min(slice, size.z - 1u);
+    return x + y * size.x + z * size.x * size.y;
+}
+
+// Fades a light out smoothly so it's completely gone at its range.
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / (1.0 + distance * distance);
+}
+
+// How much of the shadow casting light reaches a position, from 0.0 (none) to 1.0 (all of it).
+fn shadow_factor(world_position: vec3<f32>, normal: vec3<f32>, depth: f32) -> f32 {
+    var cascade = 0u;
+    while cascade < SHADOW_CASCADES && depth > shadows.splits[cascade] {
+        cascade++;
+    }
+    // Too far away to have shadows
+    if cascade == SHADOW_CASCADES {
+        return 1.0;
+    }
+
+    // Move out along the normal a bit so surfaces don't shadow themselves
+    let offset_position = world_position + normal * shadows.texel_sizes[cascade] * 1.5;
+    let light_space = shadows.view_proj[cascade] * vec4<f32>(offset_position, 1.0);
+    let coords = light_space.xyz / light_space.w;
+    let uv = coords.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || coords.z > 1.0 {
+        return 1.0;
+    }
+
+    // Percentage closer filtering: average a few samples around us for softer edges
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var lit = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let sample_uv = uv + vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, cascade, coords.z);
+        }
+    }
+
+    return lit / 9.0;
+}
+
+// How much of a fragment is still visible through the fog, from 0.0 (none) to 1.0 (all of it).
+fn fog_visibility(distance: f32) -> f32 {
+    let fogged = max(distance - fog.start, 0.0);
+
+    switch fog.mode {
+        case FOG_LINEAR: {
+            return clamp(1.0 - fogged / (fog.end - fog.start), 0.0, 1.0);
+        }
+        case FOG_EXPONENTIAL: {
+            return exp(-fog.density * fogged);
+        }
+        case FOG_EXPONENTIAL_SQUARED: {
+            let amount = fog.density * fogged;
+            return exp(-amount * amount);
+        }
+        case FOG_OFF, default: {
+            return 1.0;
+        }
+    }
+}
+
+struct Lighting {
+    diffuse: vec3<f32>,
+    specular: vec3<f32>,
+}
+
+// Where a light comes from and how much of it reaches a position.
+struct Incoming {
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+fn incoming(light: Light, world_position: vec3<f32>, shadow: f32) -> Incoming {
+    var light_dir: vec3<f32>;
+    var strength = light.intensity;
+
+    if light.kind == LIGHT_DIRECTIONAL {
+        light_dir = -light.direction;
+        if light.casts_shadows != 0u {
+            strength *= shadow;
+        }
+    } else {
+        let to_light = light.position - world_position;
+        let distance = length(to_light);
+        light_dir = to_light / distance;
+        strength *= attenuation(distance, light.range);
+
+        if light.kind == LIGHT_SPOT {
+            strength *= smoothstep(light.cos_outer, light.cos_inner, dot(-light_dir, light.direction));
+        }
+    }
+
+    return Incoming(light_dir, light.color * strength);
+}
+
+// Diffuse and specular light from a single light.
+fn shade(light: Light, world_position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, shininess: f32, shadow: f32) -> Lighting {
+    let incoming = incoming(light, world_position, shadow);
+
+    let half_dir = normalize(view_dir + incoming.direction);
+    let specular = pow(max(dot(normal, half_dir), 0.0), shininess);
+    let diffuse = max(dot(normal, incoming.direction), 0.0);
+
+    return Lighting(incoming.radiance * diffuse, incoming.radiance * specular);
+}
+
+const PI: f32 = 3.14159265359;
+
+// How much light a surface reflects straight back at us, before it's bent by the angle.
+// Everything that isn't metal reflects about 4%, metals reflect their own colour.
+fn base_reflectance(color: vec3<f32>, metallic: f32) -> vec3<f32> {
+    return mix(vec3<f32>(0.04), color, metallic);
+}
+
+// The same as `shade`, but physically based: GGX, Smith and Schlick, like glTF expects.
+// Diffuse still has to be multiplied with the surface colour, specular doesn't.
+//
+// Everything is multiplied by PI so a light is as bright here as it is in `shade`.
+fn shade_pbr(light: Light, world_position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, color: vec3<f32>, metallic: f32, roughness: f32, shadow: f32) -> Lighting {
+    let incoming = incoming(light, world_position, shadow);
+
+    let half_dir = normalize(view_dir + incoming.direction);
+    let n_dot_l = max(dot(normal, incoming.direction), 0.0);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+
+    // A perfect mirror would reflect everything into a single point, which doesn't divide well
+    let alpha = max(roughness * roughness, 0.002);
+    let alpha2 = alpha * alpha;
+    let d_denom = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d_denom * d_denom);
+
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+    let f0 = base_reflectance(color, metallic);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(half_dir, view_dir), 0.0), 5.0);
+
+    let specular = distribution * geometry * fresnel / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    // Whatever got reflected didn't make it into the surface, and metals don't have any diffuse
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic);
+
+    return Lighting(incoming.radiance * diffuse * n_dot_l, incoming.radiance * specular * n_dot_l * PI);
+}
+
 // Fragment shader
 
+// Tiles in the blocks texture on each side, and pixels on each side of a tile
+const ATLAS_TILES: vec2<f32> = vec2<f32>(4.0, 4.0);
+const TILE_SIZE: f32 = 16.0;
+
+// Scrolls the texture of flowing fluids, wrapping around inside of their tile.
+fn flowing_tex_coords(tex_coords: vec2<f32>, flow: vec2<f32>) -> vec2<f32> {
+    if all(flow == vec2<f32>(0.0)) {
+        return tex_coords;
+    }
+
+    let tile = floor(tex_coords * ATLAS_TILES);
+    let inside = fract(tex_coords * ATLAS_TILES - flow * light_globals.time.x);
+    // Keep half a pixel away from the neighbouring tiles
+    let inset = 0.5 / TILE_SIZE;
+    return (tile + clamp(inside, vec2<f32>(inset), vec2<f32>(1.0 - inset))) / ATLAS_TILES;
+}
+
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
+// Every map of the material gets sampled with this
 @group(0) @binding(1)
 var s_diffuse: sampler;
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let obj_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var t_specular: texture_2d<f32>;
+@group(0) @binding(4)
+var t_emissive: texture_2d<f32>;
+// Roughness in g, metallic in b, like glTF
+@group(0) @binding(6)
+var t_metallic_roughness: texture_2d<f32>;
+// Only r is used
+@group(0) @binding(7)
+var t_occlusion: texture_2d<f32>;
 
-    // We don't need much ambient light
-    let ambient_strength = 0.1;
-    let ambient_color = light.color * ambient_strength;
+// Has to match MaterialUniform
+struct Material {
+    // Dissolve (opacity) in w
+    diffuse: vec4<f32>,
+    // Shininess in w
+    specular: vec4<f32>,
+    emissive: vec4<f32>,
+    // Metallic and roughness
+    pbr: vec4<f32>,
+}
+@group(0) @binding(5)
+var<uniform> material: Material;
 
-    let light_dir = normalize(light.position - in.world_position);
+// Everything about a fragment's surface that lighting needs.
+struct Surface {
+    color: vec4<f32>,
+    normal: vec3<f32>,
+    specular: vec3<f32>,
+    shininess: f32,
+    emissive: vec3<f32>,
+    // Only used by `fs_pbr`
+    metallic: f32,
+    roughness: f32,
+    occlusion: f32,
+}
 
+// Bends the normal the way the normal map says, if there's anything to bend it along.
+fn mapped_normal(in: VertexOutput, tex_coords: vec2<f32>, normal: vec3<f32>) -> vec3<f32> {
+    // Sampled before anything gets decided, textures can only be sampled in uniform control flow
+    let tangent_normal = textureSample(t_normal, s_diffuse, tex_coords).xyz * 2.0 - 1.0;
+    if all(in.world_tangent == vec3<f32>(0.0)) {
+        return normal;
+    }
+
+    // Interpolating messes up the right angles, so straighten them out again
+    let tangent = normalize(in.world_tangent - normal * dot(normal, in.world_tangent));
+    let bitangent = normalize(
+        in.world_bitangent - normal * dot(normal, in.world_bitangent) - tangent * dot(tangent, in.world_bitangent)
+    );
+    return normalize(mat3x3<f32>(tangent, bitangent, normal) * tangent_normal);
+}
+
+// Reads every map of the material at `tex_coords`.
+fn surface(in: VertexOutput, tex_coords: vec2<f32>, normal: vec3<f32>) -> Surface {
+    var out: Surface;
+    out.color = textureSample(t_diffuse, s_diffuse, tex_coords) * material.diffuse * in.tint;
+    out.normal = mapped_normal(in, tex_coords, normal);
+    out.specular = textureSample(t_specular, s_diffuse, tex_coords).rgb * material.specular.rgb;
+    out.shininess = material.specular.w;
+    out.emissive = textureSample(t_emissive, s_diffuse, tex_coords).rgb * material.emissive.rgb;
+    let metallic_roughness = textureSample(t_metallic_roughness, s_diffuse, tex_coords);
+    out.metallic = metallic_roughness.b * material.pbr.x;
+    out.roughness = metallic_roughness.g * material.pbr.y;
+    out.occlusion = textureSample(t_occlusion, s_diffuse, tex_coords).r;
+    return out;
+}
+
+// Lights a surface, then fades it into the fog.
+// `pbr` picks `shade_pbr` over `shade`, see `fs_pbr`.
+fn lit(in: VertexOutput, surface: Surface, pbr: bool) -> vec4<f32> {
+    let normal = surface.normal;
     let view_dir = normalize(camera.view_pos.xyz - in.world_position);
-    let half_dir = normalize(view_dir + light_dir);
+    let depth = -(light_globals.view * vec4<f32>(in.world_position, 1.0)).z;
+    let shadow = shadow_factor(in.world_position, normal, depth);
+
+    // Directional lights come from the sky, so only they get dimmed by being out of the sun.
+    var sky = Lighting(light_globals.ambient.rgb, vec3<f32>(0.0));
+    var local = Lighting(vec3<f32>(0.0), vec3<f32>(0.0));
+    if pbr {
+        // Ambient light comes from everywhere, so there's no angle to it
+        let ambient = light_globals.ambient.rgb * surface.occlusion;
+        sky = Lighting(ambient * (1.0 - surface.metallic), ambient * base_reflectance(surface.color.rgb, surface.metallic));
+    }
 
-    let specular_strength = pow(max(dot(in.world_normal, half_dir), 0.0), 32.0);
-    let specular_color = specular_strength * light.color;
+    let cluster = clusters[cluster_index(in.clip_position.xy, depth)];
+    for (var i = 0u; i < cluster.y; i++) {
+        let light = lights[light_indices[cluster.x + i]];
+        var lighting: Lighting;
+        if pbr {
+            lighting = shade_pbr(
+                light, in.world_position, normal, view_dir,
+                surface.color.rgb, surface.metallic, surface.roughness, shadow,
+            );
+        } else {
+            lighting = shade(light, in.world_position, normal, view_dir, surface.shininess, shadow);
+        }
+        if light.kind == LIGHT_DIRECTIONAL {
+            sky.diffuse += lighting.diffuse;
+            sky.specular += lighting.specular;
+        } else {
+            local.diffuse += lighting.diffuse;
+            local.specular += lighting.specular;
+        }
+    }
 
-    let diffuse_strength = max(dot(in.world_normal, light_dir), 0.0);
-    let diffuse_color = light.color * diffuse_strength;
+    // Sunlight decides how much of the sky's light reaches us, block light adds its own warm glow.
+    // Keep a tiny bit of light around so pitch black caves aren't completely invisible.
+    let sun = max(light_curve(in.voxel_light.x), 0.02);
+    let block = light_curve(in.voxel_light.y) * step(0.001, in.voxel_light.y);
+    let block_color = vec3<f32>(1.0, 0.85, 0.6) * block;
 
-    let result = (ambient_color + diffuse_color + specular_color) * obj_color.xyz;
+    let diffuse = (sky.diffuse * sun + local.diffuse + block_color) * surface.color.rgb;
+    var specular = sky.specular * sun + local.specular;
+    // PBR has the colour of the reflection worked out already
+    if !pbr {
+        specular *= surface.specular;
+    }
+    let lit = diffuse + specular + surface.emissive;
 
-    return vec4<f32>(result, obj_color.a);
-}
\ No newline at end of file
+    // Fade into the sky, so the edge of the world doesn't pop in at the render distance
+    let visibility = fog_visibility(distance(camera.view_pos.xyz, in.world_position));
+    let result = mix(fog.color.rgb, lit, visibility);
+
+    return vec4<f32>(result, surface.color.a);
+}
+
+fn lit_color(in: VertexOutput, surface: Surface) -> vec4<f32> {
+    return lit(in, surface, false);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return lit_color(in, surface(in, flowing_tex_coords(in.tex_coords, in.flow), normalize(in.world_normal)));
+}
+
+// Leaves and such: pixels are either fully there or cut out completely, so no sorting is needed.
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    var surface = surface(in, in.tex_coords, normalize(in.world_normal));
+    if surface.color.a < 0.5 {
+        discard;
+    }
+    surface.color.a = 1.0;
+    return lit_color(in, surface);
+}
+
+// Glass and water get blended with what's behind them. Their back faces are drawn too,
+// so flip the normal when we're looking at one.
+@fragment
+fn fs_translucent(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    var normal = normalize(in.world_normal);
+    if !front_facing {
+        normal = -normal;
+    }
+    return lit_color(in, surface(in, flowing_tex_coords(in.tex_coords, in.flow), normal));
+}
+
+// Models with metallic-roughness materials, like the ones from glTF files.
+@fragment
+fn fs_pbr(in: VertexOutput) -> @location(0) vec4<f32> {
+    return lit(in, surface(in, in.tex_coords, normalize(in.world_normal)), true);
+}
@@ -0,0 +1,40 @@
+// Renders depth from the sun's point of view into a shadow cascade.
+
+struct Cascade {
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> cascade: Cascade;
+
+// Only the position of a packed `ChunkVertex`
+struct VertexInput {
+    @location(0) words: vec2<u32>,
+}
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    let position = vec3<f32>(
+        f32(model.words.x & 0x3Fu),
+        f32((model.words.x >> 12u) & 0xFFFu) / 64.0,
+        f32((model.words.x >> 6u) & 0x3Fu),
+    );
+
+    return cascade.view_proj * model_matrix * vec4<f32>(position, 1.0);
+}
@@ -0,0 +1,74 @@
+// A procedural sky: a gradient from the horizon up, a glow around the sun
+// when it rises and sets, the sun and moon themselves, and stars at night.
+
+struct Sky {
+    inverse_view_proj: mat4x4<f32>,
+    // Towards the sun, and how much daylight there is
+    sun: vec4<f32>,
+    // Towards the moon, and how visible the stars are
+    moon: vec4<f32>,
+    // The colour straight up, and the time of day
+    zenith: vec4<f32>,
+    // The colour at the horizon, and how much of a sunrise/sunset there is
+    horizon: vec4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// One triangle big enough to cover the whole screen, no vertex buffer needed.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+fn hash(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// Stars turn with the sky, so they rotate around the same axis as the sun.
+fn stars(dir: vec3<f32>) -> f32 {
+    let angle = sky.zenith.w * 6.28318530718;
+    let c = cos(angle);
+    let s = sin(angle);
+    let turned = vec3<f32>(c * dir.x + s * dir.y, -s * dir.x + c * dir.y, dir.z);
+
+    let cell = floor(turned * 300.0);
+    let h = hash(cell);
+    return smoothstep(0.9975, 1.0, h);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let world = sky.inverse_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let dir = normalize(world.xyz / world.w);
+    let height = dir.y;
+
+    // Lighter at the horizon, darker straight up. Below the horizon fades out a bit.
+    var color = mix(sky.horizon.rgb, sky.zenith.rgb, sqrt(clamp(height, 0.0, 1.0)));
+    color = mix(color, sky.horizon.rgb * 0.4, clamp(-height * 2.0, 0.0, 1.0));
+
+    // Sunrise and sunset light up the sky around the sun
+    let sun_dot = max(dot(dir, sky.sun.xyz), 0.0);
+    let glow = pow(sun_dot, 8.0) * sky.horizon.w * (1.0 - clamp(height, 0.0, 1.0));
+    color += vec3<f32>(1.0, 0.45, 0.15) * glow;
+
+    // The sun and the moon
+    color += vec3<f32>(1.0, 0.95, 0.8) * smoothstep(0.9994, 0.9997, sun_dot) * 4.0;
+    color += vec3<f32>(0.8, 0.85, 1.0) * smoothstep(0.9990, 0.9993, dot(dir, sky.moon.xyz));
+
+    // Stars only show up at night and above the horizon
+    color += vec3<f32>(stars(dir)) * sky.moon.w * step(0.0, height);
+
+    return vec4<f32>(color, 1.0);
+}
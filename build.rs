@@ -1,20 +1,58 @@
 use anyhow::*;
-use fs_extra::copy_items;
-use fs_extra::dir::CopyOptions;
 use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-/// We need to have our assets moved into the build so we can access them.
-/// Hence this build script.
+/// Has to match `vfs::Archive`.
+const MAGIC: &[u8; 4] = b"AVPK";
+const VERSION: u32 = 1;
+
+/// Packs everything in `assets/` into a single archive that gets embedded into the binary,
+/// so it runs wherever it's copied to. See `engine::vfs`.
 fn main() -> Result<()> {
     // Tells Cargo to rerun if anything changes in our assets
-    println!("cargo:rerun-if-changed=assets/*");
+    println!("cargo:rerun-if-changed=assets");
+
+    let mut files = Vec::new();
+    collect_files(Path::new("assets"), &mut files)?;
+    // Same archive every time for the same assets
+    files.sort();
 
     let out_dir = env::var("OUT_DIR")?;
-    let mut copy_options = CopyOptions::new();
-    copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("assets/");
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    let mut archive = BufWriter::new(File::create(Path::new(&out_dir).join("assets.pak"))?);
+    archive.write_all(MAGIC)?;
+    archive.write_all(&VERSION.to_le_bytes())?;
+    archive.write_all(&(files.len() as u32).to_le_bytes())?;
+
+    for file in files {
+        // Paths inside the archive always use `/`, no matter what the OS does
+        let name = file
+            .strip_prefix("assets")?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let data = std::fs::read(&file)?;
+
+        archive.write_all(&(name.len() as u32).to_le_bytes())?;
+        archive.write_all(name.as_bytes())?;
+        archive.write_all(&(data.len() as u32).to_le_bytes())?;
+        archive.write_all(&data)?;
+    }
+    archive.flush()?;
+
+    Ok(())
+}
 
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
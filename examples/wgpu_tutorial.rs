@@ -0,0 +1,16 @@
+//! The scene from the learn-wgpu tutorial the engine started out as, grown into a test bed for
+//! Models: .obj and glTF cubes drawn as entities, a PBR pipeline and an animated, skinned arm.
+//!
+//! `cargo run --example wgpu_tutorial`
+
+use aravoxel::scene::wgpu_tutorial::WgpuTutorial;
+
+fn main() {
+    pretty_env_logger::init();
+
+    if let Err(e) = pollster::block_on(aravoxel::engine::aravoxel::run::<WgpuTutorial>()) {
+        log::error!("{e}");
+        eprintln!("wgpu_tutorial couldn't start: {e}");
+        std::process::exit(1);
+    }
+}
@@ -1,4 +1,13 @@
+#[cfg(feature = "windowing")]
 pub mod aravoxel;
+pub mod assets;
+pub mod error;
+pub mod hot_reload;
+#[cfg(feature = "windowing")]
+pub mod input;
 pub mod util;
 pub mod resource;
-pub mod resource_manager;
\ No newline at end of file
+pub mod resource_manager;
+pub mod settings;
+pub mod time;
+pub mod vfs;
\ No newline at end of file
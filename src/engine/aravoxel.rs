@@ -3,24 +3,33 @@ use std::time::{Duration, Instant};
 use winit::event::{DeviceEvent, Event, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
+use crate::engine::error::{Error, Result};
+use crate::engine::hot_reload::ShaderWatcher;
+use crate::engine::input::InputMap;
+use crate::engine::settings::Settings;
+use crate::engine::time::FixedTimestep;
+use crate::engine::vfs::{self, AssetRoot};
 use crate::scene::scene::Scene;
-use crate::scene::voxel_world::VoxelWorld;
 
 /// The engine itself. Handles everything relating to the window and
 /// ensuring that the right states are doing the things.
-pub struct Aravoxel<'window> {
+pub struct Aravoxel<'window, S: Scene> {
     surface: wgpu::Surface<'window>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     window: Arc<Window>,
+    input: InputMap,
+    timestep: FixedTimestep,
+    /// There when hot reloading is on, see `DevSettings`.
+    shader_watcher: Option<ShaderWatcher>,
 
-    scene: VoxelWorld,
+    scene: S,
 }
 
-impl Aravoxel<'_> {
-    async fn new(window: Arc<Window>) -> Self {
+impl<S: Scene> Aravoxel<'_, S> {
+    async fn new(window: Arc<Window>, settings: &Settings) -> Result<Self> {
         let size = window.inner_size();
 
         // First thing's first: an instance, so we can create our surface (place to draw to) and adapter (GPU)
@@ -29,7 +38,7 @@ impl Aravoxel<'_> {
             ..Default::default()
         });
 
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = instance.create_surface(window.clone())?;
 
         let adapter = instance.request_adapter(
             &wgpu::RequestAdapterOptions {
@@ -37,22 +46,26 @@ impl Aravoxel<'_> {
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             },
-        ).await.unwrap();
+        ).await.ok_or(Error::NoAdapter)?;
+        log::info!("Using {:?}", adapter.get_info());
 
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
+                // Lets every chunk get drawn with one call, see `IndirectDraws`
+                required_features: adapter.features()
+                    & (wgpu::Features::MULTI_DRAW_INDIRECT | wgpu::Features::INDIRECT_FIRST_INSTANCE),
                 required_limits: wgpu::Limits::default(),
                 label: None,
             },
             None,
-        ).await.unwrap();
+        ).await?;
 
         // Now to set up the surface itself.
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps.formats.iter()
             .copied().find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
+            .or(surface_caps.formats.first().copied())
+            .ok_or(Error::UnsupportedSurface)?;
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -66,16 +79,36 @@ impl Aravoxel<'_> {
         };
         surface.configure(&device, &config);
 
-        let scene = VoxelWorld::new(&device, &config, &queue).await;
-        Self {
+        let scene = S::new(&device, &config, &queue, settings).await?;
+
+        let mut input = InputMap::load("input/bindings.toml").await?;
+        input.set_context(S::INPUT_CONTEXT);
+
+        let timestep = FixedTimestep::new(
+            settings.simulation.tick_rate,
+            settings.simulation.max_ticks_per_frame,
+        );
+
+        let shader_watcher = if settings.dev.hot_reload {
+            ShaderWatcher::new()
+                .inspect_err(|e| log::warn!("Shaders won't hot reload, couldn't watch them: {e}"))
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(Self {
             window,
+            input,
+            timestep,
+            shader_watcher,
             surface,
             device,
             queue,
             config,
             size,
             scene: *scene,
-        }
+        })
     }
 
     fn window(&self) -> &Window {
@@ -96,21 +129,35 @@ impl Aravoxel<'_> {
         }
     }
 
-    //TODO: Refactor this and make use of whatever scene's own input function
+    /// Feeds window events into the InputMap. Scenes read actions from it during `update`.
     fn input(&mut self, event: &WindowEvent) {
-        self.scene.input(event);
+        self.input.handle_window_event(event);
     }
-    
+
     // Handles things such as mouse movement.
     fn device_input(&mut self, event: &DeviceEvent) {
-        self.scene.device_input(event);
+        self.input.handle_device_event(event);
     }
 
+    /// Runs as many fixed ticks as the frame time allows, then the per-frame update.
     fn update(&mut self, dt: Duration) {
-        self.scene.update(&self.queue, dt);
+        if let Some(shader_watcher) = &self.shader_watcher {
+            let changed = shader_watcher.changed();
+            if !changed.is_empty() {
+                self.scene.reload_shaders(&self.device, &self.queue, &changed);
+            }
+        }
+
+        self.timestep.accumulate(dt);
+        while let Some(tick) = self.timestep.next_tick() {
+            self.scene.fixed_update(&self.input, tick, self.timestep.step());
+        }
+
+        self.scene.update(&self.device, &self.queue, &self.input, dt, self.timestep.alpha());
+        self.input.end_frame();
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+    fn render(&mut self) -> std::result::Result<(), wgpu::SurfaceError> {
         // We need to get the current SurfaceTexture to know where to draw to.
         let output = self.surface.get_current_texture()?;
 
@@ -134,16 +181,22 @@ impl Aravoxel<'_> {
     }
 }
 
-/// The only public function. Initializes the window and starts the loop.
-pub async fn run() {
-    let event_loop = EventLoop::new().unwrap();
+/// Opens the window and runs `S` in it until it gets closed.
+/// Only returns early if something needed to get going is missing, like a GPU.
+pub async fn run<S: Scene>() -> Result<()> {
+    let event_loop = EventLoop::new()?;
     let window = Arc::new(WindowBuilder::new()
         .with_resizable(false)
         .with_title("aravoxel")
-        .build(&event_loop)
-        .unwrap());
+        .build(&event_loop)?);
 
-    let mut aravoxel = Aravoxel::new(window).await;
+    let settings = Settings::load()?;
+    if let Some(root) = &settings.assets.root {
+        vfs::set_root(AssetRoot::Directory(root.clone()));
+    } else if settings.dev.hot_reload {
+        vfs::set_root(AssetRoot::source());
+    }
+    let mut aravoxel = Aravoxel::<S>::new(window, &settings).await?;
     
     let mut last_render_time = Instant::now();
     event_loop.set_control_flow(ControlFlow::Poll);
@@ -155,35 +208,35 @@ pub async fn run() {
                 aravoxel.window().request_redraw();
             }
             Event::DeviceEvent { event, ..} => aravoxel.device_input(&event),
-            Event::WindowEvent { event, window_id} => {
-                if window_id == aravoxel.window().id() {
-                    // This function handles scene specific input.
-                    aravoxel.input(&event);
-                    match event {
-                        WindowEvent::RedrawRequested => {
-                            let now = Instant::now();
-                            let dt = now - last_render_time;
-                            last_render_time = now;
-                            aravoxel.update(dt);
-                            match aravoxel.render() {
-                                Ok(_) => {}
-                                // Reconfigure if we lose the surface.
-                                Err(wgpu::SurfaceError::Lost) => aravoxel.resize(aravoxel.size),
-                                // Out of memory, let's bail.
-                                Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
-                                // Uhh... something's wrong.
-                                Err(e) => eprintln!("{:?}", e),
-                            }
-                        }
-                        WindowEvent::Resized(physical_size) => {
-                            aravoxel.resize(physical_size);
+            Event::WindowEvent { event, window_id} if window_id == aravoxel.window().id() => {
+                // Keep the InputMap up to date, the scene reads actions from it.
+                aravoxel.input(&event);
+                match event {
+                    WindowEvent::RedrawRequested => {
+                        let now = Instant::now();
+                        let dt = now - last_render_time;
+                        last_render_time = now;
+                        aravoxel.update(dt);
+                        match aravoxel.render() {
+                            Ok(_) => {}
+                            // Reconfigure if we lose the surface.
+                            Err(wgpu::SurfaceError::Lost) => aravoxel.resize(aravoxel.size),
+                            // Out of memory, let's bail.
+                            Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
+                            // Uhh... something's wrong.
+                            Err(e) => log::error!("{e}"),
                         }
-                        WindowEvent::CloseRequested => elwt.exit(),
-                        _ => ()
                     }
+                    WindowEvent::Resized(physical_size) => {
+                        aravoxel.resize(physical_size);
+                    }
+                    WindowEvent::CloseRequested => elwt.exit(),
+                    _ => ()
                 }
             }
             _ => ()
         }
-    }).expect("Window did, uh, something... bad?");
+    })?;
+
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::{mpsc, Arc};
+
+use crate::engine::error::{Error, Result};
+use crate::engine::resource::texture::{NormalMap, Texture};
+use crate::engine::util::{catch_validation_error, load_binary, load_string};
+
+/// Something that can be loaded out of a file in the assets folder.
+///
+/// Loading happens in two halves. `read` runs on a thread of its own and does everything
+/// that doesn't need the GPU, like reading and decoding the file. `create` then makes the
+/// asset out of that on the main thread.
+pub trait Asset: Sized + 'static {
+    /// Whatever `read` hands over to `create`.
+    type Data: Send + 'static;
+
+    /// * `path` - Relative to the assets folder, like `textures/blocks.png`.
+    fn read(path: &str) -> Result<Self::Data>;
+
+    fn create(data: Self::Data, path: &str, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self>;
+
+    /// What to use instead of assets that didn't load, if anything.
+    fn fallback(_device: &wgpu::Device, _queue: &wgpu::Queue) -> Option<Self> {
+        None
+    }
+}
+
+fn read_image(path: &str) -> Result<image::DynamicImage> {
+    let bytes = pollster::block_on(load_binary(path))?;
+    image::load_from_memory(&bytes).map_err(|source| Error::Image {
+        path: path.to_string(),
+        source,
+    })
+}
+
+impl Asset for Texture {
+    type Data = image::DynamicImage;
+
+    fn read(path: &str) -> Result<Self::Data> {
+        read_image(path)
+    }
+
+    fn create(data: Self::Data, path: &str, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        Ok(Texture::from_image(device, queue, &data, path))
+    }
+
+    /// Missing textures are hard to miss.
+    fn fallback(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        Some(Texture::checkerboard(device, queue))
+    }
+}
+
+impl Asset for NormalMap {
+    type Data = image::DynamicImage;
+
+    fn read(path: &str) -> Result<Self::Data> {
+        read_image(path)
+    }
+
+    fn create(data: Self::Data, path: &str, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        Ok(NormalMap(Texture::from_image_linear(device, queue, &data, path)))
+    }
+
+    /// Surfaces just look flat without their normal map.
+    fn fallback(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        Some(NormalMap::flat(device, queue))
+    }
+}
+
+impl Asset for wgpu::ShaderModule {
+    type Data = String;
+
+    fn read(path: &str) -> Result<Self::Data> {
+        pollster::block_on(load_string(path))
+    }
+
+    /// Shaders that don't compile are an error instead of a panic, so they can be fixed and reloaded.
+    fn create(data: Self::Data, path: &str, device: &wgpu::Device, _queue: &wgpu::Queue) -> Result<Self> {
+        catch_validation_error(device, || {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(path),
+                source: wgpu::ShaderSource::Wgsl(data.into()),
+            })
+        })
+        .map_err(|e| Error::Shader {
+            path: path.to_string(),
+            message: e.to_string(),
+        })
+    }
+}
+
+/// Points at an asset in `Assets`. Handles are cheap to clone, and the asset stays loaded
+/// for as long as there's at least one of them around, see `Assets::unload_unused`.
+pub struct Handle<T> {
+    inner: Arc<HandleInner>,
+    asset: PhantomData<fn() -> T>,
+}
+
+#[derive(Debug)]
+struct HandleInner {
+    id: u64,
+    path: String,
+}
+
+impl<T> Handle<T> {
+    /// Where the asset came from, relative to the assets folder.
+    pub fn path(&self) -> &str {
+        &self.inner.path
+    }
+}
+
+// Derives would want T to be all of these too
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            asset: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.id == other.inner.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({}, {:?})", self.inner.id, self.inner.path)
+    }
+}
+
+/// How far along an asset is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    /// Didn't load, and why.
+    Failed(String),
+    /// Not in these Assets at all, like a Handle that came from some other Assets.
+    NotLoaded,
+}
+
+enum Slot<T> {
+    Loading,
+    Loaded(T),
+    Failed(String),
+}
+
+struct Entry<T> {
+    /// The Assets' own copy of the handle. Nobody else has one if this is the only one left.
+    inner: Arc<HandleInner>,
+    slot: Slot<T>,
+}
+
+/// Every asset of one kind. Loading the same path twice gives the same asset.
+///
+/// Assets load in the background: `load` gives a Handle right away, and the asset shows up
+/// once `update` sees it's done. Anything that can't go on without it can `wait` for it.
+///
+/// Assets that don't load get logged, and the kind's `Asset::fallback` shows up in their place.
+pub struct Assets<T: Asset> {
+    entries: HashMap<u64, Entry<T>>,
+    /// Which path is which asset, so nothing gets loaded twice.
+    ids: HashMap<String, u64>,
+    next_id: u64,
+    /// Made the first time something fails to load.
+    fallback: Option<T>,
+    sender: mpsc::Sender<(u64, Result<T::Data>)>,
+    loaded: mpsc::Receiver<(u64, Result<T::Data>)>,
+}
+
+impl<T: Asset> Default for Assets<T> {
+    fn default() -> Self {
+        let (sender, loaded) = mpsc::channel();
+        Self {
+            entries: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+            fallback: None,
+            sender,
+            loaded,
+        }
+    }
+}
+
+impl<T: Asset> Assets<T> {
+    /// Starts loading `path` on another thread, unless it's loaded (or loading) already.
+    ///
+    /// * `path` - Relative to the assets folder, like `textures/blocks.png`.
+    pub fn load(&mut self, path: &str) -> Handle<T> {
+        if let Some(handle) = self.find(path) {
+            return handle;
+        }
+
+        let handle = self.insert(path);
+
+        let id = handle.inner.id;
+        let sender = self.sender.clone();
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            // Always send something back, `wait` would wait forever otherwise
+            let data = std::panic::catch_unwind(|| T::read(&path))
+                .unwrap_or_else(|_| Err(Error::Asset(format!("Panicked while reading {path}"))));
+            // Nobody's listening anymore if the Assets are gone, which is fine
+            let _ = sender.send((id, data));
+        });
+
+        handle
+    }
+
+    /// Finishes every asset that's done loading in the background. Call once a frame.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        while let Ok((id, data)) = self.loaded.try_recv() {
+            self.finish(id, data, device, queue);
+        }
+    }
+
+    /// Blocks until an asset is done loading, finishing any others that get done in the meantime.
+    /// Only fails if the asset didn't load and there's no fallback for it.
+    pub fn wait(&mut self, handle: &Handle<T>, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<&T> {
+        while self.state(handle) == LoadState::Loading {
+            // Can't fail, we hold on to a sender ourselves
+            let (id, data) = self.loaded.recv().unwrap();
+            self.finish(id, data, device, queue);
+        }
+
+        let Some(entry) = self.entry(handle) else {
+            return Err(Error::Asset(format!("{} isn't loaded", handle.path())));
+        };
+        match &entry.slot {
+            Slot::Loaded(asset) => Ok(asset),
+            Slot::Failed(e) => self.fallback.as_ref().ok_or_else(|| Error::Asset(e.clone())),
+            Slot::Loading => unreachable!(),
+        }
+    }
+
+    pub fn state(&self, handle: &Handle<T>) -> LoadState {
+        let Some(entry) = self.entry(handle) else {
+            return LoadState::NotLoaded;
+        };
+        match &entry.slot {
+            Slot::Loading => LoadState::Loading,
+            Slot::Loaded(_) => LoadState::Loaded,
+            Slot::Failed(e) => LoadState::Failed(e.clone()),
+        }
+    }
+
+    /// The asset, if it's done loading. Or the fallback, if it didn't load.
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        match &self.entry(handle)?.slot {
+            Slot::Loaded(asset) => Some(asset),
+            Slot::Failed(_) => self.fallback.as_ref(),
+            Slot::Loading => None,
+        }
+    }
+
+    /// Adds an asset that wasn't loaded from a file, like one that got generated.
+    /// `path` only names it. If there's one by that name already, `create` doesn't get called
+    /// and that one's Handle comes back.
+    pub fn add(&mut self, path: &str, create: impl FnOnce() -> T) -> Handle<T> {
+        if let Some(handle) = self.find(path) {
+            return handle;
+        }
+
+        let handle = self.insert(path);
+        self.entries.get_mut(&handle.inner.id).unwrap().slot = Slot::Loaded(create());
+        handle
+    }
+
+    /// The Handle of whatever was loaded or added as `path` already, if anything.
+    pub fn find(&self, path: &str) -> Option<Handle<T>> {
+        let entry = self.ids.get(path).and_then(|id| self.entries.get(id))?;
+        Some(Handle {
+            inner: entry.inner.clone(),
+            asset: PhantomData,
+        })
+    }
+
+    /// Loads an asset again right away, for when its file changed. If that doesn't work the old
+    /// version stays. Anything that was made out of the old version has to be made again.
+    ///
+    /// Gives `None` for paths that were never loaded.
+    pub fn reload(&mut self, path: &str, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Result<Handle<T>>> {
+        let entry = self.ids.get(path).and_then(|id| self.entries.get_mut(id))?;
+
+        let asset = T::read(path).and_then(|data| T::create(data, path, device, queue));
+        Some(asset.map(|asset| {
+            entry.slot = Slot::Loaded(asset);
+            Handle {
+                inner: entry.inner.clone(),
+                asset: PhantomData,
+            }
+        }))
+    }
+
+    /// Drops every asset nobody has a Handle to anymore. Gives how many there were.
+    pub fn unload_unused(&mut self) -> usize {
+        let unused: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| Arc::strong_count(&entry.inner) == 1)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &unused {
+            let entry = self.entries.remove(id).unwrap();
+            self.ids.remove(&entry.inner.path);
+        }
+        unused.len()
+    }
+
+    /// The entry a Handle points at. Ids are only unique within one Assets,
+    /// so this makes sure it really is the same entry and not just the same id.
+    fn entry(&self, handle: &Handle<T>) -> Option<&Entry<T>> {
+        self.entries
+            .get(&handle.inner.id)
+            .filter(|entry| Arc::ptr_eq(&entry.inner, &handle.inner))
+    }
+
+    /// A new entry that's still loading.
+    fn insert(&mut self, path: &str) -> Handle<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let inner = Arc::new(HandleInner {
+            id,
+            path: path.to_string(),
+        });
+        self.ids.insert(path.to_string(), id);
+        self.entries.insert(
+            id,
+            Entry {
+                inner: inner.clone(),
+                slot: Slot::Loading,
+            },
+        );
+
+        Handle {
+            inner,
+            asset: PhantomData,
+        }
+    }
+
+    fn finish(&mut self, id: u64, data: Result<T::Data>, device: &wgpu::Device, queue: &wgpu::Queue) {
+        // Unloaded before it was done
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return;
+        };
+
+        let path = &entry.inner.path;
+        entry.slot = match data.and_then(|data| T::create(data, path, device, queue)) {
+            Ok(asset) => Slot::Loaded(asset),
+            Err(e) => {
+                log::error!("{e}");
+                if self.fallback.is_none() {
+                    self.fallback = T::fallback(device, queue);
+                }
+                Slot::Failed(e.to_string())
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::util::software_device;
+
+    /// Made out of its path. Paths that start with `missing` don't load.
+    #[derive(Debug, PartialEq)]
+    struct Dummy(String);
+
+    impl Asset for Dummy {
+        type Data = String;
+
+        fn read(path: &str) -> Result<Self::Data> {
+            if path.starts_with("missing") {
+                return Err(Error::Asset(format!("There's no {path}")));
+            }
+            Ok(path.to_uppercase())
+        }
+
+        fn create(data: Self::Data, _path: &str, _device: &wgpu::Device, _queue: &wgpu::Queue) -> Result<Self> {
+            Ok(Dummy(data))
+        }
+
+        fn fallback(_device: &wgpu::Device, _queue: &wgpu::Queue) -> Option<Self> {
+            Some(Dummy("fallback".to_string()))
+        }
+    }
+
+    #[test]
+    fn the_same_path_is_the_same_asset() {
+        let mut assets = Assets::<Dummy>::default();
+        let a = assets.load("a.txt");
+        let b = assets.load("b.txt");
+
+        assert_eq!(assets.load("a.txt"), a);
+        assert_eq!(assets.find("a.txt"), Some(a.clone()));
+        assert_ne!(a, b);
+        assert_eq!(assets.find("c.txt"), None);
+        // Adding under a taken name doesn't make anything
+        assert_eq!(assets.add("a.txt", || unreachable!()), a);
+    }
+
+    #[test]
+    fn assets_unload_once_their_handles_are_gone() {
+        let mut assets = Assets::<Dummy>::default();
+        let kept = assets.add("kept", || Dummy("kept".to_string()));
+        let dropped = assets.add("dropped", || Dummy("dropped".to_string()));
+        let copy = dropped.clone();
+
+        drop(dropped);
+        assert_eq!(assets.unload_unused(), 0);
+
+        drop(copy);
+        assert_eq!(assets.unload_unused(), 1);
+        assert_eq!(assets.find("dropped"), None);
+        assert_eq!(assets.get(&kept), Some(&Dummy("kept".to_string())));
+    }
+
+    #[test]
+    fn handles_to_unloaded_assets_dont_panic() {
+        let mut other = Assets::<Dummy>::default();
+        let elsewhere = other.add("elsewhere", || Dummy("elsewhere".to_string()));
+        // Has an asset with the same id
+        let mut assets = Assets::<Dummy>::default();
+        assets.add("here", || Dummy("here".to_string()));
+
+        assert_eq!(assets.state(&elsewhere), LoadState::NotLoaded);
+        assert_eq!(assets.get(&elsewhere), None);
+    }
+
+    #[test]
+    fn load_states() {
+        let (device, queue) = software_device();
+        let mut assets = Assets::<Dummy>::default();
+
+        let added = assets.add("added", || Dummy("added".to_string()));
+        assert_eq!(assets.state(&added), LoadState::Loaded);
+
+        let found = assets.load("found.txt");
+        assert_eq!(assets.state(&found), LoadState::Loading);
+        assert_eq!(assets.get(&found), None);
+        assert_eq!(assets.wait(&found, &device, &queue).unwrap(), &Dummy("FOUND.TXT".to_string()));
+        assert_eq!(assets.state(&found), LoadState::Loaded);
+
+        // Failures get the fallback in their place
+        let missing = assets.load("missing.txt");
+        assert_eq!(assets.wait(&missing, &device, &queue).unwrap(), &Dummy("fallback".to_string()));
+        assert_eq!(assets.state(&missing), LoadState::Failed("There's no missing.txt".to_string()));
+        assert_eq!(assets.get(&missing), Some(&Dummy("fallback".to_string())));
+
+        let mut other = Assets::<Dummy>::default();
+        let elsewhere = other.add("elsewhere", || Dummy("elsewhere".to_string()));
+        assert!(assets.wait(&elsewhere, &device, &queue).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use std::io;
+
+/// Everything in aravoxel that can go wrong without it being a bug.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "No GPU that can draw to this window was found. aravoxel needs Vulkan, Metal, \
+         DirectX 12 or OpenGL 3.3 (or OpenGL ES 3) support, so make sure your graphics drivers are installed"
+    )]
+    NoAdapter,
+    #[error("The GPU can't draw to this window, it doesn't support any surface format")]
+    UnsupportedSurface,
+    #[error("Couldn't create a surface to draw to: {0}")]
+    Surface(#[from] wgpu::CreateSurfaceError),
+    #[error("The GPU didn't give us a device: {0}")]
+    Device(#[from] wgpu::RequestDeviceError),
+    #[cfg(feature = "windowing")]
+    #[error("Couldn't open the window: {0}")]
+    Window(#[from] winit::error::OsError),
+    #[cfg(feature = "windowing")]
+    #[error("The event loop stopped: {0}")]
+    EventLoop(#[from] winit::error::EventLoopError),
+
+    #[error("Couldn't read {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("{path} isn't a valid image: {source}")]
+    Image { path: String, source: image::ImageError },
+    #[error("{path} isn't a valid model: {source}")]
+    Model { path: String, source: tobj::LoadError },
+    #[error("{path} isn't a valid glTF model: {message}")]
+    Gltf { path: String, message: String },
+    #[error("{path} doesn't compile:\n{message}")]
+    Shader { path: String, message: String },
+    #[error("{path} isn't valid: {source}")]
+    Toml { path: String, source: toml::de::Error },
+    #[error("Invalid input bindings: {0}")]
+    Bindings(String),
+    /// An asset that failed to load earlier, see `Assets::wait`.
+    #[error("{0}")]
+    Asset(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
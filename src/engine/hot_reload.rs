@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::engine::vfs::{self, AssetRoot};
+
+/// Keeps an eye on the shaders in the assets folder, so they can be reloaded when they change.
+/// Needs the assets to come from a folder, the embedded ones never change.
+pub struct ShaderWatcher {
+    /// Stops watching when dropped.
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    asset_dir: PathBuf,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let AssetRoot::Directory(asset_dir) = vfs::root() else {
+            return Err(notify::Error::generic("the assets are embedded, there's nothing to watch"));
+        };
+        let shader_dir = asset_dir.join("shaders");
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&shader_dir, RecursiveMode::Recursive)?;
+        log::info!("Watching {} for changes", shader_dir.display());
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            asset_dir,
+        })
+    }
+
+    /// The shaders that changed since the last call, relative to the assets folder like
+    /// `Handle::path`.
+    /// Saving a file usually makes a couple of events, each shader only shows up once.
+    pub fn changed(&self) -> Vec<String> {
+        let mut changed = Vec::new();
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    log::warn!("Couldn't watch shaders: {e}");
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+
+            for path in event.paths {
+                if path.extension().is_none_or(|extension| extension != "wgsl") {
+                    continue;
+                }
+                let Ok(file_name) = path.strip_prefix(&self.asset_dir) else {
+                    continue;
+                };
+                let file_name = file_name.to_string_lossy().replace('\\', "/");
+                if !changed.contains(&file_name) {
+                    changed.push(file_name);
+                }
+            }
+        }
+        changed
+    }
+}
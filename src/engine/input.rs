@@ -0,0 +1,471 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::engine::error::{self, Error};
+use crate::engine::util::load_string;
+
+/// How many "pixels" a single line of scrolling is worth.
+/// Keeps line based scroll wheels and touchpads roughly equal.
+const PIXELS_PER_LINE: f32 = 100.0;
+
+/// Every key we know how to read from and write to a bindings file.
+/// The names in the file are the same as the `KeyCode` variant names.
+///
+/// Keys that aren't in here can't be bound, since they couldn't be loaded again once saved.
+const KEY_CODES: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE,
+    KeyCode::KeyF, KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ,
+    KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO,
+    KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT,
+    KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight,
+    KeyCode::AltLeft, KeyCode::AltRight,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Escape, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::Minus, KeyCode::Equal, KeyCode::BracketLeft, KeyCode::BracketRight,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon, KeyCode::Quote,
+    KeyCode::Backquote, KeyCode::Backslash,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::CapsLock, KeyCode::NumLock, KeyCode::ScrollLock, KeyCode::PrintScreen, KeyCode::Pause,
+    KeyCode::SuperLeft, KeyCode::SuperRight, KeyCode::ContextMenu,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::NumpadAdd, KeyCode::NumpadSubtract, KeyCode::NumpadMultiply, KeyCode::NumpadDivide,
+    KeyCode::NumpadDecimal, KeyCode::NumpadEnter,
+];
+
+/// One of the two axes of the mouse wheel or the mouse itself.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+/// A physical thing the player can poke at.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// The scroll wheel. Reports how much has been scrolled this frame.
+    Scroll(Axis),
+    /// Raw mouse movement. Reports how far the mouse moved this frame.
+    Motion(Axis),
+}
+
+/// An InputSource tied to an action.
+/// The scale lets us invert axes, e.g. `-Scroll:Y`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Binding {
+    pub source: InputSource,
+    pub scale: f32,
+}
+
+impl Binding {
+    pub fn new(source: InputSource) -> Self {
+        Self { source, scale: 1.0 }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = Error;
+
+    /// Bindings are written as `<device>:<name>`, like `Key:KeyW` or `Mouse:Left`.
+    /// A leading `-` inverts the value of the binding.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scale, s) = match s.trim().strip_prefix('-') {
+            Some(rest) => (-1.0, rest),
+            None => (1.0, s.trim()),
+        };
+
+        let (device, name) = s
+            .split_once(':')
+            .ok_or_else(|| Error::Bindings(format!("Binding '{s}' is missing a device, e.g. 'Key:{s}'")))?;
+
+        let source = match device {
+            "Key" => InputSource::Key(
+                *KEY_CODES
+                    .iter()
+                    .find(|code| format!("{code:?}") == name)
+                    .ok_or_else(|| Error::Bindings(format!("Unknown key '{name}'")))?,
+            ),
+            "Mouse" => InputSource::Mouse(match name {
+                "Left" => MouseButton::Left,
+                "Right" => MouseButton::Right,
+                "Middle" => MouseButton::Middle,
+                "Back" => MouseButton::Back,
+                "Forward" => MouseButton::Forward,
+                other => MouseButton::Other(
+                    other
+                        .parse()
+                        .map_err(|_| Error::Bindings(format!("Unknown mouse button '{other}'")))?,
+                ),
+            }),
+            "Scroll" => InputSource::Scroll(name.parse()?),
+            "Motion" => InputSource::Motion(name.parse()?),
+            other => return Err(Error::Bindings(format!("Unknown input device '{other}'"))),
+        };
+
+        Ok(Self { source, scale })
+    }
+}
+
+impl FromStr for Axis {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "X" => Ok(Axis::X),
+            "Y" => Ok(Axis::Y),
+            other => Err(Error::Bindings(format!("Unknown axis '{other}'"))),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale < 0.0 {
+            write!(f, "-")?;
+        }
+
+        match self.source {
+            InputSource::Key(code) => write!(f, "Key:{code:?}"),
+            InputSource::Mouse(MouseButton::Other(button)) => write!(f, "Mouse:{button}"),
+            InputSource::Mouse(button) => write!(f, "Mouse:{button:?}"),
+            InputSource::Scroll(axis) => write!(f, "Scroll:{axis:?}"),
+            InputSource::Motion(axis) => write!(f, "Motion:{axis:?}"),
+        }
+    }
+}
+
+/// Turns a held action into a single "it was just pressed" event.
+///
+/// Call `update` with `InputMap::pressed` once per tick, it's only
+/// true on the first tick the action is held down.
+#[derive(Debug, Default)]
+pub struct ActionTrigger {
+    held: bool,
+}
+
+impl ActionTrigger {
+    pub fn update(&mut self, pressed: bool) -> bool {
+        let triggered = pressed && !self.held;
+        self.held = pressed;
+        triggered
+    }
+}
+
+/// A named set of action bindings. Every Scene picks the context it wants.
+pub type InputContext = HashMap<String, Vec<Binding>>;
+
+/// Sits between winit and our Scenes.
+///
+/// Instead of matching on keys, Scenes ask for actions ("move_forward")
+/// and get a value back. Keys and buttons are either 0.0 or 1.0,
+/// scrolling and mouse motion report how far they moved this frame.
+pub struct InputMap {
+    contexts: HashMap<String, InputContext>,
+    active_context: String,
+
+    keys: HashSet<KeyCode>,
+    buttons: HashSet<MouseButton>,
+    scroll: glam::Vec2,
+    motion: glam::Vec2,
+
+    /// If set, the next key or button pressed gets bound to this (context, action).
+    pending_rebind: Option<(String, String)>,
+}
+
+impl InputMap {
+    pub fn new(contexts: HashMap<String, InputContext>) -> Self {
+        Self {
+            contexts,
+            active_context: String::new(),
+            keys: HashSet::new(),
+            buttons: HashSet::new(),
+            scroll: glam::Vec2::ZERO,
+            motion: glam::Vec2::ZERO,
+            pending_rebind: None,
+        }
+    }
+
+    /// Loads a bindings file from our assets.
+    pub async fn load(file_name: &str) -> error::Result<Self> {
+        let text = load_string(file_name).await?;
+        Self::from_toml(&text)
+    }
+
+    /// Parses a bindings file. Every table is a context,
+    /// and every key in that table is an action with a list of bindings.
+    pub fn from_toml(text: &str) -> error::Result<Self> {
+        let raw: HashMap<String, HashMap<String, Vec<String>>> =
+            toml::from_str(text).map_err(|e| Error::Bindings(e.to_string()))?;
+
+        let mut contexts = HashMap::new();
+        for (context_name, actions) in raw {
+            let mut context = InputContext::new();
+            for (action, bindings) in actions {
+                let bindings = bindings
+                    .iter()
+                    .map(|b| b.parse())
+                    .collect::<error::Result<Vec<Binding>>>()?;
+                context.insert(action, bindings);
+            }
+            contexts.insert(context_name, context);
+        }
+
+        Ok(Self::new(contexts))
+    }
+
+    /// Writes all contexts back out in the same format `from_toml` reads.
+    /// Handy for saving bindings the player changed at runtime.
+    pub fn to_toml(&self) -> error::Result<String> {
+        let raw: HashMap<&String, HashMap<&String, Vec<String>>> = self
+            .contexts
+            .iter()
+            .map(|(name, context)| {
+                let actions = context
+                    .iter()
+                    .map(|(action, bindings)| {
+                        (action, bindings.iter().map(Binding::to_string).collect())
+                    })
+                    .collect();
+                (name, actions)
+            })
+            .collect();
+
+        toml::to_string_pretty(&raw).map_err(|e| Error::Bindings(e.to_string()))
+    }
+
+    /// Switch which set of bindings is in use. Scenes call this when they become active.
+    pub fn set_context(&mut self, context: &str) {
+        if !self.contexts.contains_key(context) {
+            log::warn!("Input context '{context}' has no bindings");
+        }
+        self.active_context = context.to_string();
+    }
+
+    pub fn context(&self) -> &str {
+        &self.active_context
+    }
+
+    /// Replaces every binding of an action.
+    pub fn rebind(&mut self, context: &str, action: &str, bindings: Vec<Binding>) {
+        self.contexts
+            .entry(context.to_string())
+            .or_default()
+            .insert(action.to_string(), bindings);
+    }
+
+    /// Adds a binding to an action, keeping the existing ones.
+    pub fn bind(&mut self, context: &str, action: &str, binding: Binding) {
+        self.contexts
+            .entry(context.to_string())
+            .or_default()
+            .entry(action.to_string())
+            .or_default()
+            .push(binding);
+    }
+
+    /// Removes every binding of an action.
+    pub fn unbind(&mut self, context: &str, action: &str) {
+        if let Some(context) = self.contexts.get_mut(context) {
+            context.remove(action);
+        }
+    }
+
+    /// The next key or mouse button pressed replaces the bindings of this action.
+    /// Used for "press a key to bind" style menus.
+    pub fn listen_for_rebind(&mut self, context: &str, action: &str) {
+        self.pending_rebind = Some((context.to_string(), action.to_string()));
+    }
+
+    pub fn is_listening_for_rebind(&self) -> bool {
+        self.pending_rebind.is_some()
+    }
+
+    /// The current value of an action in the active context.
+    /// If several bindings are held at once, their values are added together.
+    pub fn value(&self, action: &str) -> f32 {
+        self.contexts
+            .get(&self.active_context)
+            .and_then(|context| context.get(action))
+            .map(|bindings| {
+                bindings
+                    .iter()
+                    .map(|binding| self.source_value(binding.source) * binding.scale)
+                    .sum()
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Whether an action is currently held down.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.value(action) != 0.0
+    }
+
+    fn source_value(&self, source: InputSource) -> f32 {
+        match source {
+            InputSource::Key(code) => self.keys.contains(&code) as u8 as f32,
+            InputSource::Mouse(button) => self.buttons.contains(&button) as u8 as f32,
+            InputSource::Scroll(Axis::X) => self.scroll.x,
+            InputSource::Scroll(Axis::Y) => self.scroll.y,
+            InputSource::Motion(Axis::X) => self.motion.x,
+            InputSource::Motion(Axis::Y) => self.motion.y,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    match event.state {
+                        ElementState::Pressed => {
+                            if !self.try_rebind(InputSource::Key(code)) {
+                                self.keys.insert(code);
+                            }
+                        }
+                        ElementState::Released => {
+                            self.keys.remove(&code);
+                        }
+                    }
+                }
+            }
+            WindowEvent::MouseInput { button, state, .. } => match state {
+                ElementState::Pressed => {
+                    if !self.try_rebind(InputSource::Mouse(*button)) {
+                        self.buttons.insert(*button);
+                    }
+                }
+                ElementState::Released => {
+                    self.buttons.remove(button);
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(x, y) => glam::Vec2::new(*x, *y) * PIXELS_PER_LINE,
+                    MouseScrollDelta::PixelDelta(pos) => glam::Vec2::new(pos.x as f32, pos.y as f32),
+                };
+            }
+            // Don't keep keys "held" if the window loses focus mid-press.
+            WindowEvent::Focused(false) => {
+                self.keys.clear();
+                self.buttons.clear();
+            }
+            _ => (),
+        }
+    }
+
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.motion += glam::Vec2::new(delta.0 as f32, delta.1 as f32);
+        }
+    }
+
+    /// Called once the frame is done. Scrolling and mouse movement
+    /// are per-frame, so they get reset here.
+    pub fn end_frame(&mut self) {
+        self.scroll = glam::Vec2::ZERO;
+        self.motion = glam::Vec2::ZERO;
+    }
+
+    fn try_rebind(&mut self, source: InputSource) -> bool {
+        let Some((context, action)) = self.pending_rebind.take() else {
+            return false;
+        };
+
+        // The bindings get saved with `to_toml`, so only take keys `from_toml` can read back.
+        // Keep listening until the player presses one of those.
+        if let InputSource::Key(code) = source {
+            if !KEY_CODES.contains(&code) {
+                log::warn!("{code:?} can't be bound, press another key for {action}");
+                self.pending_rebind = Some((context, action));
+                return true;
+            }
+        }
+
+        log::info!("Bound {action} in {context} to {}", Binding::new(source));
+        self.rebind(&context, &action, vec![Binding::new(source)]);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every source a bindings file can have in it.
+    fn supported_sources() -> Vec<InputSource> {
+        let mut sources: Vec<InputSource> = KEY_CODES.iter().map(|&code| InputSource::Key(code)).collect();
+        sources.extend(
+            [
+                MouseButton::Left,
+                MouseButton::Right,
+                MouseButton::Middle,
+                MouseButton::Back,
+                MouseButton::Forward,
+                MouseButton::Other(7),
+            ]
+            .map(InputSource::Mouse),
+        );
+        for axis in [Axis::X, Axis::Y] {
+            sources.push(InputSource::Scroll(axis));
+            sources.push(InputSource::Motion(axis));
+        }
+        sources
+    }
+
+    #[test]
+    fn bindings_round_trip() {
+        for source in supported_sources() {
+            for scale in [1.0, -1.0] {
+                let binding = Binding { source, scale };
+                let text = binding.to_string();
+                assert_eq!(text.parse::<Binding>().ok(), Some(binding), "{text}");
+            }
+        }
+    }
+
+    #[test]
+    fn bad_bindings_are_rejected() {
+        for text in [
+            "Key:NotAKey",
+            "Key:",
+            "KeyW",
+            "Joystick:A",
+            "Mouse:Banana",
+            "Scroll:Z",
+            "Motion:",
+            "--Key:KeyW",
+        ] {
+            assert!(text.parse::<Binding>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn rebound_keys_can_be_loaded_again() {
+        let mut input = InputMap::new(HashMap::new());
+
+        // Not a key a bindings file can have, so it gets skipped
+        input.listen_for_rebind("game", "jump");
+        assert!(input.try_rebind(InputSource::Key(KeyCode::F24)));
+        assert!(input.is_listening_for_rebind());
+
+        assert!(input.try_rebind(InputSource::Key(KeyCode::Numpad1)));
+        assert!(!input.is_listening_for_rebind());
+
+        let loaded = InputMap::from_toml(&input.to_toml().unwrap()).unwrap();
+        assert_eq!(
+            loaded.contexts["game"]["jump"],
+            vec![Binding::new(InputSource::Key(KeyCode::Numpad1))]
+        );
+    }
+}
@@ -1,4 +1,12 @@
 pub mod texture;
 pub mod model;
+pub mod gltf;
+pub mod animation;
 pub mod instance;
-pub mod light;
\ No newline at end of file
+pub mod light;
+pub mod shadow;
+pub mod sky;
+pub mod fog;
+pub mod frustum;
+pub mod mesh_pool;
+pub mod indirect;
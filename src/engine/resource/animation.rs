@@ -0,0 +1,618 @@
+use std::ops::Range;
+
+use wgpu::util::DeviceExt;
+
+use crate::engine::resource::model::{Material, Mesh, Model};
+use crate::engine::util::Vertex;
+use crate::entity::camera::Camera;
+
+/// Which joints a vertex moves with, and how much. Skinned Meshes have these in a vertex
+/// buffer of their own, next to their ModelVertices.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinVertex {
+    /// Indices into `Skeleton::joints`.
+    pub joints: [u32; 4],
+    /// Should add up to 1.0.
+    pub weights: [f32; 4],
+}
+
+impl SkinVertex {
+    pub fn create_buffer(file_name: &str, vertices: &[SkinVertex], device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{file_name} Skin Buffer")),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        })
+    }
+}
+
+impl Vertex for SkinVertex {
+    /// Comes after the ModelVertex and the InstanceRaw, so it starts at location 12.
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SkinVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Uint32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[u32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// Where a joint is, relative to its parent. Kept apart instead of as a matrix so poses blend nicely.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: glam::Vec3::ZERO,
+        rotation: glam::Quat::IDENTITY,
+        scale: glam::Vec3::ONE,
+    };
+
+    pub fn from_matrix(matrix: glam::Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Goes from `self` at 0.0 to `other` at 1.0.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: Option<String>,
+    /// Index into `Skeleton::joints`. None for the joints at the root.
+    pub parent: Option<usize>,
+    /// Where the joint is when nothing's playing.
+    pub rest: Transform,
+    /// Takes a vertex from the model into the joint's space, as it was when the model was made.
+    pub inverse_bind: glam::Mat4,
+}
+
+/// The joints a skinned Model moves with.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    /// Where whatever is above the root joints puts the Skeleton.
+    pub root: glam::Mat4,
+}
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Pose {
+        Pose(self.joints.iter().map(|joint| joint.rest).collect())
+    }
+
+    /// What goes into the SkinBuffer: every joint's movement away from how the model was made.
+    pub fn joint_matrices(&self, pose: &Pose) -> Vec<glam::Mat4> {
+        // Parents don't have to come before their children, so they're worked out as needed
+        let mut globals: Vec<Option<glam::Mat4>> = vec![None; self.joints.len()];
+        for joint in 0..self.joints.len() {
+            self.global(joint, pose, &mut globals);
+        }
+
+        globals
+            .into_iter()
+            .zip(&self.joints)
+            .map(|(global, joint)| global.unwrap() * joint.inverse_bind)
+            .collect()
+    }
+
+    fn global(&self, joint: usize, pose: &Pose, globals: &mut [Option<glam::Mat4>]) -> glam::Mat4 {
+        if let Some(global) = globals[joint] {
+            return global;
+        }
+        let parent = match self.joints[joint].parent {
+            Some(parent) => self.global(parent, pose, globals),
+            None => self.root,
+        };
+        let global = parent * pose.0[joint].matrix();
+        globals[joint] = Some(global);
+        global
+    }
+}
+
+/// Where every joint of a Skeleton is, relative to its parent. Same order as `Skeleton::joints`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose(pub Vec<Transform>);
+
+impl Pose {
+    /// Moves every joint `weight` of the way towards `other`.
+    pub fn blend(&mut self, other: &Pose, weight: f32) {
+        for (joint, other) in self.0.iter_mut().zip(&other.0) {
+            *joint = joint.lerp(other, weight);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Jumps from one keyframe to the next.
+    Step,
+    Linear,
+}
+
+/// The values of a Channel, one for each of its keyframes.
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<glam::Vec3>),
+    Rotation(Vec<glam::Quat>),
+    Scale(Vec<glam::Vec3>),
+}
+
+/// Moves one part of one joint's Transform.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    /// Index into `Skeleton::joints`.
+    pub joint: usize,
+    /// When each keyframe is, in seconds. Goes up.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation,
+}
+
+impl Channel {
+    /// The keyframes on either side of `time`, and how far along from the first to the second it is.
+    fn keyframes_at(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        if next == self.times.len() {
+            return (next - 1, next - 1, 0.0);
+        }
+
+        let previous = next - 1;
+        let t = match self.interpolation {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear => (time - self.times[previous]) / (self.times[next] - self.times[previous]),
+        };
+        (previous, next, t)
+    }
+
+    fn sample(&self, time: f32, transform: &mut Transform) {
+        if self.times.is_empty() {
+            return;
+        }
+        let (a, b, t) = self.keyframes_at(time);
+        match &self.keyframes {
+            Keyframes::Translation(values) => transform.translation = values[a].lerp(values[b], t),
+            Keyframes::Rotation(values) => transform.rotation = values[a].slerp(values[b], t),
+            Keyframes::Scale(values) => transform.scale = values[a].lerp(values[b], t),
+        }
+    }
+}
+
+/// Keyframes for some of the joints of a Skeleton. The ones it doesn't have keyframes for stay put.
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    /// In seconds, when the last keyframe is.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Moves the joints in `pose` to where they are `time` seconds into the clip.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            if let Some(transform) = pose.0.get_mut(channel.joint) {
+                channel.sample(time, transform);
+            }
+        }
+    }
+}
+
+/// One clip that's playing in an Animator.
+#[derive(Debug, Clone)]
+struct Layer {
+    /// Index into the clips the Animator gets.
+    clip: usize,
+    time: f32,
+    looping: bool,
+    weight: f32,
+    /// How much the weight goes up (or down) per second. The layer gets dropped once it hits 0.0.
+    fade: f32,
+}
+
+/// Plays a Model's AnimationClips, fading over from one to the next.
+///
+/// Doesn't own the clips, they stay in the Model and get passed in by index.
+#[derive(Debug, Clone, Default)]
+pub struct Animator {
+    layers: Vec<Layer>,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts playing a clip from the start, and fades out whatever was playing before.
+    ///
+    /// * `fade` - How many seconds it takes to fade over. 0.0 switches right away.
+    pub fn play(&mut self, clip: usize, looping: bool, fade: f32) {
+        if fade <= 0.0 {
+            self.layers.clear();
+        }
+        for layer in &mut self.layers {
+            layer.fade = -layer.weight / fade;
+        }
+
+        self.layers.push(Layer {
+            clip,
+            time: 0.0,
+            looping,
+            weight: if fade <= 0.0 { 1.0 } else { 0.0 },
+            fade: if fade <= 0.0 { 0.0 } else { 1.0 / fade },
+        });
+    }
+
+    /// Stops everything, which puts the Skeleton back into its rest pose.
+    pub fn stop(&mut self) {
+        self.layers.clear();
+    }
+
+    /// The clip that was played last, if it's still playing.
+    pub fn playing(&self) -> Option<usize> {
+        self.layers.last().map(|layer| layer.clip)
+    }
+
+    /// Moves every clip along. Clips that don't loop stop on their last frame.
+    pub fn update(&mut self, dt: f32, clips: &[AnimationClip]) {
+        for layer in &mut self.layers {
+            let duration = clips.get(layer.clip).map_or(0.0, |clip| clip.duration);
+            layer.time += dt;
+            if layer.looping && duration > 0.0 {
+                layer.time %= duration;
+            } else {
+                layer.time = layer.time.min(duration);
+            }
+            layer.weight = (layer.weight + layer.fade * dt).min(1.0);
+        }
+        self.layers.retain(|layer| layer.weight > 0.0 || layer.fade > 0.0);
+    }
+
+    /// Samples every clip that's playing and blends them together by their weights.
+    pub fn pose(&self, skeleton: &Skeleton, clips: &[AnimationClip]) -> Pose {
+        let mut pose = skeleton.rest_pose();
+        let mut total = 0.0;
+        for layer in &self.layers {
+            let (Some(clip), true) = (clips.get(layer.clip), layer.weight > 0.0) else {
+                continue;
+            };
+            let mut sampled = skeleton.rest_pose();
+            clip.sample(layer.time, &mut sampled);
+
+            total += layer.weight;
+            pose.blend(&sampled, layer.weight / total);
+        }
+        pose
+    }
+}
+
+/// The joint matrices of one animated Model, along with the camera it's drawn with.
+/// Takes the place of the camera's bind group with `DrawSkinnedModel`.
+///
+/// Everything drawn with the same SkinBuffer has the same pose.
+pub struct SkinBuffer {
+    buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl SkinBuffer {
+    pub fn new(skeleton: &Skeleton, camera: &Camera, layout: &wgpu::BindGroupLayout, device: &wgpu::Device) -> Self {
+        let matrices = skeleton.joint_matrices(&skeleton.rest_pose());
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Joint Buffer"),
+            contents: bytemuck::cast_slice(&matrices),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skin bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self { buffer, bind_group }
+    }
+
+    /// The camera's layout, with the joint matrices added at binding 1.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skin bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// * `matrices` - From `Skeleton::joint_matrices`, for the same Skeleton this was made with.
+    pub fn write(&self, queue: &wgpu::Queue, matrices: &[glam::Mat4]) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(matrices));
+    }
+}
+
+/// DrawModel, for Models with a Skeleton. Needs a pipeline that uses `vs_skinned`,
+/// with the SkinVertex buffer in slot 2.
+///
+/// Only the skinned Meshes get drawn, the rest are left to `DrawModel`.
+pub trait DrawSkinnedModel<'a> {
+    fn draw_skinned_mesh_instanced(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        instances: Range<u32>,
+        skin: &'a SkinBuffer,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+
+    fn draw_skinned_model_instanced(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        skin: &'a SkinBuffer,
+        light_bind_group: &'a wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawSkinnedModel<'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_skinned_mesh_instanced(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b Material,
+        instances: Range<u32>,
+        skin: &'b SkinBuffer,
+        light_bind_group: &'b wgpu::BindGroup,
+    ) {
+        let Some(skin_buffer) = &mesh.skin_buffer else {
+            return;
+        };
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_vertex_buffer(2, skin_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, &skin.bind_group, &[]);
+        self.set_bind_group(2, light_bind_group, &[]);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+
+    fn draw_skinned_model_instanced(
+        &mut self,
+        model: &'b Model,
+        instances: Range<u32>,
+        skin: &'b SkinBuffer,
+        light_bind_group: &'b wgpu::BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            self.draw_skinned_mesh_instanced(mesh, material, instances.clone(), skin, light_bind_group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(x: f32) -> Transform {
+        Transform {
+            translation: glam::vec3(x, 0.0, 0.0),
+            ..Transform::IDENTITY
+        }
+    }
+
+    /// A clip that moves joint 0 from 0.0 at the start to `to` at one second along x.
+    fn slide(to: f32, interpolation: Interpolation) -> AnimationClip {
+        AnimationClip {
+            name: None,
+            duration: 1.0,
+            channels: vec![Channel {
+                joint: 0,
+                times: vec![0.0, 1.0],
+                keyframes: Keyframes::Translation(vec![glam::Vec3::ZERO, glam::vec3(to, 0.0, 0.0)]),
+                interpolation,
+            }],
+        }
+    }
+
+    /// One joint at the origin that doesn't move the model when it's at rest.
+    fn single_joint() -> Skeleton {
+        Skeleton {
+            joints: vec![Joint {
+                name: None,
+                parent: None,
+                rest: Transform::IDENTITY,
+                inverse_bind: glam::Mat4::IDENTITY,
+            }],
+            root: glam::Mat4::IDENTITY,
+        }
+    }
+
+    fn sample(clip: &AnimationClip, time: f32) -> Transform {
+        let mut pose = Pose(vec![Transform::IDENTITY]);
+        clip.sample(time, &mut pose);
+        pose.0[0]
+    }
+
+    #[test]
+    fn step_holds_until_the_next_keyframe() {
+        let clip = slide(4.0, Interpolation::Step);
+        assert_eq!(sample(&clip, 0.0), translation(0.0));
+        assert_eq!(sample(&clip, 0.99), translation(0.0));
+        assert_eq!(sample(&clip, 1.0), translation(4.0));
+    }
+
+    #[test]
+    fn linear_goes_between_keyframes() {
+        let clip = slide(4.0, Interpolation::Linear);
+        assert_eq!(sample(&clip, 0.25), translation(1.0));
+        assert_eq!(sample(&clip, 0.5), translation(2.0));
+        // Holds the first and last keyframes outside of the clip
+        assert_eq!(sample(&clip, -1.0), translation(0.0));
+        assert_eq!(sample(&clip, 2.0), translation(4.0));
+    }
+
+    #[test]
+    fn rotations_slerp() {
+        let clip = AnimationClip {
+            name: None,
+            duration: 1.0,
+            channels: vec![Channel {
+                joint: 0,
+                times: vec![0.0, 1.0],
+                keyframes: Keyframes::Rotation(vec![
+                    glam::Quat::IDENTITY,
+                    glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                ]),
+                interpolation: Interpolation::Linear,
+            }],
+        };
+
+        let halfway = sample(&clip, 0.5).rotation;
+        assert!(halfway.abs_diff_eq(glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_4), 1e-6));
+        assert!(halfway.is_normalized());
+    }
+
+    #[test]
+    fn channels_for_missing_joints_are_ignored() {
+        let mut clip = slide(4.0, Interpolation::Linear);
+        clip.channels[0].joint = 3;
+        assert_eq!(sample(&clip, 0.5), Transform::IDENTITY);
+    }
+
+    #[test]
+    fn poses_blend_by_weight() {
+        let mut pose = Pose(vec![translation(0.0), translation(2.0)]);
+        pose.blend(&Pose(vec![translation(4.0), translation(2.0)]), 0.25);
+        assert_eq!(pose, Pose(vec![translation(1.0), translation(2.0)]));
+    }
+
+    #[test]
+    fn animator_fades_between_clips() {
+        let skeleton = single_joint();
+        // Clips that hold joint 0 still at one spot
+        let hold = |at| {
+            let mut clip = slide(at, Interpolation::Linear);
+            clip.channels[0].keyframes = Keyframes::Translation(vec![glam::vec3(at, 0.0, 0.0); 2]);
+            clip
+        };
+        let clips = [hold(0.0), hold(4.0)];
+
+        let mut animator = Animator::new();
+        animator.play(0, true, 0.0);
+        animator.play(1, true, 1.0);
+        assert_eq!(animator.pose(&skeleton, &clips).0[0], translation(0.0));
+
+        animator.update(0.5, &clips);
+        assert_eq!(animator.pose(&skeleton, &clips).0[0], translation(2.0));
+
+        // The first clip is gone once it's faded out
+        animator.update(0.5, &clips);
+        assert_eq!(animator.pose(&skeleton, &clips).0[0], translation(4.0));
+        assert_eq!(animator.layers.len(), 1);
+        assert_eq!(animator.playing(), Some(1));
+    }
+
+    #[test]
+    fn joint_matrices_work_out_parents_out_of_order() {
+        let hip = glam::Mat4::from_translation(glam::vec3(0.0, 0.0, 2.0));
+        let leg = glam::Mat4::from_translation(glam::vec3(1.0, 0.0, 0.0));
+        let root = glam::Mat4::from_translation(glam::vec3(0.0, 1.0, 0.0));
+        // The leg comes before the hip it hangs off
+        let skeleton = Skeleton {
+            joints: vec![
+                Joint {
+                    name: Some("Leg".to_string()),
+                    parent: Some(1),
+                    rest: Transform::from_matrix(leg),
+                    inverse_bind: glam::Mat4::IDENTITY,
+                },
+                Joint {
+                    name: Some("Hip".to_string()),
+                    parent: None,
+                    rest: Transform::from_matrix(hip),
+                    inverse_bind: glam::Mat4::IDENTITY,
+                },
+            ],
+            root,
+        };
+
+        let matrices = skeleton.joint_matrices(&skeleton.rest_pose());
+        assert_eq!(matrices, [root * hip * leg, root * hip]);
+
+        // Binding the model where the rest pose is means the rest pose doesn't move anything
+        let mut bound = skeleton.clone();
+        for (joint, global) in bound.joints.iter_mut().zip(&matrices) {
+            joint.inverse_bind = global.inverse();
+        }
+        for matrix in bound.joint_matrices(&bound.rest_pose()) {
+            assert!(matrix.abs_diff_eq(glam::Mat4::IDENTITY, 1e-6));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+
+/// How fog thickens with distance. Has to match the constants in `shader.wgsl`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FogMode {
+    #[default]
+    Off,
+    /// Goes from no fog at `start` to only fog at `end` in a straight line.
+    Linear,
+    /// Thickens quickly right after `start`, then slowly towards `end`.
+    Exponential,
+    /// Stays thin for a while after `start`, then thickens quickly towards `end`.
+    ExponentialSquared,
+}
+
+/// Fog that fades things into the sky the further away they are.
+/// Hides the edge of the world at the render distance.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    /// How far from the camera the fog starts.
+    pub start: f32,
+    /// How far from the camera everything is completely hidden in fog.
+    pub end: f32,
+    /// What things fade into. Should be the colour of the sky at the horizon.
+    pub color: glam::Vec3,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::Off,
+            start: 0.0,
+            end: 100.0,
+            color: glam::Vec3::ONE,
+        }
+    }
+}
+
+impl Fog {
+    /// Fog that starts at some fraction of the render distance and covers everything at the render distance.
+    pub fn for_render_distance(mode: FogMode, render_distance: f32, start: f32) -> Self {
+        Self {
+            mode,
+            start: render_distance * start.clamp(0.0, 1.0),
+            end: render_distance,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_raw(self) -> FogUniform {
+        // The exponential modes never quite reach zero, so pick a density
+        // that leaves 1% visible right at the end.
+        let distance = (self.end - self.start).max(f32::EPSILON);
+        let density = match self.mode {
+            FogMode::Exponential => 100.0f32.ln() / distance,
+            FogMode::ExponentialSquared => 100.0f32.ln().sqrt() / distance,
+            FogMode::Off | FogMode::Linear => 0.0,
+        };
+
+        FogUniform {
+            color: self.color.extend(1.0),
+            start: self.start,
+            end: self.end,
+            density,
+            mode: self.mode as u32,
+        }
+    }
+}
+
+/// The Fog data that goes into the uniform buffer.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FogUniform {
+    color: glam::Vec4,
+    start: f32,
+    end: f32,
+    density: f32,
+    mode: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [FogMode; 4] = [FogMode::Off, FogMode::Linear, FogMode::Exponential, FogMode::ExponentialSquared];
+
+    /// How much is still visible through the fog at `distance`, the way `fog_visibility` in `shader.wgsl` does it.
+    fn visibility(fog: FogUniform, distance: f32) -> f32 {
+        let fogged = (distance - fog.start).max(0.0);
+        match fog.mode {
+            1 => (1.0 - fogged / (fog.end - fog.start)).clamp(0.0, 1.0),
+            2 => (-fog.density * fogged).exp(),
+            3 => (-(fog.density * fogged).powi(2)).exp(),
+            _ => 1.0,
+        }
+    }
+
+    #[test]
+    fn fog_covers_the_render_distance() {
+        for mode in MODES {
+            let fog = Fog::for_render_distance(mode, 200.0, 0.25);
+            assert_eq!(fog.mode, mode);
+            assert_eq!(fog.start, 50.0);
+            assert_eq!(fog.end, 200.0);
+        }
+
+        // The start is a fraction of the render distance
+        assert_eq!(Fog::for_render_distance(FogMode::Linear, 200.0, -1.0).start, 0.0);
+        assert_eq!(Fog::for_render_distance(FogMode::Linear, 200.0, 2.0).start, 200.0);
+    }
+
+    #[test]
+    fn modes_match_the_shader() {
+        let modes = MODES.map(|mode| Fog { mode, ..Default::default() }.to_raw().mode);
+        assert_eq!(modes, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn only_the_exponential_modes_have_a_density() {
+        for mode in [FogMode::Off, FogMode::Linear] {
+            assert_eq!(Fog::for_render_distance(mode, 200.0, 0.5).to_raw().density, 0.0);
+        }
+
+        let exponential = Fog::for_render_distance(FogMode::Exponential, 200.0, 0.5).to_raw();
+        assert!((exponential.density - 100.0f32.ln() / 100.0).abs() < 1e-6);
+        let squared = Fog::for_render_distance(FogMode::ExponentialSquared, 200.0, 0.5).to_raw();
+        assert!((squared.density - 100.0f32.ln().sqrt() / 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fog_leaves_almost_nothing_at_the_end() {
+        for mode in [FogMode::Linear, FogMode::Exponential, FogMode::ExponentialSquared] {
+            let fog = Fog::for_render_distance(mode, 200.0, 0.5).to_raw();
+            assert_eq!(visibility(fog, 50.0), 1.0, "{mode:?}");
+            assert_eq!(visibility(fog, 100.0), 1.0, "{mode:?}");
+            assert!(visibility(fog, 200.0) <= 0.01 + 1e-4, "{mode:?}");
+
+            let mut last = 1.0;
+            for distance in (100..=200).step_by(10) {
+                let visible = visibility(fog, distance as f32);
+                assert!(visible < last || visible == 1.0, "{mode:?} thins out at {distance}");
+                last = visible;
+            }
+        }
+
+        // Nothing at all when it's off
+        let off = Fog::for_render_distance(FogMode::Off, 200.0, 0.5).to_raw();
+        assert_eq!(visibility(off, 1000.0), 1.0);
+    }
+
+    #[test]
+    fn fog_without_any_depth_stays_finite() {
+        for mode in MODES {
+            let fog = Fog::for_render_distance(mode, 100.0, 1.0).to_raw();
+            assert!(fog.density.is_finite(), "{mode:?}");
+        }
+    }
+}
@@ -0,0 +1,107 @@
+/// What a camera can see: the space between six planes, all facing inwards.
+/// Used to skip drawing things that are entirely off screen.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    /// Every plane is `normal.xyz` and `distance` in `w`. Points in front of all of them are inside.
+    planes: [glam::Vec4; 6],
+}
+
+impl Frustum {
+    /// The frustum of a view projection matrix, with depth going from 0.0 to 1.0 like wgpu's.
+    pub fn from_matrix(view_proj: glam::Mat4) -> Self {
+        let row = |i: usize| view_proj.row(i);
+
+        let planes = [
+            row(3) + row(0), // Left
+            row(3) - row(0), // Right
+            row(3) + row(1), // Bottom
+            row(3) - row(1), // Top
+            row(2),          // Near
+            row(3) - row(2), // Far
+        ]
+        .map(|plane| plane / plane.truncate().length());
+
+        Self { planes }
+    }
+
+    /// Whether any part of a box can be seen. Might say yes for boxes just outside a corner,
+    /// which only costs drawing something that wasn't needed.
+    pub fn intersects_aabb(&self, min: glam::Vec3, max: glam::Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = glam::Vec3::select(plane.truncate().cmpge(glam::Vec3::ZERO), max, min);
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looking down -z from the origin, 90 degrees wide, seeing from 1.0 to 100.0 away.
+    fn frustum() -> Frustum {
+        let proj = glam::Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        Frustum::from_matrix(proj * glam::Mat4::look_to_rh(glam::Vec3::ZERO, glam::Vec3::NEG_Z, glam::Vec3::Y))
+    }
+
+    fn cube(center: glam::Vec3) -> (glam::Vec3, glam::Vec3) {
+        (center - 0.5, center + 0.5)
+    }
+
+    #[test]
+    fn planes_face_inwards() {
+        let frustum = frustum();
+        let inside = glam::vec3(0.0, 0.0, -10.0);
+        for plane in frustum.planes {
+            assert!((plane.truncate().length() - 1.0).abs() < 1e-5);
+            assert!(plane.truncate().dot(inside) + plane.w > 0.0, "{plane} faces outwards");
+        }
+        // The near and far planes are 1.0 and 100.0 away
+        let sees = |z| frustum.intersects_aabb(glam::vec3(0.0, 0.0, z), glam::vec3(0.0, 0.0, z));
+        assert!(!sees(-0.9));
+        assert!(sees(-1.1));
+        assert!(sees(-99.9));
+        assert!(!sees(-100.1));
+    }
+
+    #[test]
+    fn boxes_inside_are_seen() {
+        let frustum = frustum();
+        let (min, max) = cube(glam::vec3(0.0, 0.0, -10.0));
+        assert!(frustum.intersects_aabb(min, max));
+        let (min, max) = cube(glam::vec3(5.0, -5.0, -50.0));
+        assert!(frustum.intersects_aabb(min, max));
+    }
+
+    #[test]
+    fn boxes_outside_are_culled() {
+        let frustum = frustum();
+        for center in [
+            // Behind the camera
+            glam::vec3(0.0, 0.0, 10.0),
+            // Off to either side, above and below
+            glam::vec3(20.0, 0.0, -10.0),
+            glam::vec3(-20.0, 0.0, -10.0),
+            glam::vec3(0.0, 20.0, -10.0),
+            glam::vec3(0.0, -20.0, -10.0),
+            // Too far away
+            glam::vec3(0.0, 0.0, -200.0),
+        ] {
+            let (min, max) = cube(center);
+            assert!(!frustum.intersects_aabb(min, max), "{center} should be culled");
+        }
+    }
+
+    #[test]
+    fn boxes_straddling_a_plane_are_seen() {
+        let frustum = frustum();
+        // Across the right plane, x = -z
+        assert!(frustum.intersects_aabb(glam::vec3(9.0, -1.0, -11.0), glam::vec3(12.0, 1.0, -9.0)));
+        // Across the near and far planes
+        assert!(frustum.intersects_aabb(glam::vec3(-1.0, -1.0, -2.0), glam::vec3(1.0, 1.0, 2.0)));
+        assert!(frustum.intersects_aabb(glam::vec3(-1.0, -1.0, -150.0), glam::vec3(1.0, 1.0, -90.0)));
+        // A box around the whole frustum
+        assert!(frustum.intersects_aabb(glam::Vec3::splat(-500.0), glam::Vec3::splat(500.0)));
+    }
+}
@@ -0,0 +1,684 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+
+use crate::engine::assets::Handle;
+use crate::engine::error::{Error, Result};
+use crate::engine::resource::animation::{
+    AnimationClip, Channel, Interpolation, Joint, Keyframes, Skeleton, SkinVertex, Transform,
+};
+use crate::engine::resource::model::{Material, MaterialMaps, MaterialProperties, Mesh, Model, ModelVertex, Node};
+use crate::engine::resource::texture::{NormalMap, Texture};
+use crate::engine::resource_manager::ResourceManager;
+use crate::engine::util::load_binary;
+
+/// For loading .gltf and .glb files, the same way `load_model` does .obj files.
+/// Draw what comes out of it with `fs_pbr`, glTF materials are metallic-roughness ones.
+///
+/// Every Mesh gets moved to where its Node puts it in the file's default scene, so the Model
+/// draws like any other. The hierarchy ends up in `Model::nodes` for anything that wants it.
+///
+/// Skinned Meshes stay where they are, their Skeleton moves them instead. Only the first skin
+/// in the file is used, and only animations of its joints end up in `Model::animations`.
+///
+/// Buffers and images can be in the .glb, embedded as base64 or in files next to the model.
+/// Only triangles and the first set of texture coordinates are supported, and the normal
+/// scale, occlusion strength and alpha mode are ignored.
+///
+/// * `file_name` - Relative to `models/`, like `load_model`.
+pub async fn load_gltf(
+    file_name: &str,
+    resources: &mut ResourceManager,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+) -> Result<Model> {
+    let path = format!("models/{file_name}");
+    let gltf = ::gltf::Gltf::from_slice(&load_binary(&path).await?).map_err(|e| Error::Gltf {
+        path: path.clone(),
+        message: e.to_string(),
+    })?;
+    // Whatever the file refers to is next to it
+    let folder = path.rsplit_once('/').map_or("", |(folder, _)| folder);
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            ::gltf::buffer::Source::Bin => gltf.blob.clone().ok_or_else(|| Error::Gltf {
+                path: path.clone(),
+                message: "it has no binary chunk".to_string(),
+            })?,
+            ::gltf::buffer::Source::Uri(uri) => read_uri(uri, folder, &path).await?,
+        };
+        buffers.push(data);
+    }
+
+    let mut textures = GltfTextures {
+        path: &path,
+        folder,
+        buffers: &buffers,
+        images: HashMap::new(),
+        resources,
+        device,
+        queue,
+    };
+
+    // All the textures get made before any of the Materials, those need the ResourceManager too
+    let mut gltf_materials = Vec::new();
+    for material in gltf.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let maps = MaterialMaps {
+            diffuse: pbr.base_color_texture().map(|info| textures.texture(info.texture(), true)).transpose()?,
+            normal: material.normal_texture().map(|info| textures.normal_map(info.texture())).transpose()?,
+            emissive: material.emissive_texture().map(|info| textures.texture(info.texture(), true)).transpose()?,
+            metallic_roughness: pbr
+                .metallic_roughness_texture()
+                .map(|info| textures.texture(info.texture(), false))
+                .transpose()?,
+            occlusion: material.occlusion_texture().map(|info| textures.texture(info.texture(), false)).transpose()?,
+            ..Default::default()
+        };
+        let (name, properties) = read_material(&material, gltf_materials.len(), &path);
+        gltf_materials.push((name, maps, properties));
+    }
+
+    let mut materials = Vec::new();
+    for (name, maps, properties) in gltf_materials {
+        materials.push(Material::new(&name, maps, properties, resources, layout, device, queue)?);
+    }
+
+    let mut model = Model {
+        meshes: Vec::new(),
+        materials,
+        nodes: read_nodes(&gltf),
+        root_nodes: Vec::new(),
+        skeleton: None,
+        animations: Vec::new(),
+    };
+
+    let parents = parents(&model.nodes);
+
+    let skin = gltf.skins().next();
+    if gltf.skins().len() > 1 {
+        log::warn!("{path} has more than one skin, only the first one is used");
+    }
+    if let Some(skin) = &skin {
+        // Which joint each Node is, if any
+        let joints = skin.joints().enumerate().map(|(joint, node)| (node.index(), joint)).collect();
+        model.skeleton = Some(read_skeleton(skin, &buffers, &model.nodes, &parents, &joints));
+        model.animations = gltf.animations().map(|animation| read_animation(animation, &buffers, &joints)).collect();
+    }
+
+    let Some(scene) = gltf.default_scene().or_else(|| gltf.scenes().next()) else {
+        log::warn!("{path} has no scenes, so there's nothing to draw");
+        return Ok(model);
+    };
+    model.root_nodes = scene.nodes().map(|node| node.index()).collect();
+
+    let mut default_material = None;
+    // Every Node along with where its parent is
+    let mut nodes: Vec<_> = scene.nodes().map(|node| (node, glam::Mat4::IDENTITY)).collect();
+    while let Some((node, parent)) = nodes.pop() {
+        let transform = parent * model.nodes[node.index()].transform;
+        nodes.extend(node.children().map(|child| (child, transform)));
+
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+        for primitive in mesh.primitives() {
+            if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                log::warn!("Skipping a part of {path} that isn't made of triangles");
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let mut vertices: Vec<ModelVertex> = positions
+                .map(|position| ModelVertex {
+                    position,
+                    // Filled in below
+                    tex_coords: [0.0; 2],
+                    normal: [0.0; 3],
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
+                })
+                .collect();
+            if let Some(tex_coords) = reader.read_tex_coords(0) {
+                // glTF starts at the top left like wgpu, so no flipping v here
+                for (vertex, tex_coords) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                    vertex.tex_coords = tex_coords;
+                }
+            }
+            if let Some(normals) = reader.read_normals() {
+                for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                    vertex.normal = normal;
+                }
+            }
+            let skinned = skin.as_ref().is_some_and(|skin| node.skin().is_some_and(|s| s.index() == skin.index()));
+            let skin_vertices = match (skinned, reader.read_joints(0), reader.read_weights(0)) {
+                (true, Some(joints), Some(weights)) => Some(
+                    joints
+                        .into_u16()
+                        .zip(weights.into_f32())
+                        .map(|(joints, weights)| SkinVertex {
+                            joints: joints.map(u32::from),
+                            weights,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                _ => None,
+            };
+            let mut indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+
+            match reader.read_tangents() {
+                Some(tangents) => {
+                    for (vertex, [x, y, z, w]) in vertices.iter_mut().zip(tangents) {
+                        let tangent = glam::vec3(x, y, z);
+                        vertex.tangent = tangent.into();
+                        vertex.bitangent = (glam::Vec3::from(vertex.normal).cross(tangent) * w).into();
+                    }
+                }
+                None => ModelVertex::compute_tangents(&mut vertices, &indices),
+            }
+
+            // The joints put skinned vertices where they go, the Node doesn't
+            let transform = match skin_vertices {
+                Some(_) => glam::Mat4::IDENTITY,
+                None => transform,
+            };
+            // Normals and tangents are directions, they don't get moved or scaled like positions
+            let normal_matrix = glam::Mat3::from_mat4(transform.inverse().transpose());
+            let direction_matrix = glam::Mat3::from_mat4(transform);
+            for vertex in &mut vertices {
+                vertex.position = transform.transform_point3(vertex.position.into()).into();
+                vertex.normal = (normal_matrix * glam::Vec3::from(vertex.normal)).normalize_or_zero().into();
+                vertex.tangent = (direction_matrix * glam::Vec3::from(vertex.tangent)).normalize_or_zero().into();
+                vertex.bitangent = (direction_matrix * glam::Vec3::from(vertex.bitangent)).normalize_or_zero().into();
+            }
+            // Mirroring turns triangles inside out, which would get them culled
+            if transform.determinant() < 0.0 {
+                for triangle in indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            }
+
+            let material = match primitive.material().index() {
+                Some(material) => material,
+                None => match default_material {
+                    Some(material) => material,
+                    None => {
+                        model.materials.push(Material::default(resources, layout, device, queue)?);
+                        *default_material.insert(model.materials.len() - 1)
+                    }
+                },
+            };
+
+            model.nodes[node.index()].meshes.push(model.meshes.len());
+            model.meshes.push(Mesh {
+                name: mesh.name().unwrap_or(file_name).to_string(),
+                vertex_buffer: ModelVertex::create_vertex_buffer(file_name, &vertices, device),
+                index_buffer: ModelVertex::create_index_buffer(file_name, &indices, device),
+                num_elements: indices.len() as u32,
+                material,
+                skin_buffer: skin_vertices.map(|skin| SkinVertex::create_buffer(file_name, &skin, device)),
+            });
+        }
+    }
+
+    Ok(model)
+}
+
+/// The name and everything but the textures. Unnamed Materials get named after the model.
+///
+/// * `index` - Which Material it is in the file.
+fn read_material(material: &::gltf::Material, index: usize, path: &str) -> (String, MaterialProperties) {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+    let properties = MaterialProperties {
+        diffuse: glam::vec3(r, g, b),
+        dissolve: a,
+        emissive: material.emissive_factor().into(),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        ..Default::default()
+    };
+    let name = material.name().map_or_else(|| format!("{path}#material{index}"), str::to_string);
+    (name, properties)
+}
+
+/// Every Node in the file, without any Meshes yet.
+fn read_nodes(gltf: &::gltf::Document) -> Vec<Node> {
+    gltf.nodes()
+        .map(|node| Node {
+            name: node.name().map(str::to_string),
+            transform: glam::Mat4::from_cols_array_2d(&node.transform().matrix()),
+            children: node.children().map(|child| child.index()).collect(),
+            meshes: Vec::new(),
+        })
+        .collect()
+}
+
+/// Which Node each Node is the child of.
+fn parents(nodes: &[Node]) -> Vec<Option<usize>> {
+    let mut parents = vec![None; nodes.len()];
+    for (index, node) in nodes.iter().enumerate() {
+        for &child in &node.children {
+            parents[child] = Some(index);
+        }
+    }
+    parents
+}
+
+/// * `joints` - Which joint each Node is.
+fn read_skeleton(
+    skin: &::gltf::Skin,
+    buffers: &[Vec<u8>],
+    nodes: &[Node],
+    parents: &[Option<usize>],
+    joints: &HashMap<usize, usize>,
+) -> Skeleton {
+    let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let mut inverse_binds = reader
+        .read_inverse_bind_matrices()
+        .map(|matrices| matrices.map(|matrix| glam::Mat4::from_cols_array_2d(&matrix)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    inverse_binds.resize(joints.len(), glam::Mat4::IDENTITY);
+
+    let mut root = glam::Mat4::IDENTITY;
+    let joints = skin
+        .joints()
+        .zip(inverse_binds)
+        .map(|(node, inverse_bind)| {
+            let parent = parents[node.index()];
+            let parent_joint = parent.and_then(|parent| joints.get(&parent).copied());
+            // Whatever's above the root joint still moves it
+            if let (None, Some(mut parent)) = (parent_joint, parent) {
+                root = nodes[parent].transform;
+                while let Some(grandparent) = parents[parent] {
+                    root = nodes[grandparent].transform * root;
+                    parent = grandparent;
+                }
+            }
+
+            Joint {
+                name: node.name().map(str::to_string),
+                parent: parent_joint,
+                rest: Transform::from_matrix(nodes[node.index()].transform),
+                inverse_bind,
+            }
+        })
+        .collect();
+
+    Skeleton { joints, root }
+}
+
+/// Channels for Nodes that aren't joints, and morph target weights, are left out.
+fn read_animation(animation: ::gltf::Animation, buffers: &[Vec<u8>], joints: &HashMap<usize, usize>) -> AnimationClip {
+    use ::gltf::animation::util::ReadOutputs;
+
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let Some(&joint) = joints.get(&channel.target().node().index()) else {
+            continue;
+        };
+        let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+
+        let interpolation = channel.sampler().interpolation();
+        // Cubic splines come with a tangent on either side of every value, those get skipped
+        // and the values get treated as linear
+        let (skip, step) = match interpolation {
+            ::gltf::animation::Interpolation::CubicSpline => (1, 3),
+            _ => (0, 1),
+        };
+        let keyframes = match outputs {
+            ReadOutputs::Translations(values) => {
+                Keyframes::Translation(values.skip(skip).step_by(step).map(glam::Vec3::from).collect())
+            }
+            ReadOutputs::Rotations(values) => {
+                Keyframes::Rotation(values.into_f32().skip(skip).step_by(step).map(glam::Quat::from_array).collect())
+            }
+            ReadOutputs::Scales(values) => {
+                Keyframes::Scale(values.skip(skip).step_by(step).map(glam::Vec3::from).collect())
+            }
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+
+        channels.push(Channel {
+            joint,
+            times: times.collect(),
+            keyframes,
+            interpolation: match interpolation {
+                ::gltf::animation::Interpolation::Step => Interpolation::Step,
+                _ => Interpolation::Linear,
+            },
+        });
+    }
+
+    AnimationClip {
+        name: animation.name().map(str::to_string),
+        duration: channels.iter().filter_map(|channel| channel.times.last()).fold(0.0, |a, &b| f32::max(a, b)),
+        channels,
+    }
+}
+
+/// A base64 data URI, or a file relative to `folder`.
+async fn read_uri(uri: &str, folder: &str, path: &str) -> Result<Vec<u8>> {
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let (_, data) = data.split_once(";base64,").ok_or_else(|| Error::Gltf {
+                path: path.to_string(),
+                message: "only base64 data URIs are supported".to_string(),
+            })?;
+            base64::engine::general_purpose::STANDARD.decode(data).map_err(|e| Error::Gltf {
+                path: path.to_string(),
+                message: e.to_string(),
+            })
+        }
+        None if folder.is_empty() => load_binary(uri).await,
+        None => load_binary(&format!("{folder}/{uri}")).await,
+    }
+}
+
+/// Turns a glTF file's images into textures, decoding each image only once.
+///
+/// The textures go into the ResourceManager named after the model, like `models/cube.gltf#image0`,
+/// so loading the model twice shares them.
+struct GltfTextures<'a> {
+    path: &'a str,
+    folder: &'a str,
+    buffers: &'a [Vec<u8>],
+    images: HashMap<usize, image::DynamicImage>,
+    resources: &'a mut ResourceManager,
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+}
+
+impl GltfTextures<'_> {
+    /// * `srgb` - Whether the texture holds colours. Ones that don't are read back as they are.
+    fn texture(&mut self, texture: ::gltf::Texture, srgb: bool) -> Result<Handle<Texture>> {
+        let image = texture.source().index();
+        let name = match srgb {
+            true => format!("{}#image{image}", self.path),
+            false => format!("{}#image{image}/linear", self.path),
+        };
+        if let Some(handle) = self.resources.textures.find(&name) {
+            return Ok(handle);
+        }
+
+        self.decode(texture.source())?;
+        let img = &self.images[&texture.source().index()];
+        let (device, queue) = (self.device, self.queue);
+        Ok(self.resources.textures.add(&name, || {
+            let mut created = match srgb {
+                true => Texture::from_image(device, queue, img, &name),
+                false => Texture::from_image_linear(device, queue, img, &name),
+            };
+            created.sampler = sampler(device, texture.sampler());
+            created
+        }))
+    }
+
+    fn normal_map(&mut self, texture: ::gltf::Texture) -> Result<Handle<NormalMap>> {
+        let name = format!("{}#image{}", self.path, texture.source().index());
+        if let Some(handle) = self.resources.normal_maps.find(&name) {
+            return Ok(handle);
+        }
+
+        self.decode(texture.source())?;
+        let img = &self.images[&texture.source().index()];
+        let (device, queue) = (self.device, self.queue);
+        Ok(self.resources.normal_maps.add(&name, || {
+            let mut created = Texture::from_image_linear(device, queue, img, &name);
+            created.sampler = sampler(device, texture.sampler());
+            NormalMap(created)
+        }))
+    }
+
+    /// Makes sure `image` is in `images`.
+    fn decode(&mut self, image: ::gltf::Image) -> Result<()> {
+        let index = image.index();
+        if !self.images.contains_key(&index) {
+            let (bytes, name) = match image.source() {
+                ::gltf::image::Source::View { view, .. } => {
+                    let buffer = &self.buffers[view.buffer().index()];
+                    let bytes = buffer.get(view.offset()..view.offset() + view.length()).ok_or_else(|| Error::Gltf {
+                        path: self.path.to_string(),
+                        message: format!("image {index} is outside of its buffer"),
+                    })?;
+                    (bytes.to_vec(), format!("{}#image{index}", self.path))
+                }
+                ::gltf::image::Source::Uri { uri, .. } => {
+                    let name = match uri.starts_with("data:") {
+                        true => format!("{}#image{index}", self.path),
+                        false => format!("{}/{uri}", self.folder),
+                    };
+                    (pollster::block_on(read_uri(uri, self.folder, self.path))?, name)
+                }
+            };
+            let img = image::load_from_memory(&bytes).map_err(|source| Error::Image { path: name, source })?;
+            self.images.insert(index, img);
+        }
+        Ok(())
+    }
+}
+
+/// glTF textures repeat unless they say otherwise.
+fn sampler(device: &wgpu::Device, sampler: ::gltf::texture::Sampler) -> wgpu::Sampler {
+    use ::gltf::texture::{MagFilter, WrappingMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+            _ => wgpu::FilterMode::Linear,
+        },
+        // Textures don't have mipmaps, so that's all there is to it
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An armature with a hip and a leg under it. The skin lists the leg before the hip,
+    /// and the animation moves the hip and the armature, which isn't a joint.
+    const JSON: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "name": "Armature", "translation": [0, 1, 0], "children": [1] },
+            { "name": "Hip", "translation": [0, 0, 2], "children": [2] },
+            { "name": "Leg", "translation": [0, -1, 0] }
+        ],
+        "skins": [{ "joints": [2, 1], "inverseBindMatrices": 0 }],
+        "materials": [
+            {
+                "name": "Skin",
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [1, 0.5, 0.25, 0.75],
+                    "metallicFactor": 0,
+                    "roughnessFactor": 0.5
+                },
+                "emissiveFactor": [0, 0, 1]
+            },
+            {}
+        ],
+        "animations": [{
+            "name": "Walk",
+            "channels": [
+                { "sampler": 0, "target": { "node": 1, "path": "rotation" } },
+                { "sampler": 1, "target": { "node": 0, "path": "translation" } }
+            ],
+            "samplers": [
+                { "input": 1, "output": 2, "interpolation": "STEP" },
+                { "input": 1, "output": 3 }
+            ]
+        }],
+        "buffers": [{ "byteLength": 192 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 128 },
+            { "buffer": 0, "byteOffset": 128, "byteLength": 8 },
+            { "buffer": 0, "byteOffset": 136, "byteLength": 32 },
+            { "buffer": 0, "byteOffset": 168, "byteLength": 24 }
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 2, "type": "MAT4" },
+            { "bufferView": 1, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1] },
+            { "bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC4" },
+            { "bufferView": 3, "componentType": 5126, "count": 2, "type": "VEC3" }
+        ]
+    }"#;
+
+    fn leg_inverse_bind() -> glam::Mat4 {
+        glam::Mat4::from_translation(glam::vec3(0.0, 0.0, -2.0))
+    }
+
+    fn hip_inverse_bind() -> glam::Mat4 {
+        glam::Mat4::from_translation(glam::vec3(0.0, -1.0, -2.0))
+    }
+
+    fn turn() -> glam::Quat {
+        glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_2)
+    }
+
+    /// What's in the binary chunk, in the order of the buffer views.
+    fn bin() -> Vec<u8> {
+        let mut floats = Vec::new();
+        floats.extend(leg_inverse_bind().to_cols_array());
+        floats.extend(hip_inverse_bind().to_cols_array());
+        floats.extend([0.0, 1.0]);
+        floats.extend(glam::Quat::IDENTITY.to_array());
+        floats.extend(turn().to_array());
+        floats.extend([0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        bytemuck::cast_slice(&floats).to_vec()
+    }
+
+    /// Puts `json` and `bin` together into a .glb.
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut bytes = Vec::new();
+        bytes.extend(b"glTF");
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        bytes.extend((json.len() as u32).to_le_bytes());
+        bytes.extend(b"JSON");
+        bytes.extend(json);
+        bytes.extend((bin.len() as u32).to_le_bytes());
+        bytes.extend(b"BIN\0");
+        bytes.extend(bin);
+        bytes
+    }
+
+    /// The parsed file along with its buffers, like `load_gltf` has them.
+    fn load() -> (::gltf::Gltf, Vec<Vec<u8>>) {
+        let gltf = ::gltf::Gltf::from_slice(&glb(JSON, &bin())).unwrap();
+        let buffers = vec![gltf.blob.clone().unwrap()];
+        (gltf, buffers)
+    }
+
+    /// Which joint each Node is, like `load_gltf` works it out.
+    fn joints(gltf: &::gltf::Gltf) -> HashMap<usize, usize> {
+        let skin = gltf.skins().next().unwrap();
+        skin.joints().enumerate().map(|(joint, node)| (node.index(), joint)).collect()
+    }
+
+    #[test]
+    fn nodes_keep_their_hierarchy() {
+        let (gltf, _) = load();
+        let nodes = read_nodes(&gltf);
+
+        let names: Vec<_> = nodes.iter().map(|node| node.name.as_deref()).collect();
+        assert_eq!(names, [Some("Armature"), Some("Hip"), Some("Leg")]);
+        assert_eq!(nodes[0].children, [1]);
+        assert_eq!(nodes[1].children, [2]);
+        assert!(nodes[2].children.is_empty());
+        assert_eq!(nodes[1].transform, glam::Mat4::from_translation(glam::vec3(0.0, 0.0, 2.0)));
+        assert_eq!(parents(&nodes), [None, Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn materials_read_their_factors() {
+        let (gltf, _) = load();
+        let materials: Vec<_> =
+            gltf.materials().enumerate().map(|(index, m)| read_material(&m, index, "models/test.glb")).collect();
+
+        let (name, skin) = &materials[0];
+        assert_eq!(name, "Skin");
+        assert_eq!(skin.diffuse, glam::vec3(1.0, 0.5, 0.25));
+        assert_eq!(skin.dissolve, 0.75);
+        assert_eq!(skin.emissive, glam::Vec3::Z);
+        assert_eq!(skin.metallic, 0.0);
+        assert_eq!(skin.roughness, 0.5);
+
+        // glTF's defaults, not the ones for .obj files
+        let (name, unnamed) = &materials[1];
+        assert_eq!(name, "models/test.glb#material1");
+        assert_eq!(unnamed.diffuse, glam::Vec3::ONE);
+        assert_eq!(unnamed.dissolve, 1.0);
+        assert_eq!(unnamed.metallic, 1.0);
+        assert_eq!(unnamed.roughness, 1.0);
+    }
+
+    #[test]
+    fn joints_point_at_their_parent_joints() {
+        let (gltf, buffers) = load();
+        let nodes = read_nodes(&gltf);
+        let skin = gltf.skins().next().unwrap();
+        let skeleton = read_skeleton(&skin, &buffers, &nodes, &parents(&nodes), &joints(&gltf));
+
+        // In the order the skin lists them, not the order of the Nodes
+        let [leg, hip] = &skeleton.joints[..] else {
+            panic!("expected two joints, got {}", skeleton.joints.len());
+        };
+        assert_eq!(leg.name.as_deref(), Some("Leg"));
+        assert_eq!(leg.parent, Some(1));
+        assert_eq!(leg.rest, Transform::from_matrix(nodes[2].transform));
+        assert_eq!(leg.inverse_bind, leg_inverse_bind());
+        assert_eq!(hip.name.as_deref(), Some("Hip"));
+        assert_eq!(hip.parent, None);
+        assert_eq!(hip.inverse_bind, hip_inverse_bind());
+        // The armature isn't a joint, but it still moves the hip
+        assert_eq!(skeleton.root, nodes[0].transform);
+    }
+
+    #[test]
+    fn animations_only_keep_joints() {
+        let (gltf, buffers) = load();
+        let clip = read_animation(gltf.animations().next().unwrap(), &buffers, &joints(&gltf));
+
+        assert_eq!(clip.name.as_deref(), Some("Walk"));
+        assert_eq!(clip.duration, 1.0);
+        let [channel] = &clip.channels[..] else {
+            panic!("expected only the hip's channel, got {}", clip.channels.len());
+        };
+        assert_eq!(channel.joint, 1);
+        assert_eq!(channel.times, [0.0, 1.0]);
+        assert_eq!(channel.interpolation, Interpolation::Step);
+        match &channel.keyframes {
+            Keyframes::Rotation(values) => assert_eq!(values, &[glam::Quat::IDENTITY, turn()]),
+            other => panic!("expected rotations, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::ops::Range;
+
+use wgpu::util::DrawIndexedIndirectArgs;
+
+use crate::engine::resource::mesh_pool::MeshPool;
+
+/// Draw calls that get collected on the CPU every frame and handed to the GPU all at once.
+///
+/// Draws are grouped into batches, one for every `draw_pool_indirect`. If the GPU supports
+/// `MULTI_DRAW_INDIRECT` (and `INDIRECT_FIRST_INSTANCE`, since draws can start at any instance)
+/// a whole batch is a single call reading its draws from a buffer, otherwise the draws are
+/// made one after another.
+pub struct IndirectDraws {
+    name: String,
+    buffer: wgpu::Buffer,
+    /// Every draw of every batch, like they are in the buffer.
+    draws: Vec<DrawIndexedIndirectArgs>,
+    /// Which draws belong to which batch.
+    batches: Vec<Range<u32>>,
+    multi_draw: bool,
+}
+
+impl IndirectDraws {
+    pub fn new(name: &str, device: &wgpu::Device) -> Self {
+        Self {
+            name: name.to_string(),
+            buffer: Self::create_buffer(name, 0, device),
+            draws: Vec::new(),
+            batches: Vec::new(),
+            multi_draw: device
+                .features()
+                .contains(wgpu::Features::MULTI_DRAW_INDIRECT | wgpu::Features::INDIRECT_FIRST_INSTANCE),
+        }
+    }
+
+    /// Replaces the draws of the last frame. Batch `i` gets drawn with `draw_pool_indirect(.., i)`.
+    pub fn write(&mut self, batches: Vec<Vec<DrawIndexedIndirectArgs>>, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.draws.clear();
+        self.batches.clear();
+        for batch in batches {
+            let start = self.draws.len() as u32;
+            self.draws.extend(batch);
+            self.batches.push(start..self.draws.len() as u32);
+        }
+
+        if !self.multi_draw {
+            return;
+        }
+
+        let bytes: Vec<u8> = self
+            .draws
+            .iter()
+            .flat_map(|draw| draw.as_bytes().to_vec())
+            .collect();
+        if bytes.len() as wgpu::BufferAddress > self.buffer.size() {
+            self.buffer = Self::create_buffer(&self.name, (bytes.len() * 2) as wgpu::BufferAddress, device);
+        }
+        if !bytes.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &bytes);
+        }
+    }
+
+    fn create_buffer(name: &str, size: wgpu::BufferAddress, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{name} Indirect Buffer")),
+            size,
+            usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+/// Draws the Meshes of a MeshPool with IndirectDraws.
+/// Bind groups and instances have to be set already, since they're shared by every draw.
+pub trait DrawIndirect<'a> {
+    fn draw_pool_indirect<V: bytemuck::Pod>(
+        &mut self,
+        pool: &'a MeshPool<V>,
+        draws: &'a IndirectDraws,
+        batch: usize,
+    );
+}
+
+impl<'a, 'b> DrawIndirect<'b> for wgpu::RenderPass<'a>
+    where
+        'b: 'a,
+{
+    fn draw_pool_indirect<V: bytemuck::Pod>(
+        &mut self,
+        pool: &'b MeshPool<V>,
+        draws: &'b IndirectDraws,
+        batch: usize,
+    ) {
+        let Some(range) = draws.batches.get(batch).cloned() else {
+            return;
+        };
+        if range.is_empty() {
+            return;
+        }
+
+        self.set_vertex_buffer(0, pool.vertex_buffer().slice(..));
+        self.set_index_buffer(pool.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
+
+        if draws.multi_draw {
+            let stride = std::mem::size_of::<DrawIndexedIndirectArgs>() as wgpu::BufferAddress;
+            self.multi_draw_indexed_indirect(&draws.buffer, range.start as wgpu::BufferAddress * stride, range.len() as u32);
+        } else {
+            for draw in &draws.draws[range.start as usize..range.end as usize] {
+                self.draw_indexed(
+                    draw.first_index..draw.first_index + draw.index_count,
+                    draw.base_vertex,
+                    draw.first_instance..draw.first_instance + draw.instance_count,
+                );
+            }
+        }
+    }
+}
@@ -1,16 +1,49 @@
+use std::ops::Range;
+
 use wgpu::util::DeviceExt;
 
+use crate::engine::resource::animation::Transform;
+
 /// We use instances when we want to render multiples of one thing in order to save time.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Instance {
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+    /// Multiplies the colour of everything the instance draws. White leaves it alone.
+    pub tint: glam::Vec4,
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            position: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+            scale: glam::Vec3::ONE,
+            tint: glam::Vec4::ONE,
+        }
+    }
 }
 
 impl Instance {
     pub fn to_raw(&self) -> InstanceRaw {
         InstanceRaw {
-            model: (glam::Mat4::from_translation(self.position) * glam::Mat4::from_quat(self.rotation)).to_cols_array_2d(),
-            normal: glam::Mat3::from_quat(self.rotation).to_cols_array_2d(),
+            model: glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.position).to_cols_array_2d(),
+            // Stretching a model one way squashes its normals the other way
+            normal: (glam::Mat3::from_quat(self.rotation) * glam::Mat3::from_diagonal(self.scale.recip()))
+                .to_cols_array_2d(),
+            tint: self.tint.to_array(),
+        }
+    }
+}
+
+impl From<Transform> for Instance {
+    fn from(transform: Transform) -> Self {
+        Self {
+            position: transform.translation,
+            rotation: transform.rotation,
+            scale: transform.scale,
+            ..Default::default()
         }
     }
 }
@@ -18,16 +51,17 @@ impl Instance {
 /// The Instance data that goes into the buffer.
 /// WGSL doesn't have a Quaternion type, so the model becomes mat4s.
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
     /// WGSL doesn't have a Quaternion type, so it gets
     /// converted into mat4's we have to assemble in the shader.
     model: [[f32; 4]; 4],
     normal: [[f32; 3]; 3],
+    tint: [f32; 4],
 }
 
 impl InstanceRaw {
-    pub(crate) fn desc() -> wgpu::VertexBufferLayout<'static> {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Instance,
@@ -70,19 +104,183 @@ impl InstanceRaw {
                     shader_location: 11,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                // 12 and 13 are taken by the SkinVertex
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 14,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 
     // Takes a Vec of Instances and turns them into a buffer that can be used in a RenderPipeline.
+    // It can't grow, see `InstanceBuffer` for instances that come and go.
     pub fn create_buffer(instances: &[Instance], device: &wgpu::Device) -> wgpu::Buffer {
         let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
         device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Instance buffer"),
                 contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             }
         )
     }
-}
\ No newline at end of file
+}
+
+/// How many instances a buffer with room for `capacity` has to grow to, to fit `len` of them.
+/// None if they fit already.
+fn grown_capacity(len: usize, capacity: usize) -> Option<usize> {
+    // Room for twice as many, so it doesn't have to grow again right away
+    (len > capacity).then_some(len * 2)
+}
+
+/// The runs of instances in `new` that are different from the ones in `old`.
+/// The ones past the end of `old` are all different, the ones past the end of `new` don't matter.
+fn changed_runs<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut index = 0;
+    while index < new.len() {
+        if old.get(index) == Some(&new[index]) {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < new.len() && old.get(index) != Some(&new[index]) {
+            index += 1;
+        }
+        runs.push(start..index);
+    }
+    runs
+}
+
+/// Instances that can change every frame, and grow.
+///
+/// Keeps a copy of what's on the GPU, so `write` only has to upload the instances that changed.
+pub struct InstanceBuffer {
+    name: String,
+    buffer: wgpu::Buffer,
+    /// What's in the buffer right now.
+    instances: Vec<InstanceRaw>,
+}
+
+impl InstanceBuffer {
+    pub fn new(name: &str, device: &wgpu::Device) -> Self {
+        Self {
+            name: name.to_string(),
+            buffer: Self::create_buffer(name, 1, device),
+            instances: Vec::new(),
+        }
+    }
+
+    /// Replaces every instance. Only the runs of instances that are different from
+    /// last time get written, unless the buffer has to grow.
+    pub fn write(&mut self, instances: &[Instance], device: &wgpu::Device, queue: &wgpu::Queue) {
+        let raw: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        let stride = std::mem::size_of::<InstanceRaw>();
+
+        if let Some(capacity) = grown_capacity(raw.len(), self.buffer.size() as usize / stride) {
+            log::info!("Growing {} Instance Buffer to {capacity} instances", self.name);
+            self.buffer = Self::create_buffer(&self.name, capacity, device);
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&raw));
+            self.instances = raw;
+            return;
+        }
+
+        for run in changed_runs(&self.instances, &raw) {
+            queue.write_buffer(
+                &self.buffer,
+                (run.start * stride) as wgpu::BufferAddress,
+                bytemuck::cast_slice(&raw[run]),
+            );
+        }
+        self.instances = raw;
+    }
+
+    /// Changes a single instance. It has to exist already, see `write`.
+    pub fn set(&mut self, index: usize, instance: &Instance, queue: &wgpu::Queue) {
+        let raw = instance.to_raw();
+        if self.instances[index] == raw {
+            return;
+        }
+        self.instances[index] = raw;
+        let offset = index * std::mem::size_of::<InstanceRaw>();
+        queue.write_buffer(&self.buffer, offset as wgpu::BufferAddress, bytemuck::bytes_of(&raw));
+    }
+
+    /// The amount of instances that were written.
+    pub fn len(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Goes in vertex slot 1, next to the Model's vertices.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    fn create_buffer(name: &str, capacity: usize, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{name} Instance Buffer")),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `changed_runs` as start and end pairs, which are easier to compare.
+    fn runs(old: &[i32], new: &[i32]) -> Vec<(usize, usize)> {
+        changed_runs(old, new).into_iter().map(|run| (run.start, run.end)).collect()
+    }
+
+    #[test]
+    fn only_changed_runs_get_written() {
+        assert_eq!(runs(&[1, 2, 3, 4, 5], &[1, 2, 3, 4, 5]), []);
+        assert_eq!(runs(&[1, 2, 3, 4, 5], &[1, 9, 9, 4, 9]), [(1, 3), (4, 5)]);
+        assert_eq!(runs(&[1, 2, 3], &[7, 2, 3]), [(0, 1)]);
+        assert_eq!(runs(&[], &[]), []);
+    }
+
+    #[test]
+    fn new_instances_count_as_changed() {
+        assert_eq!(runs(&[], &[1, 2]), [(0, 2)]);
+        // Joins up with a change right before them
+        assert_eq!(runs(&[1, 2], &[1, 9, 3, 4]), [(1, 4)]);
+        assert_eq!(runs(&[1, 2], &[1, 2, 3]), [(2, 3)]);
+    }
+
+    #[test]
+    fn shrinking_writes_nothing_past_the_end() {
+        assert_eq!(runs(&[1, 2, 3, 4], &[1, 2]), []);
+        assert_eq!(runs(&[1, 2, 3, 4], &[1, 9]), [(1, 2)]);
+        assert_eq!(runs(&[1, 2, 3, 4], &[]), []);
+    }
+
+    #[test]
+    fn grows_to_twice_what_it_needs() {
+        assert_eq!(grown_capacity(0, 1), None);
+        assert_eq!(grown_capacity(1, 1), None);
+        assert_eq!(grown_capacity(2, 1), Some(4));
+        assert_eq!(grown_capacity(5, 4), Some(10));
+        // Never shrinks
+        assert_eq!(grown_capacity(3, 10), None);
+    }
+
+    #[test]
+    fn raw_instances_carry_the_tint() {
+        let tinted = Instance {
+            tint: glam::vec4(1.0, 0.0, 0.0, 0.5),
+            ..Default::default()
+        };
+        assert!(tinted.to_raw() != Instance::default().to_raw());
+        assert_eq!(tinted.to_raw().tint, [1.0, 0.0, 0.0, 0.5]);
+    }
+}
@@ -1,32 +1,527 @@
 use wgpu::util::DeviceExt;
 
+use crate::engine::resource::fog::Fog;
+use crate::entity::camera::Projection;
+
+/// How many clusters the view frustum is cut into on each axis.
+/// X and Y are screen tiles, Z are depth slices that get thicker further away.
+const CLUSTERS_X: u32 = 16;
+const CLUSTERS_Y: u32 = 9;
+const CLUSTERS_Z: u32 = 24;
+const CLUSTER_COUNT: usize = (CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z) as usize;
+
+/// What kind of light something is. Has to match the constants in the shaders.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    /// Shines in every direction from its position.
+    Point,
+    /// Shines in a cone from its position.
+    /// The light fades out between the inner and outer angle (in radians, from the center of the cone).
+    Spot {
+        direction: glam::Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    /// Shines everywhere from very far away, like the sun. Position and range are ignored.
+    Directional { direction: glam::Vec3 },
+}
+
+/// A single light in the scene.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Light {
-    pub light_uniform: LightUniform,
-    pub buffer: wgpu::Buffer,
+    pub kind: LightKind,
+    pub position: glam::Vec3,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+    /// How far the light reaches. It fades out completely at this distance.
+    pub range: f32,
+    /// Whether the ShadowMap is rendered from this light. Only works for one directional light.
+    pub casts_shadows: bool,
 }
 
 impl Light {
-    pub fn new(position: glam::Vec3, color: glam::Vec3, device: &wgpu::Device) -> Self {
-        let light_uniform = LightUniform::new(position, color);
-
-        let buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Light"),
-                contents: bytemuck::cast_slice(&[light_uniform]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    pub fn point(position: glam::Vec3, color: glam::Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point,
+            position,
+            color,
+            intensity,
+            range,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn spot(
+        position: glam::Vec3,
+        direction: glam::Vec3,
+        color: glam::Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            },
+            position,
+            color,
+            intensity,
+            range,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn directional(direction: glam::Vec3, color: glam::Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional { direction },
+            position: glam::Vec3::ZERO,
+            color,
+            intensity,
+            range: f32::INFINITY,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn to_raw(self) -> LightRaw {
+        let (kind, direction, cos_inner, cos_outer) = match self.kind {
+            LightKind::Point => (0, glam::Vec3::ZERO, 0.0, 0.0),
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            } => (1, direction.normalize_or_zero(), inner_angle.cos(), outer_angle.cos()),
+            LightKind::Directional { direction } => (2, direction.normalize_or_zero(), 0.0, 0.0),
+        };
+
+        LightRaw {
+            position: self.position,
+            kind,
+            direction,
+            // The shader doesn't like infinity much
+            range: self.range.min(f32::MAX),
+            color: self.color,
+            intensity: self.intensity,
+            cos_inner,
+            cos_outer,
+            casts_shadows: self.casts_shadows as u32,
+            _padding: 0,
+        }
+    }
+}
+
+/// The Light data that goes into the storage buffer.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    position: glam::Vec3,
+    kind: u32,
+    direction: glam::Vec3,
+    range: f32,
+    color: glam::Vec3,
+    intensity: f32,
+    cos_inner: f32,
+    cos_outer: f32,
+    casts_shadows: u32,
+    _padding: u32,
+}
+
+/// Refers to a Light added to `Lights`. Stays valid until the Light is removed.
+/// After that it never refers to anything again, not even once its spot gets reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LightId {
+    index: usize,
+    generation: u32,
+}
+
+/// The Lights themselves, and which LightId refers to which. The part of `Lights`
+/// that has nothing to do with the GPU.
+#[derive(Debug, Default)]
+pub struct LightList {
+    lights: Vec<Option<Light>>,
+    /// How often every spot in `lights` was emptied, so old LightIds don't find the new Light.
+    generations: Vec<u32>,
+}
+
+impl LightList {
+    pub fn add(&mut self, light: Light) -> LightId {
+        // Reuse the spot of a removed Light if there is one
+        let index = match self.lights.iter().position(Option::is_none) {
+            Some(index) => {
+                self.lights[index] = Some(light);
+                index
+            }
+            None => {
+                self.lights.push(Some(light));
+                self.generations.push(0);
+                self.lights.len() - 1
             }
+        };
+        LightId {
+            index,
+            generation: self.generations[index],
+        }
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        if !self.contains(id) {
+            return None;
+        }
+        self.generations[id.index] += 1;
+        self.lights[id.index].take()
+    }
+
+    /// Whether the Light is still there.
+    pub fn contains(&self, id: LightId) -> bool {
+        self.generations.get(id.index) == Some(&id.generation) && self.lights[id.index].is_some()
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        if !self.contains(id) {
+            return None;
+        }
+        self.lights[id.index].as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        if !self.contains(id) {
+            return None;
+        }
+        self.lights[id.index].as_mut()
+    }
+
+    /// The amount of lights in the scene.
+    pub fn len(&self) -> usize {
+        self.lights.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.lights.iter().zip(&self.generations).enumerate().filter_map(|(index, (light, &generation))| {
+            light.as_ref().map(|light| (LightId { index, generation }, light))
+        })
+    }
+}
+
+/// Everything the shaders need to know to find the lights for a fragment.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightGlobals {
+    view: glam::Mat4,
+    ambient: glam::Vec4,
+    /// Clusters on each axis, and the amount of lights in total.
+    clusters: [u32; 4],
+    /// Screen width and height, and the near and far plane.
+    screen: [f32; 4],
+    /// Seconds since the scene started. The rest is padding.
+    time: [f32; 4],
+}
+
+/// All the lights in a scene, and the buffers to get them to the GPU.
+///
+/// Lights are assigned to clusters (a grid of small boxes the view frustum is cut into)
+/// every frame, so a fragment only has to look at the lights that can actually reach it.
+pub struct Lights {
+    list: LightList,
+    /// Light that's everywhere, no matter what.
+    pub ambient: glam::Vec3,
+    /// Fades things into the sky with distance. Sent along with the lights.
+    pub fog: Fog,
+    /// Seconds since the scene started, for anything animated like flowing water.
+    /// Sent along with the lights too.
+    pub time: f32,
+
+    width: u32,
+    height: u32,
+    /// The view space box of every cluster. Only changes when the projection does.
+    cluster_bounds: Vec<(glam::Vec3, glam::Vec3)>,
+    cluster_projection: glam::Mat4,
+
+    globals_buffer: wgpu::Buffer,
+    fog_buffer: wgpu::Buffer,
+    light_buffer: wgpu::Buffer,
+    light_capacity: usize,
+    /// Offset and count into the index buffer for every cluster.
+    cluster_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_capacity: usize,
+
+    layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Lights {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light globals"),
+            size: std::mem::size_of::<LightGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let fog_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fog"),
+            contents: bytemuck::cast_slice(&[Fog::default().to_raw()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let cluster_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light clusters"),
+            contents: bytemuck::cast_slice(&[[0u32; 2]; CLUSTER_COUNT]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let light_capacity = 16;
+        let light_buffer = Self::create_storage_buffer::<LightRaw>("Lights", light_capacity, device);
+        let index_capacity = CLUSTER_COUNT;
+        let index_buffer = Self::create_storage_buffer::<u32>("Light indices", index_capacity, device);
+
+        let layout = Self::bind_group_layout(device);
+        let bind_group = Self::create_bind_group(
+            &layout,
+            &globals_buffer,
+            &fog_buffer,
+            &light_buffer,
+            &cluster_buffer,
+            &index_buffer,
+            device,
         );
 
         Self {
-            light_uniform,
-            buffer,
+            list: LightList::default(),
+            ambient: glam::Vec3::splat(0.1),
+            time: 0.0,
+            fog: Fog::default(),
+            width: config.width,
+            height: config.height,
+            cluster_bounds: Vec::new(),
+            cluster_projection: glam::Mat4::ZERO,
+            globals_buffer,
+            fog_buffer,
+            light_buffer,
+            light_capacity,
+            cluster_buffer,
+            index_buffer,
+            index_capacity,
+            layout,
+            bind_group,
         }
     }
 
-    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
+    pub fn add(&mut self, light: Light) -> LightId {
+        self.list.add(light)
+    }
+
+    pub fn remove(&mut self, id: LightId) -> Option<Light> {
+        self.list.remove(id)
+    }
+
+    /// Whether the Light is still there.
+    pub fn contains(&self, id: LightId) -> bool {
+        self.list.contains(id)
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&Light> {
+        self.list.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+        self.list.get_mut(id)
+    }
+
+    /// The amount of lights in the scene.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+        self.list.iter()
+    }
+
+    /// For anything that only changes the Lights, like `LightSync`.
+    pub fn list_mut(&mut self) -> &mut LightList {
+        &mut self.list
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// The layout the `bind_group` uses. Pipelines that want lights need it.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Assigns the lights to clusters and sends everything to the GPU. Call once a frame
+    /// after the camera moved.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: glam::Mat4,
+        projection: &Projection,
+    ) {
+        let projection_matrix = projection.calc_matrix();
+        if projection_matrix != self.cluster_projection {
+            self.cluster_projection = projection_matrix;
+            self.cluster_bounds = Self::cluster_bounds(projection);
+        }
+
+        let lights: Vec<&Light> = self.list.lights.iter().flatten().collect();
+        let raw: Vec<LightRaw> = lights.iter().map(|light| light.to_raw()).collect();
+
+        // Every light gets a bounding sphere in view space, directional lights reach everything.
+        let spheres: Vec<Option<(glam::Vec3, f32)>> = lights
+            .iter()
+            .map(|light| match light.kind {
+                LightKind::Directional { .. } => None,
+                _ => Some((view.transform_point3(light.position), light.range)),
+            })
+            .collect();
+
+        let mut clusters = Vec::with_capacity(CLUSTER_COUNT);
+        let mut indices = Vec::new();
+        for (min, max) in &self.cluster_bounds {
+            let offset = indices.len() as u32;
+            for (index, sphere) in spheres.iter().enumerate() {
+                let reaches = match sphere {
+                    None => true,
+                    Some((center, radius)) => center.clamp(*min, *max).distance_squared(*center) <= radius * radius,
+                };
+                if reaches {
+                    indices.push(index as u32);
+                }
+            }
+            clusters.push([offset, indices.len() as u32 - offset]);
+        }
+
+        // Make room if we've outgrown the buffers. The bind group has to be made again then.
+        let mut recreated = false;
+        if raw.len() > self.light_capacity {
+            self.light_capacity = raw.len().next_power_of_two();
+            self.light_buffer = Self::create_storage_buffer::<LightRaw>("Lights", self.light_capacity, device);
+            recreated = true;
+        }
+        if indices.len() > self.index_capacity {
+            self.index_capacity = indices.len().next_power_of_two();
+            self.index_buffer = Self::create_storage_buffer::<u32>("Light indices", self.index_capacity, device);
+            recreated = true;
+        }
+        if recreated {
+            self.bind_group = Self::create_bind_group(
+                &self.layout,
+                &self.globals_buffer,
+                &self.fog_buffer,
+                &self.light_buffer,
+                &self.cluster_buffer,
+                &self.index_buffer,
+                device,
+            );
+        }
+
+        let globals = LightGlobals {
+            view,
+            ambient: self.ambient.extend(0.0),
+            clusters: [CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z, raw.len() as u32],
+            screen: [
+                self.width as f32,
+                self.height as f32,
+                projection.z_near(),
+                projection.z_far(),
+            ],
+            time: [self.time, 0.0, 0.0, 0.0],
+        };
+
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::cast_slice(&[globals]));
+        queue.write_buffer(&self.fog_buffer, 0, bytemuck::cast_slice(&[self.fog.to_raw()]));
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&raw));
+        queue.write_buffer(&self.cluster_buffer, 0, bytemuck::cast_slice(&clusters));
+        queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+    }
+
+    /// Cuts the view frustum into clusters and finds the box around each one in view space.
+    ///
+    /// Depth slices are spaced exponentially, so clusters close to the camera are small
+    /// and the ones far away are big. Has to match `cluster_index` in the shader.
+    fn cluster_bounds(projection: &Projection) -> Vec<(glam::Vec3, glam::Vec3)> {
+        let inverse = projection.calc_matrix().inverse();
+        let (z_near, z_far) = (projection.z_near(), projection.z_far());
+
+        // Where a point on the screen ends up at a depth of 1 in front of the camera.
+        let unproject = |x: f32, y: f32| {
+            let point = inverse.project_point3(glam::Vec3::new(x, y, 0.0));
+            point / -point.z
+        };
+
+        let mut bounds = Vec::with_capacity(CLUSTER_COUNT);
+        for z in 0..CLUSTERS_Z {
+            let near = z_near * (z_far / z_near).powf(z as f32 / CLUSTERS_Z as f32);
+            let far = z_near * (z_far / z_near).powf((z + 1) as f32 / CLUSTERS_Z as f32);
+
+            for y in 0..CLUSTERS_Y {
+                // Tiles start at the top of the screen, NDC starts at the bottom
+                let top = 1.0 - 2.0 * y as f32 / CLUSTERS_Y as f32;
+                let bottom = 1.0 - 2.0 * (y + 1) as f32 / CLUSTERS_Y as f32;
+
+                for x in 0..CLUSTERS_X {
+                    let left = -1.0 + 2.0 * x as f32 / CLUSTERS_X as f32;
+                    let right = -1.0 + 2.0 * (x + 1) as f32 / CLUSTERS_X as f32;
+
+                    let mut min = glam::Vec3::splat(f32::INFINITY);
+                    let mut max = glam::Vec3::splat(f32::NEG_INFINITY);
+                    for corner in [
+                        unproject(left, bottom),
+                        unproject(left, top),
+                        unproject(right, bottom),
+                        unproject(right, top),
+                    ] {
+                        for depth in [near, far] {
+                            min = min.min(corner * depth);
+                            max = max.max(corner * depth);
+                        }
+                    }
+                    bounds.push((min, max));
+                }
+            }
+        }
+
+        bounds
+    }
+
+    fn create_storage_buffer<T>(label: &str, capacity: usize, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (std::mem::size_of::<T>() * capacity) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
                     binding: 0,
                     visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
@@ -35,42 +530,184 @@ impl Light {
                         min_binding_size: None,
                     },
                     count: None,
-                }],
-                label: None,
-            })
+                },
+                storage(1),
+                storage(2),
+                storage(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Lights bind group layout"),
+        })
     }
 
-    pub fn create_bind_group(&self, layout: &wgpu::BindGroupLayout, device: &wgpu::Device) -> wgpu::BindGroup {
-        device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout,
-                entries: &[wgpu::BindGroupEntry {
+    fn create_bind_group(
+        layout: &wgpu::BindGroupLayout,
+        globals_buffer: &wgpu::Buffer,
+        fog_buffer: &wgpu::Buffer,
+        light_buffer: &wgpu::Buffer,
+        cluster_buffer: &wgpu::Buffer,
+        index_buffer: &wgpu::Buffer,
+        device: &wgpu::Device,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: self.buffer.as_entire_binding(),
-                }],
-                label: None,
-            }
-        )
+                    resource: globals_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cluster_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: index_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: fog_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Lights bind group"),
+        })
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct LightUniform {
-    pub position: glam::Vec3,
-    // Uniforms in WGSL require 16 bytes (4 floats) of spacing, so we need to pad it out
-    _padding: u32,
-    color: glam::Vec3,
-    _padding2: u32,
+/// The corners of the cube `LightMarkers` draws, from -1 to 1.
+const MARKER_CORNERS: [[f32; 3]; 8] = [
+    [-1.0, -1.0, -1.0],
+    [1.0, -1.0, -1.0],
+    [-1.0, 1.0, -1.0],
+    [1.0, 1.0, -1.0],
+    [-1.0, -1.0, 1.0],
+    [1.0, -1.0, 1.0],
+    [-1.0, 1.0, 1.0],
+    [1.0, 1.0, 1.0],
+];
+/// Two triangles for every side of the cube, counter-clockwise from the outside.
+const MARKER_INDICES: [u16; 36] = [
+    1, 3, 7, 1, 7, 5, 4, 6, 2, 4, 2, 0, 2, 6, 7, 2, 7, 3, 1, 5, 4, 1, 4, 0, 4, 5, 7, 4, 7, 6, 2, 3, 1, 2, 1, 0,
+];
+
+/// A small cube in the colour of every light, to see where they are.
+///
+/// Drawn with `vs_main` in `light.wgsl`, which looks the light up by its instance
+/// and scales the cube down.
+pub struct LightMarkers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
 }
 
-impl LightUniform {
-    fn new(position: glam::Vec3, color: glam::Vec3) -> Self {
+impl LightMarkers {
+    pub fn new(device: &wgpu::Device) -> Self {
         Self {
-            position,
-            _padding: 0,
-            color,
-            _padding2: 0,
+            vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Light Marker Vertex Buffer"),
+                contents: bytemuck::cast_slice(&MARKER_CORNERS),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Light Marker Index Buffer"),
+                contents: bytemuck::cast_slice(&MARKER_INDICES),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+        }
+    }
+
+    /// The cube's vertices, just a position each.
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            }],
         }
     }
-}
\ No newline at end of file
+
+    /// Draws a cube for every light, with whatever pipeline and bind groups are set.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, lights: &Lights) {
+        if lights.is_empty() {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..MARKER_INDICES.len() as u32, 0, 0..lights.len() as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light(intensity: f32) -> Light {
+        Light::point(glam::Vec3::ZERO, glam::Vec3::ONE, intensity, 10.0)
+    }
+
+    #[test]
+    fn added_lights_can_be_found() {
+        let mut list = LightList::default();
+        let a = list.add(light(1.0));
+        let b = list.add(light(2.0));
+
+        assert_ne!(a, b);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.get(a).unwrap().intensity, 1.0);
+        list.get_mut(b).unwrap().intensity = 3.0;
+        assert_eq!(list.get(b).unwrap().intensity, 3.0);
+    }
+
+    #[test]
+    fn removed_ids_stay_removed() {
+        let mut list = LightList::default();
+        let old = list.add(light(1.0));
+        let kept = list.add(light(2.0));
+
+        assert_eq!(list.remove(old).unwrap().intensity, 1.0);
+        assert!(!list.contains(old));
+        assert_eq!(list.remove(old), None);
+
+        // The new Light takes the old one's spot, but the old id still doesn't find it
+        let new = list.add(light(4.0));
+        assert_eq!(new.index, old.index);
+        assert!(!list.contains(old));
+        assert_eq!(list.get(old), None);
+        assert!(list.get_mut(old).is_none());
+        assert_eq!(list.remove(old), None);
+
+        assert_eq!(list.get(new).unwrap().intensity, 4.0);
+        assert_eq!(list.get(kept).unwrap().intensity, 2.0);
+        assert_eq!(list.len(), 2);
+    }
+
+    #[test]
+    fn iter_skips_removed_lights() {
+        let mut list = LightList::default();
+        let ids: Vec<LightId> = (0..4).map(|i| list.add(light(i as f32))).collect();
+        list.remove(ids[1]);
+        list.remove(ids[2]);
+
+        let left: Vec<(LightId, f32)> = list.iter().map(|(id, light)| (id, light.intensity)).collect();
+        assert_eq!(left, vec![(ids[0], 0.0), (ids[3], 3.0)]);
+
+        list.remove(ids[0]);
+        list.remove(ids[3]);
+        assert!(list.is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use wgpu::util::DrawIndexedIndirectArgs;
+
+/// Where a Mesh lives in a MeshPool. Indices count from the Mesh's first vertex.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolMesh {
+    vertices: Range<u32>,
+    indices: Range<u32>,
+}
+
+impl PoolMesh {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// How many indices the Mesh has.
+    pub fn num_elements(&self) -> u32 {
+        self.indices.len() as u32
+    }
+
+    /// How many vertices the Mesh has.
+    pub fn num_vertices(&self) -> u32 {
+        self.vertices.len() as u32
+    }
+
+    /// What it takes to draw the Mesh out of the pool's buffers, once for every instance in `instances`.
+    pub fn draw_args(&self, instances: Range<u32>) -> DrawIndexedIndirectArgs {
+        DrawIndexedIndirectArgs {
+            index_count: self.num_elements(),
+            instance_count: instances.len() as u32,
+            first_index: self.indices.start,
+            base_vertex: self.vertices.start as i32,
+            first_instance: instances.start,
+        }
+    }
+}
+
+/// Lots of small Meshes sharing one big vertex buffer and one big index buffer, so they can
+/// all be drawn without switching buffers in between. That's what lets `IndirectDraws` draw
+/// every one of them with a single call.
+///
+/// The buffers grow when they run out of room, so nothing has to know how much fits up front.
+pub struct MeshPool<V> {
+    name: String,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    vertices: RangeAllocator,
+    indices: RangeAllocator,
+    vertex: PhantomData<V>,
+}
+
+impl<V: bytemuck::Pod> MeshPool<V> {
+    /// * `vertex_capacity` / `index_capacity` - How much fits before the buffers have to grow.
+    pub fn new(name: &str, vertex_capacity: u32, index_capacity: u32, device: &wgpu::Device) -> Self {
+        Self {
+            name: name.to_string(),
+            vertex_buffer: Self::create_buffer(name, "Vertex", Self::vertex_bytes(vertex_capacity), wgpu::BufferUsages::VERTEX, device),
+            index_buffer: Self::create_buffer(name, "Index", Self::index_bytes(index_capacity), wgpu::BufferUsages::INDEX, device),
+            vertices: RangeAllocator::new(vertex_capacity),
+            indices: RangeAllocator::new(index_capacity),
+            vertex: PhantomData,
+        }
+    }
+
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        &self.vertex_buffer
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        &self.index_buffer
+    }
+
+    /// Puts a Mesh in the pool.
+    pub fn insert(&mut self, vertices: &[V], indices: &[u32], device: &wgpu::Device, queue: &wgpu::Queue) -> PoolMesh {
+        let mesh = self.allocate(vertices.len() as u32, indices.len() as u32, device, queue);
+        if !mesh.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, Self::vertex_bytes(mesh.vertices.start), bytemuck::cast_slice(vertices));
+            queue.write_buffer(&self.index_buffer, Self::index_bytes(mesh.indices.start), bytemuck::cast_slice(indices));
+        }
+        mesh
+    }
+
+    /// Puts a Mesh in the pool that's already on the GPU, at the start of two other buffers.
+    /// The buffers need `COPY_SRC`.
+    pub fn insert_from_buffers(
+        &mut self,
+        vertex_buffer: &wgpu::Buffer,
+        vertex_count: u32,
+        index_buffer: &wgpu::Buffer,
+        index_count: u32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> PoolMesh {
+        let mesh = self.allocate(vertex_count, index_count, device, queue);
+        if !mesh.is_empty() {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some(&format!("{} Copy Encoder", self.name)),
+            });
+            encoder.copy_buffer_to_buffer(
+                vertex_buffer,
+                0,
+                &self.vertex_buffer,
+                Self::vertex_bytes(mesh.vertices.start),
+                Self::vertex_bytes(vertex_count),
+            );
+            encoder.copy_buffer_to_buffer(
+                index_buffer,
+                0,
+                &self.index_buffer,
+                Self::index_bytes(mesh.indices.start),
+                Self::index_bytes(index_count),
+            );
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        mesh
+    }
+
+    /// Frees up the room a Mesh took.
+    pub fn remove(&mut self, mesh: &PoolMesh) {
+        if mesh.is_empty() {
+            return;
+        }
+        self.vertices.free(mesh.vertices.clone());
+        self.indices.free(mesh.indices.clone());
+    }
+
+    /// Overwrites the indices of a Mesh, which have to be just as many as before.
+    pub fn write_indices(&self, mesh: &PoolMesh, indices: &[u32], queue: &wgpu::Queue) {
+        debug_assert_eq!(indices.len(), mesh.indices.len());
+        queue.write_buffer(&self.index_buffer, Self::index_bytes(mesh.indices.start), bytemuck::cast_slice(indices));
+    }
+
+    fn allocate(&mut self, vertex_count: u32, index_count: u32, device: &wgpu::Device, queue: &wgpu::Queue) -> PoolMesh {
+        if index_count == 0 {
+            return PoolMesh::default();
+        }
+
+        let vertices = match self.vertices.allocate(vertex_count) {
+            Some(vertices) => vertices,
+            None => {
+                let capacity = self.vertices.grow(vertex_count);
+                self.vertex_buffer = self.grow_buffer(&self.vertex_buffer, "Vertex", Self::vertex_bytes(capacity), wgpu::BufferUsages::VERTEX, device, queue);
+                self.vertices.allocate(vertex_count).unwrap()
+            }
+        };
+        let indices = match self.indices.allocate(index_count) {
+            Some(indices) => indices,
+            None => {
+                let capacity = self.indices.grow(index_count);
+                self.index_buffer = self.grow_buffer(&self.index_buffer, "Index", Self::index_bytes(capacity), wgpu::BufferUsages::INDEX, device, queue);
+                self.indices.allocate(index_count).unwrap()
+            }
+        };
+
+        PoolMesh { vertices, indices }
+    }
+
+    /// A bigger buffer with everything that was in `buffer` copied over.
+    fn grow_buffer(
+        &self,
+        buffer: &wgpu::Buffer,
+        kind: &str,
+        size: wgpu::BufferAddress,
+        usage: wgpu::BufferUsages,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> wgpu::Buffer {
+        log::info!("Growing {} {kind} Buffer to {size} bytes", self.name);
+
+        let new_buffer = Self::create_buffer(&self.name, kind, size, usage, device);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(&format!("{} Grow Encoder", self.name)),
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &new_buffer, 0, buffer.size());
+        queue.submit(std::iter::once(encoder.finish()));
+        new_buffer
+    }
+
+    fn create_buffer(
+        name: &str,
+        kind: &str,
+        size: wgpu::BufferAddress,
+        usage: wgpu::BufferUsages,
+        device: &wgpu::Device,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{name} {kind} Buffer")),
+            size,
+            // Written to when Meshes come in, copied from when the buffer grows
+            usage: usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn vertex_bytes(count: u32) -> wgpu::BufferAddress {
+        (count as usize * std::mem::size_of::<V>()) as wgpu::BufferAddress
+    }
+
+    fn index_bytes(count: u32) -> wgpu::BufferAddress {
+        (count as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress
+    }
+}
+
+/// Hands out ranges of a buffer, first fit.
+#[derive(Debug)]
+struct RangeAllocator {
+    capacity: u32,
+    /// Ranges nobody has, sorted and never touching each other.
+    free: Vec<Range<u32>>,
+}
+
+impl RangeAllocator {
+    fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            free: std::iter::once(0..capacity).collect(),
+        }
+    }
+
+    fn allocate(&mut self, size: u32) -> Option<Range<u32>> {
+        let index = self.free.iter().position(|range| range.len() as u32 >= size)?;
+        let range = &mut self.free[index];
+        let allocated = range.start..range.start + size;
+
+        range.start += size;
+        if range.start == range.end {
+            self.free.remove(index);
+        }
+        Some(allocated)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        let index = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(index, range);
+
+        // Merge with the neighbours it touches
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free.remove(index + 1).end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free.remove(index).end;
+        }
+    }
+
+    /// Makes room for at least `size` more at the end, at least doubling the capacity.
+    /// Gives the new capacity.
+    fn grow(&mut self, size: u32) -> u32 {
+        let old_capacity = self.capacity;
+        self.capacity = (old_capacity * 2).max(old_capacity + size);
+        self.free(old_capacity..self.capacity);
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_first_fit() {
+        let mut allocator = RangeAllocator::new(100);
+        assert_eq!(allocator.allocate(10), Some(0..10));
+        assert_eq!(allocator.allocate(20), Some(10..30));
+        assert_eq!(allocator.allocate(70), Some(30..100));
+        assert_eq!(allocator.allocate(1), None);
+        assert!(allocator.free.is_empty());
+    }
+
+    #[test]
+    fn freed_ranges_get_reused() {
+        let mut allocator = RangeAllocator::new(100);
+        let a = allocator.allocate(10).unwrap();
+        let _b = allocator.allocate(10).unwrap();
+        allocator.free(a);
+
+        // The gap at the start is the first one big enough
+        assert_eq!(allocator.allocate(5), Some(0..5));
+        // Too big for what's left of it
+        assert_eq!(allocator.allocate(8), Some(20..28));
+        assert_eq!(allocator.allocate(5), Some(5..10));
+    }
+
+    #[test]
+    fn freeing_merges_with_both_neighbours() {
+        let mut allocator = RangeAllocator::new(30);
+        let a = allocator.allocate(10).unwrap();
+        let b = allocator.allocate(10).unwrap();
+        let c = allocator.allocate(10).unwrap();
+
+        allocator.free(a);
+        allocator.free(c);
+        assert_eq!(allocator.free, [0..10, 20..30]);
+        allocator.free(b);
+        assert_eq!(allocator.free, std::iter::once(0..30).collect::<Vec<_>>());
+        assert_eq!(allocator.allocate(30), Some(0..30));
+    }
+
+    #[test]
+    fn freeing_merges_with_one_neighbour() {
+        let mut allocator = RangeAllocator::new(40);
+        let a = allocator.allocate(10).unwrap();
+        let b = allocator.allocate(10).unwrap();
+        let _c = allocator.allocate(10).unwrap();
+
+        allocator.free(b);
+        // Joins up with the free space before it, but not the allocated range after
+        allocator.free(a);
+        assert_eq!(allocator.free, [0..20, 30..40]);
+    }
+
+    #[test]
+    fn growing_adds_to_the_free_space_at_the_end() {
+        let mut allocator = RangeAllocator::new(10);
+        let _a = allocator.allocate(6).unwrap();
+
+        // At least doubles
+        assert_eq!(allocator.grow(1), 20);
+        assert_eq!(allocator.free, std::iter::once(6..20).collect::<Vec<_>>());
+        assert_eq!(allocator.allocate(14), Some(6..20));
+
+        // Or more, when that's not enough
+        assert_eq!(allocator.grow(50), 70);
+        assert_eq!(allocator.free, std::iter::once(20..70).collect::<Vec<_>>());
+    }
+}
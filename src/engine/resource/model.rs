@@ -2,7 +2,11 @@ use std::ops::Range;
 
 use wgpu::util::DeviceExt;
 
-use crate::engine::resource::texture::Texture;
+use crate::engine::assets::Handle;
+use crate::engine::resource::animation::{AnimationClip, Skeleton};
+use crate::engine::error::Result;
+use crate::engine::resource::texture::{NormalMap, Texture};
+use crate::engine::resource_manager::ResourceManager;
 use crate::engine::util::Vertex;
 
 /// ModelVertex contains all Vertex information we want.
@@ -15,9 +19,52 @@ pub struct ModelVertex {
     pub tex_coords: [f32; 2],
     /// The normal of this vertex.
     pub normal: [f32; 3],
+    /// Which way the texture's u goes along the surface. For normal mapping.
+    pub tangent: [f32; 3],
+    /// Which way the texture's v goes along the surface. For normal mapping.
+    pub bitangent: [f32; 3],
 }
 
 impl ModelVertex {
+    /// Works out the tangents and bitangents from the positions and texture coordinates.
+    /// Vertices shared by several triangles get the average of all of them.
+    pub fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+        let mut tangents = vec![glam::Vec3::ZERO; vertices.len()];
+        let mut bitangents = vec![glam::Vec3::ZERO; vertices.len()];
+
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+            let [pos_a, pos_b, pos_c] = [a, b, c].map(|v| glam::Vec3::from(v.position));
+            let [uv_a, uv_b, uv_c] = [a, b, c].map(|v| glam::Vec2::from(v.tex_coords));
+
+            let edge_1 = pos_b - pos_a;
+            let edge_2 = pos_c - pos_a;
+            let delta_uv_1 = uv_b - uv_a;
+            let delta_uv_2 = uv_c - uv_a;
+
+            let determinant = delta_uv_1.x * delta_uv_2.y - delta_uv_1.y * delta_uv_2.x;
+            // No texture coordinates to go by
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / determinant;
+            // Texture coordinates start at the top left, so v goes down the image.
+            // Normal maps have their y going up it, hence the flipped sign.
+            let tangent = (edge_1 * delta_uv_2.y - edge_2 * delta_uv_1.y) * r;
+            let bitangent = (edge_2 * delta_uv_1.x - edge_1 * delta_uv_2.x) * -r;
+
+            for &i in triangle {
+                tangents[i as usize] += tangent;
+                bitangents[i as usize] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+            vertex.tangent = tangent.normalize_or_zero().to_array();
+            vertex.bitangent = bitangent.normalize_or_zero().to_array();
+        }
+    }
+
     /// Create a Vertex Buffer out of a ModelVertex vector.
     pub fn create_vertex_buffer(
         file_name: &str,
@@ -67,6 +114,16 @@ impl Vertex for ModelVertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
@@ -79,18 +136,325 @@ pub struct Model {
     pub meshes: Vec<Mesh>,
     /// A list of all Materials the Model contains.
     pub materials: Vec<Material>,
+    /// How the Model is put together, for formats that say so, like glTF.
+    /// Empty for everything else.
+    pub nodes: Vec<Node>,
+    /// The Nodes that aren't the child of any other Node.
+    pub root_nodes: Vec<usize>,
+    /// What the skinned Meshes move with, if there are any.
+    pub skeleton: Option<Skeleton>,
+    /// Ways the Skeleton can move, see `Animator`.
+    pub animations: Vec<AnimationClip>,
+}
+
+impl Model {
+    /// The index of the animation called `name`, for `Animator::play`.
+    pub fn animation(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|clip| clip.name.as_deref() == Some(name))
+    }
+}
+
+/// A part of a Model's hierarchy.
+///
+/// Meshes already are where their Nodes put them, so nothing needs these to draw the Model.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: Option<String>,
+    /// Relative to the parent Node.
+    pub transform: glam::Mat4,
+    /// Indices into `Model::nodes`.
+    pub children: Vec<usize>,
+    /// Indices into `Model::meshes`.
+    pub meshes: Vec<usize>,
+}
+
+/// The textures a Material is made of. Any that aren't there don't change anything.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialMaps {
+    pub diffuse: Option<Handle<Texture>>,
+    /// Which way the surface faces, in tangent space.
+    pub normal: Option<Handle<NormalMap>>,
+    /// How shiny the surface is, on top of `MaterialProperties::specular`.
+    pub specular: Option<Handle<Texture>>,
+    /// Light the surface gives off by itself, on top of `MaterialProperties::emissive`.
+    pub emissive: Option<Handle<Texture>>,
+    /// Roughness in green and metalness in blue, like glTF has them. Only for PBR.
+    /// Not a colour, so it has to be made with `Texture::from_image_linear`.
+    pub metallic_roughness: Option<Handle<Texture>>,
+    /// How much ambient light reaches the surface, in red. Also not a colour.
+    pub occlusion: Option<Handle<Texture>>,
+}
+
+/// The numbers that go with a Material's textures, like the ones in an MTL file or the
+/// PBR ones from glTF. Every one of them gets multiplied with its map.
+///
+/// Which of them matter depends on what the Material gets drawn with: `fs_main` and friends
+/// use `specular` and `shininess`, `fs_pbr` uses `metallic` and `roughness` instead.
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialProperties {
+    /// `Kd`
+    pub diffuse: glam::Vec3,
+    /// `Ks`
+    pub specular: glam::Vec3,
+    /// `Ns`. The higher, the smaller and sharper the highlights.
+    pub shininess: f32,
+    /// `Ke`
+    pub emissive: glam::Vec3,
+    /// `d`, the opacity. 1.0 is fully opaque.
+    pub dissolve: f32,
+    /// 0.0 for things like plastic or stone, 1.0 for bare metal.
+    pub metallic: f32,
+    /// 0.0 is a perfect mirror, 1.0 doesn't reflect at all.
+    pub roughness: f32,
+}
+
+impl Default for MaterialProperties {
+    fn default() -> Self {
+        Self {
+            diffuse: glam::Vec3::ONE,
+            specular: glam::Vec3::splat(0.5),
+            shininess: 32.0,
+            emissive: glam::Vec3::ZERO,
+            dissolve: 1.0,
+            metallic: 0.0,
+            roughness: 1.0,
+        }
+    }
+}
+
+impl MaterialProperties {
+    /// Whatever the MTL material has, the defaults for the rest.
+    pub fn from_mtl(material: &tobj::Material) -> Self {
+        let default = Self::default();
+        // tobj doesn't know about `Ke`
+        let emissive = material
+            .unknown_param
+            .get("Ke")
+            .and_then(|ke| {
+                let ke = ke.split_whitespace().map(|v| v.parse().ok()).collect::<Option<Vec<f32>>>()?;
+                Some(glam::Vec3::from_slice(ke.get(..3)?))
+            });
+
+        Self {
+            diffuse: material.diffuse.map_or(default.diffuse, glam::Vec3::from),
+            specular: material.specular.map_or(default.specular, glam::Vec3::from),
+            shininess: material.shininess.unwrap_or(default.shininess),
+            emissive: emissive.unwrap_or(default.emissive),
+            dissolve: material.dissolve.unwrap_or(default.dissolve),
+            ..default
+        }
+    }
+}
+
+/// MaterialProperties the way the shader wants them.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    /// `dissolve` is in w.
+    diffuse: [f32; 4],
+    /// `shininess` is in w.
+    specular: [f32; 4],
+    emissive: [f32; 4],
+    /// Metallic and roughness, the rest is padding.
+    pbr: [f32; 4],
 }
 
-/// A Material. A Texture + BindGroup essentially.
+impl From<MaterialProperties> for MaterialUniform {
+    fn from(properties: MaterialProperties) -> Self {
+        Self {
+            diffuse: properties.diffuse.extend(properties.dissolve).to_array(),
+            specular: properties.specular.extend(properties.shininess).to_array(),
+            emissive: properties.emissive.extend(0.0).to_array(),
+            pbr: [properties.metallic, properties.roughness, 0.0, 0.0],
+        }
+    }
+}
+
+/// A Material. Its textures and properties, all in one BindGroup.
 pub struct Material {
     /// Internal name of the Material.
     pub name: String,
-    /// The actual Texture.
-    pub diffuse_texture: Texture,
-    /// The BindGroup for this Materials Texture.
+    pub diffuse_texture: Handle<Texture>,
+    pub normal_texture: Handle<NormalMap>,
+    pub specular_texture: Handle<Texture>,
+    pub emissive_texture: Handle<Texture>,
+    pub metallic_roughness_texture: Handle<Texture>,
+    pub occlusion_texture: Handle<Texture>,
+    pub properties: MaterialProperties,
+    /// Where the properties are for the shader.
+    properties_buffer: wgpu::Buffer,
+    /// The BindGroup for everything above, see `bind_group_layout`.
     pub bind_group: wgpu::BindGroup,
 }
 
+impl Material {
+    /// Waits for the textures if they're still loading. Textures that don't load get their
+    /// fallback instead. Missing maps get a plain white texture, or a flat normal map.
+    pub fn new(
+        name: &str,
+        maps: MaterialMaps,
+        properties: MaterialProperties,
+        resources: &mut ResourceManager,
+        layout: &wgpu::BindGroupLayout,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self> {
+        let mut texture_or_default = |texture: Option<Handle<Texture>>| {
+            texture.unwrap_or_else(|| resources.default_texture(device, queue))
+        };
+        let diffuse_texture = texture_or_default(maps.diffuse);
+        let specular_texture = texture_or_default(maps.specular);
+        let emissive_texture = texture_or_default(maps.emissive);
+        // White works for these too, it's 1.0 whether it's read as sRGB or not
+        let metallic_roughness_texture = texture_or_default(maps.metallic_roughness);
+        let occlusion_texture = texture_or_default(maps.occlusion);
+        let normal_texture = maps.normal.unwrap_or_else(|| resources.flat_normal_map(device, queue));
+
+        for texture in [
+            &diffuse_texture,
+            &specular_texture,
+            &emissive_texture,
+            &metallic_roughness_texture,
+            &occlusion_texture,
+        ] {
+            resources.textures.wait(texture, device, queue)?;
+        }
+        resources.normal_maps.wait(&normal_texture, device, queue)?;
+        // They're all there after the waits above, if only as their fallbacks
+        let texture = |handle| resources.textures.get(handle).unwrap();
+        let diffuse = texture(&diffuse_texture);
+        let normal = &resources.normal_maps.get(&normal_texture).unwrap().0;
+
+        let properties_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{name} Material Buffer")),
+            contents: bytemuck::bytes_of(&MaterialUniform::from(properties)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{name} Material Bind Group")),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse.view),
+                },
+                // Every map gets sampled the same way the diffuse texture does
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&texture(&specular_texture).view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&texture(&emissive_texture).view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: properties_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&texture(&metallic_roughness_texture).view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&texture(&occlusion_texture).view),
+                },
+            ],
+        });
+
+        Ok(Self {
+            name: name.to_string(),
+            diffuse_texture,
+            normal_texture,
+            specular_texture,
+            emissive_texture,
+            metallic_roughness_texture,
+            occlusion_texture,
+            properties,
+            properties_buffer,
+            bind_group,
+        })
+    }
+
+    /// Plain white, for Meshes that don't say what they're made of.
+    pub fn default(
+        resources: &mut ResourceManager,
+        layout: &wgpu::BindGroupLayout,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self> {
+        Self::new(
+            "default",
+            MaterialMaps::default(),
+            MaterialProperties::default(),
+            resources,
+            layout,
+            device,
+            queue,
+        )
+    }
+
+    /// Changes the properties without having to make the Material again.
+    pub fn set_properties(&mut self, properties: MaterialProperties, queue: &wgpu::Queue) {
+        self.properties = properties;
+        queue.write_buffer(&self.properties_buffer, 0, bytemuck::bytes_of(&MaterialUniform::from(properties)));
+    }
+
+    /// The BindGroupLayout every Material's BindGroup has. `shader.wgsl` has it as group 0.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material bind group layout"),
+            entries: &[
+                // Diffuse
+                texture(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Normal, specular and emissive
+                texture(2),
+                texture(3),
+                texture(4),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Metallic roughness and occlusion
+                texture(6),
+                texture(7),
+            ],
+        })
+    }
+}
+
 /// A Mesh. A collection of vertices and indices.
 pub struct Mesh {
     /// Internal name of the Mesh.
@@ -108,6 +472,9 @@ pub struct Mesh {
     ///
     /// This is specifically used to index that list. The `materials` of the Model.
     pub material: usize,
+    /// The SkinVertices, for Meshes that move with the Model's Skeleton.
+    /// Those get drawn with `DrawSkinnedModel` instead.
+    pub skin_buffer: Option<wgpu::Buffer>,
 }
 
 pub trait DrawModel<'a> {
@@ -140,7 +507,7 @@ pub trait DrawModel<'a> {
     );
 
     /// Uses a Model and draws the amount of specified instances.
-    /// Uses `draw_mesh_instanced` in a loop.
+    /// Uses `draw_mesh_instanced` in a loop. Skinned Meshes are left out.
     fn draw_model_instanced(
         &mut self,
         model: &'a Model,
@@ -196,7 +563,7 @@ where
         camera_bind_group: &'b wgpu::BindGroup,
         light_bind_group: &'a wgpu::BindGroup,
     ) {
-        for mesh in &model.meshes {
+        for mesh in model.meshes.iter().filter(|mesh| mesh.skin_buffer.is_none()) {
             let material = &model.materials[mesh.material];
             self.draw_mesh_instanced(
                 mesh,
@@ -297,4 +664,194 @@ impl<'a, 'b> DrawLight<'b> for wgpu::RenderPass<'a>
             self.draw_light_mesh_instanced(mesh, instances.clone(), camera_bind_group, light_bind_group);
         }
     }
-}
\ No newline at end of file
+}
+
+/// DrawShadow only needs the shape of things.
+/// Materials don't matter when rendering into a ShadowMap.
+pub trait DrawShadow<'a> {
+    /// Draws the amount of instances specified of a Mesh into a shadow cascade.
+    fn draw_shadow_mesh_instanced(
+        &mut self,
+        mesh: &'a Mesh,
+        instances: Range<u32>,
+        cascade_bind_group: &'a wgpu::BindGroup,
+    );
+
+    /// Uses a Model and draws the amount of specified instances.
+    /// Uses `draw_shadow_mesh_instanced` in a loop.
+    fn draw_shadow_model_instanced(
+        &mut self,
+        model: &'a Model,
+        instances: Range<u32>,
+        cascade_bind_group: &'a wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawShadow<'b> for wgpu::RenderPass<'a>
+    where
+        'b: 'a,
+{
+    fn draw_shadow_mesh_instanced(
+        &mut self,
+        mesh: &'b Mesh,
+        instances: Range<u32>,
+        cascade_bind_group: &'b wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, cascade_bind_group, &[]);
+        self.draw_indexed(0..mesh.num_elements, 0, instances);
+    }
+
+    fn draw_shadow_model_instanced(
+        &mut self,
+        model: &'b Model,
+        instances: Range<u32>,
+        cascade_bind_group: &'b wgpu::BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            self.draw_shadow_mesh_instanced(mesh, instances.clone(), cascade_bind_group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], tex_coords: [f32; 2]) -> ModelVertex {
+        ModelVertex {
+            position,
+            tex_coords,
+            normal: [0.0, 0.0, 1.0],
+            tangent: [0.0; 3],
+            bitangent: [0.0; 3],
+        }
+    }
+
+    /// A square facing +z, with the texture the right way up on it.
+    fn quad() -> Vec<ModelVertex> {
+        vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+            vertex([1.0, 1.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+        ]
+    }
+
+    const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    fn close(a: [f32; 3], b: glam::Vec3) -> bool {
+        glam::Vec3::from(a).abs_diff_eq(b, 1e-5)
+    }
+
+    /// Parses an MTL file with a single material in it.
+    fn mtl(source: &str) -> MaterialProperties {
+        let (materials, _) = tobj::load_mtl_buf(&mut source.as_bytes()).unwrap();
+        MaterialProperties::from_mtl(&materials[0])
+    }
+
+    #[test]
+    fn tangents_follow_the_texture() {
+        let mut vertices = quad();
+        ModelVertex::compute_tangents(&mut vertices, &QUAD_INDICES);
+
+        for vertex in &vertices {
+            assert!(close(vertex.tangent, glam::Vec3::X), "{:?}", vertex.tangent);
+            // Up the image, the way normal maps have it
+            assert!(close(vertex.bitangent, glam::Vec3::Y), "{:?}", vertex.bitangent);
+        }
+    }
+
+    #[test]
+    fn mirrored_textures_flip_the_tangent() {
+        let mut vertices = quad();
+        for vertex in &mut vertices {
+            vertex.tex_coords[0] = 1.0 - vertex.tex_coords[0];
+        }
+        ModelVertex::compute_tangents(&mut vertices, &QUAD_INDICES);
+
+        for vertex in &vertices {
+            assert!(close(vertex.tangent, glam::Vec3::NEG_X), "{:?}", vertex.tangent);
+            assert!(close(vertex.bitangent, glam::Vec3::Y), "{:?}", vertex.bitangent);
+        }
+    }
+
+    #[test]
+    fn triangles_without_texture_coordinates_are_skipped() {
+        let mut vertices = quad();
+        // The second triangle has all of its texture coordinates in one spot
+        vertices.push(vertex([0.0, 0.0, 0.0], [0.5, 0.5]));
+        vertices.push(vertex([1.0, 1.0, 0.0], [0.5, 0.5]));
+        vertices.push(vertex([0.0, 1.0, 0.0], [0.5, 0.5]));
+        ModelVertex::compute_tangents(&mut vertices, &[0, 1, 2, 4, 5, 6]);
+
+        assert!(close(vertices[0].tangent, glam::Vec3::X));
+        for vertex in &vertices[4..] {
+            assert_eq!(vertex.tangent, [0.0; 3]);
+            assert_eq!(vertex.bitangent, [0.0; 3]);
+        }
+        // Not part of any triangle
+        assert_eq!(vertices[3].tangent, [0.0; 3]);
+    }
+
+    #[test]
+    fn shared_vertices_average_their_triangles() {
+        // Two triangles folded along the y axis, one facing +z and one facing +x
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+            vertex([0.0, 0.0, -1.0], [1.0, 1.0]),
+        ];
+        ModelVertex::compute_tangents(&mut vertices, &[0, 1, 2, 0, 3, 2]);
+
+        let between = (glam::Vec3::X + glam::Vec3::NEG_Z).normalize();
+        assert!(close(vertices[0].tangent, between), "{:?}", vertices[0].tangent);
+        assert!(close(vertices[2].tangent, between), "{:?}", vertices[2].tangent);
+        assert!(close(vertices[1].tangent, glam::Vec3::X));
+        assert!(close(vertices[3].tangent, glam::Vec3::NEG_Z));
+        for vertex in &vertices {
+            assert!(close(vertex.bitangent, glam::Vec3::Y));
+        }
+    }
+
+    #[test]
+    fn mtl_properties_are_read() {
+        let properties = mtl(
+            "newmtl lamp\nKd 0.5 0.25 1.0\nKs 0.1 0.2 0.3\nNs 64\nd 0.5\nKe 1.0 0.5 0.0\n",
+        );
+
+        assert_eq!(properties.diffuse, glam::vec3(0.5, 0.25, 1.0));
+        assert_eq!(properties.specular, glam::vec3(0.1, 0.2, 0.3));
+        assert_eq!(properties.shininess, 64.0);
+        assert_eq!(properties.dissolve, 0.5);
+        assert_eq!(properties.emissive, glam::vec3(1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn missing_mtl_properties_get_the_defaults() {
+        let default = MaterialProperties::default();
+        let properties = mtl("newmtl plain\n");
+
+        assert_eq!(properties.diffuse, default.diffuse);
+        assert_eq!(properties.specular, default.specular);
+        assert_eq!(properties.shininess, default.shininess);
+        assert_eq!(properties.dissolve, default.dissolve);
+        assert_eq!(properties.emissive, default.emissive);
+    }
+
+    #[test]
+    fn malformed_emission_is_ignored() {
+        let default = MaterialProperties::default();
+        for ke in ["Ke 1.0 0.5", "Ke 1.0 bright 0.0", "Ke"] {
+            let properties = mtl(&format!("newmtl broken\nKd 0.5 0.5 0.5\n{ke}\n"));
+            assert_eq!(properties.emissive, default.emissive, "{ke}");
+            // The rest still gets read
+            assert_eq!(properties.diffuse, glam::Vec3::splat(0.5));
+        }
+
+        // Anything past the third value is left out
+        assert_eq!(mtl("newmtl extra\nKe 1 2 3 4\n").emissive, glam::vec3(1.0, 2.0, 3.0));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::engine::resource::texture::Texture;
+use crate::entity::camera::Projection;
+
+/// How many cascades the view frustum is split into. Has to match the shaders.
+pub const CASCADES: usize = 3;
+const SHADOW_MAP_SIZE: u32 = 2048;
+/// Nothing further away from the camera than this gets shadows.
+const SHADOW_DISTANCE: f32 = 100.0;
+/// How the splits are spaced. 0.0 is evenly, 1.0 is logarithmic (more detail up close).
+const SPLIT_LAMBDA: f32 = 0.6;
+/// Extra room towards the sun, so things between the sun and the camera still cast shadows.
+const CASTER_DISTANCE: f32 = 64.0;
+
+/// The shadow cascades as the main shader sees them.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    view_proj: [glam::Mat4; CASCADES],
+    /// How far from the camera each cascade reaches.
+    splits: glam::Vec4,
+    /// How big a single shadow map texel is in the world, for each cascade.
+    texel_sizes: glam::Vec4,
+}
+
+impl ShadowUniform {
+    /// Every cascade, each one fitted around its slice of the camera's view, see `ShadowMap::update`.
+    fn fit(view: glam::Mat4, projection: &Projection, direction: glam::Vec3) -> Self {
+        let z_near = projection.z_near();
+        let z_far = projection.z_far().min(SHADOW_DISTANCE);
+        let inverse_projection = projection.calc_matrix().inverse();
+        let inverse_view = view.inverse();
+
+        // The corners of the view frustum at some distance from the camera, in the world.
+        let corners_at = |depth: f32| {
+            [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)].map(|(x, y)| {
+                let point = inverse_projection.project_point3(glam::Vec3::new(x, y, 0.0));
+                inverse_view.transform_point3(point / -point.z * depth)
+            })
+        };
+
+        let direction = direction.normalize();
+        let up = if direction.y.abs() > 0.99 { glam::Vec3::Z } else { glam::Vec3::Y };
+        let light_view = glam::Mat4::look_to_rh(glam::Vec3::ZERO, direction, up);
+
+        let mut uniform: Self = bytemuck::Zeroable::zeroed();
+        let mut near = z_near;
+        for cascade in 0..CASCADES {
+            let t = (cascade + 1) as f32 / CASCADES as f32;
+            let logarithmic = z_near * (z_far / z_near).powf(t);
+            let linear = z_near + (z_far - z_near) * t;
+            let far = SPLIT_LAMBDA * logarithmic + (1.0 - SPLIT_LAMBDA) * linear;
+
+            let corners = [corners_at(near), corners_at(far)].concat();
+            let center = corners.iter().sum::<glam::Vec3>() / corners.len() as f32;
+            // A sphere around the slice keeps the cascade the same size when the camera turns
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            // Only move the cascade a whole texel at a time, otherwise shadow edges shimmer
+            let texel_size = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+            let mut snapped = light_view.transform_point3(center);
+            snapped.x = (snapped.x / texel_size).floor() * texel_size;
+            snapped.y = (snapped.y / texel_size).floor() * texel_size;
+            let center = light_view.inverse().transform_point3(snapped);
+
+            let eye = center - direction * (radius + CASTER_DISTANCE);
+            let cascade_view = glam::Mat4::look_to_rh(eye, direction, up);
+            let cascade_projection =
+                glam::Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, 2.0 * radius + CASTER_DISTANCE);
+
+            uniform.view_proj[cascade] = cascade_projection * cascade_view;
+            uniform.splits[cascade] = far;
+            uniform.texel_sizes[cascade] = texel_size;
+            near = far;
+        }
+
+        uniform
+    }
+}
+
+/// Shadows for a directional light, using cascaded shadow maps.
+///
+/// The view frustum is cut into a few slices along its depth, and each slice gets its own
+/// shadow map (a layer in a depth texture array). Close by slices are smaller, so they
+/// get more detailed shadows than the ones far away.
+pub struct ShadowMap {
+    pub texture: Texture,
+    /// A view of every single layer, to render each cascade into.
+    cascade_views: Vec<wgpu::TextureView>,
+
+    uniform: ShadowUniform,
+    buffer: wgpu::Buffer,
+
+    /// The view projection of every cascade on its own, for rendering the shadow maps.
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+    cascade_layout: wgpu::BindGroupLayout,
+
+    layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture = Texture::create_depth_texture_with_size(
+            device,
+            wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: CASCADES as u32,
+            },
+            "Shadow map",
+        );
+
+        let cascade_views = (0..CASCADES as u32)
+            .map(|layer| {
+                texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow cascade"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let uniform = ShadowUniform {
+            view_proj: [glam::Mat4::IDENTITY; CASCADES],
+            splits: glam::Vec4::ZERO,
+            texel_sizes: glam::Vec4::ZERO,
+        };
+        let buffer = Self::create_uniform_buffer("Shadows", std::mem::size_of::<ShadowUniform>(), device);
+
+        let cascade_layout = Self::cascade_bind_group_layout(device);
+        let cascade_buffers: Vec<wgpu::Buffer> = (0..CASCADES)
+            .map(|_| Self::create_uniform_buffer("Shadow cascade", std::mem::size_of::<glam::Mat4>(), device))
+            .collect();
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Shadow cascade bind group"),
+                    layout: &cascade_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                })
+            })
+            .collect();
+
+        let layout = Self::bind_group_layout(device);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadows bind group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+
+        Self {
+            texture,
+            cascade_views,
+            uniform,
+            buffer,
+            cascade_buffers,
+            cascade_bind_groups,
+            cascade_layout,
+            layout,
+            bind_group,
+        }
+    }
+
+    /// The layout the `bind_group` uses. Pipelines that want to receive shadows need it.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Fits every cascade around its slice of the camera's view. Call once a frame
+    /// after the camera moved.
+    ///
+    /// * `direction` - Which way the light shines.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        view: glam::Mat4,
+        projection: &Projection,
+        direction: glam::Vec3,
+    ) {
+        self.uniform = ShadowUniform::fit(view, projection, direction);
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+        for (buffer, view_proj) in self.cascade_buffers.iter().zip(self.uniform.view_proj) {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[view_proj]));
+        }
+    }
+
+    /// Starts a render pass that draws into a single cascade.
+    /// Draw things with the pipeline from `create_pipeline` and `cascade_bind_group` at group 0.
+    pub fn begin_cascade_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        cascade: usize,
+    ) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.cascade_views[cascade],
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        })
+    }
+
+    pub fn cascade_bind_group(&self, cascade: usize) -> &wgpu::BindGroup {
+        &self.cascade_bind_groups[cascade]
+    }
+
+    /// A depth only pipeline to render things into the cascades with.
+    pub fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        vertex_layouts: &[wgpu::VertexBufferLayout],
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&self.cascade_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Render Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: vertex_layouts,
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                // Pushes the depth back a bit so surfaces don't shadow themselves
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    fn create_uniform_buffer(label: &str, size: usize, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn cascade_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow cascade bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+
+    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadows bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUN: glam::Vec3 = glam::Vec3::new(-0.4, -1.0, 0.3);
+
+    fn projection(z_far: f32) -> Projection {
+        Projection::new(1600, 900, 70f32.to_radians(), 0.1, z_far)
+    }
+
+    fn view(eye: glam::Vec3, yaw: f32) -> glam::Mat4 {
+        let (sin, cos) = yaw.sin_cos();
+        glam::Mat4::look_to_rh(eye, glam::Vec3::new(cos, -0.3, sin), glam::Vec3::Y)
+    }
+
+    #[test]
+    fn splits_go_up_to_the_shadow_distance() {
+        for z_far in [40.0, SHADOW_DISTANCE, 1000.0] {
+            let uniform = ShadowUniform::fit(view(glam::Vec3::ZERO, 0.0), &projection(z_far), SUN);
+            let splits = &uniform.splits.to_array()[..CASCADES];
+
+            assert!(splits[0] > 0.1, "{splits:?}");
+            assert!(splits.windows(2).all(|pair| pair[0] < pair[1]), "{splits:?}");
+            let last = splits[CASCADES - 1];
+            assert!((last - z_far.min(SHADOW_DISTANCE)).abs() < 1e-3, "{splits:?} for {z_far}");
+        }
+    }
+
+    #[test]
+    fn closer_cascades_are_sharper() {
+        let uniform = ShadowUniform::fit(view(glam::Vec3::ZERO, 0.0), &projection(1000.0), SUN);
+        let texel_sizes = &uniform.texel_sizes.to_array()[..CASCADES];
+        assert!(texel_sizes.windows(2).all(|pair| pair[0] < pair[1]), "{texel_sizes:?}");
+    }
+
+    #[test]
+    fn cascades_move_in_whole_texels() {
+        let projection = projection(1000.0);
+        let first = ShadowUniform::fit(view(glam::Vec3::ZERO, 0.0), &projection, SUN);
+
+        for (eye, yaw) in [
+            (glam::Vec3::new(0.3, 0.0, 0.0), 0.0),
+            (glam::Vec3::new(12.71, 3.2, -7.05), 0.0),
+            (glam::Vec3::new(-40.17, 10.0, 25.5), 1.3),
+        ] {
+            let moved = ShadowUniform::fit(view(eye, yaw), &projection, SUN);
+            for cascade in 0..CASCADES {
+                // Turning and moving doesn't change how big the cascades are
+                assert_eq!(moved.texel_sizes[cascade], first.texel_sizes[cascade]);
+
+                // So a point that stays put lands the same distance from a texel edge
+                let texels = |uniform: &ShadowUniform| {
+                    let ndc = uniform.view_proj[cascade].project_point3(glam::Vec3::new(5.0, 1.0, -3.0));
+                    ndc.truncate() * SHADOW_MAP_SIZE as f32 / 2.0
+                };
+                let shift = texels(&moved) - texels(&first);
+                assert!(shift.abs_diff_eq(shift.round(), 0.02), "cascade {cascade} moved {shift} texels");
+            }
+        }
+    }
+
+    #[test]
+    fn cascades_cover_their_slice() {
+        let eye = glam::Vec3::new(3.0, 20.0, -4.0);
+        let view = view(eye, 0.7);
+        let uniform = ShadowUniform::fit(view, &projection(1000.0), SUN);
+
+        // The point straight ahead at each split shows up inside its own cascade
+        let forward = view.inverse().transform_vector3(glam::Vec3::NEG_Z);
+        for cascade in 0..CASCADES {
+            let point = eye + forward * uniform.splits[cascade] * 0.99;
+            let ndc = uniform.view_proj[cascade].project_point3(point);
+            assert!(ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0, "cascade {cascade}: {ndc}");
+            assert!((0.0..=1.0).contains(&ndc.z), "cascade {cascade}: {ndc}");
+        }
+    }
+}
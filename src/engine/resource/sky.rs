@@ -0,0 +1,256 @@
+use crate::engine::resource::texture::Texture;
+use crate::engine::time::{smoothstep, WorldClock};
+use crate::engine::util::catch_validation_error;
+use crate::entity::camera::Projection;
+
+const DAY_ZENITH: glam::Vec3 = glam::Vec3::new(0.25, 0.45, 0.85);
+const DAY_HORIZON: glam::Vec3 = glam::Vec3::new(0.65, 0.78, 0.95);
+const NIGHT_ZENITH: glam::Vec3 = glam::Vec3::new(0.005, 0.008, 0.03);
+const NIGHT_HORIZON: glam::Vec3 = glam::Vec3::new(0.03, 0.04, 0.08);
+const SUNSET_HORIZON: glam::Vec3 = glam::Vec3::new(0.95, 0.5, 0.25);
+
+const DAY_AMBIENT: glam::Vec3 = glam::Vec3::new(0.22, 0.24, 0.28);
+const NIGHT_AMBIENT: glam::Vec3 = glam::Vec3::new(0.02, 0.025, 0.05);
+
+const NOON_SUN: glam::Vec3 = glam::Vec3::new(1.0, 0.95, 0.85);
+const LOW_SUN: glam::Vec3 = glam::Vec3::new(1.0, 0.55, 0.3);
+const MOON: glam::Vec3 = glam::Vec3::new(0.55, 0.65, 0.9);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    /// Turns a point on the screen back into a direction. Has no translation,
+    /// the sky is infinitely far away so it doesn't move with the camera.
+    inverse_view_proj: glam::Mat4,
+    /// Towards the sun, and how much daylight there is.
+    sun: glam::Vec4,
+    /// Towards the moon, and how visible the stars are.
+    moon: glam::Vec4,
+    /// The colour straight up, and the time of day.
+    zenith: glam::Vec4,
+    /// The colour at the horizon, and how much of a sunrise/sunset there is.
+    horizon: glam::Vec4,
+}
+
+/// A procedural sky that follows the WorldClock. Drawn first, behind everything else.
+///
+/// Also decides what colour the sun, moon and ambient light are,
+/// so the lighting matches the sky.
+pub struct Sky {
+    uniform: SkyUniform,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Sky {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat, shader: &wgpu::ShaderModule) -> Self {
+        let uniform = SkyUniform {
+            inverse_view_proj: glam::Mat4::IDENTITY,
+            sun: glam::Vec4::ZERO,
+            moon: glam::Vec4::ZERO,
+            zenith: glam::Vec4::ZERO,
+            horizon: glam::Vec4::ZERO,
+        };
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sky"),
+            size: std::mem::size_of::<SkyUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, color_format, shader);
+
+        Self {
+            uniform,
+            buffer,
+            bind_group,
+            pipeline_layout,
+            color_format,
+            pipeline,
+        }
+    }
+
+    /// Rebuilds the pipeline with another version of the shader.
+    /// If that doesn't work the old pipeline stays.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) -> Result<(), wgpu::Error> {
+        self.pipeline = catch_validation_error(device, || {
+            Self::create_pipeline(device, &self.pipeline_layout, self.color_format, shader)
+        })?;
+        Ok(())
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Render Pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Drawn first in the same pass as everything else, but never in front of anything
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Call once a frame after the camera moved.
+    pub fn update(&mut self, queue: &wgpu::Queue, clock: &WorldClock, view: glam::Mat4, projection: &Projection) {
+        let rotation = glam::Mat4::from_mat3(glam::Mat3::from_mat4(view));
+        let stars = 1.0 - smoothstep(-0.15, 0.05, clock.sun_position().y);
+
+        self.uniform = SkyUniform {
+            inverse_view_proj: (projection.calc_matrix() * rotation).inverse(),
+            sun: clock.sun_position().extend(clock.daylight()),
+            moon: clock.moon_position().extend(stars),
+            zenith: Self::zenith_color(clock).extend(clock.time_of_day()),
+            horizon: Self::horizon_color(clock).extend(Self::sunset(clock)),
+        };
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    /// Draws the sky over the whole screen. Do this first in the pass.
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// How much of a sunrise or sunset is going on, strongest with the sun right at the horizon.
+    fn sunset(clock: &WorldClock) -> f32 {
+        1.0 - smoothstep(0.0, 0.35, clock.sun_position().y.abs())
+    }
+
+    /// The colour of the sky straight up.
+    pub fn zenith_color(clock: &WorldClock) -> glam::Vec3 {
+        NIGHT_ZENITH.lerp(DAY_ZENITH, clock.daylight())
+    }
+
+    /// The colour of the sky at the horizon. Far away things fade into this.
+    pub fn horizon_color(clock: &WorldClock) -> glam::Vec3 {
+        NIGHT_HORIZON
+            .lerp(DAY_HORIZON, clock.daylight())
+            .lerp(SUNSET_HORIZON, Self::sunset(clock) * 0.6)
+    }
+
+    /// The light that's everywhere, brighter during the day.
+    pub fn ambient_color(clock: &WorldClock) -> glam::Vec3 {
+        NIGHT_AMBIENT.lerp(DAY_AMBIENT, clock.daylight())
+    }
+
+    /// The colour and intensity of sunlight. Goes orange when the sun is low, and out when it's down.
+    pub fn sun_light(clock: &WorldClock) -> (glam::Vec3, f32) {
+        let height = clock.sun_position().y;
+        let color = LOW_SUN.lerp(NOON_SUN, smoothstep(0.0, 0.4, height));
+        (color, 0.9 * smoothstep(-0.05, 0.1, height))
+    }
+
+    /// The colour and intensity of moonlight. Much weaker than the sun.
+    pub fn moon_light(clock: &WorldClock) -> (glam::Vec3, f32) {
+        (MOON, 0.12 * smoothstep(-0.05, 0.1, clock.moon_position().y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time_of_day: f32) -> WorldClock {
+        WorldClock::new(100.0, 1.0, time_of_day)
+    }
+
+    #[test]
+    fn sky_goes_from_night_to_day() {
+        assert_eq!(Sky::zenith_color(&at(0.0)), NIGHT_ZENITH);
+        assert_eq!(Sky::zenith_color(&at(0.5)), DAY_ZENITH);
+        assert_eq!(Sky::horizon_color(&at(0.0)), NIGHT_HORIZON);
+        assert_eq!(Sky::horizon_color(&at(0.5)), DAY_HORIZON);
+        assert_eq!(Sky::ambient_color(&at(0.0)), NIGHT_AMBIENT);
+        assert_eq!(Sky::ambient_color(&at(0.5)), DAY_AMBIENT);
+    }
+
+    #[test]
+    fn horizon_glows_at_sunset() {
+        assert_eq!(Sky::sunset(&at(0.5)), 0.0);
+        assert_eq!(Sky::sunset(&at(0.75)), 1.0);
+
+        // Day and night horizons are both bluer than they are red, sunsets aren't
+        let glow = Sky::horizon_color(&at(0.75));
+        for clear in [Sky::horizon_color(&at(0.0)), Sky::horizon_color(&at(0.5))] {
+            assert!(clear.z > clear.x, "{clear}");
+        }
+        assert!(glow.x > glow.z, "{glow}");
+    }
+
+    #[test]
+    fn sun_and_moon_take_turns() {
+        let (noon_color, noon) = Sky::sun_light(&at(0.5));
+        assert_eq!(noon_color, NOON_SUN);
+        assert_eq!(noon, 0.9);
+        assert_eq!(Sky::moon_light(&at(0.5)).1, 0.0);
+
+        assert_eq!(Sky::sun_light(&at(0.0)).1, 0.0);
+        assert_eq!(Sky::moon_light(&at(0.0)), (MOON, 0.12));
+
+        // Low and orange just after sunrise
+        let (color, intensity) = Sky::sun_light(&at(0.26));
+        assert!(intensity > 0.0 && intensity < 0.9);
+        assert!(color.z < NOON_SUN.z);
+    }
+}
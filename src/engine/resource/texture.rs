@@ -1,5 +1,7 @@
 use image::GenericImageView;
 
+use crate::engine::error::{Error, Result};
+
 pub struct Texture {
     label: String,
     pub texture: wgpu::Texture,
@@ -10,6 +12,11 @@ pub struct Texture {
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+    /// Where the Texture came from, or what it's for.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
@@ -20,8 +27,18 @@ impl Texture {
             depth_or_array_layers: 1,
         };
 
+        Self::create_depth_texture_with_size(device, size, "depth_texture")
+    }
+
+    /// A depth texture of any size, with a comparison sampler for reading it back in shaders.
+    /// Use `depth_or_array_layers` to make an array of them, like for shadow cascades.
+    pub fn create_depth_texture_with_size(
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        label: &str,
+    ) -> Self {
         let desc = wgpu::TextureDescriptor {
-            label: Some("depth_texture"),
+            label: Some(label),
             size,
             mip_level_count: 1,
             sample_count: 1,
@@ -49,7 +66,7 @@ impl Texture {
             }
         );
 
-        Self { texture, view, sampler, label: "depth_texture".to_string() }
+        Self { texture, view, sampler, label: label.to_string() }
     }
 
     pub fn create(
@@ -57,9 +74,61 @@ impl Texture {
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: &str,
-    ) -> anyhow::Result<Self> {
-        let img = image::load_from_memory(bytes)?;
+    ) -> Result<Self> {
+        let img = image::load_from_memory(bytes).map_err(|source| Error::Image {
+            path: label.to_string(),
+            source,
+        })?;
+        Ok(Self::from_image(device, queue, &img, label))
+    }
+
+    /// Magenta and black squares, for standing in for textures that didn't load.
+    pub fn checkerboard(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        const SIZE: u32 = 8;
+        let img = image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+            if (x + y) % 2 == 0 {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        });
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), "missing_texture")
+    }
+
+    /// A single pixel of one color. Samples the same everywhere.
+    pub fn solid(device: &wgpu::Device, queue: &wgpu::Queue, color: [u8; 4], label: &str) -> Self {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(color));
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), label)
+    }
+
+    /// Uploads an image that's already been decoded.
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: &str,
+    ) -> Self {
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    /// Same as `from_image`, for images that aren't colours, like normal maps.
+    /// Those have to be read back exactly as they are, without converting from sRGB.
+    pub fn from_image_linear(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: &str,
+    ) -> Self {
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8Unorm)
+    }
 
+    fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: &str,
+        format: wgpu::TextureFormat,
+    ) -> Self {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
@@ -76,7 +145,7 @@ impl Texture {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                format,
                 // Use the texture in shaders, allow copying
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
@@ -111,51 +180,18 @@ impl Texture {
                 ..Default::default()
             });
 
-        Ok(Self { texture, view, sampler, label: label.to_string() })
+        Self { texture, view, sampler, label: label.to_string() }
     }
+}
 
-    /// Creates a bind group for a texture.
-    pub fn create_bind_group(texture: &Texture, layout: &wgpu::BindGroupLayout, device: &wgpu::Device) -> wgpu::BindGroup {
-        device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                label: Some(format!("{} Bind Group", texture.label).as_str()),
-                layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                    }
-                ],
-            }
-        )
-    }
+/// A Texture that says which way surfaces face, in tangent space.
+/// Its own kind of asset so it loads without sRGB, and falls back to a flat one.
+pub struct NormalMap(pub Texture);
 
-    /// The BindGroupLayout for Textures.
-    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Texture bind group layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-        })
+impl NormalMap {
+    /// Points straight out of the surface everywhere, so the normals stay as they are.
+    pub fn flat(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255]));
+        Self(Texture::from_image_linear(device, queue, &image::DynamicImage::ImageRgba8(img), "flat_normal_map"))
     }
-}
\ No newline at end of file
+}
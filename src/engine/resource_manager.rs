@@ -1,12 +1,12 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use crate::engine::resource::texture::Texture;
-use crate::engine::util::{create_shader_module, load_texture};
+use crate::engine::assets::{Assets, Handle};
+use crate::engine::resource::texture::{NormalMap, Texture};
 
 // A place to store shaders, textures, what have you... Resources.
+// Everything in here gets loaded once and shared, see `Assets`.
 pub struct ResourceManager {
-    pub textures: Arc<Mutex<HashMap<String, Texture>>>,
-    pub shaders: Arc<Mutex<HashMap<String, wgpu::ShaderModule>>>,
+    pub textures: Assets<Texture>,
+    pub normal_maps: Assets<NormalMap>,
+    pub shaders: Assets<wgpu::ShaderModule>,
 
     pub depth_texture: Texture,
 }
@@ -14,31 +14,61 @@ pub struct ResourceManager {
 impl ResourceManager {
     pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
         Self {
-            textures: Arc::new(Mutex::new(HashMap::new())),
-            shaders: Arc::new(Mutex::new(HashMap::new())),
+            textures: Assets::default(),
+            normal_maps: Assets::default(),
+            shaders: Assets::default(),
             depth_texture: Texture::create_depth_texture(device, config),
         }
     }
 
-    #[allow(dead_code)]
-    pub async fn load_texture(
+    /// Finishes whatever got done loading in the background. Call once a frame.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.textures.update(device, queue);
+        self.normal_maps.update(device, queue);
+        self.shaders.update(device, queue);
+    }
+
+    /// Plain white, for whatever doesn't have a texture of its own.
+    pub fn default_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Handle<Texture> {
+        self.textures.add("default_texture", || Texture::solid(device, queue, [255; 4], "default_texture"))
+    }
+
+    /// For whatever doesn't have a normal map of its own.
+    pub fn flat_normal_map(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Handle<NormalMap> {
+        self.normal_maps.add("flat_normal_map", || NormalMap::flat(device, queue))
+    }
+
+    /// Loads the shaders in `paths` again, if they were loaded before.
+    /// Shaders that don't compile anymore get logged and keep their old version.
+    ///
+    /// Gives the shaders that got replaced, so whatever was built out of them can be rebuilt too.
+    pub fn reload_shaders(
         &mut self,
-        file_name: &str,
-        label: &str,
+        paths: &[String],
         device: &wgpu::Device,
-        queue: &wgpu::Queue
-    ) {
-            let texture = load_texture(file_name, device, queue).await.unwrap();
-            self.textures.lock().unwrap().insert(label.to_string(), texture);
+        queue: &wgpu::Queue,
+    ) -> Vec<Handle<wgpu::ShaderModule>> {
+        paths
+            .iter()
+            .filter_map(|path| match self.shaders.reload(path, device, queue)? {
+                Ok(handle) => {
+                    log::info!("Reloaded {path}");
+                    Some(handle)
+                }
+                Err(e) => {
+                    log::error!("{path} doesn't compile, keeping the old version:\n{e}");
+                    None
+                }
+            })
+            .collect()
     }
 
-    pub async fn load_shader(
-        &mut self,
-        file_name: &str,
-        label: &str,
-        device: &wgpu::Device
-    ) {
-        let shader = create_shader_module(device, file_name, label).await;
-        self.shaders.lock().unwrap().insert(label.to_string(), shader);
+    /// Drops every texture and shader nothing uses anymore.
+    pub fn unload_unused(&mut self) {
+        let unloaded =
+            self.textures.unload_unused() + self.normal_maps.unload_unused() + self.shaders.unload_unused();
+        if unloaded > 0 {
+            log::info!("Unloaded {unloaded} unused assets");
+        }
     }
-}
\ No newline at end of file
+}
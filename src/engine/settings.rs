@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::engine::error::{Error, Result};
+use crate::engine::resource::fog::FogMode;
+use crate::voxel::gpu_mesher::MeshingMode;
+
+/// Whatever is in `settings.toml`. Anything missing falls back to its default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub simulation: SimulationSettings,
+    pub world: WorldSettings,
+    pub graphics: GraphicsSettings,
+    pub assets: AssetSettings,
+    pub dev: DevSettings,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SimulationSettings {
+    /// How many simulation ticks we run per second.
+    pub tick_rate: u32,
+    /// The most ticks we'll run in a single frame before we give up catching up.
+    pub max_ticks_per_frame: u32,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60,
+            max_ticks_per_frame: 8,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    /// How many seconds a whole day and night takes.
+    pub day_length: f32,
+    /// How fast time passes. 0.0 freezes the time of day.
+    pub time_scale: f32,
+    /// The time of day the world starts at. 0.0 is midnight, 0.5 is noon.
+    pub start_time: f32,
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            day_length: 1200.0,
+            time_scale: 1.0,
+            start_time: 0.35,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    /// How far we can see. Nothing further away than this gets drawn.
+    pub render_distance: f32,
+    /// How the fog thickens towards the render distance. One of
+    /// "off", "linear", "exponential" or "exponential_squared".
+    pub fog: FogMode,
+    /// Where the fog starts, as a fraction of the render distance.
+    pub fog_start: f32,
+    /// Chunks closer than this are drawn in full detail. Every time the distance doubles
+    /// after that, Chunks are drawn with half as many voxels on each side. 0.0 turns this off.
+    pub lod_distance: f32,
+    /// Where Chunks get meshed. One of "cpu", "gpu" or "validate".
+    /// "validate" meshes on both and logs whenever they don't agree.
+    pub meshing: MeshingMode,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            render_distance: 100.0,
+            fog: FogMode::Linear,
+            fog_start: 0.6,
+            lod_distance: 48.0,
+            meshing: MeshingMode::Cpu,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AssetSettings {
+    /// Loads assets from this folder instead of the ones built into the binary.
+    /// Has to be laid out like `assets/`. Relative to the working directory.
+    pub root: Option<PathBuf>,
+}
+
+/// Things that only help while working on aravoxel itself.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DevSettings {
+    /// Loads assets from the source `assets/` folder instead of the ones built into the binary,
+    /// unless `assets.root` says otherwise, and reloads shaders whenever they're saved.
+    pub hot_reload: bool,
+}
+
+impl Settings {
+    /// Reads `settings.toml` from the working directory.
+    /// Not having one is fine, we just use the defaults.
+    pub fn load() -> Result<Self> {
+        const PATH: &str = "settings.toml";
+        match std::fs::read_to_string(PATH) {
+            Ok(text) => toml::from_str(&text).map_err(|source| Error::Toml {
+                path: PATH.to_string(),
+                source,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(Error::Io {
+                path: PATH.to_string(),
+                source,
+            }),
+        }
+    }
+}
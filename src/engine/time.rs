@@ -0,0 +1,362 @@
+use std::time::Duration;
+
+/// Runs the simulation at a fixed rate, no matter how fast we render.
+///
+/// Frame time gets added to an accumulator, and every full `step` in there
+/// is one tick of simulation. Whatever is left over is the `alpha`,
+/// how far we are between the previous tick and the next, which
+/// is used to interpolate what we draw.
+///
+/// Since every tick is exactly `step` long and counted, running the same
+/// inputs through the same ticks gives the same result. Good for replays.
+#[derive(Debug)]
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+    tick: u64,
+    max_ticks_per_frame: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32, max_ticks_per_frame: u32) -> Self {
+        Self {
+            step: Self::step_for(tick_rate),
+            accumulator: Duration::ZERO,
+            tick: 0,
+            max_ticks_per_frame: max_ticks_per_frame.max(1),
+        }
+    }
+
+    fn step_for(tick_rate: u32) -> Duration {
+        Duration::from_secs(1) / tick_rate.max(1)
+    }
+
+    /// Changes how many ticks we run per second. The tick counter keeps going.
+    pub fn set_tick_rate(&mut self, tick_rate: u32) {
+        self.step = Self::step_for(tick_rate);
+        self.accumulator = self.accumulator.min(self.step);
+    }
+
+    /// How long a single tick is.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// The amount of ticks run so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Adds the time the last frame took.
+    ///
+    /// If we fall too far behind (a breakpoint, dragging the window...)
+    /// we drop the extra time instead of trying to catch up forever.
+    pub fn accumulate(&mut self, frame_time: Duration) {
+        self.accumulator = (self.accumulator + frame_time).min(self.step * self.max_ticks_per_frame);
+    }
+
+    /// Takes a tick's worth of time out of the accumulator, if there is one.
+    /// Returns the number of the tick to run.
+    pub fn next_tick(&mut self) -> Option<u64> {
+        if self.accumulator < self.step {
+            return None;
+        }
+
+        self.accumulator -= self.step;
+        let tick = self.tick;
+        self.tick += 1;
+        Some(tick)
+    }
+
+    /// How far we are between the last tick and the next one, 0.0 to 1.0.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+/// The time of day in the world. Drives the sun, the moon and the sky.
+///
+/// `time_of_day` goes from 0.0 to 1.0 over a whole day.
+/// 0.0 is midnight, 0.25 is sunrise, 0.5 is noon and 0.75 is sunset.
+#[derive(Debug)]
+pub struct WorldClock {
+    time_of_day: f32,
+    day: u64,
+    /// How many seconds a whole day lasts at a time scale of 1.0.
+    day_length: f32,
+    /// How fast time passes. 0.0 stops time, 2.0 runs it twice as fast.
+    time_scale: f32,
+}
+
+impl WorldClock {
+    pub fn new(day_length: f32, time_scale: f32, time_of_day: f32) -> Self {
+        Self {
+            time_of_day: time_of_day.rem_euclid(1.0),
+            day: 0,
+            day_length: day_length.max(f32::EPSILON),
+            time_scale,
+        }
+    }
+
+    /// Moves time forward. Runs once every simulation tick.
+    pub fn advance(&mut self, step: Duration) {
+        let time = self.time_of_day + step.as_secs_f32() * self.time_scale / self.day_length;
+        // Negative time scales run the clock backwards, past midnight into the previous day
+        self.day = self.day.saturating_add_signed(time.floor() as i64);
+        self.time_of_day = time.rem_euclid(1.0);
+    }
+
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    /// Jumps to another time of the same day.
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.0);
+    }
+
+    /// How many days have passed.
+    pub fn day(&self) -> u64 {
+        self.day
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale;
+    }
+
+    /// Points from the world towards the sun.
+    pub fn sun_position(&self) -> glam::Vec3 {
+        // Rises in the east (+X), sets in the west. Tilted a bit so shadows aren't perfectly straight.
+        let angle = (self.time_of_day - 0.25) * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        glam::Vec3::new(cos, sin, 0.3 * sin).normalize()
+    }
+
+    /// Points from the world towards the moon. Always opposite of the sun.
+    pub fn moon_position(&self) -> glam::Vec3 {
+        -self.sun_position()
+    }
+
+    /// How much daylight there is, 0.0 at night to 1.0 during the day.
+    /// Fades smoothly around sunrise and sunset.
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.1, 0.2, self.sun_position().y)
+    }
+}
+
+/// Smoothly goes from 0.0 to 1.0 as `x` goes from `edge0` to `edge1`. Same as in WGSL.
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs every tick there's time for, giving how many there were.
+    fn run_ticks(timestep: &mut FixedTimestep) -> u64 {
+        let mut ticks = 0;
+        while let Some(tick) = timestep.next_tick() {
+            assert_eq!(tick, timestep.tick() - 1);
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn whole_steps_become_ticks() {
+        let mut timestep = FixedTimestep::new(60, 5);
+        let step = timestep.step();
+
+        timestep.accumulate(step * 3);
+        assert_eq!(timestep.next_tick(), Some(0));
+        assert_eq!(timestep.next_tick(), Some(1));
+        assert_eq!(timestep.next_tick(), Some(2));
+        assert_eq!(timestep.next_tick(), None);
+        assert_eq!(timestep.tick(), 3);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn leftover_time_is_alpha() {
+        let mut timestep = FixedTimestep::new(50, 5);
+        let step = timestep.step();
+
+        timestep.accumulate(step / 4);
+        assert_eq!(run_ticks(&mut timestep), 0);
+        assert!((timestep.alpha() - 0.25).abs() < 1e-6);
+
+        // Adds up with what was left over
+        timestep.accumulate(step);
+        assert_eq!(run_ticks(&mut timestep), 1);
+        assert!((timestep.alpha() - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut timestep = FixedTimestep::new(60, 5);
+
+        // A whole second behind only runs as many ticks as a frame may have
+        timestep.accumulate(Duration::from_secs(1));
+        assert_eq!(run_ticks(&mut timestep), 5);
+        assert_eq!(timestep.alpha(), 0.0);
+
+        // At least one tick per frame, even when asked for none
+        let mut timestep = FixedTimestep::new(60, 0);
+        timestep.accumulate(Duration::from_secs(1));
+        assert_eq!(run_ticks(&mut timestep), 1);
+    }
+
+    #[test]
+    fn changing_the_tick_rate() {
+        let mut timestep = FixedTimestep::new(60, 5);
+        timestep.accumulate(Duration::from_millis(40));
+        assert_eq!(run_ticks(&mut timestep), 2);
+
+        timestep.accumulate(timestep.step() * 3);
+        timestep.set_tick_rate(20);
+        assert_eq!(timestep.step(), Duration::from_millis(50));
+        // No more than a tick's worth carries over, and the counter keeps going
+        assert_eq!(timestep.next_tick(), Some(2));
+        assert_eq!(timestep.next_tick(), None);
+
+        // A tick rate of 0 would never tick at all
+        timestep.set_tick_rate(0);
+        assert_eq!(timestep.step(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn same_frames_give_same_ticks() {
+        // Uneven frame times, like a real game would have
+        let frames: Vec<Duration> = (0..200).map(|i| Duration::from_micros(4_000 + (i * 7_919) % 20_000)).collect();
+
+        let run = || {
+            let mut timestep = FixedTimestep::new(60, 5);
+            let mut ticks_per_frame = Vec::new();
+            for &frame in &frames {
+                timestep.accumulate(frame);
+                ticks_per_frame.push(run_ticks(&mut timestep));
+            }
+            (ticks_per_frame, timestep.tick(), timestep.alpha())
+        };
+        assert_eq!(run(), run());
+
+        // Nothing got clamped, so every bit of time turned into ticks or alpha
+        let mut timestep = FixedTimestep::new(60, 5);
+        for &frame in &frames {
+            timestep.accumulate(frame);
+            run_ticks(&mut timestep);
+        }
+        let total: Duration = frames.iter().sum();
+        assert_eq!(timestep.tick(), (total.as_nanos() / timestep.step().as_nanos()) as u64);
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn clock_rolls_over_into_the_next_day() {
+        let mut clock = WorldClock::new(100.0, 1.0, 0.9);
+        clock.advance(Duration::from_secs(5));
+        assert!(close(clock.time_of_day(), 0.95));
+        assert_eq!(clock.day(), 0);
+
+        clock.advance(Duration::from_secs(10));
+        assert!(close(clock.time_of_day(), 0.05));
+        assert_eq!(clock.day(), 1);
+
+        // Several days in one go
+        clock.advance(Duration::from_secs(300));
+        assert!(close(clock.time_of_day(), 0.05));
+        assert_eq!(clock.day(), 4);
+    }
+
+    #[test]
+    fn clock_runs_at_its_time_scale() {
+        let mut clock = WorldClock::new(100.0, 2.0, 0.0);
+        clock.advance(Duration::from_secs(10));
+        assert!(close(clock.time_of_day(), 0.2));
+
+        clock.set_time_scale(0.0);
+        clock.advance(Duration::from_secs(10));
+        assert!(close(clock.time_of_day(), 0.2));
+    }
+
+    #[test]
+    fn clock_runs_backwards_into_the_previous_day() {
+        let mut clock = WorldClock::new(100.0, 1.0, 0.5);
+        clock.advance(Duration::from_secs(100));
+        assert_eq!(clock.day(), 1);
+
+        clock.set_time_scale(-1.0);
+        clock.advance(Duration::from_secs(60));
+        assert!(close(clock.time_of_day(), 0.9));
+        assert_eq!(clock.day(), 0);
+
+        // There's no day before the first one
+        clock.advance(Duration::from_secs(100));
+        assert!(close(clock.time_of_day(), 0.9));
+        assert_eq!(clock.day(), 0);
+    }
+
+    #[test]
+    fn times_of_day_wrap() {
+        let mut clock = WorldClock::new(100.0, 1.0, 1.25);
+        assert!(close(clock.time_of_day(), 0.25));
+        clock.set_time_of_day(-0.25);
+        assert!(close(clock.time_of_day(), 0.75));
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_and_peaks_at_noon() {
+        let at = |time_of_day| WorldClock::new(100.0, 1.0, time_of_day);
+
+        let sunrise = at(0.25).sun_position();
+        assert!(close(sunrise.y, 0.0) && sunrise.x > 0.99);
+        let noon = at(0.5).sun_position();
+        assert!(noon.y > 0.9 && close(noon.x, 0.0));
+        let sunset = at(0.75).sun_position();
+        assert!(close(sunset.y, 0.0) && sunset.x < -0.99);
+        assert!(at(0.0).sun_position().y < -0.9);
+
+        for time_of_day in [0.0, 0.1, 0.3, 0.6, 0.9] {
+            let clock = at(time_of_day);
+            assert!(close(clock.sun_position().length(), 1.0));
+            assert_eq!(clock.moon_position(), -clock.sun_position());
+        }
+    }
+
+    #[test]
+    fn daylight_fades_around_sunrise() {
+        let daylight = |time_of_day| WorldClock::new(100.0, 1.0, time_of_day).daylight();
+        assert_eq!(daylight(0.5), 1.0);
+        assert_eq!(daylight(0.0), 0.0);
+        let dawn = daylight(0.25);
+        assert!(dawn > 0.0 && dawn < 1.0, "{dawn}");
+
+        // Only ever gets brighter on the way to noon
+        let mut last = 0.0;
+        for step in 0..=50 {
+            let light = daylight(0.15 + step as f32 * 0.007);
+            assert!(light >= last);
+            last = light;
+        }
+    }
+
+    #[test]
+    fn smoothstep_eases_between_its_edges() {
+        assert_eq!(smoothstep(0.0, 1.0, -1.0), 0.0);
+        assert_eq!(smoothstep(0.0, 1.0, 0.0), 0.0);
+        assert_eq!(smoothstep(0.0, 1.0, 0.5), 0.5);
+        assert_eq!(smoothstep(0.0, 1.0, 1.0), 1.0);
+        assert_eq!(smoothstep(0.0, 1.0, 2.0), 1.0);
+        assert!(smoothstep(0.0, 1.0, 0.25) < 0.25);
+        assert!(close(smoothstep(-0.1, 0.2, 0.05), 0.5));
+    }
+}
@@ -1,46 +1,46 @@
 use std::io::{BufReader, Cursor};
 
-use crate::engine::resource::model::{Material, Mesh, Model, ModelVertex};
-use crate::engine::resource::texture::Texture;
+use crate::engine::error::{self, Error};
+use crate::engine::resource::model::{Material, MaterialMaps, MaterialProperties, Mesh, Model, ModelVertex};
+use crate::engine::resource_manager::ResourceManager;
+use crate::engine::vfs;
 
 pub trait Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
 }
 
-pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
-    let path = std::path::Path::new(env!("OUT_DIR")).join("assets").join(file_name);
-    let txt = std::fs::read_to_string(path)?;
+/// * `file_name` - Relative to the assets folder, see `vfs`.
+pub async fn load_string(file_name: &str) -> error::Result<String> {
+    let txt = vfs::read_to_string(file_name).map_err(|source| Error::Io {
+        path: file_name.to_string(),
+        source,
+    })?;
 
     Ok(txt)
 }
 
-pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("assets")
-        .join(file_name);
-    println!("{:?}", path);
-    let data = std::fs::read(path)?;
+pub async fn load_binary(file_name: &str) -> error::Result<Vec<u8>> {
+    let data = vfs::read(file_name).map_err(|source| Error::Io {
+        path: file_name.to_string(),
+        source,
+    })?;
 
     Ok(data)
 }
 
-pub async fn load_texture(
-    file_name: &str,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-) -> anyhow::Result<Texture> {
-    let data = load_binary(file_name).await?;
-    Texture::create(device, queue, &data, file_name)
-}
-
 /// For loading .obj files and assembling them into something we can render.
+/// The textures go through the ResourceManager, so models share them.
+///
+/// Materials without a texture, and Meshes without a Material, get `Material::default`.
 pub async fn load_model(
     file_name: &str,
+    resources: &mut ResourceManager,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     layout: &wgpu::BindGroupLayout,
-) -> anyhow::Result<Model> {
-    let obj_text = load_string(format!("models/{file_name}").as_str()).await?;
+) -> error::Result<Model> {
+    let path = format!("models/{file_name}");
+    let obj_text = load_string(&path).await?;
     let obj_cursor = Cursor::new(obj_text);
 
     let mut obj_reader = BufReader::new(obj_cursor);
@@ -53,69 +53,128 @@ pub async fn load_model(
             ..Default::default()
         },
         |p| async move {
-            let mat_text = load_string(format!("models/{}", &p).as_str()).await.unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            match load_string(format!("models/{}", &p).as_str()).await {
+                Ok(mat_text) => tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text))),
+                Err(e) => {
+                    log::error!("{e}");
+                    Err(tobj::LoadError::OpenFileFailed)
+                }
+            }
         },
-    ).await?;
+    ).await.map_err(|source| Error::Model { path: path.clone(), source })?;
 
-    // Get all the materials found in the .obj and make textures out of them
-    let mut materials = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(
-            format!(
-                "models/{}",
-                &m.diffuse_texture.unwrap()).as_str(),
-            device,
-            queue,
-        ).await?;
-        let bind_group = Texture::create_bind_group(&diffuse_texture, layout, device);
-
-        materials.push(Material {
-            name: m.name,
-            diffuse_texture,
-            bind_group,
+    // A missing .mtl only costs us the textures
+    let obj_materials = obj_materials.unwrap_or_else(|e| {
+        log::error!("Couldn't load the materials of {path}: {e}");
+        Vec::new()
+    });
+
+    // Get all the materials found in the .obj and make textures out of them.
+    // They all start loading before we wait for any of them.
+    let obj_materials: Vec<(String, MaterialMaps, MaterialProperties)> = obj_materials
+        .into_iter()
+        .map(|m| {
+            let mut texture = |texture: Option<&String>| {
+                texture.map(|texture| resources.textures.load(&format!("models/{texture}")))
+            };
+            let maps = MaterialMaps {
+                diffuse: texture(m.diffuse_texture.as_ref()),
+                specular: texture(m.specular_texture.as_ref()),
+                // tobj doesn't know about `map_Ke`
+                emissive: texture(m.unknown_param.get("map_Ke")),
+                normal: m
+                    .normal_texture
+                    .as_ref()
+                    .map(|texture| resources.normal_maps.load(&format!("models/{texture}"))),
+                ..Default::default()
+            };
+            let properties = MaterialProperties::from_mtl(&m);
+            (m.name, maps, properties)
         })
+        .collect();
+
+    let mut materials = Vec::new();
+    for (name, maps, properties) in obj_materials {
+        materials.push(Material::new(&name, maps, properties, resources, layout, device, queue)?);
     };
 
-    // Build meshes out of the models found inside of the .obj
-    let meshes = models.into_iter().map(|m| {
-        let vertices = (0..m.mesh.positions.len() / 3)
+    // Build meshes out of the models found inside of the .obj.
+    // Anything that's missing texture coordinates or normals gets them all zeroed.
+    let mut default_material = None;
+    let mut meshes = Vec::new();
+    for m in models {
+        let mut vertices = (0..m.mesh.positions.len() / 3)
             .map(|i| ModelVertex {
                 position: [
                     m.mesh.positions[i * 3],
                     m.mesh.positions[i * 3 + 1],
                     m.mesh.positions[i * 3 + 2],
                 ],
-                tex_coords: [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]],
-                normal: [
-                    m.mesh.normals[i * 3],
-                    m.mesh.normals[i * 3 + 1],
-                    m.mesh.normals[i * 3 + 2],
-                ],
+                tex_coords: m.mesh.texcoords
+                    .get(i * 2..i * 2 + 2)
+                    .map_or([0.0; 2], |uv| [uv[0], 1.0 - uv[1]]),
+                normal: m.mesh.normals
+                    .get(i * 3..i * 3 + 3)
+                    .map_or([0.0; 3], |n| [n[0], n[1], n[2]]),
+                // Filled in below
+                tangent: [0.0; 3],
+                bitangent: [0.0; 3],
             }).collect::<Vec<_>>();
+        ModelVertex::compute_tangents(&mut vertices, &m.mesh.indices);
 
         let vertex_buffer = ModelVertex::create_vertex_buffer(file_name, &vertices, device);
         let index_buffer = ModelVertex::create_index_buffer(file_name, &m.mesh.indices, device);
 
-        Mesh {
+        let material = match m.mesh.material_id {
+            Some(material) if material < materials.len() => material,
+            _ => match default_material {
+                Some(material) => material,
+                None => {
+                    materials.push(Material::default(resources, layout, device, queue)?);
+                    *default_material.insert(materials.len() - 1)
+                }
+            },
+        };
+
+        meshes.push(Mesh {
             name: file_name.to_string(),
             vertex_buffer,
             index_buffer,
             num_elements: m.mesh.indices.len() as u32,
-            material: m.mesh.material_id.unwrap_or(0),
-        }
-    }).collect::<Vec<_>>();
+            material,
+            skin_buffer: None,
+        });
+    }
 
-    Ok(Model { meshes, materials })
+    Ok(Model {
+        meshes,
+        materials,
+        nodes: Vec::new(),
+        root_nodes: Vec::new(),
+        skeleton: None,
+        animations: Vec::new(),
+    })
 }
 
-pub async fn create_shader_module(device: &wgpu::Device, shader_file: &str, label: &str) -> wgpu::ShaderModule {
-    let file = load_string(format!("shaders/{shader_file}").as_str()).await.unwrap();
-    
-    device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some(label),
-        source: wgpu::ShaderSource::Wgsl(file.into())
-    })
+/// Runs `create` with wgpu's validation errors caught, instead of them ending the program.
+/// For things that get rebuilt while running, where a mistake should only be logged.
+pub fn catch_validation_error<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> Result<T, wgpu::Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error),
+        None => Ok(value),
+    }
+}
+
+/// How a RenderPipeline mixes what it draws with what's already there.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites whatever is behind it and writes depth.
+    Opaque,
+    /// Blends over whatever is behind it using alpha. Doesn't write depth and draws back faces,
+    /// so draw these last and back to front.
+    Translucent,
 }
 
 /// Creates a RenderPipeline out of a bunch of parameters.
@@ -125,6 +184,7 @@ pub async fn create_shader_module(device: &wgpu::Device, shader_file: &str, labe
 /// it'll get very mess quickly with copy-pasting them.
 /// 
 /// This aims to make it more... readable.
+#[allow(clippy::too_many_arguments)]
 pub fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu:: PipelineLayout,
@@ -132,25 +192,31 @@ pub fn create_render_pipeline(
     depth_format: Option<wgpu::TextureFormat>,
     vertex_layouts: &[wgpu::VertexBufferLayout],
     shader: &wgpu::ShaderModule,
+    vertex_entry: &str,
+    fragment_entry: &str,
+    blend_mode: BlendMode,
     label: Option<&str>,
 ) -> wgpu::RenderPipeline {
+    let blend = match blend_mode {
+        BlendMode::Opaque => wgpu::BlendState::REPLACE,
+        BlendMode::Translucent => wgpu::BlendState::ALPHA_BLENDING,
+    };
+    let translucent = blend_mode == BlendMode::Translucent;
+
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label,
         layout: Some(layout),
         vertex: wgpu::VertexState {
             module: shader,
-            entry_point: "vs_main",
+            entry_point: vertex_entry,
             buffers: vertex_layouts
         },
         fragment: Some(wgpu::FragmentState {
             module: shader,
-            entry_point: "fs_main",
+            entry_point: fragment_entry,
             targets: &[Some(wgpu::ColorTargetState {
                 format: color_format,
-                blend: Some(wgpu::BlendState {
-                    alpha: wgpu::BlendComponent::REPLACE,
-                    color: wgpu::BlendComponent::REPLACE,
-                }),
+                blend: Some(blend),
                 write_mask: wgpu::ColorWrites::ALL,
             })]
         }),
@@ -158,7 +224,7 @@ pub fn create_render_pipeline(
             topology: wgpu::PrimitiveTopology::TriangleList,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
+            cull_mode: if translucent { None } else { Some(wgpu::Face::Back) },
             // Setting this to another other than FIll requires Features::NON_FILL_POLYGON_MODE
             polygon_mode: wgpu::PolygonMode::Fill,
             // Requires Features::DEPTH_CLIP_CONTROL,
@@ -168,7 +234,7 @@ pub fn create_render_pipeline(
         },
         depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
             format,
-            depth_write_enabled: true,
+            depth_write_enabled: !translucent,
             depth_compare: wgpu::CompareFunction::Less,
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
@@ -180,4 +246,21 @@ pub fn create_render_pipeline(
         },
         multiview: None,
     })
-}
\ No newline at end of file
+}
+
+/// A software device for tests. Panics if there isn't one, rather than letting GPU tests
+/// pass without running.
+///
+/// Installing a software Vulkan driver gives you one, like Mesa's lavapipe
+/// (`mesa-vulkan-drivers` on Debian and Ubuntu).
+#[cfg(test)]
+pub(crate) fn software_device() -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        force_fallback_adapter: true,
+        ..Default::default()
+    }))
+    .expect("No software adapter for the GPU tests, install a software Vulkan driver like lavapipe");
+    pollster::block_on(adapter.request_device(&Default::default(), None))
+        .expect("The software adapter couldn't make a device")
+}
@@ -0,0 +1,182 @@
+//! Where assets get read from. Paths are always relative to the assets folder and use `/`,
+//! like `textures/blocks.png`, whichever of the roots below they come out of.
+//!
+//! By default every asset comes out of an archive that `build.rs` packs and that gets embedded
+//! into the binary, so it can be shipped on its own. A folder can be used instead, which is
+//! what hot reloading needs.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+/// What `build.rs` made out of the `assets/` folder.
+static EMBEDDED: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/assets.pak"));
+
+/// Where assets are read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetRoot {
+    /// The archive inside the binary.
+    Embedded,
+    /// A folder laid out like `assets/`.
+    Directory(PathBuf),
+}
+
+impl AssetRoot {
+    /// The `assets/` folder next to `Cargo.toml`. Only exists where aravoxel was built.
+    pub fn source() -> Self {
+        AssetRoot::Directory(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets"))
+    }
+}
+
+static ROOT: RwLock<AssetRoot> = RwLock::new(AssetRoot::Embedded);
+
+/// Reads every asset from `root` from now on.
+pub fn set_root(root: AssetRoot) {
+    log::info!("Loading assets from {root:?}");
+    *ROOT.write().unwrap() = root;
+}
+
+pub fn root() -> AssetRoot {
+    ROOT.read().unwrap().clone()
+}
+
+pub fn read(path: &str) -> io::Result<Vec<u8>> {
+    match root() {
+        AssetRoot::Embedded => archive()?
+            .get(path)
+            .map(|data| data.to_vec())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file in the embedded assets")),
+        AssetRoot::Directory(dir) => std::fs::read(dir.join(path)),
+    }
+}
+
+pub fn read_to_string(path: &str) -> io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The files in the embedded archive, by path. Only fails if the archive is broken,
+/// which a clean build should fix.
+fn archive() -> io::Result<&'static HashMap<&'static str, &'static [u8]>> {
+    static ARCHIVE: OnceLock<Option<HashMap<&'static str, &'static [u8]>>> = OnceLock::new();
+    ARCHIVE
+        .get_or_init(|| Archive::parse(EMBEDDED))
+        .as_ref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the embedded assets are broken"))
+}
+
+/// Reads the format `build.rs` writes. Everything is little endian:
+///
+/// ```text
+/// "AVPK" | version: u32 | file count: u32
+/// for every file: path length: u32 | path | data length: u32 | data
+/// ```
+struct Archive<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    const MAGIC: &'static [u8; 4] = b"AVPK";
+    const VERSION: u32 = 1;
+
+    fn parse(bytes: &'a [u8]) -> Option<HashMap<&'a str, &'a [u8]>> {
+        let mut archive = Archive { bytes };
+        if archive.take(4)? != Self::MAGIC || archive.u32()? != Self::VERSION {
+            return None;
+        }
+
+        let count = archive.u32()?;
+        let mut files = HashMap::with_capacity(count as usize);
+        for _ in 0..count {
+            let path_len = archive.u32()? as usize;
+            let path = std::str::from_utf8(archive.take(path_len)?).ok()?;
+            let data_len = archive.u32()? as usize;
+            files.insert(path, archive.take(data_len)?);
+        }
+        Some(files)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs files the same way `build.rs` does.
+    fn pack(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = Archive::MAGIC.to_vec();
+        bytes.extend(Archive::VERSION.to_le_bytes());
+        bytes.extend((files.len() as u32).to_le_bytes());
+        for (path, data) in files {
+            bytes.extend((path.len() as u32).to_le_bytes());
+            bytes.extend(path.as_bytes());
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(*data);
+        }
+        bytes
+    }
+
+    const FILES: [(&str, &[u8]); 3] = [
+        ("shaders/shader.wgsl", b"@vertex fn vs_main() {}"),
+        ("textures/empty.png", b""),
+        ("models/cube.obj", &[0, 1, 2, 255]),
+    ];
+
+    #[test]
+    fn round_trip() {
+        let bytes = pack(&FILES);
+        let files = Archive::parse(&bytes).unwrap();
+
+        assert_eq!(files.len(), FILES.len());
+        for (path, data) in FILES {
+            assert_eq!(files[path], data, "{path}");
+        }
+        assert_eq!(Archive::parse(&pack(&[])).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn truncated_archives_are_rejected() {
+        let bytes = pack(&FILES);
+        for len in 0..bytes.len() {
+            assert_eq!(Archive::parse(&bytes[..len]), None, "cut off after {len} bytes");
+        }
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        let mut bad_magic = pack(&FILES);
+        bad_magic[..4].copy_from_slice(b"PK\x03\x04");
+        assert_eq!(Archive::parse(&bad_magic), None);
+
+        let mut newer = pack(&FILES);
+        newer[4..8].copy_from_slice(&(Archive::VERSION + 1).to_le_bytes());
+        assert_eq!(Archive::parse(&newer), None);
+    }
+
+    #[test]
+    fn paths_have_to_be_utf8() {
+        let mut bytes = pack(&[("ab", b"data")]);
+        // The first byte of the path
+        bytes[16] = 0xFF;
+        assert_eq!(Archive::parse(&bytes), None);
+    }
+
+    #[test]
+    fn embedded_archive_matches_the_assets() {
+        let files = archive().unwrap();
+        let shader = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/shaders/shader.wgsl")).unwrap();
+        assert_eq!(files["shaders/shader.wgsl"], shader);
+    }
+}
@@ -1 +1,7 @@
-pub mod camera;
\ No newline at end of file
+pub mod camera;
+pub mod components;
+pub mod ecs;
+#[cfg(feature = "windowing")]
+pub mod player;
+pub mod render;
+pub mod systems;
@@ -1,8 +1,9 @@
 use std::f32::consts::FRAC_PI_2;
 use std::time::Duration;
 use wgpu::util::DeviceExt;
-use winit::event::{ElementState, KeyEvent, MouseScrollDelta};
-use winit::keyboard::{KeyCode, PhysicalKey};
+
+#[cfg(feature = "windowing")]
+use crate::engine::input::InputMap;
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
@@ -12,6 +13,9 @@ pub struct CameraController {
     pub projection: Projection,
     pub camera_uniform: CameraUniform,
 
+    /// Where the camera was last tick, so we can interpolate between ticks.
+    previous_position: glam::Vec3,
+
     amount_left: f32,
     amount_right: f32,
     amount_forward: f32,
@@ -21,14 +25,14 @@ pub struct CameraController {
     rotate_horizontal: f32,
     rotate_vertical: f32,
     scroll: f32,
-    speed: f32,
     sensitivity: f32,
 }
 
 impl CameraController {
+    /// * `render_distance` - How far the camera can see, anything further away is cut off.
     pub fn new(
-        speed: f32,
         sensitivity: f32,
+        render_distance: f32,
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration
     ) -> Self {
@@ -52,12 +56,13 @@ impl CameraController {
             config.height,
             45.0,
             0.1,
-            100.0
+            render_distance
         );
 
         camera_uniform.update_view_proj(&camera, &projection);
 
         Self {
+            previous_position: camera.position,
             camera,
             camera_uniform,
             projection,
@@ -70,72 +75,73 @@ impl CameraController {
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
             scroll: 0.0,
-            speed,
             sensitivity,
         }
     }
 
-    pub fn keyboard_input(&mut self, event: &KeyEvent) {
-        let amount = if event.state == ElementState::Pressed {
-            1.0
-        } else {
-            0.0
-        };
-
-        match event.physical_key {
-            PhysicalKey::Code(KeyCode::KeyW) | PhysicalKey::Code(KeyCode::ArrowUp) => {
-                self.amount_forward = amount;
-            }
-            PhysicalKey::Code(KeyCode::KeyA) | PhysicalKey::Code(KeyCode::ArrowLeft) => {
-                self.amount_left = amount;
-            }
-            PhysicalKey::Code(KeyCode::KeyS) | PhysicalKey::Code(KeyCode::ArrowDown) => {
-                self.amount_backward = amount;
-            }
-            PhysicalKey::Code(KeyCode::KeyD) | PhysicalKey::Code(KeyCode::ArrowRight) => {
-                self.amount_right = amount;
-            }
-            PhysicalKey::Code(KeyCode::Space) => {
-                self.amount_up = amount;
-            }
-            PhysicalKey::Code(KeyCode::ShiftLeft) => {
-                self.amount_down = amount;
-            }
-            _ => (),
-        }
-    }
-
-    pub fn mouse_input(&mut self, mouse_dx: f64, mouse_dy: f64) {
-        self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
+    /// Reads the held movement actions from the active input context. Runs once every simulation tick.
+    #[cfg(feature = "windowing")]
+    pub fn input(&mut self, input: &InputMap) {
+        self.amount_forward = input.value("move_forward");
+        self.amount_backward = input.value("move_backward");
+        self.amount_left = input.value("move_left");
+        self.amount_right = input.value("move_right");
+        self.amount_up = input.value("move_up");
+        self.amount_down = input.value("move_down");
     }
 
-    pub fn scroll_input(&mut self, delta: &MouseScrollDelta) {
-        self.scroll = -match delta {
-            MouseScrollDelta::LineDelta(_, scroll) => scroll * 100.0,
-            MouseScrollDelta::PixelDelta(winit::dpi::PhysicalPosition { y: scroll, .. }) => {
-                *scroll as f32
-            }
+    /// Reads how far the mouse moved and scrolled this frame. Runs once every frame, since
+    /// those only last a frame. Scrolling adds up until the next tick uses it.
+    ///
+    /// Mouse movement only rotates the camera while `look` is held.
+    #[cfg(feature = "windowing")]
+    pub fn frame_input(&mut self, input: &InputMap) {
+        if input.pressed("look") {
+            self.rotate_horizontal = input.value("look_x");
+            self.rotate_vertical = input.value("look_y");
         }
+
+        self.scroll += input.value("zoom");
     }
 
-    pub fn update_camera(&mut self, dt: Duration) {
-        let dt = dt.as_secs_f32();
+    /// Flies the camera around. Runs once every simulation tick.
+    ///
+    /// * `speed` - How far the camera moves in a second.
+    pub fn update_position(&mut self, speed: f32, step: Duration) {
+        let step = step.as_secs_f32();
+        self.previous_position = self.camera.position;
+
         let (yaw_sin, yaw_cos) = self.camera.yaw.sin_cos();
 
         // Moving forward/backward, left/right
         let forward = glam::Vec3::new(yaw_cos, 0.0, yaw_sin).normalize();
         let right = glam::Vec3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        self.camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        self.camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
+        self.camera.position += forward * (self.amount_forward - self.amount_backward) * speed * step;
+        self.camera.position += right * (self.amount_right - self.amount_left) * speed * step;
 
         // "zoom"
         let (pitch_sin, pitch_cos) = self.camera.pitch.sin_cos();
         let scroll = glam::Vec3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
-        self.camera.position += scroll * self.scroll * self.sensitivity * dt;
+        self.camera.position += scroll * self.scroll * self.sensitivity * step;
+        // Used up, so it only counts once no matter how many ticks the frame has
+        self.scroll = 0.0;
 
         // Move up/down
-        self.camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        self.camera.position.y += (self.amount_up - self.amount_down) * speed * step;
+    }
+
+    /// Moves the camera to a position decided by something else, like the Player.
+    /// Runs once every simulation tick in place of `update_position`.
+    pub fn follow(&mut self, position: glam::Vec3) {
+        self.previous_position = self.camera.position;
+        self.camera.position = position;
+        // No zooming away from whatever it follows
+        self.scroll = 0.0;
+    }
+
+    /// Rotates the camera. Runs every frame so looking around stays responsive.
+    pub fn update_rotation(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
 
         // Rotate
         self.camera.yaw += self.rotate_horizontal * self.sensitivity * dt;
@@ -146,11 +152,24 @@ impl CameraController {
         self.rotate_horizontal = 0.0;
 
         // Restrict from going too high
-        if self.camera.pitch < -SAFE_FRAC_PI_2 {
-            self.camera.pitch = -SAFE_FRAC_PI_2
-        } else if self.camera.pitch > SAFE_FRAC_PI_2 {
-            self.camera.pitch = SAFE_FRAC_PI_2
-        }
+        self.camera.pitch = self.camera.pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2);
+    }
+
+    /// Updates the uniform with a position somewhere between the last two ticks.
+    ///
+    /// * `alpha` - How far we are between the previous tick and the current one.
+    pub fn update_view_proj(&mut self, alpha: f32) {
+        let position = self.interpolated_position(alpha);
+        self.camera_uniform.update_view_proj_at(&self.camera, &self.projection, position);
+    }
+
+    /// The view matrix from the same position `update_view_proj` uses.
+    pub fn view_matrix(&self, alpha: f32) -> glam::Mat4 {
+        self.camera.calc_matrix_at(self.interpolated_position(alpha))
+    }
+
+    fn interpolated_position(&self, alpha: f32) -> glam::Vec3 {
+        self.previous_position.lerp(self.camera.position, alpha)
     }
 }
 
@@ -173,15 +192,25 @@ impl Camera {
         }
     }
 
-    pub fn calc_matrix(&self) -> glam::Mat4 {
+    /// Which way we're looking horizontally, in radians.
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    /// The direction the camera is looking in.
+    pub fn forward(&self) -> glam::Vec3 {
         let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
 
-        glam::Mat4::look_to_rh(
-            self.position,
-            glam::Vec3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize(),
-            glam::Vec3::Y,
-        )
+        glam::Vec3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize()
+    }
+
+    pub fn calc_matrix(&self) -> glam::Mat4 {
+        self.calc_matrix_at(self.position)
+    }
+
+    pub fn calc_matrix_at(&self, position: glam::Vec3) -> glam::Mat4 {
+        glam::Mat4::look_to_rh(position, self.forward(), glam::Vec3::Y)
     }
 
     pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
@@ -240,6 +269,14 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    pub fn z_near(&self) -> f32 {
+        self.z_near
+    }
+
+    pub fn z_far(&self) -> f32 {
+        self.z_far
+    }
+
     pub fn calc_matrix(&self) -> glam::Mat4 {
         glam::Mat4::perspective_rh(self.fov_y, self.aspect, self.z_near, self.z_far)
     }
@@ -253,6 +290,12 @@ pub struct CameraUniform {
     view_proj: glam::Mat4,
 }
 
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
@@ -262,7 +305,12 @@ impl CameraUniform {
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera, projection: &Projection) {
-        self.view_pos = camera.position.extend(0.0);
-        self.view_proj = projection.calc_matrix() * camera.calc_matrix();
+        self.update_view_proj_at(camera, projection, camera.position);
+    }
+
+    /// Same as `update_view_proj`, but looking from `position` instead of the camera's own.
+    pub fn update_view_proj_at(&mut self, camera: &Camera, projection: &Projection, position: glam::Vec3) {
+        self.view_pos = position.extend(0.0);
+        self.view_proj = projection.calc_matrix() * camera.calc_matrix_at(position);
     }
 }
@@ -0,0 +1,74 @@
+//! The components most entities are made of. Anything else can be a component too, see `World`.
+
+use crate::engine::resource::light::{Light, LightId};
+use crate::entity::render::ModelId;
+use crate::voxel::collision::Aabb;
+
+/// Where an Entity is. The same Transform skeletons use for their joints.
+pub use crate::engine::resource::animation::Transform;
+
+/// How fast an Entity moves and turns, per second. See `systems::movement`.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Velocity {
+    pub linear: glam::Vec3,
+    /// Turns around this axis, by its length in radians.
+    pub angular: glam::Vec3,
+}
+
+/// A box around the Entity's position that keeps it out of the voxels, and lets
+/// `systems::collisions` tell when entities bump into each other.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Collider {
+    /// Half of how big the box is on each axis. It doesn't turn with the Entity.
+    pub half_extents: glam::Vec3,
+}
+
+impl Collider {
+    pub fn new(half_extents: glam::Vec3) -> Self {
+        Self { half_extents }
+    }
+
+    /// The box, for an Entity at `transform`.
+    pub fn aabb(&self, transform: &Transform) -> Aabb {
+        let half_extents = self.half_extents * transform.scale.abs();
+        Aabb::new(transform.translation - half_extents, transform.translation + half_extents)
+    }
+}
+
+/// Draws a Model wherever the Entity is, see `RenderBatches`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Renderable {
+    pub model: ModelId,
+    /// Multiplies the Model's colours. White leaves them alone.
+    pub tint: glam::Vec4,
+}
+
+impl Renderable {
+    pub fn new(model: ModelId) -> Self {
+        Self {
+            model,
+            tint: glam::Vec4::ONE,
+        }
+    }
+}
+
+/// Makes an Entity shine. The Light follows the Entity around, and turns with it if it has a direction.
+/// See `LightSync`.
+#[derive(Debug, Clone)]
+pub struct LightSource {
+    /// The Light as it'd be at the Entity's Transform with no rotation.
+    pub light: Light,
+    /// Set once the Light's been added to the Scene's Lights.
+    pub(crate) id: Option<LightId>,
+}
+
+impl LightSource {
+    pub fn new(light: Light) -> Self {
+        Self { light, id: None }
+    }
+
+    /// Where the Light ended up in the Scene's Lights, once `LightSync` has run.
+    pub fn id(&self) -> Option<LightId> {
+        self.id
+    }
+}
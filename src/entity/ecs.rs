@@ -0,0 +1,359 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Something in a World. On its own it's just an id, its components are what make it anything.
+///
+/// Stays valid until it's despawned. After that it never refers to anything again,
+/// not even once its spot gets reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+/// Every component of one type, indexed by Entity index.
+struct Components<T> {
+    slots: Vec<Option<T>>,
+}
+
+/// What the World needs from a `Components<T>` without knowing the T.
+trait AnyComponents: Any {
+    fn remove(&mut self, index: usize);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyComponents for Components<T> {
+    fn remove(&mut self, index: usize) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            *slot = None;
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Every Entity in a Scene and their components. Any `'static` type can be a component,
+/// an Entity has at most one of each.
+///
+/// Systems are plain functions (or `Systems`) that query the World for the components they
+/// care about, like `world.query2_mut::<Transform, Velocity>()`.
+#[derive(Default)]
+pub struct World {
+    /// The current generation of every index. Odd while it's alive.
+    generations: Vec<u32>,
+    /// Indices of despawned entities, ready to be reused.
+    free: Vec<u32>,
+    components: HashMap<TypeId, Box<dyn AnyComponents>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => {
+                let generation = &mut self.generations[index as usize];
+                *generation += 1;
+                Entity {
+                    index,
+                    generation: *generation,
+                }
+            }
+            None => {
+                self.generations.push(1);
+                Entity {
+                    index: self.generations.len() as u32 - 1,
+                    generation: 1,
+                }
+            }
+        }
+    }
+
+    /// Removes an Entity along with all of its components. Gives false if it was gone already.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        for components in self.components.values_mut() {
+            components.remove(entity.index as usize);
+        }
+        self.generations[entity.index as usize] += 1;
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.generations.get(entity.index as usize) == Some(&entity.generation)
+    }
+
+    /// The amount of entities that are alive.
+    pub fn len(&self) -> usize {
+        self.generations.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gives an Entity a component, or replaces the one it had. Gives back the old one, if any.
+    /// Despawned entities don't get anything, their component is dropped.
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        let slots = &mut self.components_mut::<T>().slots;
+        let index = entity.index as usize;
+        if slots.len() <= index {
+            slots.resize_with(index + 1, || None);
+        }
+        slots[index].replace(component)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.components_mut::<T>().slots.get_mut(entity.index as usize)?.take()
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.components::<T>()?.slots.get(entity.index as usize)?.as_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.components_mut::<T>().slots.get_mut(entity.index as usize)?.as_mut()
+    }
+
+    /// Every Entity with a T.
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        let slots = self.components::<T>().map_or(&[][..], |components| &components.slots[..]);
+        slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((self.entity(index), slot.as_ref()?)))
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        let generations = &self.generations;
+        let slots = match self.components.get_mut(&TypeId::of::<T>()) {
+            Some(components) => &mut downcast_mut::<T>(components).slots[..],
+            None => &mut [][..],
+        };
+        slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((entity(generations, index), slot.as_mut()?)))
+    }
+
+    /// Every Entity with both an A and a B.
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (Entity, &A, &B)> {
+        let b = self.components::<B>();
+        self.query::<A>().filter_map(move |(entity, a)| {
+            let b = b?.slots.get(entity.index as usize)?.as_ref()?;
+            Some((entity, a, b))
+        })
+    }
+
+    /// Every Entity with both an A and a B, with the A changeable.
+    ///
+    /// Panics if A and B are the same type.
+    pub fn query2_mut<A: 'static, B: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut A, &B)> {
+        assert_ne!(TypeId::of::<A>(), TypeId::of::<B>(), "query2_mut needs two different components");
+        let generations = &self.generations;
+        let (a, b) = match self.components.get_disjoint_mut([&TypeId::of::<A>(), &TypeId::of::<B>()]) {
+            [Some(a), Some(b)] => (&mut downcast_mut::<A>(a).slots[..], &downcast_mut::<B>(b).slots[..]),
+            _ => (&mut [][..], &[][..]),
+        };
+        a.iter_mut().zip(b).enumerate().filter_map(|(index, (a, b))| {
+            Some((entity(generations, index), a.as_mut()?, b.as_ref()?))
+        })
+    }
+
+    fn entity(&self, index: usize) -> Entity {
+        entity(&self.generations, index)
+    }
+
+    fn components<T: 'static>(&self) -> Option<&Components<T>> {
+        let components = self.components.get(&TypeId::of::<T>())?;
+        // Only ever a Components<T> under T's TypeId
+        Some(components.as_any().downcast_ref().unwrap())
+    }
+
+    fn components_mut<T: 'static>(&mut self) -> &mut Components<T> {
+        let components = self
+            .components
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Components::<T> { slots: Vec::new() }));
+        downcast_mut(components)
+    }
+}
+
+fn entity(generations: &[u32], index: usize) -> Entity {
+    Entity {
+        index: index as u32,
+        generation: generations[index],
+    }
+}
+
+fn downcast_mut<T: 'static>(components: &mut Box<dyn AnyComponents>) -> &mut Components<T> {
+    components.as_any_mut().downcast_mut().unwrap()
+}
+
+/// A system that only needs the World, and how long a tick is.
+pub type System = Box<dyn FnMut(&mut World, Duration)>;
+
+/// Systems a Scene runs every tick, in the order they were added.
+///
+/// For the ones that only need the World. Ones that need more, like `systems::movement`
+/// needing the voxels, get called by the Scene itself.
+#[derive(Default)]
+pub struct Systems {
+    systems: Vec<System>,
+}
+
+impl Systems {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, system: impl FnMut(&mut World, Duration) + 'static) -> &mut Self {
+        self.systems.push(Box::new(system));
+        self
+    }
+
+    /// * `step` - How long a tick is, see `Scene::fixed_update`.
+    pub fn run(&mut self, world: &mut World, step: Duration) {
+        for system in &mut self.systems {
+            system(world, step);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[test]
+    fn despawned_spots_get_reused_with_a_new_generation() {
+        let mut world = World::new();
+        let first = world.spawn();
+        let second = world.spawn();
+        assert_eq!(world.len(), 2);
+
+        assert!(world.despawn(first));
+        assert!(!world.despawn(first));
+        assert_eq!(world.len(), 1);
+
+        let reused = world.spawn();
+        assert_eq!(reused.index, first.index);
+        assert_ne!(reused.generation, first.generation);
+        assert_ne!(reused, first);
+        assert!(world.is_alive(second));
+        assert_eq!(world.len(), 2);
+    }
+
+    #[test]
+    fn stale_ids_are_dead() {
+        let mut world = World::new();
+        let old = world.spawn();
+        world.insert(old, Name("old"));
+        world.despawn(old);
+        let new = world.spawn();
+        world.insert(new, Name("new"));
+
+        assert!(!world.is_alive(old));
+        assert!(world.is_alive(new));
+        // The old id doesn't see the new Entity's components, and can't give it any
+        assert_eq!(world.get::<Name>(old), None);
+        assert_eq!(world.insert(old, Name("stale")), None);
+        assert_eq!(world.get::<Name>(new), Some(&Name("new")));
+        assert_eq!(world.remove::<Name>(old), None);
+        assert!(world.get_mut::<Name>(old).is_none());
+
+        // Ids from a World that's never had that many entities aren't alive either
+        let other = World::new();
+        assert!(!other.is_alive(new));
+    }
+
+    #[test]
+    fn despawning_drops_every_component() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Name("gone"));
+        world.insert(entity, Health(3));
+        world.despawn(entity);
+
+        let reused = world.spawn();
+        assert_eq!(world.get::<Name>(reused), None);
+        assert_eq!(world.get::<Health>(reused), None);
+        assert_eq!(world.query::<Name>().count(), 0);
+    }
+
+    #[test]
+    fn query2_skips_entities_missing_a_component() {
+        let mut world = World::new();
+        // Spawned first so Health's slots end before Name's do
+        let both = world.spawn();
+        let no_name = world.spawn();
+        let no_health = world.spawn();
+        world.insert(both, Name("both"));
+        world.insert(both, Health(1));
+        world.insert(no_name, Health(2));
+        world.insert(no_health, Name("no health"));
+
+        let found: Vec<_> = world.query2::<Name, Health>().map(|(entity, _, _)| entity).collect();
+        assert_eq!(found, [both]);
+        let found: Vec<_> = world.query2::<Health, Name>().map(|(entity, _, _)| entity).collect();
+        assert_eq!(found, [both]);
+
+        for (_, health, _) in world.query2_mut::<Health, Name>() {
+            health.0 += 10;
+        }
+        assert_eq!(world.get::<Health>(both), Some(&Health(11)));
+        assert_eq!(world.get::<Health>(no_name), Some(&Health(2)));
+        let found: Vec<_> = world.query2_mut::<Name, Health>().map(|(entity, _, _)| entity).collect();
+        assert_eq!(found, [both]);
+    }
+
+    #[test]
+    fn queries_for_components_nobody_has_are_empty() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Name("alone"));
+
+        assert_eq!(world.query::<Health>().count(), 0);
+        assert_eq!(world.query_mut::<Health>().count(), 0);
+        assert_eq!(world.query2::<Name, Health>().count(), 0);
+        assert_eq!(world.query2_mut::<Name, Health>().count(), 0);
+        assert_eq!(world.query2_mut::<Health, Name>().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "two different components")]
+    fn query2_mut_needs_two_different_components() {
+        let mut world = World::new();
+        let _ = world.query2_mut::<Name, Name>();
+    }
+}
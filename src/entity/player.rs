@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use crate::engine::input::{ActionTrigger, InputMap};
+use crate::voxel::chunk::ChunkModel;
+use crate::voxel::collision::{is_colliding, move_and_collide, Aabb};
+
+const HALF_WIDTH: f32 = 0.3;
+const HEIGHT: f32 = 1.8;
+const EYE_HEIGHT: f32 = 1.62;
+
+const WALK_SPEED: f32 = 5.0;
+const FLY_SPEED: f32 = 14.0;
+const JUMP_SPEED: f32 = 8.5;
+const GRAVITY: f32 = 28.0;
+const TERMINAL_VELOCITY: f32 = 60.0;
+/// How high of a ledge we walk up onto without jumping.
+const STEP_HEIGHT: f32 = 1.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Gravity, jumping and collisions.
+    Walking,
+    /// No gravity, but still collides with the world.
+    Flying,
+    /// No gravity, no collisions. Straight through everything.
+    Noclip,
+}
+
+/// The player. A box that walks around the voxel world with the camera at eye height.
+#[derive(Debug)]
+pub struct Player {
+    /// The point between the player's feet.
+    pub position: glam::Vec3,
+    pub velocity: glam::Vec3,
+    pub mode: MovementMode,
+
+    on_ground: bool,
+
+    // So holding a key only toggles once.
+    fly_toggle: ActionTrigger,
+    noclip_toggle: ActionTrigger,
+}
+
+impl Player {
+    pub fn new(position: glam::Vec3) -> Self {
+        Self {
+            position,
+            velocity: glam::Vec3::ZERO,
+            mode: MovementMode::Walking,
+            on_ground: false,
+            fly_toggle: ActionTrigger::default(),
+            noclip_toggle: ActionTrigger::default(),
+        }
+    }
+
+    /// Places the player standing on top of the highest voxel of a column.
+    pub fn spawn(x: i32, z: i32, world: &ChunkModel) -> Self {
+        let ground = world.highest_solid(x, z).unwrap_or(0);
+        Self::new(glam::Vec3::new(x as f32, ground as f32 + 0.5 + 0.01, z as f32))
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_feet(self.position, HALF_WIDTH, HEIGHT)
+    }
+
+    pub fn on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    /// Where the camera goes.
+    pub fn eye_position(&self) -> glam::Vec3 {
+        self.position + glam::Vec3::Y * EYE_HEIGHT
+    }
+
+    /// Moves the player. Runs once every simulation tick.
+    ///
+    /// * `yaw` - The direction the camera looks in, so "forward" is where we're looking.
+    pub fn update(&mut self, input: &InputMap, yaw: f32, world: &ChunkModel, step: Duration) {
+        let step = step.as_secs_f32();
+        self.toggle_modes(input);
+
+        let (yaw_sin, yaw_cos) = yaw.sin_cos();
+        let forward = glam::Vec3::new(yaw_cos, 0.0, yaw_sin);
+        let right = glam::Vec3::new(-yaw_sin, 0.0, yaw_cos);
+        let wish = (forward * (input.value("move_forward") - input.value("move_backward"))
+            + right * (input.value("move_right") - input.value("move_left")))
+            .normalize_or_zero();
+
+        match self.mode {
+            MovementMode::Walking => {
+                self.velocity.x = wish.x * WALK_SPEED;
+                self.velocity.z = wish.z * WALK_SPEED;
+
+                if self.on_ground && input.pressed("jump") {
+                    self.velocity.y = JUMP_SPEED;
+                }
+                self.velocity.y = (self.velocity.y - GRAVITY * step).max(-TERMINAL_VELOCITY);
+            }
+            MovementMode::Flying | MovementMode::Noclip => {
+                let vertical = input.value("move_up") - input.value("move_down");
+                self.velocity = (wish + glam::Vec3::Y * vertical) * FLY_SPEED;
+            }
+        }
+
+        let delta = self.velocity * step;
+        match self.mode {
+            MovementMode::Noclip => {
+                self.position += delta;
+                self.on_ground = false;
+            }
+            MovementMode::Walking | MovementMode::Flying => self.move_and_collide(delta, world),
+        }
+    }
+
+    fn toggle_modes(&mut self, input: &InputMap) {
+        if self.fly_toggle.update(input.pressed("toggle_fly")) {
+            self.mode = match self.mode {
+                MovementMode::Walking => MovementMode::Flying,
+                _ => MovementMode::Walking,
+            };
+        }
+
+        if self.noclip_toggle.update(input.pressed("toggle_noclip")) {
+            self.mode = match self.mode {
+                MovementMode::Noclip => MovementMode::Flying,
+                _ => MovementMode::Noclip,
+            };
+        }
+
+        if self.mode != MovementMode::Walking {
+            self.velocity = glam::Vec3::ZERO;
+        }
+    }
+
+    fn move_and_collide(&mut self, delta: glam::Vec3, world: &ChunkModel) {
+        let start = self.aabb();
+        let (mut moved, mut hit) = move_and_collide(start, delta, world);
+
+        // Walked into something while on the ground. See if it's a ledge we can step onto
+        // by trying the same horizontal move again from STEP_HEIGHT higher up.
+        if self.mode == MovementMode::Walking && self.on_ground && (hit.x || hit.z) {
+            let raised = start.translated(glam::Vec3::Y * STEP_HEIGHT);
+            if !is_colliding(&raised, world) {
+                let horizontal = glam::Vec3::new(delta.x, 0.0, delta.z);
+                let (stepped, step_hit) = move_and_collide(raised, horizontal, world);
+                // Then back down onto whatever we stepped on.
+                let (stepped, down_hit) = move_and_collide(stepped, glam::Vec3::Y * -STEP_HEIGHT, world);
+
+                let travelled = |aabb: &Aabb| {
+                    let offset = aabb.min - start.min;
+                    glam::Vec2::new(offset.x, offset.z).length()
+                };
+                if travelled(&stepped) > travelled(&moved) {
+                    moved = stepped;
+                    hit = glam::BVec3::new(step_hit.x, down_hit.y, step_hit.z);
+                }
+            }
+        }
+
+        self.on_ground = hit.y && delta.y < 0.0;
+        if hit.y {
+            self.velocity.y = 0.0;
+        }
+        if hit.x {
+            self.velocity.x = 0.0;
+        }
+        if hit.z {
+            self.velocity.z = 0.0;
+        }
+
+        self.position = glam::Vec3::new(
+            (moved.min.x + moved.max.x) / 2.0,
+            moved.min.y,
+            (moved.min.z + moved.max.z) / 2.0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::voxel::block::Block;
+
+    /// A stone floor at y = 0, with stacks of stone on top of it.
+    fn floor_with(stacks: &[(glam::IVec3, i32)]) -> ChunkModel {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        for x in 0..16 {
+            for z in 0..16 {
+                world.set_block(glam::IVec3::new(x, 0, z), Block::Stone);
+            }
+        }
+        for &(base, height) in stacks {
+            for y in 0..height {
+                world.set_block(base + glam::IVec3::Y * y, Block::Stone);
+            }
+        }
+        world
+    }
+
+    /// A Player standing on the floor, about to walk along +x.
+    fn standing_player() -> Player {
+        let mut player = Player::new(glam::Vec3::new(4.0, 0.5, 4.0));
+        player.on_ground = true;
+        player
+    }
+
+    #[test]
+    fn falls_onto_the_floor() {
+        let world = floor_with(&[]);
+        let input = InputMap::new(HashMap::new());
+        let mut player = Player::new(glam::Vec3::new(4.0, 3.0, 4.0));
+
+        for _ in 0..60 {
+            player.update(&input, 0.0, &world, Duration::from_secs_f32(1.0 / 60.0));
+        }
+
+        assert!(player.on_ground());
+        assert!((player.position.y - 0.5).abs() < 0.01, "standing at {}", player.position.y);
+        assert_eq!(player.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn steps_onto_a_single_block() {
+        let world = floor_with(&[(glam::IVec3::new(6, 1, 4), 1)]);
+        let mut player = standing_player();
+
+        player.move_and_collide(glam::Vec3::new(1.5, -0.01, 0.0), &world);
+
+        assert!((player.position.y - 1.5).abs() < 0.01, "standing at {}", player.position.y);
+        assert!((player.position.x - 5.5).abs() < 0.01);
+        assert!(player.on_ground());
+    }
+
+    #[test]
+    fn does_not_step_onto_a_two_block_ledge() {
+        let world = floor_with(&[(glam::IVec3::new(6, 1, 4), 2)]);
+        let mut player = standing_player();
+        player.velocity.x = WALK_SPEED;
+
+        player.move_and_collide(glam::Vec3::new(1.5, -0.01, 0.0), &world);
+
+        assert!((player.position.y - 0.5).abs() < 0.01, "standing at {}", player.position.y);
+        // Up against the ledge, which starts at 5.5
+        assert!((player.position.x + HALF_WIDTH - 5.5).abs() < 0.01);
+        assert_eq!(player.velocity.x, 0.0);
+    }
+
+    #[test]
+    fn does_not_step_up_in_the_air() {
+        let world = floor_with(&[(glam::IVec3::new(6, 1, 4), 1)]);
+        let mut player = standing_player();
+        player.on_ground = false;
+
+        player.move_and_collide(glam::Vec3::new(1.5, -0.01, 0.0), &world);
+
+        assert!((player.position.y - 0.5).abs() < 0.01);
+        assert!(player.position.x < 5.5 - HALF_WIDTH + 0.01);
+    }
+}
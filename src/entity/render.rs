@@ -0,0 +1,165 @@
+use std::ops::Range;
+
+use crate::engine::resource::instance::{Instance, InstanceBuffer};
+use crate::engine::resource::model::{DrawModel, Model};
+use crate::entity::components::{Renderable, Transform};
+use crate::entity::ecs::World;
+
+/// Refers to a Model added to `Models`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModelId(usize);
+
+/// Every Model a Scene's entities can be drawn as, see `Renderable`.
+#[derive(Default)]
+pub struct Models {
+    models: Vec<Model>,
+}
+
+impl Models {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, model: Model) -> ModelId {
+        self.models.push(model);
+        ModelId(self.models.len() - 1)
+    }
+
+    pub fn get(&self, id: ModelId) -> &Model {
+        &self.models[id.0]
+    }
+}
+
+/// The instance of every Entity with a Renderable and a Transform, sorted by Model,
+/// and which of them are which Model's.
+fn group_by_model(world: &World) -> (Vec<(ModelId, Range<u32>)>, Vec<Instance>) {
+    let mut renderables: Vec<(ModelId, Instance)> = world
+        .query2::<Renderable, Transform>()
+        .map(|(_, renderable, transform)| {
+            let instance = Instance {
+                tint: renderable.tint,
+                ..Instance::from(*transform)
+            };
+            (renderable.model, instance)
+        })
+        .collect();
+    // Stable, so entities keep their spot and don't count as changed
+    renderables.sort_by_key(|(model, _)| *model);
+
+    let mut batches: Vec<(ModelId, Range<u32>)> = Vec::new();
+    for (index, (model, _)) in renderables.iter().enumerate() {
+        let index = index as u32;
+        match batches.last_mut() {
+            Some((last, instances)) if last == model => instances.end = index + 1,
+            _ => batches.push((*model, index..index + 1)),
+        }
+    }
+
+    (batches, renderables.into_iter().map(|(_, instance)| instance).collect())
+}
+
+/// Every Renderable Entity, as instances grouped by Model so each Model takes a single draw.
+pub struct RenderBatches {
+    /// The instances of every batch, one after the other.
+    instances: InstanceBuffer,
+    batches: Vec<(ModelId, Range<u32>)>,
+}
+
+impl RenderBatches {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            instances: InstanceBuffer::new("Entity", device),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Gathers every Entity with a Renderable and a Transform. Call once a frame, before drawing.
+    /// Only the entities that moved or changed colour get uploaded again.
+    pub fn update(&mut self, world: &World, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let (batches, instances) = group_by_model(world);
+        self.batches = batches;
+        self.instances.write(&instances, device, queue);
+    }
+
+    /// Which instances in the buffer are which Model's.
+    pub fn batches(&self) -> impl Iterator<Item = (ModelId, Range<u32>)> + '_ {
+        self.batches.iter().cloned()
+    }
+
+    /// Draws every Entity that looks like `model`, with whatever pipeline is set.
+    /// Models drawn with different pipelines get a call each.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        model: ModelId,
+        models: &'a Models,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        for (_, instances) in self.batches.iter().filter(|(batch, _)| *batch == model) {
+            render_pass.set_vertex_buffer(1, self.instances.buffer().slice(..));
+            render_pass.draw_model_instanced(models.get(model), instances.clone(), camera_bind_group, light_bind_group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32) -> Transform {
+        Transform {
+            translation: glam::vec3(x, 0.0, 0.0),
+            ..Transform::IDENTITY
+        }
+    }
+
+    /// Spawns an Entity that looks like `model`, at `x`.
+    fn spawn(world: &mut World, model: usize, x: f32) -> crate::entity::ecs::Entity {
+        let entity = world.spawn();
+        world.insert(entity, Renderable::new(ModelId(model)));
+        world.insert(entity, at(x));
+        entity
+    }
+
+    #[test]
+    fn instances_are_grouped_by_model() {
+        let mut world = World::new();
+        spawn(&mut world, 1, 0.0);
+        spawn(&mut world, 0, 1.0);
+        spawn(&mut world, 1, 2.0);
+        spawn(&mut world, 2, 3.0);
+        spawn(&mut world, 0, 4.0);
+
+        let (batches, instances) = group_by_model(&world);
+
+        assert_eq!(batches, [(ModelId(0), 0..2), (ModelId(1), 2..4), (ModelId(2), 4..5)]);
+        // Entities of the same Model stay in the order they were spawned in
+        let xs: Vec<_> = instances.iter().map(|instance| instance.position.x).collect();
+        assert_eq!(xs, [1.0, 4.0, 0.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn entities_need_a_renderable_and_a_transform() {
+        let mut world = World::new();
+        let drawn = spawn(&mut world, 0, 0.0);
+        world.get_mut::<Renderable>(drawn).unwrap().tint = glam::Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let hidden = world.spawn();
+        world.insert(hidden, Renderable::new(ModelId(0)));
+        let nowhere = world.spawn();
+        world.insert(nowhere, at(5.0));
+
+        let (batches, instances) = group_by_model(&world);
+
+        assert_eq!(batches, [(ModelId(0), 0..1)]);
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].tint, glam::Vec4::new(1.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn no_entities_make_no_batches() {
+        let (batches, instances) = group_by_model(&World::new());
+        assert!(batches.is_empty());
+        assert!(instances.is_empty());
+    }
+}
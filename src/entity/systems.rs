@@ -0,0 +1,267 @@
+//! The systems that go with the `components`. Scenes run them from `Scene::fixed_update`.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::engine::resource::light::{Light, LightId, LightKind, LightList};
+use crate::entity::components::{Collider, LightSource, Transform, Velocity};
+use crate::entity::ecs::{Entity, World};
+use crate::voxel::chunk::ChunkModel;
+use crate::voxel::collision::move_and_collide;
+
+/// Moves and turns every Entity with a Transform and a Velocity.
+///
+/// With `voxels`, entities that have a Collider stop at solid voxels,
+/// and lose their speed along the axes they bumped into.
+pub fn movement(world: &mut World, step: Duration, voxels: Option<&ChunkModel>) {
+    let dt = step.as_secs_f32();
+    // The Transforms get borrowed mutably below, so the Colliders have to be copied out first
+    let colliders: HashMap<Entity, Collider> = match voxels {
+        Some(_) => world.query::<Collider>().map(|(entity, collider)| (entity, *collider)).collect(),
+        None => HashMap::new(),
+    };
+
+    let mut blocked = Vec::new();
+    for (entity, transform, velocity) in world.query2_mut::<Transform, Velocity>() {
+        let delta = velocity.linear * dt;
+        match (voxels, colliders.get(&entity)) {
+            (Some(voxels), Some(collider)) => {
+                let (aabb, hit) = move_and_collide(collider.aabb(transform), delta, voxels);
+                transform.translation = (aabb.min + aabb.max) / 2.0;
+                if hit.any() {
+                    blocked.push((entity, hit));
+                }
+            }
+            _ => transform.translation += delta,
+        }
+
+        let angle = velocity.angular.length() * dt;
+        if angle > 0.0 {
+            let turn = glam::Quat::from_axis_angle(velocity.angular / velocity.angular.length(), angle);
+            transform.rotation = (turn * transform.rotation).normalize();
+        }
+    }
+
+    for (entity, hit) in blocked {
+        if let Some(velocity) = world.get_mut::<Velocity>(entity) {
+            velocity.linear = glam::Vec3::select(hit, glam::Vec3::ZERO, velocity.linear);
+        }
+    }
+}
+
+/// Every pair of entities whose Colliders overlap. Each pair only shows up once.
+pub fn collisions(world: &World) -> Vec<(Entity, Entity)> {
+    let boxes: Vec<_> = world
+        .query2::<Collider, Transform>()
+        .map(|(entity, collider, transform)| (entity, collider.aabb(transform)))
+        .collect();
+
+    let mut pairs = Vec::new();
+    for (i, (a, a_box)) in boxes.iter().enumerate() {
+        for (b, b_box) in &boxes[i + 1..] {
+            if a_box.intersects(b_box) {
+                pairs.push((*a, *b));
+            }
+        }
+    }
+    pairs
+}
+
+/// Keeps a Scene's Lights in line with the entities that have a LightSource.
+/// Lights get added for new ones, follow their Entity around, and get removed with it.
+#[derive(Debug, Default)]
+pub struct LightSync {
+    /// The Lights that belong to entities, so they can be removed once the Entity is gone.
+    ids: HashMap<Entity, LightId>,
+}
+
+impl LightSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Entities need a Transform too for their Light to show up.
+    ///
+    /// * `lights` - The Scene's, see `Lights::list_mut`.
+    pub fn run(&mut self, world: &mut World, lights: &mut LightList) {
+        let mut alive = HashSet::new();
+        for (entity, source, transform) in world.query2_mut::<LightSource, Transform>() {
+            let light = placed(source.light, transform);
+            let id = match source.id.and_then(|id| lights.get_mut(id).map(|existing| (id, existing))) {
+                Some((id, existing)) => {
+                    *existing = light;
+                    id
+                }
+                None => lights.add(light),
+            };
+            source.id = Some(id);
+            // A LightSource that replaced another one brings a Light of its own
+            if let Some(previous) = self.ids.insert(entity, id).filter(|&previous| previous != id) {
+                lights.remove(previous);
+            }
+            alive.insert(entity);
+        }
+
+        // Despawned, or lost their LightSource
+        self.ids.retain(|entity, id| {
+            let keep = alive.contains(entity);
+            if !keep {
+                lights.remove(*id);
+            }
+            keep
+        });
+    }
+}
+
+/// Where a LightSource's Light ends up when its Entity is at `transform`.
+fn placed(light: Light, transform: &Transform) -> Light {
+    let kind = match light.kind {
+        LightKind::Point => LightKind::Point,
+        LightKind::Spot {
+            direction,
+            inner_angle,
+            outer_angle,
+        } => LightKind::Spot {
+            direction: transform.rotation * direction,
+            inner_angle,
+            outer_angle,
+        },
+        LightKind::Directional { direction } => LightKind::Directional {
+            direction: transform.rotation * direction,
+        },
+    };
+    Light {
+        kind,
+        position: transform.matrix().transform_point3(light.position),
+        ..light
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::block::Block;
+
+    /// A stone floor at y = 0, with its top at 0.5.
+    fn floor() -> ChunkModel {
+        let mut voxels = ChunkModel::new();
+        voxels.add_empty_chunk(glam::IVec3::ZERO);
+        voxels.fill(glam::IVec3::ZERO, glam::IVec3::new(15, 0, 15), Block::Stone);
+        voxels
+    }
+
+    fn moving(world: &mut World, at: glam::Vec3, linear: glam::Vec3) -> Entity {
+        let entity = world.spawn();
+        world.insert(
+            entity,
+            Transform {
+                translation: at,
+                ..Transform::IDENTITY
+            },
+        );
+        world.insert(
+            entity,
+            Velocity {
+                linear,
+                ..Default::default()
+            },
+        );
+        entity
+    }
+
+    #[test]
+    fn movement_stops_at_voxels_and_zeroes_velocity_on_a_hit() {
+        let voxels = floor();
+        let mut world = World::new();
+        let falling = moving(&mut world, glam::vec3(4.0, 3.0, 4.0), glam::vec3(1.0, -10.0, 0.0));
+        world.insert(falling, Collider::new(glam::Vec3::splat(0.4)));
+        // No Collider, so it goes right through
+        let ghost = moving(&mut world, glam::vec3(8.0, 3.0, 8.0), glam::vec3(0.0, -10.0, 0.0));
+
+        movement(&mut world, Duration::from_secs(1), Some(&voxels));
+
+        let transform = world.get::<Transform>(falling).unwrap();
+        assert!((transform.translation.y - 0.9).abs() < 0.01, "landed at {}", transform.translation.y);
+        assert!((transform.translation.x - 5.0).abs() < 0.01, "slid to {}", transform.translation.x);
+        // Only the axis it bumped into loses its speed
+        assert_eq!(world.get::<Velocity>(falling).unwrap().linear, glam::vec3(1.0, 0.0, 0.0));
+
+        assert_eq!(world.get::<Transform>(ghost).unwrap().translation, glam::vec3(8.0, -7.0, 8.0));
+        assert_eq!(world.get::<Velocity>(ghost).unwrap().linear, glam::vec3(0.0, -10.0, 0.0));
+    }
+
+    #[test]
+    fn movement_without_voxels_ignores_colliders() {
+        let mut world = World::new();
+        let entity = moving(&mut world, glam::Vec3::ZERO, glam::vec3(0.0, -2.0, 0.0));
+        world.insert(entity, Collider::new(glam::Vec3::splat(0.4)));
+
+        movement(&mut world, Duration::from_millis(500), None);
+
+        assert_eq!(world.get::<Transform>(entity).unwrap().translation, glam::vec3(0.0, -1.0, 0.0));
+        assert_eq!(world.get::<Velocity>(entity).unwrap().linear, glam::vec3(0.0, -2.0, 0.0));
+    }
+
+    #[test]
+    fn light_sync_follows_and_removes_lights() {
+        let mut world = World::new();
+        let mut lights = LightList::default();
+        let mut sync = LightSync::new();
+        let lamp = moving(&mut world, glam::vec3(1.0, 2.0, 3.0), glam::Vec3::ZERO);
+        let light = Light::point(glam::Vec3::Y, glam::Vec3::ONE, 1.0, 10.0);
+        world.insert(lamp, LightSource::new(light));
+        // No Transform, so no Light
+        let nowhere = world.spawn();
+        world.insert(nowhere, LightSource::new(light));
+
+        sync.run(&mut world, &mut lights);
+        let id = world.get::<LightSource>(lamp).unwrap().id().unwrap();
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights.get(id).unwrap().position, glam::vec3(1.0, 3.0, 3.0));
+
+        world.get_mut::<Transform>(lamp).unwrap().translation = glam::Vec3::ZERO;
+        sync.run(&mut world, &mut lights);
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights.get(id).unwrap().position, glam::Vec3::Y);
+
+        world.despawn(lamp);
+        sync.run(&mut world, &mut lights);
+        assert!(!lights.contains(id));
+        assert!(lights.is_empty());
+    }
+
+    #[test]
+    fn light_sync_removes_lights_of_entities_that_lost_their_source() {
+        let mut world = World::new();
+        let mut lights = LightList::default();
+        let mut sync = LightSync::new();
+        let lamp = moving(&mut world, glam::Vec3::ZERO, glam::Vec3::ZERO);
+        world.insert(lamp, LightSource::new(Light::point(glam::Vec3::ZERO, glam::Vec3::ONE, 1.0, 10.0)));
+
+        sync.run(&mut world, &mut lights);
+        let id = world.remove::<LightSource>(lamp).unwrap().id().unwrap();
+        sync.run(&mut world, &mut lights);
+
+        assert!(!lights.contains(id));
+        assert!(world.is_alive(lamp));
+    }
+
+    #[test]
+    fn light_sync_removes_the_light_of_a_replaced_source() {
+        let mut world = World::new();
+        let mut lights = LightList::default();
+        let mut sync = LightSync::new();
+        let lamp = moving(&mut world, glam::Vec3::ZERO, glam::Vec3::ZERO);
+        world.insert(lamp, LightSource::new(Light::point(glam::Vec3::ZERO, glam::Vec3::ONE, 1.0, 10.0)));
+        sync.run(&mut world, &mut lights);
+        let old = world.get::<LightSource>(lamp).unwrap().id().unwrap();
+
+        world.insert(lamp, LightSource::new(Light::point(glam::Vec3::ZERO, glam::Vec3::ONE, 2.0, 10.0)));
+        sync.run(&mut world, &mut lights);
+        let new = world.get::<LightSource>(lamp).unwrap().id().unwrap();
+
+        assert!(!lights.contains(old));
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights.get(new).unwrap().intensity, 2.0);
+    }
+}
@@ -0,0 +1,17 @@
+//! aravoxel, a voxel engine built on wgpu.
+//!
+//! `engine` has everything that isn't about voxels: loading assets, rendering resources,
+//! settings and, with the `windowing` feature, the window and input. `voxel` has the chunks,
+//! their meshing, lighting and block updates. `scene` and `entity` are the games built on top.
+//!
+//! Without the `windowing` feature there's no window, input or scenes, which is all a headless
+//! tool (meshing chunks offline, say) needs. The `demo` feature, on by default, is only there for
+//! the demo and the example, so depend on the engine with `default-features = false`.
+
+pub mod engine;
+pub mod entity;
+#[cfg(feature = "windowing")]
+pub mod scene;
+pub mod voxel;
+
+pub use engine::error::{Error, Result};
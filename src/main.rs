@@ -1,9 +1,11 @@
-
-mod engine;
-mod scene;
-mod entity;
-mod voxel;
+use aravoxel::scene::voxel_world::VoxelWorld;
 
 fn main() {
-    pollster::block_on(engine::aravoxel::run());
+    pretty_env_logger::init();
+
+    if let Err(e) = pollster::block_on(aravoxel::engine::aravoxel::run::<VoxelWorld>()) {
+        log::error!("{e}");
+        eprintln!("aravoxel couldn't start: {e}");
+        std::process::exit(1);
+    }
 }
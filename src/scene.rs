@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod scene;
 pub mod wgpu_tutorial;
 pub mod voxel_world;
\ No newline at end of file
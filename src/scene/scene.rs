@@ -1,18 +1,66 @@
 use std::time::Duration;
-use winit::event::{DeviceEvent, WindowEvent};
+
+use crate::engine::error::Result;
+use crate::engine::input::InputMap;
+use crate::engine::resource::light::{Light, LightId, Lights};
+use crate::engine::settings::Settings;
 
 /// All of our Scenes implement this.
 pub trait Scene {
-    async fn new(_device: &wgpu::Device, _config: &wgpu::SurfaceConfiguration, queue: &wgpu::Queue) -> Box<Self>;
+    /// The input context (a table in `input/bindings.toml`) this Scene reads its actions from.
+    const INPUT_CONTEXT: &'static str;
+
+    /// Fails when something the Scene can't do without doesn't load.
+    // Scenes get made inside `pollster::block_on`, nothing needs the future to be Send
+    #[allow(async_fn_in_trait)]
+    async fn new(
+        _device: &wgpu::Device,
+        _config: &wgpu::SurfaceConfiguration,
+        queue: &wgpu::Queue,
+        settings: &Settings,
+    ) -> Result<Box<Self>>;
 
-    fn update(&mut self, queue: &wgpu::Queue, _dt: Duration);
+    /// Called by aravoxel at a fixed rate. Anything that simulates (movement, physics...)
+    /// goes in here so it behaves the same no matter the frame rate.
+    ///
+    /// * `tick` - The number of this tick. Counts up by one every call.
+    /// * `step` - How long a tick is. Always the same.
+    fn fixed_update(&mut self, input: &InputMap, tick: u64, step: Duration);
+
+    /// Called by aravoxel every frame, right before `render`.
+    ///
+    /// * `dt` - How long the last frame took.
+    /// * `alpha` - How far we are between the last tick and the next one (0.0 to 1.0).
+    ///   Use it to interpolate between the previous and current tick's state.
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, input: &InputMap, _dt: Duration, alpha: f32);
 
     /// Called by aravoxel every frame.
     fn render(&mut self, _view: &wgpu::TextureView, _encoder: &mut wgpu::CommandEncoder);
 
-    fn input(&mut self, _event: &WindowEvent);
+    fn resize(&mut self, _new_size: winit::dpi::PhysicalSize<u32>, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration);
+
+    /// Called by aravoxel when shader files changed, if hot reloading is on.
+    /// Scenes reload the shaders they use and rebuild whatever was built out of them.
+    ///
+    /// * `shader_files` - The files that changed, relative to the assets folder.
+    fn reload_shaders(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue, _shader_files: &[String]) {
+    }
 
-    fn device_input(&mut self, _event: &DeviceEvent);
+    /// The lights shining on this Scene.
+    fn lights(&mut self) -> &mut Lights;
 
-    fn resize(&mut self, _new_size: winit::dpi::PhysicalSize<u32>, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration);
-}
\ No newline at end of file
+    fn add_light(&mut self, light: Light) -> LightId {
+        self.lights().add(light)
+    }
+
+    fn remove_light(&mut self, id: LightId) -> Option<Light> {
+        self.lights().remove(id)
+    }
+
+    /// Moves a light somewhere else. Does nothing if the light was removed.
+    fn move_light(&mut self, id: LightId, position: glam::Vec3) {
+        if let Some(light) = self.lights().get_mut(id) {
+            light.position = position;
+        }
+    }
+}
@@ -1,57 +1,344 @@
-use crate::engine::resource::instance::{Instance, InstanceRaw};
-use crate::engine::resource::light::Light;
+use crate::engine::resource::instance::InstanceRaw;
+use crate::engine::resource::light::{Light, LightId, LightKind, LightMarkers, Lights};
 use std::time::Duration;
+use wgpu::util::DrawIndexedIndirectArgs;
 use winit::dpi::PhysicalSize;
-use winit::event::{DeviceEvent, ElementState, MouseButton, WindowEvent};
 
-use crate::engine::resource::model::{DrawLight, DrawModel, Model, ModelVertex};
+use crate::engine::assets::Handle;
+use crate::engine::error::Result;
+use crate::engine::input::{ActionTrigger, InputMap};
+use crate::engine::resource::shadow::{ShadowMap, CASCADES};
 use crate::engine::resource::texture::Texture;
+use crate::engine::resource::fog::Fog;
+use crate::engine::resource::frustum::Frustum;
+use crate::engine::resource::indirect::{DrawIndirect, IndirectDraws};
+use crate::engine::resource::model::{Material, MaterialMaps, MaterialProperties, ModelVertex};
+use crate::engine::resource::sky::Sky;
 use crate::engine::resource_manager::ResourceManager;
-use crate::engine::util::{create_render_pipeline, Vertex};
+use crate::engine::settings::Settings;
+use crate::engine::time::WorldClock;
+use crate::engine::util::{catch_validation_error, create_render_pipeline, load_model, BlendMode, Vertex};
 use crate::entity::camera::{Camera, CameraController};
+use crate::entity::components::{Collider, LightSource, Renderable, Transform, Velocity};
+use crate::entity::ecs::{Systems, World};
+use crate::entity::render::{Models, RenderBatches};
+use crate::entity::systems::{self, LightSync};
+use crate::entity::player::Player;
 use crate::scene::scene::Scene;
+use crate::voxel::block::{Block, RenderLayer};
 use crate::voxel::chunk::ChunkModel;
+use crate::voxel::collision::{raycast, Aabb};
+use crate::voxel::gpu_mesher::{GpuMesher, MeshingMode};
+use crate::voxel::update::BlockUpdates;
+use crate::voxel::vertex::ChunkVertex;
 
-#[allow(dead_code)]
 pub struct VoxelWorld {
     resource_manager: ResourceManager,
+    /// Until the next `ResourceManager::unload_unused`.
+    unload_timer: Duration,
+    shaders: WorldShaders,
     render_pipeline: wgpu::RenderPipeline,
+    cutout_render_pipeline: wgpu::RenderPipeline,
+    translucent_render_pipeline: wgpu::RenderPipeline,
+    /// For the entities, they're Models instead of chunks.
+    model_render_pipeline: wgpu::RenderPipeline,
     light_render_pipeline: wgpu::RenderPipeline,
+    shadow_render_pipeline: wgpu::RenderPipeline,
+    /// Kept around so the pipelines can be rebuilt when their shaders get reloaded.
+    render_pipeline_layout: wgpu::PipelineLayout,
+    light_pipeline_layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
 
     chunk_model: ChunkModel,
+    /// What the chunks get drawn with this frame, one batch per `ChunkBatch`.
+    chunk_draws: IndirectDraws,
+    block_updates: BlockUpdates,
+    /// See `GraphicsSettings::lod_distance`.
+    lod_distance: f32,
 
     camera_controller: CameraController,
     camera_bind_group: wgpu::BindGroup,
 
-    instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
+    player: Player,
+    /// Everything else that moves around, the lanterns for now.
+    entities: World,
+    systems: Systems,
+    light_sync: LightSync,
+    models: Models,
+    batches: RenderBatches,
+
+    break_trigger: ActionTrigger,
+    place_trigger: ActionTrigger,
+    /// What gets placed, picked with the number keys.
+    selected_block: Block,
+    time_faster_trigger: ActionTrigger,
+    time_slower_trigger: ActionTrigger,
+
+    clock: WorldClock,
+    sky: Sky,
+
+    lights: Lights,
+    light_markers: LightMarkers,
+    sun: LightId,
+    moon: LightId,
+    shadow_map: ShadowMap,
 
-    light: Light,
-    light_bind_group: wgpu::BindGroup,
+}
+
+/// The shaders a VoxelWorld uses, kept so the pipelines can be rebuilt when they change.
+struct WorldShaders {
+    color: Handle<wgpu::ShaderModule>,
+    light: Handle<wgpu::ShaderModule>,
+    shadow: Handle<wgpu::ShaderModule>,
+    sky: Handle<wgpu::ShaderModule>,
+    /// Only there when chunks get meshed on the GPU.
+    chunk_mesh: Option<Handle<wgpu::ShaderModule>>,
+}
+
+/// Every time the chunks get drawn in a frame. Each one gets its own batch in `chunk_draws`.
+#[derive(Debug, Copy, Clone)]
+enum ChunkBatch {
+    Opaque,
+    Cutout,
+    Translucent,
+    /// The opaque blocks, the only ones that cast shadows. Not culled, since things the camera
+    /// can't see still cast shadows onto things it can.
+    Shadow,
+}
 
-    mouse_pressed: bool,
+impl ChunkBatch {
+    const ALL: [ChunkBatch; 4] = [
+        ChunkBatch::Opaque,
+        ChunkBatch::Cutout,
+        ChunkBatch::Translucent,
+        ChunkBatch::Shadow,
+    ];
+}
+
+/// How far away the player can reach blocks from.
+const REACH: f32 = 6.0;
+
+/// How fast entities with `Gravity` speed up falling, in voxels per second squared.
+struct Gravity(f32);
+
+/// How many lanterns get dropped around the player when the world starts.
+const LANTERNS: i32 = 4;
+
+/// How often textures and shaders nothing uses anymore get dropped.
+const UNLOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The blocks the number keys pick, `hotbar_1` first and `hotbar_10` last.
+const HOTBAR: [Block; 10] = [
+    Block::Stone,
+    Block::Dirt,
+    Block::Grass,
+    Block::Lamp,
+    Block::Glass,
+    Block::Leaves,
+    Block::Water,
+    Block::Lava,
+    Block::Sand,
+    Block::Gravel,
+];
+
+impl VoxelWorld {
+    /// Digs out or places the block the player is looking at.
+    fn edit_blocks(&mut self, input: &InputMap) {
+        let break_block = self.break_trigger.update(input.pressed("break_block"));
+        let place_block = self.place_trigger.update(input.pressed("place_block"));
+
+        for (i, block) in HOTBAR.into_iter().enumerate() {
+            if input.pressed(&format!("hotbar_{}", i + 1)) {
+                self.selected_block = block;
+            }
+        }
+
+        let Some(hit) = raycast(
+            self.player.eye_position(),
+            self.camera_controller.camera.forward(),
+            REACH,
+            &self.chunk_model,
+        ) else {
+            return;
+        };
+
+        if break_block {
+            self.chunk_model.set_block(hit.voxel, Block::Air);
+        } else if place_block {
+            let target = hit.voxel + hit.normal;
+            // Don't build ourselves into a wall
+            if Aabb::voxel(target).intersects(&self.player.aabb()) {
+                return;
+            }
+
+            self.chunk_model.set_block(target, self.selected_block);
+        }
+    }
+
+    /// The clock that decides the time of day. Change the time or how fast it passes through here.
+    pub fn clock(&mut self) -> &mut WorldClock {
+        &mut self.clock
+    }
+
+    /// Points the sun and moon where the clock says they are, and colours them,
+    /// the ambient light and the fog to match.
+    fn update_sky_lights(&mut self) {
+        let (sun_color, sun_intensity) = Sky::sun_light(&self.clock);
+        if let Some(sun) = self.lights.get_mut(self.sun) {
+            sun.kind = LightKind::Directional {
+                direction: -self.clock.sun_position(),
+            };
+            sun.color = sun_color;
+            sun.intensity = sun_intensity;
+        }
+
+        let (moon_color, moon_intensity) = Sky::moon_light(&self.clock);
+        if let Some(moon) = self.lights.get_mut(self.moon) {
+            moon.kind = LightKind::Directional {
+                direction: -self.clock.moon_position(),
+            };
+            moon.color = moon_color;
+            moon.intensity = moon_intensity;
+        }
+
+        self.lights.ambient = Sky::ambient_color(&self.clock);
+        self.lights.fog.color = Sky::horizon_color(&self.clock);
+    }
+
+    /// The opaque, cutout and translucent pipelines the chunks get drawn with.
+    fn create_chunk_pipelines(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+    ) -> [wgpu::RenderPipeline; 3] {
+        let chunk_pipeline = |fragment_entry: &str, blend_mode: BlendMode, label: &str| {
+            create_render_pipeline(
+                device,
+                layout,
+                color_format,
+                Some(Texture::DEPTH_FORMAT),
+                &[ChunkVertex::desc(), InstanceRaw::desc()],
+                shader,
+                "vs_chunk",
+                fragment_entry,
+                blend_mode,
+                Some(label),
+            )
+        };
+
+        [
+            chunk_pipeline("fs_main", BlendMode::Opaque, "Color Render Pipeline"),
+            chunk_pipeline("fs_cutout", BlendMode::Opaque, "Cutout Render Pipeline"),
+            chunk_pipeline("fs_translucent", BlendMode::Translucent, "Translucent Render Pipeline"),
+        ]
+    }
+
+    /// What the entities get drawn with. Models don't have voxel light, so they're always in full sunlight.
+    fn create_model_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        create_render_pipeline(
+            device,
+            layout,
+            color_format,
+            Some(Texture::DEPTH_FORMAT),
+            &[ModelVertex::desc(), InstanceRaw::desc()],
+            shader,
+            "vs_main",
+            "fs_main",
+            BlendMode::Opaque,
+            Some("Model Render Pipeline"),
+        )
+    }
+
+    fn create_light_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        create_render_pipeline(
+            device,
+            layout,
+            color_format,
+            Some(Texture::DEPTH_FORMAT),
+            &[LightMarkers::desc()],
+            shader,
+            "vs_main",
+            "fs_main",
+            BlendMode::Opaque,
+            Some("Light Render Pipeline"),
+        )
+    }
+
+    /// Draws one of the batches `chunk_draw_args` made this frame.
+    fn draw_chunks<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+        batch: ChunkBatch,
+    ) {
+        render_pass.set_vertex_buffer(1, self.chunk_model.instance_buffer().slice(..));
+        render_pass.set_bind_group(0, &self.chunk_model.material().bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.lights.bind_group, &[]);
+        render_pass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
+        render_pass.set_pipeline(pipeline);
+        render_pass.draw_pool_indirect(self.chunk_model.pool(), &self.chunk_draws, batch as usize);
+    }
+
+    /// What gets drawn in a batch, leaving out the chunks outside of `frustum` where that's fine.
+    fn chunk_draw_args(&self, batch: ChunkBatch, frustum: &Frustum) -> Vec<DrawIndexedIndirectArgs> {
+        let chunks = &self.chunk_model;
+
+        match batch {
+            ChunkBatch::Opaque => chunks.draw_args(RenderLayer::Opaque, Some(frustum)),
+            ChunkBatch::Cutout => chunks.draw_args(RenderLayer::Cutout, Some(frustum)),
+            ChunkBatch::Translucent => chunks.draw_args(RenderLayer::Translucent, Some(frustum)),
+            // Glass and water let the sun through. The shadow pipeline has no fragment stage
+            // to cut the holes out of leaves with, so they'd cast solid shadows. None is better.
+            ChunkBatch::Shadow => chunks.draw_args(RenderLayer::Opaque, None),
+        }
+    }
 }
 
 impl Scene for VoxelWorld {
+    const INPUT_CONTEXT: &'static str = "voxel_world";
+
     async fn new(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         queue: &wgpu::Queue,
-    ) -> Box<Self> {
+        settings: &Settings,
+    ) -> Result<Box<Self>> {
         let mut resource_manager = ResourceManager::new(device, config);
 
-        // Shader setup
-        resource_manager
-            .load_shader("shader.wgsl", "color_shader", device)
-            .await;
-
-        resource_manager
-            .load_shader("light.wgsl", "light_shader", device)
-            .await;
+        // Everything loads in the background while the world generates
+        let shaders = WorldShaders {
+            color: resource_manager.shaders.load("shaders/shader.wgsl"),
+            light: resource_manager.shaders.load("shaders/light.wgsl"),
+            shadow: resource_manager.shaders.load("shaders/shadow.wgsl"),
+            sky: resource_manager.shaders.load("shaders/sky.wgsl"),
+            chunk_mesh: (settings.graphics.meshing != MeshingMode::Cpu)
+                .then(|| resource_manager.shaders.load("shaders/chunk_mesh.wgsl")),
+        };
+        // Every block shares one texture with a tile for each of them
+        let blocks_texture = resource_manager.textures.load("textures/blocks.png");
 
-        let texture_bind_group_layout = Texture::bind_group_layout(device);
+        let texture_bind_group_layout = Material::bind_group_layout(device);
 
         let mut chunk_model = ChunkModel::new();
+        if let Some(chunk_mesh) = &shaders.chunk_mesh {
+            chunk_model.set_gpu_mesher(GpuMesher::new(
+                device,
+                resource_manager.shaders.wait(chunk_mesh, device, queue)?,
+                settings.graphics.meshing == MeshingMode::Validate,
+            ));
+        }
 
         for x in 0..5 {
             for z in 0..5 {
@@ -61,124 +348,274 @@ impl Scene for VoxelWorld {
             }
         }
         
-        chunk_model.build(&texture_bind_group_layout, device, queue).await;
+        let material = Material::new(
+            "blocks",
+            MaterialMaps {
+                diffuse: Some(blocks_texture),
+                ..Default::default()
+            },
+            MaterialProperties::default(),
+            &mut resource_manager,
+            &texture_bind_group_layout,
+            device,
+            queue,
+        )?;
+        chunk_model.build(material, device, queue);
+
+        let player = Player::spawn(80, 80, &chunk_model);
+
+        // A few lanterns drop out of the sky around the player and land on whatever's there
+        let mut models = Models::new();
+        let lantern = models.add(
+            load_model("cube.obj", &mut resource_manager, device, queue, &texture_bind_group_layout).await?,
+        );
+        let mut entities = World::new();
+        for i in 0..LANTERNS {
+            let angle = i as f32 / LANTERNS as f32 * std::f32::consts::TAU;
+            let (x, z) = (80 + (angle.cos() * 5.0) as i32, 80 + (angle.sin() * 5.0) as i32);
+            let ground = chunk_model.highest_solid(x, z).unwrap_or(0);
+
+            let entity = entities.spawn();
+            entities.insert(entity, Transform {
+                translation: glam::Vec3::new(x as f32 + 0.5, (ground + 12 + i * 3) as f32, z as f32 + 0.5),
+                scale: glam::Vec3::splat(0.25),
+                ..Default::default()
+            });
+            entities.insert(entity, Velocity { angular: glam::Vec3::Y, ..Default::default() });
+            entities.insert(entity, Gravity(28.0));
+            entities.insert(entity, Collider::new(glam::Vec3::ONE));
+            entities.insert(entity, Renderable {
+                tint: glam::Vec4::new(1.0, 0.8, 0.4, 1.0),
+                ..Renderable::new(lantern)
+            });
+            entities.insert(entity, LightSource::new(Light::point(
+                glam::Vec3::ZERO,
+                glam::Vec3::new(1.0, 0.7, 0.3),
+                4.0,
+                10.0,
+            )));
+        }
+
+        let mut systems = Systems::new();
+        systems.add(|entities, step| {
+            for (_, velocity, gravity) in entities.query2_mut::<Velocity, Gravity>() {
+                velocity.linear.y -= gravity.0 * step.as_secs_f32();
+            }
+        });
 
         // Camera
-        let camera_controller = CameraController::new(14.0, 0.4, device, config);
+        let camera_controller = CameraController::new(
+            0.4,
+            settings.graphics.render_distance,
+            device,
+            config,
+        );
         let camera_bind_group_layout = Camera::bind_group_layout(device);
         let camera_bind_group = camera_controller
             .camera
             .create_bind_group(&camera_bind_group_layout, device);
 
-        let instances = vec![Instance {
-            position: glam::Vec3::ZERO,
-            rotation: glam::Quat::from_axis_angle(glam::Vec3::ZERO, 0.0),
-        }];
-        let instance_buffer = InstanceRaw::create_buffer(&instances, device);
+        let clock = WorldClock::new(
+            settings.world.day_length,
+            settings.world.time_scale,
+            settings.world.start_time,
+        );
 
-        let light = Light::new(
-            glam::Vec3::new(2.0, 2.0, 2.0),
+        // The sun and moon get moved and coloured by the clock every frame
+        let mut lights = Lights::new(device, config);
+        lights.fog = Fog::for_render_distance(
+            settings.graphics.fog,
+            settings.graphics.render_distance,
+            settings.graphics.fog_start,
+        );
+        let sun = lights.add(Light {
+            casts_shadows: true,
+            ..Light::directional(-clock.sun_position(), glam::Vec3::ONE, 0.0)
+        });
+        let moon = lights.add(Light::directional(-clock.moon_position(), glam::Vec3::ONE, 0.0));
+        // Never moves, so there's no need to keep its LightId
+        lights.add(Light::point(
+            glam::Vec3::new(21.0, 7.0, 7.0),
             glam::Vec3::new(1.0, 1.0, 1.0),
+            10.0,
+            16.0,
+        ));
+        let shadow_map = ShadowMap::new(device);
+
+        // The world gets drawn in three passes that only differ in how they treat transparency
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Voxel World Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    lights.layout(),
+                    shadow_map.layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+        let light_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, lights.layout()],
+                push_constant_ranges: &[],
+            });
+
+        for shader in [&shaders.color, &shaders.light, &shaders.shadow, &shaders.sky] {
+            resource_manager.shaders.wait(shader, device, queue)?;
+        }
+        // They're all loaded after the waits above
+        let shader = |handle| resource_manager.shaders.get(handle).unwrap();
+        let [render_pipeline, cutout_render_pipeline, translucent_render_pipeline] =
+            Self::create_chunk_pipelines(device, &render_pipeline_layout, config.format, shader(&shaders.color));
+        let model_render_pipeline =
+            Self::create_model_pipeline(device, &render_pipeline_layout, config.format, shader(&shaders.color));
+        let light_render_pipeline =
+            Self::create_light_pipeline(device, &light_pipeline_layout, config.format, shader(&shaders.light));
+        let shadow_render_pipeline = shadow_map.create_pipeline(
             device,
+            &[ChunkVertex::desc(), InstanceRaw::desc()],
+            shader(&shaders.shadow),
         );
-        let light_bind_group_layout = Light::bind_group_layout(device);
-        let light_bind_group = light.create_bind_group(&light_bind_group_layout, device);
-
-        let render_pipeline = {
-            let render_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("WgpuTutorial Pipeline Layout"),
-                    bind_group_layouts: &[
-                        &texture_bind_group_layout,
-                        &camera_bind_group_layout,
-                        &light_bind_group_layout,
-                    ],
-                    push_constant_ranges: &[],
-                });
+        let sky = Sky::new(device, config.format, shader(&shaders.sky));
 
-            create_render_pipeline(
-                device,
-                &render_pipeline_layout,
-                config.format,
-                Some(Texture::DEPTH_FORMAT),
-                &[ModelVertex::desc(), InstanceRaw::desc()],
-                resource_manager
-                    .shaders
-                    .lock()
-                    .unwrap()
-                    .get("color_shader")
-                    .unwrap(),
-                Some("Color Render Pipeline"),
-            )
-        };
+        // Anything that only got loaded to build the world or its models can go now
+        resource_manager.unload_unused();
 
-        let light_render_pipeline = {
-            let light_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Light Pipeline Layout"),
-                    bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout],
-                    push_constant_ranges: &[],
-                });
-
-            create_render_pipeline(
-                device,
-                &light_pipeline_layout,
-                config.format,
-                Some(Texture::DEPTH_FORMAT),
-                &[ModelVertex::desc()],
-                resource_manager
-                    .shaders
-                    .lock()
-                    .unwrap()
-                    .get("light_shader")
-                    .unwrap(),
-                Some("Light Render Pipeline"),
-            )
-        };
-
-        Box::from(Self {
+        Ok(Box::from(Self {
             resource_manager,
+            unload_timer: UNLOAD_INTERVAL,
+            shaders,
             render_pipeline,
+            cutout_render_pipeline,
+            translucent_render_pipeline,
+            model_render_pipeline,
             light_render_pipeline,
+            shadow_render_pipeline,
+            render_pipeline_layout,
+            light_pipeline_layout,
+            color_format: config.format,
             camera_controller,
-            instances,
-            instance_buffer,
+            player,
+            entities,
+            systems,
+            light_sync: LightSync::new(),
+            models,
+            batches: RenderBatches::new(device),
+            break_trigger: ActionTrigger::default(),
+            place_trigger: ActionTrigger::default(),
+            selected_block: Block::Stone,
+            time_faster_trigger: ActionTrigger::default(),
+            time_slower_trigger: ActionTrigger::default(),
+            clock,
+            sky,
             chunk_model,
+            chunk_draws: IndirectDraws::new("Chunk", device),
+            block_updates: BlockUpdates::new(42069),
+            lod_distance: settings.graphics.lod_distance,
             camera_bind_group,
-            light_bind_group,
-            light,
-            mouse_pressed: false,
-        })
+            light_markers: LightMarkers::new(device),
+            lights,
+            sun,
+            moon,
+            shadow_map,
+        }))
     }
 
-    fn update(&mut self, queue: &wgpu::Queue, dt: Duration) {
-        // Updating camera position
-        self.camera_controller.update_camera(dt);
-        self.camera_controller.camera_uniform.update_view_proj(
-            &self.camera_controller.camera,
-            &self.camera_controller.projection,
-        );
-        queue.write_buffer(
-            &self.camera_controller.camera.buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_controller.camera_uniform]),
+    fn fixed_update(&mut self, input: &InputMap, _tick: u64, step: Duration) {
+        // The camera sits in the player's head
+        self.player.update(
+            input,
+            self.camera_controller.camera.yaw(),
+            &self.chunk_model,
+            step,
         );
+        self.camera_controller.follow(self.player.eye_position());
 
-        // Update light position
-        //self.light.light_uniform.position =
-        //    glam::Quat::from_axis_angle(glam::Vec3::new(0.0, 1.0, 0.0), 1.0 * dt.as_secs_f32())
-        //        * self.light.light_uniform.position;
+        self.edit_blocks(input);
+        self.block_updates.tick(&mut self.chunk_model);
 
-        self.light.light_uniform.position = glam::Vec3::new(21.0, 7.0, 7.0);
+        self.systems.run(&mut self.entities, step);
+        systems::movement(&mut self.entities, step, Some(&self.chunk_model));
+        self.light_sync.run(&mut self.entities, self.lights.list_mut());
 
+        if self.time_faster_trigger.update(input.pressed("time_faster")) {
+            self.clock.set_time_scale(self.clock.time_scale() * 2.0);
+        }
+        if self.time_slower_trigger.update(input.pressed("time_slower")) {
+            self.clock.set_time_scale(self.clock.time_scale() / 2.0);
+        }
+        self.clock.advance(step);
+    }
+
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        input: &InputMap,
+        dt: Duration,
+        alpha: f32,
+    ) {
+        self.resource_manager.update(device, queue);
+        self.unload_timer = self.unload_timer.saturating_sub(dt);
+        if self.unload_timer.is_zero() {
+            self.unload_timer = UNLOAD_INTERVAL;
+            self.resource_manager.unload_unused();
+        }
+        self.lights.time += dt.as_secs_f32();
+        self.batches.update(&self.entities, device, queue);
+
+        // Anything we dug, built or that flowed this frame needs new meshes
+        let eye = self.camera_controller.camera.position;
+        self.chunk_model.rebuild_dirty(device, queue);
+        self.chunk_model.select_lods(device, queue, eye, self.lod_distance);
+        self.chunk_model.sort_translucent(queue, eye);
+
+        // Looking around is done every frame, moving is done every tick
+        self.camera_controller.frame_input(input);
+        self.camera_controller.update_rotation(dt);
+        self.camera_controller.update_view_proj(alpha);
         queue.write_buffer(
-            &self.light.buffer,
+            &self.camera_controller.camera.buffer,
             0,
-            bytemuck::cast_slice(&[self.light.light_uniform]),
+            bytemuck::cast_slice(&[self.camera_controller.camera_uniform]),
         );
+
+        self.update_sky_lights();
+
+        let view = self.camera_controller.view_matrix(alpha);
+        self.sky
+            .update(queue, &self.clock, view, &self.camera_controller.projection);
+        self.lights.update(device, queue, view, &self.camera_controller.projection);
+
+        // Only the chunks the camera can see get drawn
+        let frustum = Frustum::from_matrix(self.camera_controller.projection.calc_matrix() * view);
+        let batches = ChunkBatch::ALL
+            .map(|batch| self.chunk_draw_args(batch, &frustum))
+            .to_vec();
+        self.chunk_draws.write(batches, device, queue);
+
+        // Fit the shadow cascades around what the camera can see
+        if let Some(Light {
+            kind: LightKind::Directional { direction },
+            ..
+        }) = self.lights.get(self.sun)
+        {
+            self.shadow_map
+                .update(queue, view, &self.camera_controller.projection, *direction);
+        }
     }
 
     fn render(&mut self, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        // The sun's view of the world goes first, so the main pass can look up shadows.
+        for cascade in 0..CASCADES {
+            let mut shadow_pass = self.shadow_map.begin_cascade_pass(encoder, cascade);
+            shadow_pass.set_pipeline(&self.shadow_render_pipeline);
+            shadow_pass.set_vertex_buffer(1, self.chunk_model.instance_buffer().slice(..));
+            shadow_pass.set_bind_group(0, self.shadow_map.cascade_bind_group(cascade), &[]);
+            shadow_pass.draw_pool_indirect(self.chunk_model.pool(), &self.chunk_draws, ChunkBatch::Shadow as usize);
+        }
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[
@@ -187,12 +624,8 @@ impl Scene for VoxelWorld {
                     view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        // The sky covers everything anyway
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: wgpu::StoreOp::Store,
                     },
                 }),
@@ -209,49 +642,31 @@ impl Scene for VoxelWorld {
             timestamp_writes: None,
         });
 
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        self.sky.render(&mut render_pass);
 
         render_pass.set_pipeline(&self.light_render_pipeline);
-        render_pass.draw_light_model(
-            &self.chunk_model.model[0],
-            &self.camera_bind_group,
-            &self.light_bind_group,
-        );
-
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.draw_model_instanced(
-            &self.chunk_model.model[0],
-            0..self.instances.len() as u32,
-            &self.camera_bind_group,
-            &self.light_bind_group,
-        );
-    }
-
-    fn input(&mut self, event: &WindowEvent) {
-        match event {
-            WindowEvent::KeyboardInput { event, .. } => {
-                self.camera_controller.keyboard_input(event);
-            }
-            WindowEvent::MouseWheel { delta, .. } => {
-                self.camera_controller.scroll_input(delta);
-            }
-            WindowEvent::MouseInput {
-                button: MouseButton::Left,
-                state,
-                ..
-            } => {
-                self.mouse_pressed = *state == ElementState::Pressed;
-            }
-            _ => (),
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.lights.bind_group, &[]);
+        self.light_markers.draw(&mut render_pass, &self.lights);
+
+        // Solid blocks first, then the ones with holes in them, then the entities, and finally
+        // everything see-through from back to front so it blends over what's behind it.
+        self.draw_chunks(&mut render_pass, &self.render_pipeline, ChunkBatch::Opaque);
+        self.draw_chunks(&mut render_pass, &self.cutout_render_pipeline, ChunkBatch::Cutout);
+
+        // The entities' Models bring their own Materials and instances
+        render_pass.set_pipeline(&self.model_render_pipeline);
+        for (model, _) in self.batches.batches() {
+            self.batches.draw(
+                &mut render_pass,
+                model,
+                &self.models,
+                &self.camera_bind_group,
+                &self.lights.bind_group,
+            );
         }
-    }
 
-    fn device_input(&mut self, event: &DeviceEvent) {
-        if let DeviceEvent::MouseMotion { delta } = event {
-            if self.mouse_pressed {
-                self.camera_controller.mouse_input(delta.0, delta.1);
-            }
-        }
+        self.draw_chunks(&mut render_pass, &self.translucent_render_pipeline, ChunkBatch::Translucent);
     }
 
     fn resize(
@@ -264,5 +679,60 @@ impl Scene for VoxelWorld {
         self.camera_controller
             .projection
             .resize(new_size.width, new_size.height);
+        self.lights.resize(new_size.width, new_size.height);
+    }
+
+    fn reload_shaders(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, shader_files: &[String]) {
+        let reloaded = self.resource_manager.reload_shaders(shader_files, device, queue);
+
+        for handle in reloaded {
+            let shader = self.resource_manager.shaders.get(&handle).unwrap();
+            // Pipelines that don't build with the new shader keep the old one
+            let result = if handle == self.shaders.color {
+                catch_validation_error(device, || {
+                    Self::create_chunk_pipelines(device, &self.render_pipeline_layout, self.color_format, shader)
+                })
+                .and_then(|chunk_pipelines| {
+                    let model_pipeline = catch_validation_error(device, || {
+                        Self::create_model_pipeline(device, &self.render_pipeline_layout, self.color_format, shader)
+                    })?;
+                    Ok((chunk_pipelines, model_pipeline))
+                })
+                .map(|([opaque, cutout, translucent], model)| {
+                    self.render_pipeline = opaque;
+                    self.cutout_render_pipeline = cutout;
+                    self.translucent_render_pipeline = translucent;
+                    self.model_render_pipeline = model;
+                })
+            } else if handle == self.shaders.light {
+                catch_validation_error(device, || {
+                    Self::create_light_pipeline(device, &self.light_pipeline_layout, self.color_format, shader)
+                })
+                .map(|pipeline| self.light_render_pipeline = pipeline)
+            } else if handle == self.shaders.shadow {
+                catch_validation_error(device, || {
+                    self.shadow_map
+                        .create_pipeline(device, &[ChunkVertex::desc(), InstanceRaw::desc()], shader)
+                })
+                .map(|pipeline| self.shadow_render_pipeline = pipeline)
+            } else if handle == self.shaders.sky {
+                self.sky.reload_shader(device, shader)
+            } else if self.shaders.chunk_mesh.as_ref() == Some(&handle) {
+                match self.chunk_model.gpu_mesher_mut() {
+                    Some(gpu_mesher) => gpu_mesher.reload_shader(device, shader),
+                    None => Ok(()),
+                }
+            } else {
+                Ok(())
+            };
+
+            if let Err(e) = result {
+                log::error!("Couldn't rebuild the pipelines using {}, keeping the old ones:\n{e}", handle.path());
+            }
+        }
+    }
+
+    fn lights(&mut self) -> &mut Lights {
+        &mut self.lights
     }
 }
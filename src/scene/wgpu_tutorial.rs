@@ -1,185 +1,380 @@
 use std::time::Duration;
+use crate::engine::resource::animation::{Animator, DrawSkinnedModel, SkinBuffer, SkinVertex};
+use crate::engine::resource::gltf::load_gltf;
 use crate::engine::resource::instance::{Instance, InstanceRaw};
-use crate::engine::resource::light::Light;
+use crate::engine::resource::light::{Light, Lights};
 use winit::dpi::PhysicalSize;
-use winit::event::{WindowEvent, MouseButton, ElementState, DeviceEvent};
 
-use crate::engine::resource::model::{DrawLight, DrawModel, Model, ModelVertex};
+use crate::engine::assets::Handle;
+use crate::engine::error::{Error, Result};
+use crate::engine::input::InputMap;
+use crate::engine::resource::model::{DrawLight, Material, Model, ModelVertex};
+use crate::engine::resource::shadow::ShadowMap;
 use crate::engine::resource::texture::Texture;
 use crate::engine::resource_manager::ResourceManager;
-use crate::engine::util::{create_render_pipeline, load_model, Vertex};
+use crate::engine::settings::Settings;
+use crate::engine::util::{catch_validation_error, create_render_pipeline, load_model, BlendMode, Vertex};
 use crate::entity::camera::{Camera, CameraController};
+use crate::entity::components::{Collider, LightSource, Renderable, Transform, Velocity};
+use crate::entity::ecs::{Systems, World};
+use crate::entity::render::{ModelId, Models, RenderBatches};
+use crate::entity::systems::{self, LightSync};
 use crate::scene::scene::Scene;
 
-#[allow(dead_code)]
+/// How fast the camera flies around.
+const CAMERA_SPEED: f32 = 4.0;
+
 pub struct WgpuTutorial {
     resource_manager: ResourceManager,
+    color_shader: Handle<wgpu::ShaderModule>,
+    light_shader: Handle<wgpu::ShaderModule>,
     render_pipeline: wgpu::RenderPipeline,
+    pbr_render_pipeline: wgpu::RenderPipeline,
+    skinned_render_pipeline: wgpu::RenderPipeline,
     light_render_pipeline: wgpu::RenderPipeline,
-
-    obj_model: Model,
+    /// Kept around so the pipelines can be rebuilt when their shaders get reloaded.
+    render_pipeline_layout: wgpu::PipelineLayout,
+    skinned_pipeline_layout: wgpu::PipelineLayout,
+    light_pipeline_layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+
+    models: Models,
+    obj_cube: ModelId,
+    /// Drawn with PBR on the far half of the cubes, next to the .obj one.
+    gltf_cube: ModelId,
+
+    /// The cubes and the lamp.
+    world: World,
+    systems: Systems,
+    light_sync: LightSync,
+    batches: RenderBatches,
+
+    /// Stands on the middle cube, switching between its animations every now and then.
+    arm_model: Model,
+    arm_animator: Animator,
+    arm_skin: SkinBuffer,
+    arm_instance_buffer: wgpu::Buffer,
+    /// Until the arm switches to its other animation.
+    arm_switch: Duration,
 
     camera_controller: CameraController,
     camera_bind_group: wgpu::BindGroup,
 
-    instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
+    lights: Lights,
+    // Nothing here casts shadows, but the shader still wants a ShadowMap bound
+    shadow_map: ShadowMap,
+
+}
+
+impl WgpuTutorial {
+    /// The Phong, PBR and skinned pipelines the models get drawn with.
+    ///
+    /// * `layouts` - The one the first two share, and the skinned one.
+    fn create_model_pipelines(
+        device: &wgpu::Device,
+        [layout, skinned_layout]: [&wgpu::PipelineLayout; 2],
+        color_format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+    ) -> [wgpu::RenderPipeline; 3] {
+        let model_pipeline = |layout, buffers: &[wgpu::VertexBufferLayout], vertex_entry, fragment_entry, label| {
+            create_render_pipeline(
+                device,
+                layout,
+                color_format,
+                Some(Texture::DEPTH_FORMAT),
+                buffers,
+                shader,
+                vertex_entry,
+                fragment_entry,
+                BlendMode::Opaque,
+                Some(label),
+            )
+        };
 
-    light: Light,
-    light_bind_group: wgpu::BindGroup,
+        [
+            model_pipeline(layout, &[ModelVertex::desc(), InstanceRaw::desc()], "vs_main", "fs_main", "Color Render Pipeline"),
+            // Same thing, just with metallic-roughness materials
+            model_pipeline(layout, &[ModelVertex::desc(), InstanceRaw::desc()], "vs_main", "fs_pbr", "PBR Render Pipeline"),
+            // And once more for models with a skeleton, those have their joints where the camera goes
+            model_pipeline(
+                skinned_layout,
+                &[ModelVertex::desc(), InstanceRaw::desc(), SkinVertex::desc()],
+                "vs_skinned",
+                "fs_pbr",
+                "Skinned Render Pipeline",
+            ),
+        ]
+    }
 
-    mouse_pressed: bool,
+    fn create_light_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        create_render_pipeline(
+            device,
+            layout,
+            color_format,
+            Some(Texture::DEPTH_FORMAT),
+            &[ModelVertex::desc()],
+            shader,
+            "vs_main",
+            "fs_main",
+            BlendMode::Opaque,
+            Some("Light Render Pipeline"),
+        )
+    }
 }
 
 const NUM_INSTANCES_PER_ROW: u32 = 10;
 
+/// Circles around the Y axis, this many radians per second.
+struct Orbit(f32);
+const ARM_SWITCH_TIME: Duration = Duration::from_secs(6);
+
 impl Scene for WgpuTutorial {
+    const INPUT_CONTEXT: &'static str = "wgpu_tutorial";
+
     async fn new(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         queue: &wgpu::Queue,
-    ) -> Box<Self> {
+        _settings: &Settings,
+    ) -> Result<Box<Self>> {
         let mut resource_manager = ResourceManager::new(device, config);
 
-        // Shader setup
-        resource_manager.load_shader(
-            "shader.wgsl",
-            "color_shader",
-            device
-        ).await;
+        // Shader setup, they load while the model does
+        let color_shader = resource_manager.shaders.load("shaders/shader.wgsl");
+        let light_shader = resource_manager.shaders.load("shaders/light.wgsl");
 
-        resource_manager.load_shader(
-            "light.wgsl",
-            "light_shader",
-            device
-        ).await;
+        let texture_bind_group_layout = Material::bind_group_layout(device);
 
-        let texture_bind_group_layout = Texture::bind_group_layout(device);
+        let mut models = Models::new();
+        let obj_cube = models.add(
+            load_model("cube.obj", &mut resource_manager, device, queue, &texture_bind_group_layout).await?,
+        );
+        let gltf_cube = models.add(
+            load_gltf("cube.gltf", &mut resource_manager, device, queue, &texture_bind_group_layout).await?,
+        );
+        let arm_model =
+            load_gltf("arm.gltf", &mut resource_manager, device, queue, &texture_bind_group_layout).await?;
 
-        let obj_model = load_model("cube.obj", device, queue, &texture_bind_group_layout)
-            .await
-            .unwrap();
+        // After these, `get` always has them
+        resource_manager.shaders.wait(&color_shader, device, queue)?;
+        resource_manager.shaders.wait(&light_shader, device, queue)?;
 
         // Camera
-        let camera_controller = CameraController::new(4.0, 0.4, device, config);
+        let camera_controller = CameraController::new(0.4, 100.0, device, config);
         let camera_bind_group_layout = Camera::bind_group_layout(device);
         let camera_bind_group = camera_controller
             .camera
             .create_bind_group(&camera_bind_group_layout, device);
 
-        // Instances - iterate through the amount we have, then create a buffer.
-        let instances = (0..NUM_INSTANCES_PER_ROW)
-            .flat_map(|z| {
-                (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-                    let x = 3.0 * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-                    let z = 3.0 * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-
-                    let position = glam::Vec3 { x, y: 0.0, z };
-
-                    let rotation = if position.is_nan() {
-                        // Needed so an object at (0, 0, 0) doesn't get scaled to 0
-                        // since Quaternions can effect scale if they're not "correct"
-                        glam::Quat::from_axis_angle(glam::Vec3::Z, 0.0)
-                    } else {
-                        glam::Quat::from_axis_angle(position.normalize(), 45.0)
-                    };
-
-                    Instance { position, rotation }
-                })
-            })
-            .collect::<Vec<_>>();
-        let instance_buffer = InstanceRaw::create_buffer(&instances, device);
-
-        let light = Light::new(
-            glam::Vec3::new(2.0, 2.0, 2.0),
-            glam::Vec3::new(1.0, 1.0, 1.0),
+        let skin_bind_group_layout = SkinBuffer::bind_group_layout(device);
+        let Some(arm_skeleton) = &arm_model.skeleton else {
+            return Err(Error::Asset("arm.gltf doesn't have a skeleton".to_string()));
+        };
+        let arm_skin = SkinBuffer::new(arm_skeleton, &camera_controller.camera, &skin_bind_group_layout, device);
+        let mut arm_animator = Animator::new();
+        if let Some(sway) = arm_model.animation("sway") {
+            arm_animator.play(sway, true, 0.0);
+        }
+        // On top of the cube in the middle
+        let arm_instance_buffer = InstanceRaw::create_buffer(
+            &[Instance {
+                position: glam::Vec3::new(0.0, 1.0, 0.0),
+                ..Default::default()
+            }],
             device,
         );
-        let light_bind_group_layout = Light::bind_group_layout(device);
-        let light_bind_group = light.create_bind_group(&light_bind_group_layout, device);
-
-        let render_pipeline = {
-            let render_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("WgpuTutorial Pipeline Layout"),
-                    bind_group_layouts: &[
-                        &texture_bind_group_layout,
-                        &camera_bind_group_layout,
-                        &light_bind_group_layout,
-                    ],
-                    push_constant_ranges: &[],
-                });
 
-            create_render_pipeline(
-                device,
-                &render_pipeline_layout,
-                config.format,
-                Some(Texture::DEPTH_FORMAT),
-                &[ModelVertex::desc(), InstanceRaw::desc()],
-                resource_manager.shaders.lock().unwrap().get("color_shader").unwrap(),
-                Some("Color Render Pipeline")
-            )
-        };
+        // A grid of cubes, the near half from the .obj and the far half from the .gltf
+        let mut world = World::new();
+        for z in 0..NUM_INSTANCES_PER_ROW {
+            for x in 0..NUM_INSTANCES_PER_ROW {
+                let position = glam::Vec3 {
+                    x: 3.0 * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0),
+                    y: 0.0,
+                    z: 3.0 * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0),
+                };
+
+                let rotation = if position == glam::Vec3::ZERO {
+                    // Needed so an object at (0, 0, 0) doesn't get scaled to 0
+                    // since Quaternions can effect scale if they're not "correct"
+                    glam::Quat::from_axis_angle(glam::Vec3::Z, 0.0)
+                } else {
+                    glam::Quat::from_axis_angle(position.normalize(), 45.0)
+                };
+
+                let cube = world.spawn();
+                world.insert(cube, Transform { translation: position, rotation, ..Default::default() });
+                world.insert(cube, Collider::new(glam::Vec3::ONE));
+                let mut renderable =
+                    Renderable::new(if z < NUM_INSTANCES_PER_ROW / 2 { obj_cube } else { gltf_cube });
+                // The ones along the edges spin slowly, and are a bit warmer
+                if x == 0 || z == 0 || x == NUM_INSTANCES_PER_ROW - 1 || z == NUM_INSTANCES_PER_ROW - 1 {
+                    world.insert(cube, Velocity { angular: glam::Vec3::Y * 0.5, ..Default::default() });
+                    renderable.tint = glam::Vec4::new(1.0, 0.8, 0.6, 1.0);
+                }
+                world.insert(cube, renderable);
+            }
+        }
 
-        let light_render_pipeline = {
-            let light_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Light Pipeline Layout"),
-                    bind_group_layouts: &[
-                        &camera_bind_group_layout,
-                        &light_bind_group_layout,
-                    ],
-                    push_constant_ranges: &[],
-                });
+        let lamp = world.spawn();
+        world.insert(lamp, Transform { translation: glam::Vec3::new(2.0, 2.0, 2.0), ..Default::default() });
+        world.insert(lamp, LightSource::new(Light::point(
+            glam::Vec3::ZERO,
+            glam::Vec3::new(1.0, 1.0, 1.0),
+            10.0,
+            30.0,
+        )));
+        world.insert(lamp, Orbit(1.0));
+
+        let mut systems = Systems::new();
+        systems.add(|world, step| {
+            for (_, transform, orbit) in world.query2_mut::<Transform, Orbit>() {
+                let turn = glam::Quat::from_axis_angle(glam::Vec3::Y, orbit.0 * step.as_secs_f32());
+                transform.translation = turn * transform.translation;
+            }
+        });
 
-            create_render_pipeline(
-                device,
-                &light_pipeline_layout,
-                config.format,
-                Some(Texture::DEPTH_FORMAT),
-                &[ModelVertex::desc()],
-                resource_manager.shaders.lock().unwrap().get("light_shader").unwrap(),
-                Some("Light Render Pipeline"),
-            )
-        };
+        let mut lights = Lights::new(device, config);
+        let mut light_sync = LightSync::new();
+        light_sync.run(&mut world, lights.list_mut());
+        let shadow_map = ShadowMap::new(device);
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("WgpuTutorial Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    lights.layout(),
+                    shadow_map.layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let skinned_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skinned Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &skin_bind_group_layout,
+                    lights.layout(),
+                    shadow_map.layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+        let light_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light Pipeline Layout"),
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    lights.layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let [render_pipeline, pbr_render_pipeline, skinned_render_pipeline] = Self::create_model_pipelines(
+            device,
+            [&render_pipeline_layout, &skinned_pipeline_layout],
+            config.format,
+            resource_manager.shaders.get(&color_shader).unwrap(),
+        );
+        let light_render_pipeline = Self::create_light_pipeline(
+            device,
+            &light_pipeline_layout,
+            config.format,
+            resource_manager.shaders.get(&light_shader).unwrap(),
+        );
 
-        Box::from(Self {
+        Ok(Box::from(Self {
             resource_manager,
+            color_shader,
+            light_shader,
             render_pipeline,
+            pbr_render_pipeline,
+            skinned_render_pipeline,
             light_render_pipeline,
+            render_pipeline_layout,
+            skinned_pipeline_layout,
+            light_pipeline_layout,
+            color_format: config.format,
             camera_controller,
-            instances,
-            instance_buffer,
-            obj_model,
+            models,
+            obj_cube,
+            gltf_cube,
+            world,
+            systems,
+            light_sync,
+            batches: RenderBatches::new(device),
+            arm_model,
+            arm_animator,
+            arm_skin,
+            arm_instance_buffer,
+            arm_switch: ARM_SWITCH_TIME,
             camera_bind_group,
-            light_bind_group,
-            light,
-            mouse_pressed: false
-        })
+            lights,
+            shadow_map,
+        }))
     }
 
-    fn update(&mut self, queue: &wgpu::Queue, dt: Duration) {
+    fn fixed_update(&mut self, input: &InputMap, _tick: u64, step: Duration) {
         // Updating camera position
-        self.camera_controller.update_camera(dt);
-        self.camera_controller
-            .camera_uniform
-            .update_view_proj(&self.camera_controller.camera, &self.camera_controller.projection);
+        self.camera_controller.input(input);
+        self.camera_controller.update_position(CAMERA_SPEED, step);
+
+        self.systems.run(&mut self.world, step);
+        systems::movement(&mut self.world, step, None);
+        self.light_sync.run(&mut self.world, self.lights.list_mut());
+
+        // Take turns between swaying and twisting, fading over so it doesn't snap
+        self.arm_switch = self.arm_switch.saturating_sub(step);
+        if self.arm_switch.is_zero() {
+            self.arm_switch = ARM_SWITCH_TIME;
+            let next = match self.arm_animator.playing() {
+                Some(playing) => (playing + 1) % self.arm_model.animations.len(),
+                None => 0,
+            };
+            self.arm_animator.play(next, true, 0.5);
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        input: &InputMap,
+        dt: Duration,
+        alpha: f32,
+    ) {
+        self.resource_manager.update(device, queue);
+        self.batches.update(&self.world, device, queue);
+
+        // Looking around is done every frame, moving is done every tick
+        self.camera_controller.frame_input(input);
+        self.camera_controller.update_rotation(dt);
+        self.camera_controller.update_view_proj(alpha);
         queue.write_buffer(
             &self.camera_controller.camera.buffer,
             0,
             bytemuck::cast_slice(&[self.camera_controller.camera_uniform]),
         );
 
-        // Update light position
-        self.light.light_uniform.position =
-            glam::Quat::from_axis_angle(glam::Vec3::new(0.0, 1.0, 0.0), 1.0 * dt.as_secs_f32())
-                * self.light.light_uniform.position;
-
-        queue.write_buffer(
-            &self.light.buffer,
-            0,
-            bytemuck::cast_slice(&[self.light.light_uniform]),
+        self.lights.update(
+            device,
+            queue,
+            self.camera_controller.view_matrix(alpha),
+            &self.camera_controller.projection,
         );
+
+        if let Some(skeleton) = &self.arm_model.skeleton {
+            self.arm_animator.update(dt.as_secs_f32(), &self.arm_model.animations);
+            let pose = self.arm_animator.pose(skeleton, &self.arm_model.animations);
+            self.arm_skin.write(queue, &skeleton.joint_matrices(&pose));
+        }
     }
 
     fn render(&mut self, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
@@ -213,48 +408,36 @@ impl Scene for WgpuTutorial {
             timestamp_writes: None,
         });
 
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-
         render_pass.set_pipeline(&self.light_render_pipeline);
-        render_pass.draw_light_model(
-            &self.obj_model,
+        render_pass.draw_light_model_instanced(
+            self.models.get(self.obj_cube),
+            0..self.lights.len() as u32,
             &self.camera_bind_group,
-            &self.light_bind_group,
+            &self.lights.bind_group,
         );
 
         render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.draw_model_instanced(
-            &self.obj_model,
-            0..self.instances.len() as u32,
+        render_pass.set_bind_group(3, &self.shadow_map.bind_group, &[]);
+        self.batches.draw(
+            &mut render_pass,
+            self.obj_cube,
+            &self.models,
             &self.camera_bind_group,
-            &self.light_bind_group,
+            &self.lights.bind_group,
         );
-    }
 
-    fn input(&mut self, event: &WindowEvent) {
-        match event {
-            WindowEvent::KeyboardInput { event, .. } => {
-                self.camera_controller.keyboard_input(event);
-            }
-            WindowEvent::MouseWheel { delta, ..} => {
-                self.camera_controller.scroll_input(delta);
-            }
-            WindowEvent::MouseInput {
-                button: MouseButton::Left, state,
-                ..
-            } => {
-                self.mouse_pressed = *state == ElementState::Pressed;
-            }
-            _ => (),
-        }
-    }
+        render_pass.set_pipeline(&self.pbr_render_pipeline);
+        self.batches.draw(
+            &mut render_pass,
+            self.gltf_cube,
+            &self.models,
+            &self.camera_bind_group,
+            &self.lights.bind_group,
+        );
 
-    fn device_input(&mut self, event: &DeviceEvent) {
-        if let DeviceEvent::MouseMotion { delta} = event {
-            if self.mouse_pressed {
-                self.camera_controller.mouse_input(delta.0, delta.1);
-            }
-        }
+        render_pass.set_pipeline(&self.skinned_render_pipeline);
+        render_pass.set_vertex_buffer(1, self.arm_instance_buffer.slice(..));
+        render_pass.draw_skinned_model_instanced(&self.arm_model, 0..1, &self.arm_skin, &self.lights.bind_group);
     }
 
     fn resize(
@@ -265,5 +448,98 @@ impl Scene for WgpuTutorial {
     ) {
         self.resource_manager.depth_texture = Texture::create_depth_texture(device, config);
         self.camera_controller.projection.resize(new_size.width, new_size.height);
+        self.lights.resize(new_size.width, new_size.height);
+    }
+
+    fn reload_shaders(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, shader_files: &[String]) {
+        let reloaded = self.resource_manager.reload_shaders(shader_files, device, queue);
+
+        for handle in reloaded {
+            let shader = self.resource_manager.shaders.get(&handle).unwrap();
+            // Pipelines that don't build with the new shader keep the old one
+            let result = if handle == self.color_shader {
+                catch_validation_error(device, || {
+                    Self::create_model_pipelines(
+                        device,
+                        [&self.render_pipeline_layout, &self.skinned_pipeline_layout],
+                        self.color_format,
+                        shader,
+                    )
+                })
+                .map(|[color, pbr, skinned]| {
+                    self.render_pipeline = color;
+                    self.pbr_render_pipeline = pbr;
+                    self.skinned_render_pipeline = skinned;
+                })
+            } else if handle == self.light_shader {
+                catch_validation_error(device, || {
+                    Self::create_light_pipeline(device, &self.light_pipeline_layout, self.color_format, shader)
+                })
+                .map(|pipeline| self.light_render_pipeline = pipeline)
+            } else {
+                Ok(())
+            };
+
+            if let Err(e) = result {
+                log::error!("Couldn't rebuild the pipelines using {}, keeping the old ones:\n{e}", handle.path());
+            }
+        }
+    }
+
+    fn lights(&mut self) -> &mut Lights {
+        &mut self.lights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::engine::util::software_device;
+
+    /// Builds every pipeline (Phong, PBR, skinned and lights), so any of them not fitting their
+    /// bind groups or vertex layouts fails validation, then animates and draws a frame.
+    #[test]
+    fn builds_and_renders() {
+        let (device, queue) = software_device();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: 64,
+            height: 64,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+        let mut scene = pollster::block_on(WgpuTutorial::new(&device, &config, &queue, &Settings::default())).unwrap();
+
+        let input = InputMap::new(HashMap::new());
+        let step = Duration::from_millis(16);
+        for tick in 0..10 {
+            scene.fixed_update(&input, tick, step);
+            scene.update(&device, &queue, &input, step, 1.0);
+        }
+
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Test Target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = target.create_view(&Default::default());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        scene.render(&view, &mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+        device.poll(wgpu::Maintain::Wait);
     }
 }
@@ -1,3 +1,11 @@
+pub mod behaviour;
+pub mod block;
 pub mod chunk;
-pub mod world;
-pub mod util;
\ No newline at end of file
+pub mod collision;
+pub mod util;
+pub mod light;
+pub mod fluid;
+pub mod gpu_mesher;
+pub mod lod;
+pub mod update;
+pub mod vertex;
\ No newline at end of file
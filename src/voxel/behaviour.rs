@@ -0,0 +1,214 @@
+use crate::voxel::block::Block;
+use crate::voxel::chunk::ChunkModel;
+use crate::voxel::fluid;
+use crate::voxel::light::LightChannel;
+use crate::voxel::update::BlockUpdates;
+
+/// Gameplay rules for a kind of block, run by `BlockUpdates`.
+/// Everything does nothing by default, so a behaviour only has to fill in what it reacts to.
+pub trait BlockBehaviour {
+    /// The voxel at `world_pos` or one right next to it changed.
+    fn neighbour_changed(&self, _world: &mut ChunkModel, _updates: &mut BlockUpdates, _world_pos: glam::IVec3) {}
+
+    /// A tick this block scheduled came up.
+    fn scheduled_tick(&self, _world: &mut ChunkModel, _updates: &mut BlockUpdates, _world_pos: glam::IVec3) {}
+
+    /// This block got picked at random. Good for things that should happen slowly and
+    /// all over the place, like plants growing.
+    fn random_tick(&self, _world: &mut ChunkModel, _updates: &mut BlockUpdates, _world_pos: glam::IVec3) {}
+}
+
+/// Blocks that don't do anything on their own.
+pub struct Inert;
+
+impl BlockBehaviour for Inert {}
+
+/// How many ticks a falling block waits before it drops another voxel.
+const FALL_DELAY: u64 = 2;
+
+/// Blocks like sand that fall down when there's nothing under them.
+/// They drop one voxel at a time, pushing fluids out of the way.
+pub struct Falling;
+
+impl BlockBehaviour for Falling {
+    fn neighbour_changed(&self, world: &mut ChunkModel, updates: &mut BlockUpdates, world_pos: glam::IVec3) {
+        if !world.is_solid(world_pos - glam::IVec3::Y) {
+            updates.schedule(world_pos, FALL_DELAY);
+        }
+    }
+
+    fn scheduled_tick(&self, world: &mut ChunkModel, _updates: &mut BlockUpdates, world_pos: glam::IVec3) {
+        let below = world_pos - glam::IVec3::Y;
+        // Don't fall out of the world
+        if world.is_solid(below) || !world.is_loaded(below) {
+            return;
+        }
+
+        let block = world.block(world_pos);
+        // Whatever fluid was under it swaps places with it, and flows on from there
+        let displaced = world.block(below);
+        if displaced.is_fluid() {
+            let level = world.fluid_level(below);
+            world.set_fluid(world_pos, displaced, level);
+        } else {
+            world.set_block(world_pos, Block::Air);
+        }
+        // Moving the block counts as a change, so it gets to fall again from there
+        world.set_block(below, block);
+    }
+}
+
+/// Grass and dirt both need at least this much light for grass to grow on them.
+const GRASS_LIGHT: u8 = 9;
+
+/// Grass dies when it's covered up, and slowly spreads to dirt around it otherwise.
+pub struct Grass;
+
+impl Grass {
+    /// Whatever's above the voxel doesn't smother grass, and there's enough light to grow.
+    fn can_grow(world: &ChunkModel, world_pos: glam::IVec3) -> bool {
+        let above = world_pos + glam::IVec3::Y;
+        let light = world
+            .light(above, LightChannel::Sun)
+            .max(world.light(above, LightChannel::Block));
+
+        !world.block(above).is_opaque() && !world.block(above).is_fluid() && light >= GRASS_LIGHT
+    }
+}
+
+impl BlockBehaviour for Grass {
+    fn random_tick(&self, world: &mut ChunkModel, updates: &mut BlockUpdates, world_pos: glam::IVec3) {
+        let above = world.block(world_pos + glam::IVec3::Y);
+        if above.is_opaque() || above.is_fluid() {
+            world.set_block(world_pos, Block::Dirt);
+            return;
+        }
+        if !Self::can_grow(world, world_pos) {
+            return;
+        }
+
+        // Try some dirt nearby, a bit further up or down too
+        let target = world_pos
+            + glam::IVec3::new(
+                updates.random(3) as i32 - 1,
+                updates.random(3) as i32 - 1,
+                updates.random(3) as i32 - 1,
+            );
+        if world.block(target) == Block::Dirt && Self::can_grow(world, target) {
+            world.set_block(target, Block::Grass);
+        }
+    }
+}
+
+/// Water and lava. The rules for how they flow live in `voxel::fluid`.
+pub struct Fluid;
+
+impl BlockBehaviour for Fluid {
+    fn neighbour_changed(&self, world: &mut ChunkModel, updates: &mut BlockUpdates, world_pos: glam::IVec3) {
+        updates.schedule(world_pos, world.block(world_pos).fluid_delay());
+    }
+
+    fn scheduled_tick(&self, world: &mut ChunkModel, _updates: &mut BlockUpdates, world_pos: glam::IVec3) {
+        fluid::update(world, world_pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: i32, y: i32, z: i32) -> glam::IVec3 {
+        glam::IVec3::new(x, y, z)
+    }
+
+    /// An empty, lit Chunk with a stone floor at y = 0 and some blocks in it.
+    fn floor_with(blocks: &[(glam::IVec3, Block)]) -> ChunkModel {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        world.fill(at(0, 0, 0), at(31, 0, 31), Block::Stone);
+        for &(world_pos, block) in blocks {
+            world.fill(world_pos, world_pos, block);
+        }
+        world.light_all();
+        world
+    }
+
+    #[test]
+    fn falling_blocks_stop_on_solid_ground() {
+        let mut world = floor_with(&[(at(5, 2, 5), Block::Gravel)]);
+        let mut updates = BlockUpdates::new(1);
+
+        Falling.scheduled_tick(&mut world, &mut updates, at(5, 2, 5));
+        assert_eq!(world.block(at(5, 1, 5)), Block::Gravel);
+        assert_eq!(world.block(at(5, 2, 5)), Block::Air);
+
+        Falling.scheduled_tick(&mut world, &mut updates, at(5, 1, 5));
+        assert_eq!(world.block(at(5, 1, 5)), Block::Gravel);
+    }
+
+    #[test]
+    fn falling_blocks_stay_above_unloaded_chunks() {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        world.fill(at(5, 0, 5), at(5, 0, 5), Block::Sand);
+        let mut updates = BlockUpdates::new(1);
+
+        Falling.neighbour_changed(&mut world, &mut updates, at(5, 0, 5));
+        Falling.scheduled_tick(&mut world, &mut updates, at(5, 0, 5));
+        assert_eq!(world.block(at(5, 0, 5)), Block::Sand);
+    }
+
+    #[test]
+    fn falling_blocks_swap_places_with_fluid() {
+        let mut world = floor_with(&[(at(5, 2, 5), Block::Sand)]);
+        world.set_fluid(at(5, 1, 5), Block::Water, 3);
+        let mut updates = BlockUpdates::new(1);
+
+        Falling.scheduled_tick(&mut world, &mut updates, at(5, 2, 5));
+
+        assert_eq!(world.block(at(5, 1, 5)), Block::Sand);
+        assert_eq!(world.block(at(5, 2, 5)), Block::Water);
+        assert_eq!(world.fluid_level(at(5, 2, 5)), 3);
+    }
+
+    #[test]
+    fn fluids_flow_on_their_tick() {
+        let mut world = floor_with(&[]);
+        world.set_fluid(at(5, 1, 5), Block::Lava, 0);
+        let mut updates = BlockUpdates::new(1);
+
+        Fluid.scheduled_tick(&mut world, &mut updates, at(5, 1, 5));
+        assert_eq!(world.block(at(6, 1, 5)), Block::Lava);
+        assert_eq!(world.fluid_level(at(6, 1, 5)), Block::Lava.fluid_spread());
+    }
+
+    #[test]
+    fn covered_grass_dies() {
+        let mut world = floor_with(&[(at(5, 1, 5), Block::Grass), (at(5, 2, 5), Block::Stone)]);
+        let mut updates = BlockUpdates::new(1);
+
+        Grass.random_tick(&mut world, &mut updates, at(5, 1, 5));
+        assert_eq!(world.block(at(5, 1, 5)), Block::Dirt);
+    }
+
+    #[test]
+    fn grass_spreads_at_most_one_voxel_up_or_down() {
+        let grass = at(16, 5, 16);
+        let mut world = floor_with(&[
+            (grass, Block::Grass),
+            (grass - glam::IVec3::Y, Block::Stone),
+            (grass + at(1, 1, 0), Block::Dirt),
+            (grass + at(-1, -2, 0), Block::Dirt),
+            (grass + at(0, 2, -1), Block::Dirt),
+        ]);
+        let mut updates = BlockUpdates::new(1);
+
+        for _ in 0..500 {
+            Grass.random_tick(&mut world, &mut updates, grass);
+        }
+
+        assert_eq!(world.block(grass + at(1, 1, 0)), Block::Grass);
+        assert_eq!(world.block(grass + at(-1, -2, 0)), Block::Dirt);
+        assert_eq!(world.block(grass + at(0, 2, -1)), Block::Dirt);
+    }
+}
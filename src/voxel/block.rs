@@ -0,0 +1,146 @@
+use crate::voxel::behaviour::{BlockBehaviour, Falling, Fluid, Grass, Inert};
+
+/// Every kind of block a Voxel can be.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Block {
+    #[default]
+    Air,
+    Grass,
+    Dirt,
+    Stone,
+    /// Gives off light.
+    Lamp,
+    Glass,
+    Leaves,
+    Water,
+    /// Flows like water, but slower and not as far. Glows.
+    Lava,
+    /// Falls down when there's nothing under it.
+    Sand,
+    /// Falls down when there's nothing under it.
+    Gravel,
+}
+
+/// Which pass a block gets drawn in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RenderLayer {
+    /// Fully covers whatever is behind it.
+    Opaque,
+    /// Either fully there or not there at all, per pixel. Like leaves with holes in them.
+    Cutout,
+    /// Partly see-through, blended with whatever is behind it. Drawn last, back to front.
+    Translucent,
+}
+
+impl RenderLayer {
+    pub const ALL: [RenderLayer; 3] = [RenderLayer::Opaque, RenderLayer::Cutout, RenderLayer::Translucent];
+}
+
+impl Block {
+    /// Every block, in the order of their ids.
+    pub const ALL: [Block; 11] = [
+        Block::Air,
+        Block::Grass,
+        Block::Dirt,
+        Block::Stone,
+        Block::Lamp,
+        Block::Glass,
+        Block::Leaves,
+        Block::Water,
+        Block::Lava,
+        Block::Sand,
+        Block::Gravel,
+    ];
+
+    /// Whether things collide with this block.
+    pub fn is_solid(self) -> bool {
+        !matches!(self, Block::Air) && !self.is_fluid()
+    }
+
+    /// Whether this block flows. See `voxel::fluid`.
+    pub fn is_fluid(self) -> bool {
+        matches!(self, Block::Water | Block::Lava)
+    }
+
+    /// How much the level of a fluid drops for every voxel it flows sideways.
+    pub fn fluid_spread(self) -> u8 {
+        match self {
+            Block::Lava => 2,
+            _ => 1,
+        }
+    }
+
+    /// How many ticks a fluid waits before it flows any further.
+    pub fn fluid_delay(self) -> u64 {
+        match self {
+            Block::Lava => 30,
+            _ => 5,
+        }
+    }
+
+    /// Whether this block gets drawn at all.
+    pub fn is_visible(self) -> bool {
+        self != Block::Air
+    }
+
+    /// Whether this block stops light from passing through, and hides the faces of the blocks next to it.
+    /// Fluids never are, since they don't always fill the whole voxel.
+    pub fn is_opaque(self) -> bool {
+        self.is_visible() && self.render_layer() == RenderLayer::Opaque && !self.is_fluid()
+    }
+
+    pub fn render_layer(self) -> RenderLayer {
+        match self {
+            Block::Leaves => RenderLayer::Cutout,
+            Block::Glass | Block::Water => RenderLayer::Translucent,
+            _ => RenderLayer::Opaque,
+        }
+    }
+
+    /// Whether a face of `block` is hidden when this block is right next to it.
+    pub fn hides_face_of(self, block: Block) -> bool {
+        // Touching glass or water looks like one big block, so the faces in between are left out.
+        self.is_opaque() || (self == block && block.render_layer() == RenderLayer::Translucent)
+    }
+
+    /// How much light this block gives off, 0 to `MAX_LIGHT`.
+    pub fn light_emission(self) -> u8 {
+        match self {
+            Block::Lamp | Block::Lava => 15,
+            _ => 0,
+        }
+    }
+
+    /// Which tile in `blocks.png` the face pointing towards `normal` uses.
+    /// Tiles are numbered left to right, top to bottom.
+    pub fn texture(self, normal: glam::IVec3) -> u32 {
+        match self {
+            Block::Air => 0,
+            Block::Grass => match normal.y {
+                1 => 0,
+                -1 => 2,
+                _ => 1,
+            },
+            Block::Dirt => 2,
+            Block::Stone => 3,
+            Block::Lamp => 4,
+            Block::Glass => 5,
+            Block::Leaves => 6,
+            Block::Water => 7,
+            Block::Lava => 8,
+            Block::Sand => 9,
+            Block::Gravel => 10,
+        }
+    }
+
+    /// What this block does when things change around it. See `voxel::behaviour`.
+    pub fn behaviour(self) -> &'static dyn BlockBehaviour {
+        match self {
+            Block::Grass => &Grass,
+            Block::Water | Block::Lava => &Fluid,
+            Block::Sand | Block::Gravel => &Falling,
+            _ => &Inert,
+        }
+    }
+}
@@ -1,110 +1,534 @@
-use crate::engine::resource::model::{Material, Mesh, Model, ModelVertex};
-use crate::engine::resource::texture::Texture;
-use crate::engine::util::load_texture;
+use crate::engine::resource::frustum::Frustum;
+use crate::engine::resource::instance::{Instance, InstanceBuffer};
+use crate::engine::resource::mesh_pool::{MeshPool, PoolMesh};
+use crate::engine::resource::model::Material;
+use crate::voxel::block::{Block, RenderLayer};
+use crate::voxel::fluid::SEA_LEVEL;
+use crate::voxel::gpu_mesher::GpuMesher;
+use crate::voxel::light::{self, LightChannel};
+use crate::voxel::lod::{lod_scale, select_lod, Downsampled, LODS};
 use crate::voxel::util::{
-    create_chunk_mesh_data, CHUNK_AREA, CHUNK_SIZE, CHUNK_SIZE_F32, CHUNK_VOL,
+    create_chunk_mesh_data, create_fluid_mesh_data, create_lod_mesh_data, voxel_index, world_to_chunk, CHUNK_AREA, CHUNK_SIZE, CHUNK_SIZE_F32,
+    CHUNK_VOL,
 };
+use crate::voxel::vertex::ChunkVertex;
 use libnoise::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use wgpu::util::DrawIndexedIndirectArgs;
 
-#[derive(Debug, Clone)]
+/// How many vertices the MeshPool has room for before it has to grow.
+/// Roughly what the starting world needs.
+const INITIAL_POOL_VERTICES: u32 = 1 << 19;
+/// Chunks closer to the camera than this get their translucent faces sorted again whenever
+/// the camera moves into another voxel. Further away, moving a voxel hardly changes which face
+/// is in front of which, so those only get sorted again once the camera is in another Chunk.
+const RESORT_DISTANCE: f32 = CHUNK_SIZE_F32;
+
+#[derive(Debug, Clone, Default)]
 struct Voxel {
-    pub is_solid: bool,
+    pub block: Block,
+    /// How far a fluid is from its source. See `voxel::fluid`.
+    pub level: u8,
+}
+
+/// A face of a translucent block. Kept around after meshing so the faces can be re-sorted
+/// back to front whenever the camera moves.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TranslucentFace {
+    center: glam::Vec3,
+    indices: [u32; 6],
+}
+
+/// A Chunk's Meshes in the MeshPool, and what the ChunkModel needs to know to draw them.
+#[derive(Debug)]
+struct ChunkMeshes {
+    /// One Mesh per RenderLayer, in `RenderLayer` order, for every level of detail.
+    meshes: [[PoolMesh; 3]; LODS],
+    /// The level of detail that gets drawn.
+    lod: usize,
+    /// Which levels of detail have Meshes that match the Chunk as it is now.
+    /// The rest get built once they're needed.
+    built: [bool; LODS],
+    /// The faces in the translucent Mesh of every level of detail.
+    translucent_faces: [Vec<TranslucentFace>; LODS],
+    /// The voxel the camera was in when the translucent faces of `lod` were last sorted.
+    /// None if they haven't been since they were built.
+    sorted_from: Option<glam::IVec3>,
+    /// The Instance that moves the Chunk's vertices to where it is in the world.
+    instance: u32,
+}
+
+impl ChunkMeshes {
+    fn mesh(&self, lod: usize, layer: RenderLayer) -> &PoolMesh {
+        &self.meshes[lod][layer as usize]
+    }
+}
+
+/// What a ChunkModel draws with. Made by `build`, so Chunks can be loaded and edited
+/// without a device before that.
+struct ChunkRender {
+    /// Every Chunk Mesh, in a couple of big shared buffers.
+    pool: MeshPool<ChunkVertex>,
+    /// The Material every Chunk Mesh uses.
+    material: Material,
+    /// One Instance per Chunk, see `ChunkMeshes::instance`.
+    instance_buffer: InstanceBuffer,
 }
 
-/// The ChunkModel holds both the Model that is
+/// The ChunkModel holds both the Meshes that are
 /// used to render our World, but also the Chunks themselves.
 /// This is so we can easily access adjacent chunks during rendering,
 /// as well as modify them based on player input.
+///
+/// Every Chunk gets one Mesh per RenderLayer for every level of detail, all of them in one
+/// MeshPool so they can be drawn together. Only one level of detail per Chunk gets drawn,
+/// picked by `select_lods`.
+///
+/// The vertices of a Mesh are relative to its Chunk, which is what lets them be packed so
+/// small. Every Chunk gets an Instance with its position to draw it with instead.
 pub struct ChunkModel {
-    pub model: Vec<Model>,
     chunks: HashMap<glam::IVec3, Chunk>,
+    /// There once the ChunkModel is built.
+    render: Option<ChunkRender>,
+    /// Which Meshes in the pool belong to which Chunk.
+    meshes: HashMap<glam::IVec3, ChunkMeshes>,
+    /// Chunks that have changed since their Mesh was built.
+    dirty: HashSet<glam::IVec3>,
+    /// The voxel the camera was in when the translucent faces were last sorted.
+    sorted_from: Option<glam::IVec3>,
+    /// Chunks with anything in their translucent Mesh, furthest from the camera first.
+    translucent_order: Vec<glam::IVec3>,
+    /// Voxels that changed since the last `take_changes`.
+    changes: Vec<glam::IVec3>,
+    /// Builds full detail Meshes on the GPU, if that's turned on.
+    gpu_mesher: Option<GpuMesher>,
+}
+
+impl Default for ChunkModel {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChunkModel {
     pub fn new() -> Self {
         Self {
-            model: Vec::new(),
             chunks: HashMap::new(),
+            render: None,
+            meshes: HashMap::new(),
+            dirty: HashSet::new(),
+            sorted_from: None,
+            translucent_order: Vec::new(),
+            changes: Vec::new(),
+            gpu_mesher: None,
         }
     }
-    /// Build the Model.
+
+    /// Builds Meshes with a compute shader from now on, wherever it can. See `GpuMesher`.
+    pub fn set_gpu_mesher(&mut self, gpu_mesher: GpuMesher) {
+        self.gpu_mesher = Some(gpu_mesher);
+    }
+
+    pub fn gpu_mesher_mut(&mut self) -> Option<&mut GpuMesher> {
+        self.gpu_mesher.as_mut()
+    }
+
+    /// Build the Meshes.
     /// Iterates through all of the existing chunks to generate all of the
     /// vertices, indices, materials... etc.
-    pub async fn build(
+    ///
+    /// Only full detail Meshes get built here, the others wait until `select_lods` needs them.
+    ///
+    /// * `material` - What every Chunk gets drawn with, the blocks texture.
+    pub fn build(&mut self, material: Material, device: &wgpu::Device, queue: &wgpu::Queue) {
+        // Light has to be figured out before meshing, since it's baked into the vertices
+        light::light_all(&mut self.chunks);
+
+        let render = match self.render.take() {
+            Some(mut render) => {
+                for chunk in std::mem::take(&mut self.meshes).into_values() {
+                    for mesh in chunk.meshes.iter().flatten() {
+                        render.pool.remove(mesh);
+                    }
+                }
+                render.material = material;
+                render
+            }
+            None => ChunkRender {
+                pool: MeshPool::new("Chunk", INITIAL_POOL_VERTICES, INITIAL_POOL_VERTICES / 4 * 6, device),
+                material,
+                instance_buffer: InstanceBuffer::new("Chunk", device),
+            },
+        };
+        let render = self.render.insert(render);
+
+        let chunk_positions: Vec<glam::IVec3> = self.chunks.keys().copied().collect();
+        let instances: Vec<Instance> = chunk_positions
+            .iter()
+            .map(|&chunk_pos| Instance {
+                position: Self::bounds(chunk_pos).0,
+                ..Default::default()
+            })
+            .collect();
+        render.instance_buffer.write(&instances, device, queue);
+
+        for (instance, &chunk_pos) in chunk_positions.iter().enumerate() {
+            self.meshes.insert(
+                chunk_pos,
+                ChunkMeshes {
+                    meshes: Default::default(),
+                    lod: 0,
+                    built: [false; LODS],
+                    translucent_faces: Default::default(),
+                    sorted_from: None,
+                    instance: instance as u32,
+                },
+            );
+            self.build_lod(chunk_pos, 0, device, queue);
+        }
+        self.dirty.clear();
+        self.sorted_from = None;
+
+        let vertices: u32 = self
+            .meshes
+            .values()
+            .flat_map(|chunk| &chunk.meshes[0])
+            .map(PoolMesh::num_vertices)
+            .sum();
+        let bytes = vertices as usize * std::mem::size_of::<ChunkVertex>();
+        log::info!(
+            "Chunk vertices take {} KiB, {} KiB per Chunk ({} bytes per vertex)",
+            bytes / 1024,
+            bytes / self.meshes.len().max(1) / 1024,
+            std::mem::size_of::<ChunkVertex>(),
+        );
+    }
+
+    /// Rebuilds the Meshes of every Chunk that changed since the last build.
+    /// Only the level of detail that's drawn gets rebuilt right away.
+    pub fn rebuild_dirty(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.render.is_none() {
+            return;
+        }
+
+        for chunk_pos in std::mem::take(&mut self.dirty) {
+            if let Some(chunk) = self.meshes.get_mut(&chunk_pos) {
+                chunk.built = [false; LODS];
+                let lod = chunk.lod;
+                self.build_lod(chunk_pos, lod, device, queue);
+            }
+        }
+    }
+
+    /// Picks the level of detail of every Chunk by how far it is from `eye`,
+    /// building the Meshes of any that aren't up to date.
+    ///
+    /// * `lod_distance` - Chunks closer than this get full detail. See `lod::select_lod`.
+    pub fn select_lods(
         &mut self,
-        layout: &wgpu::BindGroupLayout,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        eye: glam::Vec3,
+        lod_distance: f32,
     ) {
-        // We first iterate through our chunks and build all of the meshes
-        // based on the chunk data we have
-        let mut meshes: Vec<Mesh> = Vec::new();
-
-        for (chunk_pos, chunk) in &self.chunks {
-            let mut vertices: Vec<ModelVertex> = Vec::new();
-            let mut indices: Vec<u32> = Vec::new();
-
-            for x in 0..CHUNK_SIZE {
-                for y in 0..CHUNK_SIZE {
-                    for z in 0..CHUNK_SIZE {
-                        let index = (x + CHUNK_SIZE * z + CHUNK_AREA * y) as usize;
-
-                        if let Some(voxel) = chunk.voxels.get(index) {
-                            if !voxel.is_solid {
-                                continue;
-                            }
-
-                            let local_pos = glam::Vec3::new(x as f32, y as f32, z as f32);
-
-                            let world_pos = glam::Vec3::new(
-                                x as f32 + chunk_pos.x as f32 * CHUNK_SIZE_F32,
-                                y as f32 + chunk_pos.y as f32 * CHUNK_SIZE_F32,
-                                z as f32 + chunk_pos.z as f32 * CHUNK_SIZE_F32,
-                            );
-
-                            //println!("{}", world_pos);
-
-                            let (m_vert, m_idx) = create_chunk_mesh_data(
-                                chunk,
-                                local_pos,
-                                world_pos,
-                                vertices.len() as u32,
-                                &self.chunks,
-                            );
-
-                            vertices.extend(m_vert);
-                            indices.extend(m_idx);
-                            //vertices.extend(create_chunk_vertices(pos));
-                            //indices.extend(create_chunk_indices(vertices.len() as u32));
+        if self.render.is_none() {
+            return;
+        }
+
+        let chunk_positions: Vec<glam::IVec3> = self.meshes.keys().copied().collect();
+        for chunk_pos in chunk_positions {
+            let (min, max) = Self::bounds(chunk_pos);
+            // Distance to the closest point of the Chunk
+            let distance = eye.distance(eye.clamp(min, max));
+            let lod = select_lod(distance, lod_distance);
+
+            let chunk = self.meshes.get_mut(&chunk_pos).unwrap();
+            if chunk.lod == lod {
+                continue;
+            }
+            chunk.lod = lod;
+            chunk.sorted_from = None;
+            if !chunk.built[lod] {
+                self.build_lod(chunk_pos, lod, device, queue);
+            }
+            // Different translucent Meshes get drawn now
+            self.sorted_from = None;
+        }
+    }
+
+    /// The box a Chunk takes up in the world.
+    fn bounds(chunk_pos: glam::IVec3) -> (glam::Vec3, glam::Vec3) {
+        let min = (chunk_pos * CHUNK_SIZE).as_vec3() - 0.5;
+        (min, min + CHUNK_SIZE_F32)
+    }
+
+    /// Replaces the Meshes of one level of detail of a Chunk.
+    fn build_lod(&mut self, chunk_pos: glam::IVec3, lod: usize, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let Some(render) = &mut self.render else {
+            return;
+        };
+        let (chunk_meshes, translucent_faces) =
+            Self::create_meshes(&self.chunks, self.gpu_mesher.as_ref(), &mut render.pool, chunk_pos, lod, device, queue);
+        let chunk = self.meshes.get_mut(&chunk_pos).unwrap();
+
+        for (old, new) in chunk.meshes[lod].iter_mut().zip(chunk_meshes) {
+            render.pool.remove(old);
+            *old = new;
+        }
+        chunk.translucent_faces[lod] = translucent_faces;
+        chunk.sorted_from = None;
+        chunk.built[lod] = true;
+        // The new translucent Mesh hasn't been sorted yet
+        self.sorted_from = None;
+    }
+
+    /// Creates the Meshes for a single Chunk at a level of detail, one for every RenderLayer,
+    /// as well as the faces in the translucent one.
+    ///
+    /// Full detail opaque and cutout Meshes get built by the GpuMesher if there is one
+    /// and it can handle the Chunk.
+    fn create_meshes(
+        chunks: &HashMap<glam::IVec3, Chunk>,
+        gpu_mesher: Option<&GpuMesher>,
+        pool: &mut MeshPool<ChunkVertex>,
+        chunk_pos: glam::IVec3,
+        lod: usize,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> ([PoolMesh; 3], Vec<TranslucentFace>) {
+        let gpu_mesher = gpu_mesher.filter(|_| lod == 0 && GpuMesher::can_mesh(&chunks[&chunk_pos]));
+
+        // When validating, the CPU meshes everything anyway to have something to compare with
+        let cpu_layers: Vec<RenderLayer> = RenderLayer::ALL
+            .into_iter()
+            .filter(|&layer| match gpu_mesher {
+                Some(mesher) => mesher.validates() || !GpuMesher::meshes_layer(layer),
+                None => true,
+            })
+            .collect();
+        let (vertices, indices, translucent_faces) = Self::mesh_data(chunks, chunk_pos, lod, &cpu_layers);
+
+        if let Some(mesher) = gpu_mesher {
+            mesher.upload(queue, &chunks[&chunk_pos], chunks);
+        }
+
+        let meshes = RenderLayer::ALL.map(|layer| {
+            let mut layer_data = (&vertices[layer as usize], &indices[layer as usize]);
+            let fallback;
+            if let Some(mesher) = gpu_mesher.filter(|_| GpuMesher::meshes_layer(layer)) {
+                let name = format!("chunk {chunk_pos} {layer:?}");
+                let expected = mesher
+                    .validates()
+                    .then_some((layer_data.0.as_slice(), layer_data.1.as_slice()));
+                if let Some(mesh) = mesher.mesh(device, queue, layer, &name, expected, pool) {
+                    return mesh;
+                }
+                // The GPU couldn't do it after all, so the CPU has to
+                if !mesher.validates() {
+                    fallback = Self::mesh_data(chunks, chunk_pos, lod, &[layer]);
+                    layer_data = (&fallback.0[layer as usize], &fallback.1[layer as usize]);
+                }
+            }
+            let (vertices, indices) = layer_data;
+
+            pool.insert(vertices, indices, device, queue)
+        });
+
+        (meshes, translucent_faces)
+    }
+
+    /// Meshes the blocks of some RenderLayers of a Chunk at a level of detail on the CPU.
+    /// Gives the vertices and indices of every RenderLayer, as well as the translucent faces.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn mesh_data(
+        chunks: &HashMap<glam::IVec3, Chunk>,
+        chunk_pos: glam::IVec3,
+        lod: usize,
+        layers: &[RenderLayer],
+    ) -> ([Vec<ChunkVertex>; 3], [Vec<u32>; 3], Vec<TranslucentFace>) {
+        let chunk = &chunks[&chunk_pos];
+        let mut vertices: [Vec<ChunkVertex>; 3] = Default::default();
+        let mut indices: [Vec<u32>; 3] = Default::default();
+        let mut translucent_faces = Vec::new();
+
+        let cells = (lod > 0).then(|| Downsampled::new(chunk, lod));
+        let scale = lod_scale(lod);
+        let size = CHUNK_SIZE / scale;
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let cell = glam::IVec3::new(x, y, z);
+                    let block = match &cells {
+                        Some(cells) => cells.block(cell),
+                        None => chunk.block(cell),
+                    };
+                    if !block.is_visible() || !layers.contains(&block.render_layer()) {
+                        continue;
+                    }
+
+                    // The middle of the cell, which is just the voxel at full detail
+                    let center = (cell * scale).as_vec3() + scale as f32 / 2.0;
+                    let layer = block.render_layer() as usize;
+                    let start_index = vertices[layer].len() as u32;
+
+                    let (m_vert, m_idx) = match &cells {
+                        Some(cells) => create_lod_mesh_data(
+                            chunk,
+                            cells,
+                            cell,
+                            block,
+                            center,
+                            start_index,
+                            chunks,
+                        ),
+                        None if block.is_fluid() => create_fluid_mesh_data(
+                            chunk,
+                            block,
+                            cell,
+                            center,
+                            start_index,
+                            chunks,
+                        ),
+                        None => create_chunk_mesh_data(
+                            chunk,
+                            block,
+                            cell,
+                            center,
+                            start_index,
+                            chunks,
+                        ),
+                    };
+
+                    if block.render_layer() == RenderLayer::Translucent {
+                        // Every face is 4 vertices and 6 indices
+                        for (face_verts, face_indices) in m_vert.chunks(4).zip(m_idx.chunks(6)) {
+                            translucent_faces.push(TranslucentFace {
+                                center: Self::bounds(chunk_pos).0
+                                    + center
+                                    + face_verts[0].normal().as_vec3() * scale as f32 * 0.5,
+                                indices: face_indices.try_into().unwrap(),
+                            });
                         }
                     }
+
+                    vertices[layer].extend(m_vert);
+                    indices[layer].extend(m_idx);
                 }
             }
+        }
+
+        (vertices, indices, translucent_faces)
+    }
+
+    /// The Material every Chunk Mesh uses.
+    pub fn material(&self) -> &Material {
+        &self.render.as_ref().unwrap().material
+    }
+
+    /// The Instances to draw Chunks with. `draw_args` says which one goes with which Chunk.
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        self.render.as_ref().unwrap().instance_buffer.buffer()
+    }
+
+    /// Colours a whole Chunk, on top of its blocks' textures. White takes it off again.
+    /// Does nothing before the ChunkModel is built, or for Chunks that aren't loaded.
+    pub fn set_tint(&mut self, chunk_pos: glam::IVec3, tint: glam::Vec4, queue: &wgpu::Queue) {
+        let (Some(render), Some(chunk)) = (&mut self.render, self.meshes.get(&chunk_pos)) else {
+            return;
+        };
+        let instance = Instance {
+            position: Self::bounds(chunk_pos).0,
+            tint,
+            ..Default::default()
+        };
+        render.instance_buffer.set(chunk.instance as usize, &instance, queue);
+    }
+
+    /// The MeshPool every Chunk Mesh is in.
+    pub fn pool(&self) -> &MeshPool<ChunkVertex> {
+        &self.render.as_ref().unwrap().pool
+    }
+
+    /// How to draw the Meshes of one RenderLayer, at the level of detail each Chunk is drawn at,
+    /// each with its Chunk's Instance. Chunks `frustum` can't see, and Meshes without anything
+    /// in them, are left out.
+    ///
+    /// Translucent Meshes come furthest from the camera first, as of the last `sort_translucent`.
+    /// Everything else is in no particular order.
+    pub fn draw_args(
+        &self,
+        layer: RenderLayer,
+        frustum: Option<&Frustum>,
+    ) -> Vec<DrawIndexedIndirectArgs> {
+        let chunk_positions: Box<dyn Iterator<Item = &glam::IVec3>> = match layer {
+            RenderLayer::Translucent => Box::new(self.translucent_order.iter()),
+            _ => Box::new(self.meshes.keys()),
+        };
+
+        chunk_positions
+            .filter(|&&chunk_pos| {
+                let (min, max) = Self::bounds(chunk_pos);
+                frustum.is_none_or(|frustum| frustum.intersects_aabb(min, max))
+            })
+            .filter_map(|chunk_pos| {
+                let chunk = &self.meshes[chunk_pos];
+                let mesh = chunk.mesh(chunk.lod, layer);
+                (!mesh.is_empty()).then(|| mesh.draw_args(chunk.instance..chunk.instance + 1))
+            })
+            .collect()
+    }
+
+    /// Sorts the translucent faces inside every Chunk, and the Chunks themselves, back to front
+    /// as seen from `eye`. Blending only looks right if the furthest faces are drawn first.
+    ///
+    /// Only does anything after the camera moved into another voxel, or a Chunk got rebuilt.
+    /// Even then only the Chunks whose faces could have changed order get sorted and uploaded
+    /// again, see `RESORT_DISTANCE`.
+    pub fn sort_translucent(&mut self, queue: &wgpu::Queue, eye: glam::Vec3) {
+        let Some(render) = &self.render else {
+            return;
+        };
+        let eye_voxel = eye.round().as_ivec3();
+        if self.sorted_from == Some(eye_voxel) {
+            return;
+        }
+        self.sorted_from = Some(eye_voxel);
+
+        let mut translucent = Vec::new();
+        for (chunk_pos, chunk) in &mut self.meshes {
+            let faces = &mut chunk.translucent_faces[chunk.lod];
+            if faces.is_empty() {
+                continue;
+            }
+            translucent.push(*chunk_pos);
+            if !Self::needs_sorting(*chunk_pos, chunk.sorted_from, eye_voxel) {
+                continue;
+            }
+
+            chunk.sorted_from = Some(eye_voxel);
+            let indices = sort_back_to_front(faces, eye);
+            render.pool
+                .write_indices(&chunk.meshes[chunk.lod][RenderLayer::Translucent as usize], &indices, queue);
+        }
+
+        self.translucent_order = chunks_back_to_front(translucent, eye);
+    }
 
-            meshes.push(Mesh {
-                name: "".to_string(),
-                vertex_buffer: ModelVertex::create_vertex_buffer("chunk", &vertices, device),
-                index_buffer: ModelVertex::create_index_buffer("chunk", &indices, device),
-                num_elements: indices.len() as u32,
-                material: 0,
-            });
-        }
-
-        // Right now we won't use any materials, but we need atleast one in the current implementation
-        let diffuse_texture = load_texture("happy-tree.png", device, queue).await.unwrap();
-        let bind_group = Texture::create_bind_group(&diffuse_texture, layout, device);
-        let material = Material {
-            name: "mat".to_string(),
-            diffuse_texture,
-            bind_group,
+    /// Whether the translucent faces of a Chunk have to be sorted again with the camera in
+    /// `eye_voxel`, when they were last sorted with it in `sorted_from`.
+    fn needs_sorting(chunk_pos: glam::IVec3, sorted_from: Option<glam::IVec3>, eye_voxel: glam::IVec3) -> bool {
+        let Some(sorted_from) = sorted_from else {
+            return true;
         };
+        if sorted_from == eye_voxel {
+            return false;
+        }
 
-        self.model.clear();
-        self.model.push(Model {
-            meshes,
-            materials: vec![material],
-        })
+        let (min, max) = Self::bounds(chunk_pos);
+        let eye = eye_voxel.as_vec3();
+        eye.distance(eye.clamp(min, max)) < RESORT_DISTANCE
+            || world_to_chunk(sorted_from).0 != world_to_chunk(eye_voxel).0
     }
 
     pub fn add_chunk(&mut self, chunk_pos: glam::IVec3) {
@@ -113,25 +537,270 @@ impl ChunkModel {
 
         self.chunks.insert(chunk_pos, chunk);
     }
+
+    /// The block at a world position. Chunks that aren't loaded are all air.
+    pub fn block(&self, world_pos: glam::IVec3) -> Block {
+        let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+
+        self.chunks
+            .get(&chunk_pos)
+            .map_or(Block::Air, |chunk| chunk.block(local_pos))
+    }
+
+    /// Whether the Chunk a world position is in is loaded.
+    pub fn is_loaded(&self, world_pos: glam::IVec3) -> bool {
+        self.chunks.contains_key(&world_to_chunk(world_pos).0)
+    }
+
+    /// The positions of every loaded Chunk. Always in the same order,
+    /// so anything random that goes through them plays out the same every run.
+    pub fn chunk_positions(&self) -> Vec<glam::IVec3> {
+        let mut positions: Vec<glam::IVec3> = self.chunks.keys().copied().collect();
+        positions.sort_by_key(|chunk_pos| chunk_pos.to_array());
+        positions
+    }
+
+    /// The light level at a world position. Chunks that aren't loaded are dark.
+    pub fn light(&self, world_pos: glam::IVec3, channel: LightChannel) -> u8 {
+        light::get_light(&self.chunks, world_pos, channel).unwrap_or(0)
+    }
+
+    /// Whether the voxel at a world position is solid.
+    /// Voxels in chunks that aren't loaded never are.
+    pub fn is_solid(&self, world_pos: glam::IVec3) -> bool {
+        self.block(world_pos).is_solid()
+    }
+
+    /// Changes the block at a world position and updates the light around it.
+    /// Affected Chunks get rebuilt on the next `rebuild_dirty`.
+    pub fn set_block(&mut self, world_pos: glam::IVec3, block: Block) {
+        let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return;
+        };
+        if chunk.block(local_pos) == block {
+            return;
+        }
+
+        chunk.set_block(local_pos, block);
+        self.mark_changed(world_pos);
+
+        light::update_light(&mut self.chunks, world_pos, block, &mut self.dirty);
+    }
+
+    /// How far the fluid at a world position is from its source. 0 if there's no fluid.
+    pub fn fluid_level(&self, world_pos: glam::IVec3) -> u8 {
+        let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+
+        self.chunks
+            .get(&chunk_pos)
+            .map_or(0, |chunk| chunk.level(local_pos))
+    }
+
+    /// Puts a fluid at a world position, at the given level.
+    pub fn set_fluid(&mut self, world_pos: glam::IVec3, block: Block, level: u8) {
+        self.set_block(world_pos, block);
+
+        let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return;
+        };
+        if chunk.level(local_pos) != level {
+            chunk.set_level(local_pos, level);
+            self.mark_changed(world_pos);
+        }
+    }
+
+    /// Every voxel that changed since the last time this was called, possibly more than once.
+    pub fn take_changes(&mut self) -> Vec<glam::IVec3> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Remembers a voxel changed, and marks every Chunk that might look different because of it.
+    fn mark_changed(&mut self, world_pos: glam::IVec3) {
+        self.changes.push(world_pos);
+
+        // Faces of the neighbouring Chunks might have been covered or uncovered,
+        // and fluid surfaces lean towards their diagonal neighbours too.
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    self.dirty
+                        .insert(world_to_chunk(world_pos + glam::IVec3::new(x, y, z)).0);
+                }
+            }
+        }
+    }
+
+    /// The world height of the highest solid voxel in a column, if there is one.
+    pub fn highest_solid(&self, x: i32, z: i32) -> Option<i32> {
+        let top = self
+            .chunks
+            .keys()
+            .map(|chunk_pos| (chunk_pos.y + 1) * CHUNK_SIZE - 1)
+            .max()?;
+        let bottom = self.chunks.keys().map(|chunk_pos| chunk_pos.y * CHUNK_SIZE).min()?;
+
+        (bottom..=top)
+            .rev()
+            .find(|&y| self.is_solid(glam::IVec3::new(x, y, z)))
+    }
+}
+
+/// Sorts `faces` so the furthest from `eye` comes first, giving their indices in that order.
+fn sort_back_to_front(faces: &mut [TranslucentFace], eye: glam::Vec3) -> Vec<u32> {
+    faces.sort_by(|a, b| eye.distance_squared(b.center).total_cmp(&eye.distance_squared(a.center)));
+    faces.iter().flat_map(|face| face.indices).collect()
+}
+
+/// The Chunks at `chunk_positions`, the one whose center is furthest from `eye` first.
+fn chunks_back_to_front(chunk_positions: Vec<glam::IVec3>, eye: glam::Vec3) -> Vec<glam::IVec3> {
+    let mut order: Vec<(f32, glam::IVec3)> = chunk_positions
+        .into_iter()
+        .map(|chunk_pos| {
+            let center = (chunk_pos * CHUNK_SIZE).as_vec3() + glam::Vec3::splat(CHUNK_SIZE_F32 / 2.0);
+            (eye.distance_squared(center), chunk_pos)
+        })
+        .collect();
+    order.sort_by(|a, b| b.0.total_cmp(&a.0));
+    order.into_iter().map(|(_, chunk_pos)| chunk_pos).collect()
+}
+
+#[cfg(test)]
+impl ChunkModel {
+    /// Loads a Chunk of nothing but air, to build little worlds in with `set_block`.
+    pub(crate) fn add_empty_chunk(&mut self, chunk_pos: glam::IVec3) {
+        self.chunks.insert(chunk_pos, Chunk::empty(chunk_pos));
+    }
+
+    /// Fills a box of voxels, `min` to `max` inclusive, without touching the light.
+    /// Much quicker than `set_block` for building a world before `light_all`.
+    pub(crate) fn fill(&mut self, min: glam::IVec3, max: glam::IVec3, block: Block) {
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let (chunk_pos, local_pos) = world_to_chunk(glam::IVec3::new(x, y, z));
+                    if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+                        chunk.set_block(local_pos, block);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lights every Chunk from scratch, the same way `build` does.
+    pub(crate) fn light_all(&mut self) {
+        light::light_all(&mut self.chunks);
+    }
+}
+
+#[cfg(test)]
+impl Chunk {
+    /// A Chunk of nothing but air, unlike `new` which has no voxels at all until it's generated.
+    pub(crate) fn empty(position: glam::IVec3) -> Self {
+        let mut chunk = Self::new(position);
+        chunk.voxels = vec![Voxel::default(); CHUNK_VOL as usize];
+        chunk
+    }
 }
 
 #[derive(Debug)]
 pub struct Chunk {
     position: glam::IVec3,
     voxels: Vec<Voxel>,
+    /// The light level of every voxel.
+    /// Sunlight lives in the upper four bits, block light in the lower four.
+    light: Vec<u8>,
 }
 
 impl Chunk {
     pub fn new(position: glam::IVec3) -> Self {
         Self {
             position,
-            voxels: Vec::new()
+            voxels: Vec::new(),
+            light: vec![0; CHUNK_VOL as usize],
         }
     }
 
+    /// Where the Chunk is, in Chunks.
+    pub fn position(&self) -> glam::IVec3 {
+        self.position
+    }
+
+    pub fn block(&self, local_pos: glam::IVec3) -> Block {
+        self.voxels
+            .get(voxel_index(local_pos))
+            .map_or(Block::Air, |voxel| voxel.block)
+    }
+
+    pub(crate) fn set_block(&mut self, local_pos: glam::IVec3, block: Block) {
+        if let Some(voxel) = self.voxels.get_mut(voxel_index(local_pos)) {
+            voxel.block = block;
+            voxel.level = 0;
+        }
+    }
+
+    pub fn level(&self, local_pos: glam::IVec3) -> u8 {
+        self.voxels
+            .get(voxel_index(local_pos))
+            .map_or(0, |voxel| voxel.level)
+    }
+
+    fn set_level(&mut self, local_pos: glam::IVec3, level: u8) {
+        if let Some(voxel) = self.voxels.get_mut(voxel_index(local_pos)) {
+            voxel.level = level;
+        }
+    }
+
+    pub fn light(&self, local_pos: glam::IVec3, channel: LightChannel) -> u8 {
+        let light = self.light[voxel_index(local_pos)];
+        match channel {
+            LightChannel::Sun => light >> 4,
+            LightChannel::Block => light & 0x0F,
+        }
+    }
+
+    pub(crate) fn set_light(&mut self, local_pos: glam::IVec3, channel: LightChannel, level: u8) {
+        let light = &mut self.light[voxel_index(local_pos)];
+        *light = match channel {
+            LightChannel::Sun => (*light & 0x0F) | (level << 4),
+            LightChannel::Block => (*light & 0xF0) | (level & 0x0F),
+        };
+    }
+
+    /// Whether there's any water or lava in the Chunk.
+    pub fn has_fluid(&self) -> bool {
+        self.voxels.iter().any(|voxel| voxel.block.is_fluid())
+    }
+
+    /// Local positions of every voxel that gives off light.
+    pub(crate) fn emitters(&self) -> Vec<glam::IVec3> {
+        self.voxels
+            .iter()
+            .enumerate()
+            .filter(|(_, voxel)| voxel.block.light_emission() > 0)
+            .map(|(index, _)| {
+                let index = index as i32;
+                glam::IVec3::new(index % CHUNK_SIZE, index / CHUNK_AREA, (index / CHUNK_SIZE) % CHUNK_SIZE)
+            })
+            .collect()
+    }
+
+    /// The light shining on a voxel, 0 to `MAX_LIGHT` for both sunlight and block light.
+    /// Like `block_at`, this looks into the neighbouring Chunk if the position is outside of ours.
+    pub fn light_at(
+        &self,
+        voxel_pos: glam::IVec3,
+        world_chunks: &HashMap<glam::IVec3, Chunk>,
+    ) -> [u8; 2] {
+        let world_pos = self.position * CHUNK_SIZE + voxel_pos;
+        [LightChannel::Sun, LightChannel::Block]
+            .map(|channel| light::get_light(world_chunks, world_pos, channel).unwrap_or(0))
+    }
+
     /// Generate a Chunk based on current position.
     pub fn generate(&mut self, chunk_pos: glam::IVec3) {
-        let mut voxels: Vec<Voxel> = vec![Voxel { is_solid: false }; CHUNK_VOL as usize];
+        let mut voxels: Vec<Voxel> = vec![Voxel::default(); CHUNK_VOL as usize];
         let noise = Source::simplex(42069).fbm(1, 1.0, 2.0, 0.5);
 
         let new_pos = chunk_pos * CHUNK_SIZE;
@@ -147,98 +816,115 @@ impl Chunk {
                 let local_height = i32::min(world_height - new_pos.y, CHUNK_SIZE);
 
                 for y in 0..local_height {
-                    let _wy = y + new_pos.y;
+                    let wy = y + new_pos.y;
                     let index = (x + CHUNK_SIZE * z + CHUNK_AREA * y) as usize;
 
-                    //voxels[index] = wy + 1;
-                    voxels[index].is_solid = true;
+                    // Grass on top, a bit of dirt under it and stone all the way down.
+                    voxels[index].block = match world_height - 1 - wy {
+                        // Beaches along the water, and gravel further out
+                        0..=2 if world_height <= SEA_LEVEL - 6 => Block::Gravel,
+                        0..=2 if world_height <= SEA_LEVEL + 1 => Block::Sand,
+                        0 => Block::Grass,
+                        1..=3 => Block::Dirt,
+                        _ => Block::Stone,
+                    };
+                }
+
+                // Anything low enough fills up with water
+                let water_top = i32::min(SEA_LEVEL - new_pos.y, CHUNK_SIZE);
+                for y in local_height.max(0)..water_top {
+                    let index = (x + CHUNK_SIZE * z + CHUNK_AREA * y) as usize;
+                    voxels[index].block = Block::Water;
                 }
             }
         }
         self.voxels = voxels;
     }
 
-    pub fn is_void(
+    /// The block at a position relative to this Chunk. Positions outside of it get looked up
+    /// in the neighbouring Chunk, which gives `None` if that Chunk isn't loaded.
+    pub fn block_at(
         &self,
         voxel_pos: glam::IVec3,
-        world_chunks: &HashMap<glam::IVec3, Chunk>
-    ) -> bool {
-        let x = voxel_pos.x;
-        let y = voxel_pos.y;
-        let z = voxel_pos.z;
-
-        // First check if the position is local
-        if (0..CHUNK_SIZE).contains(&x)
-            && (0..CHUNK_SIZE).contains(&y)
-            && (0..CHUNK_SIZE).contains(&z)
-        {
-            // Voxel exists inside of our chunk. Get the index and check it.
-            let idx = (x + CHUNK_SIZE * z + CHUNK_AREA * y) as usize;
-            if let Some(voxel) = self.voxels.get(idx) {
-                return !voxel.is_solid;
-            }
-        } else {
-            // Voxel exceeds chunk boundaries.
-            // We need to know check the neighboring Chunk's voxel
-            // to find out if we should draw or not.
-            // FIXME: Probably refactor, this isn't great...
-            
-            let c_pos = self.position;
-            let mut neighbor_chunk_idx = glam::IVec3::ZERO;
-            let mut neightbor_voxel_pos = glam::IVec3::ZERO;
-            if x > (CHUNK_SIZE - 1) {
-                neighbor_chunk_idx = glam::IVec3::new(c_pos.x + 1, c_pos.y, c_pos.z);
-                neightbor_voxel_pos = glam::IVec3::new(0, y, z);
-            } else if x < 0 {
-                neighbor_chunk_idx = glam::IVec3::new(c_pos.x - 1, c_pos.y, c_pos.z);
-                neightbor_voxel_pos = glam::IVec3::new(31, y, z);
-            }
+        world_chunks: &HashMap<glam::IVec3, Chunk>,
+    ) -> Option<Block> {
+        if voxel_pos.cmpge(glam::IVec3::ZERO).all() && voxel_pos.cmplt(glam::IVec3::splat(CHUNK_SIZE)).all() {
+            return Some(self.block(voxel_pos));
+        }
 
-            if y > (CHUNK_SIZE - 1) {
-                neighbor_chunk_idx = glam::IVec3::new(c_pos.x, c_pos.y + 1, c_pos.z);
-                neightbor_voxel_pos = glam::IVec3::new(x, 0, z);
-            } else if y < 0 {
-                neighbor_chunk_idx = glam::IVec3::new(c_pos.x, c_pos.y - 1, c_pos.z);
-                neightbor_voxel_pos = glam::IVec3::new(x, 31, z);
-            }
+        let (chunk_pos, local_pos) = world_to_chunk(self.position * CHUNK_SIZE + voxel_pos);
+        world_chunks.get(&chunk_pos).map(|chunk| chunk.block(local_pos))
+    }
 
-            if z > (CHUNK_SIZE - 1) {
-                neighbor_chunk_idx = glam::IVec3::new(c_pos.x, c_pos.y, c_pos.z + 1);
-                neightbor_voxel_pos = glam::IVec3::new(x, y, 0);
-            } else if z < 0 {
-                neighbor_chunk_idx = glam::IVec3::new(c_pos.x, c_pos.y, c_pos.z - 1);
-                neightbor_voxel_pos = glam::IVec3::new(x, y, 31);
-            }
+    /// Same as `block_at`, but for the fluid level.
+    pub fn level_at(
+        &self,
+        voxel_pos: glam::IVec3,
+        world_chunks: &HashMap<glam::IVec3, Chunk>,
+    ) -> u8 {
+        let (chunk_pos, local_pos) = world_to_chunk(self.position * CHUNK_SIZE + voxel_pos);
+        world_chunks
+            .get(&chunk_pos)
+            .map_or(0, |chunk| chunk.level(local_pos))
+    }
+}
 
-            return Chunk::check_neighboring_chunk(neighbor_chunk_idx, neightbor_voxel_pos, world_chunks)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The translucent faces of a Chunk with a few glass blocks scattered around it.
+    fn glass_faces() -> Vec<TranslucentFace> {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        for pos in [glam::IVec3::new(1, 1, 1), glam::IVec3::new(8, 4, 2), glam::IVec3::new(14, 10, 12)] {
+            world.fill(pos, pos, Block::Glass);
         }
-        true
+        let (_, _, faces) = ChunkModel::mesh_data(&world.chunks, glam::IVec3::ZERO, 0, &[RenderLayer::Translucent]);
+        faces
     }
-    
-    /// Tries to check the desired Voxel inside of a specified Chunk.
-    /// 
-    /// * `chunk_idx`: The key for the Chunk we're interested in.
-    /// * `voxel_pos`: The local position of the Voxel we're interested in.
-    /// * `world_chunks`: All of the loaded chunks located in our world.
-    fn check_neighboring_chunk(
-        chunk_idx: glam::IVec3,
-        voxel_pos: glam::IVec3,
-        world_chunks: &HashMap<glam::IVec3, Chunk>
-    ) -> bool {
-        let x = voxel_pos.x;
-        let y = voxel_pos.y;
-        let z = voxel_pos.z;
-        match world_chunks.get(&chunk_idx) {
-            Some(chunk) => {
-                let voxel_idx = (x + CHUNK_SIZE * z + CHUNK_AREA * y) as usize;
-
-                if let Some(voxel) = chunk.voxels.get(voxel_idx) {
-                    return !voxel.is_solid;
-                }
-                
-                true
-            }
-            None => false
+
+    #[test]
+    fn translucent_faces_sort_back_to_front() {
+        let mut faces = glass_faces();
+        assert_eq!(faces.len(), 18);
+
+        for eye in [glam::Vec3::new(-5.0, 2.0, 0.0), glam::Vec3::new(8.0, 4.0, 20.0), glam::Vec3::splat(7.3)] {
+            let indices = sort_back_to_front(&mut faces, eye);
+
+            let distances: Vec<f32> = faces.iter().map(|face| eye.distance(face.center)).collect();
+            assert!(distances.windows(2).all(|pair| pair[0] >= pair[1]), "{distances:?} from {eye}");
+            // The indices come along with their faces
+            let expected: Vec<u32> = faces.iter().flat_map(|face| face.indices).collect();
+            assert_eq!(indices, expected);
         }
     }
+
+    #[test]
+    fn chunks_sort_back_to_front() {
+        let chunks = vec![glam::IVec3::ZERO, glam::IVec3::new(3, 0, 0), glam::IVec3::new(-1, 0, 0)];
+        let eye = glam::Vec3::new(4.5 * CHUNK_SIZE_F32, 8.0, 8.0);
+        assert_eq!(
+            chunks_back_to_front(chunks, eye),
+            [glam::IVec3::new(-1, 0, 0), glam::IVec3::ZERO, glam::IVec3::new(3, 0, 0)]
+        );
+        assert!(chunks_back_to_front(Vec::new(), eye).is_empty());
+    }
+
+    #[test]
+    fn only_nearby_chunks_sort_every_voxel() {
+        let here = glam::IVec3::new(8, 8, 8);
+        let next_voxel = glam::IVec3::new(9, 8, 8);
+        let next_chunk = glam::IVec3::new(CHUNK_SIZE + 8, 8, 8);
+        let near = glam::IVec3::ZERO;
+        let far = glam::IVec3::new(5, 0, 0);
+
+        // Never sorted, or rebuilt since
+        assert!(ChunkModel::needs_sorting(far, None, here));
+        assert!(!ChunkModel::needs_sorting(near, Some(here), here));
+
+        assert!(ChunkModel::needs_sorting(near, Some(here), next_voxel));
+        assert!(!ChunkModel::needs_sorting(far, Some(here), next_voxel));
+        assert!(ChunkModel::needs_sorting(far, Some(here), next_chunk));
+    }
 }
@@ -0,0 +1,281 @@
+use crate::voxel::chunk::ChunkModel;
+
+/// Keeps things from ending up exactly touching a voxel,
+/// which would count as being inside of it due to rounding.
+const SKIN: f32 = 0.001;
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: glam::Vec3, max: glam::Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// A box standing on `feet`, `half_width` out to each side and `height` tall.
+    pub fn from_feet(feet: glam::Vec3, half_width: f32, height: f32) -> Self {
+        Self {
+            min: feet - glam::Vec3::new(half_width, 0.0, half_width),
+            max: feet + glam::Vec3::new(half_width, height, half_width),
+        }
+    }
+
+    /// The box a single voxel takes up. Voxels are centered on their position.
+    pub fn voxel(voxel_pos: glam::IVec3) -> Self {
+        let center = voxel_pos.as_vec3();
+        Self {
+            min: center - glam::Vec3::splat(0.5),
+            max: center + glam::Vec3::splat(0.5),
+        }
+    }
+
+    pub fn translated(&self, offset: glam::Vec3) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmplt(other.max).all() && self.max.cmpgt(other.min).all()
+    }
+
+    /// Every voxel position this box overlaps.
+    pub fn voxels(&self) -> impl Iterator<Item = glam::IVec3> {
+        let lo = (self.min + 0.5).floor().as_ivec3();
+        let hi = (self.max + 0.5 - SKIN).floor().as_ivec3();
+
+        (lo.y..=hi.y).flat_map(move |y| {
+            (lo.z..=hi.z).flat_map(move |z| (lo.x..=hi.x).map(move |x| glam::IVec3::new(x, y, z)))
+        })
+    }
+}
+
+/// Moves a box by `delta` through the voxel world, stopping at solid voxels.
+///
+/// Each axis is moved and resolved on its own (Y first, then X and Z),
+/// which lets things slide along walls instead of sticking to them.
+/// The whole path of an axis is checked, so fast movement won't tunnel through thin walls.
+///
+/// Returns the moved box and which axes hit something.
+pub fn move_and_collide(aabb: Aabb, delta: glam::Vec3, world: &ChunkModel) -> (Aabb, glam::BVec3) {
+    let mut aabb = aabb;
+    let mut hit = [false; 3];
+
+    for axis in [1, 0, 2] {
+        let d = delta[axis];
+        if d == 0.0 {
+            continue;
+        }
+
+        let mut offset = glam::Vec3::ZERO;
+        offset[axis] = d;
+        let swept = aabb.union(&aabb.translated(offset));
+
+        let mut allowed = d;
+        for voxel_pos in swept.voxels() {
+            if !world.is_solid(voxel_pos) {
+                continue;
+            }
+
+            let voxel = Aabb::voxel(voxel_pos);
+            if d > 0.0 {
+                allowed = allowed.min(voxel.min[axis] - aabb.max[axis] - SKIN);
+            } else {
+                allowed = allowed.max(voxel.max[axis] - aabb.min[axis] + SKIN);
+            }
+        }
+
+        // Never let the resolution push us backwards.
+        if d > 0.0 {
+            allowed = allowed.max(0.0);
+        } else {
+            allowed = allowed.min(0.0);
+        }
+
+        if allowed != d {
+            hit[axis] = true;
+        }
+
+        offset[axis] = allowed;
+        aabb = aabb.translated(offset);
+    }
+
+    (aabb, glam::BVec3::new(hit[0], hit[1], hit[2]))
+}
+
+/// Whether the box overlaps any solid voxel.
+pub fn is_colliding(aabb: &Aabb, world: &ChunkModel) -> bool {
+    aabb.voxels().any(|voxel_pos| world.is_solid(voxel_pos))
+}
+
+/// What a raycast ran into.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RaycastHit {
+    /// The solid voxel that was hit.
+    pub voxel: glam::IVec3,
+    /// Which side of the voxel was hit. Adding it to `voxel` gives the voxel in front of that side.
+    pub normal: glam::IVec3,
+    pub distance: f32,
+}
+
+/// Finds the first solid voxel along a ray, stepping through the grid one voxel at a time.
+pub fn raycast(
+    origin: glam::Vec3,
+    direction: glam::Vec3,
+    max_distance: f32,
+    world: &ChunkModel,
+) -> Option<RaycastHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == glam::Vec3::ZERO {
+        return None;
+    }
+
+    // Voxels are centered on their position, shift things so they span [n, n + 1) instead.
+    let origin = origin + 0.5;
+    let mut voxel = origin.floor().as_ivec3();
+    let step = direction.signum().as_ivec3();
+
+    // How far along the ray we have to go to cross a voxel on each axis,
+    // and how far until we cross the next boundary on each axis.
+    let delta = direction.recip().abs();
+    let mut next = glam::Vec3::ZERO;
+    for axis in 0..3 {
+        next[axis] = if direction[axis] == 0.0 {
+            f32::INFINITY
+        } else if direction[axis] > 0.0 {
+            (voxel[axis] as f32 + 1.0 - origin[axis]) * delta[axis]
+        } else {
+            (origin[axis] - voxel[axis] as f32) * delta[axis]
+        };
+    }
+
+    let mut normal = glam::IVec3::ZERO;
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        if world.is_solid(voxel) {
+            return Some(RaycastHit { voxel, normal, distance });
+        }
+
+        let axis = if next.x < next.y && next.x < next.z {
+            0
+        } else if next.y < next.z {
+            1
+        } else {
+            2
+        };
+
+        distance = next[axis];
+        next[axis] += delta[axis];
+        voxel[axis] += step[axis];
+        normal = glam::IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::block::Block;
+
+    /// A stone floor at y = 0, with a wall two blocks high along x = 8.
+    fn floor_and_wall() -> ChunkModel {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        for x in 0..16 {
+            for z in 0..16 {
+                world.set_block(glam::IVec3::new(x, 0, z), Block::Stone);
+            }
+        }
+        for z in 0..16 {
+            for y in 1..=2 {
+                world.set_block(glam::IVec3::new(8, y, z), Block::Stone);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn lands_on_the_floor() {
+        let world = floor_and_wall();
+        let aabb = Aabb::from_feet(glam::Vec3::new(4.0, 3.0, 4.0), 0.3, 1.8);
+
+        let (moved, hit) = move_and_collide(aabb, glam::Vec3::new(0.0, -10.0, 0.0), &world);
+
+        assert!(hit.y && !hit.x && !hit.z);
+        // The top of the floor voxel is at 0.5
+        assert!((moved.min.y - 0.5).abs() < 0.01, "landed at {}", moved.min.y);
+        assert!(!is_colliding(&moved, &world));
+    }
+
+    #[test]
+    fn stops_at_a_wall() {
+        let world = floor_and_wall();
+        let aabb = Aabb::from_feet(glam::Vec3::new(5.0, 0.51, 4.0), 0.3, 1.8);
+
+        let (moved, hit) = move_and_collide(aabb, glam::Vec3::new(5.0, 0.0, 0.0), &world);
+
+        assert!(hit.x && !hit.y && !hit.z);
+        // The wall starts at 7.5
+        assert!((moved.max.x - 7.5).abs() < 0.01, "stopped at {}", moved.max.x);
+        assert_eq!(moved.min.y, aabb.min.y);
+    }
+
+    #[test]
+    fn slides_along_a_wall() {
+        let world = floor_and_wall();
+        let aabb = Aabb::from_feet(glam::Vec3::new(7.0, 0.51, 4.0), 0.3, 1.8);
+
+        let (moved, hit) = move_and_collide(aabb, glam::Vec3::new(1.0, 0.0, 2.0), &world);
+
+        assert!(hit.x && !hit.z);
+        assert!((moved.min.z - aabb.min.z - 2.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn raycast_hits_the_floor_from_above() {
+        let world = floor_and_wall();
+
+        let hit = raycast(glam::Vec3::new(4.0, 3.0, 4.0), glam::Vec3::NEG_Y, 10.0, &world).unwrap();
+
+        assert_eq!(hit.voxel, glam::IVec3::new(4, 0, 4));
+        assert_eq!(hit.normal, glam::IVec3::Y);
+        assert!((hit.distance - 2.5).abs() < 0.001, "hit at {}", hit.distance);
+    }
+
+    #[test]
+    fn raycast_hits_the_side_of_a_wall() {
+        let world = floor_and_wall();
+
+        let hit = raycast(glam::Vec3::new(4.0, 1.0, 4.0), glam::Vec3::new(1.0, 0.0, 0.1), 10.0, &world).unwrap();
+
+        assert_eq!(hit.voxel, glam::IVec3::new(8, 1, 4));
+        assert_eq!(hit.normal, glam::IVec3::NEG_X);
+        // The voxel in front of the side that was hit is free to place something in
+        assert!(!world.is_solid(hit.voxel + hit.normal));
+    }
+
+    #[test]
+    fn raycast_misses() {
+        let world = floor_and_wall();
+
+        // Straight up, and towards the wall but not far enough to reach it
+        assert_eq!(raycast(glam::Vec3::new(4.0, 3.0, 4.0), glam::Vec3::Y, 10.0, &world), None);
+        assert_eq!(raycast(glam::Vec3::new(4.0, 1.0, 4.0), glam::Vec3::X, 2.0, &world), None);
+        assert_eq!(raycast(glam::Vec3::new(4.0, 1.0, 4.0), glam::Vec3::ZERO, 10.0, &world), None);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::voxel::block::Block;
+use crate::voxel::chunk::ChunkModel;
+
+/// Everything below this height fills up with water when the world gets generated.
+pub const SEA_LEVEL: i32 = 16;
+
+/// The furthest a fluid gets from its source. Level 0 is the source itself,
+/// every voxel it flows sideways adds `Block::fluid_spread` until it runs out past this.
+pub const MAX_LEVEL: u8 = 7;
+
+/// Added to the level of fluid that's falling down. It fills the whole voxel,
+/// and spreads out as if it were a source once it lands.
+pub const FALLING: u8 = 8;
+
+const HORIZONTAL: [glam::IVec3; 4] = [
+    glam::IVec3::X,
+    glam::IVec3::NEG_X,
+    glam::IVec3::Z,
+    glam::IVec3::NEG_Z,
+];
+
+const NEIGHBOURS: [glam::IVec3; 7] = [
+    glam::IVec3::ZERO,
+    glam::IVec3::X,
+    glam::IVec3::NEG_X,
+    glam::IVec3::Y,
+    glam::IVec3::NEG_Y,
+    glam::IVec3::Z,
+    glam::IVec3::NEG_Z,
+];
+
+/// How high the surface of a fluid at `level` is, 0.0 to 1.0 of the voxel.
+pub fn surface_height(level: u8) -> f32 {
+    let level = if level & FALLING != 0 { 0 } else { level };
+    (8 - level.min(MAX_LEVEL)) as f32 / 9.0
+}
+
+/// Lets the fluid at a position react to what's around it, then flow.
+///
+/// Gets run by `BlockUpdates` a few ticks after something next to the fluid changed
+/// (how many depends on the fluid). Flowing changes things too, so it keeps going
+/// until everything settles down.
+pub fn update(world: &mut ChunkModel, world_pos: glam::IVec3) {
+    let block = world.block(world_pos);
+    if !block.is_fluid() {
+        return;
+    }
+
+    // Lava touching water cools down into stone
+    if block == Block::Lava
+        && NEIGHBOURS[1..]
+            .iter()
+            .any(|&offset| world.block(world_pos + offset) == Block::Water)
+    {
+        world.set_block(world_pos, Block::Stone);
+        return;
+    }
+
+    let level = world.fluid_level(world_pos);
+    let level = if level == 0 {
+        level
+    } else {
+        match flowing_level(world, world_pos, block) {
+            // The fluid that fed this one is gone
+            None => {
+                world.set_block(world_pos, Block::Air);
+                return;
+            }
+            Some(new_level) => {
+                if new_level != level {
+                    world.set_fluid(world_pos, block, new_level);
+                }
+                new_level
+            }
+        }
+    };
+
+    spread(world, world_pos, block, level);
+}
+
+/// What level flowing fluid should be at, judging by the fluid around it that feeds it.
+fn flowing_level(world: &ChunkModel, world_pos: glam::IVec3, block: Block) -> Option<u8> {
+    if world.block(world_pos + glam::IVec3::Y) == block {
+        return Some(FALLING);
+    }
+
+    let mut sources = 0;
+    let mut lowest: Option<u8> = None;
+    for offset in HORIZONTAL {
+        let neighbour = world_pos + offset;
+        if world.block(neighbour) != block {
+            continue;
+        }
+
+        let level = world.fluid_level(neighbour);
+        if level == 0 {
+            sources += 1;
+        }
+        // Falling fluid spreads like a source once it lands
+        let level = if level & FALLING != 0 { 0 } else { level };
+        lowest = Some(lowest.map_or(level, |lowest| lowest.min(level)));
+    }
+
+    // Water between two sources becomes a source itself, as long as it has something to sit on
+    let below = world_pos - glam::IVec3::Y;
+    if block == Block::Water
+        && sources >= 2
+        && (world.is_solid(below) || (world.block(below) == block && world.fluid_level(below) == 0))
+    {
+        return Some(0);
+    }
+
+    lowest
+        .map(|level| level + block.fluid_spread())
+        .filter(|&level| level <= MAX_LEVEL)
+}
+
+/// Whether `block` can flow into a position where the fluid would end up at `level`.
+fn can_flow_into(world: &ChunkModel, world_pos: glam::IVec3, block: Block, level: u8) -> bool {
+    let existing = world.block(world_pos);
+    if existing == Block::Air {
+        return true;
+    }
+
+    // Only replace the same fluid if we'd make it fuller
+    let existing_level = world.fluid_level(world_pos);
+    existing == block && existing_level != 0 && existing_level & FALLING == 0 && existing_level > level
+}
+
+/// Flows down if it can, otherwise out to the sides.
+fn spread(world: &mut ChunkModel, world_pos: glam::IVec3, block: Block, level: u8) {
+    let below = world_pos - glam::IVec3::Y;
+    if can_flow_into(world, below, block, 0) {
+        world.set_fluid(below, block, FALLING);
+    }
+    // Only sources keep spreading sideways while they pour down,
+    // flowing fluid needs something to sit on.
+    if level != 0 && !world.is_solid(below) {
+        return;
+    }
+
+    let level = if level & FALLING != 0 { 0 } else { level };
+    let next = level + block.fluid_spread();
+    if next > MAX_LEVEL {
+        return;
+    }
+
+    for offset in HORIZONTAL {
+        let neighbour = world_pos + offset;
+        if can_flow_into(world, neighbour, block, next) {
+            world.set_fluid(neighbour, block, next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty Chunk with a stone floor at y = 0.
+    fn floor() -> ChunkModel {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        world.fill(glam::IVec3::ZERO, glam::IVec3::new(31, 0, 31), Block::Stone);
+        world
+    }
+
+    /// Updates everything next to a change until nothing changes anymore, the way
+    /// `BlockUpdates` would given enough ticks.
+    fn settle(world: &mut ChunkModel) {
+        for _ in 0..1000 {
+            let mut changes = world.take_changes();
+            if changes.is_empty() {
+                return;
+            }
+            changes.sort_by_key(|world_pos| world_pos.to_array());
+            changes.dedup();
+
+            for world_pos in changes {
+                for offset in NEIGHBOURS {
+                    update(world, world_pos + offset);
+                }
+            }
+        }
+        panic!("Fluid never settled");
+    }
+
+    fn at(x: i32, y: i32, z: i32) -> glam::IVec3 {
+        glam::IVec3::new(x, y, z)
+    }
+
+    #[test]
+    fn a_source_spreads_to_max_level_and_stops() {
+        let mut world = floor();
+        world.set_fluid(at(16, 1, 16), Block::Water, 0);
+        settle(&mut world);
+
+        for distance in 0..=MAX_LEVEL as i32 {
+            assert_eq!(world.block(at(16 + distance, 1, 16)), Block::Water);
+            assert_eq!(world.fluid_level(at(16 + distance, 1, 16)), distance as u8);
+        }
+        // Levels count the steps around, not straight line distance
+        assert_eq!(world.fluid_level(at(13, 1, 18)), 5);
+        assert_eq!(world.block(at(16 + MAX_LEVEL as i32 + 1, 1, 16)), Block::Air);
+        assert_eq!(world.block(at(20, 1, 20)), Block::Air);
+        assert_eq!(world.block(at(16, 2, 16)), Block::Air);
+    }
+
+    #[test]
+    fn lava_does_not_flow_as_far() {
+        let mut world = floor();
+        world.set_fluid(at(16, 1, 16), Block::Lava, 0);
+        settle(&mut world);
+
+        assert_eq!(world.fluid_level(at(17, 1, 16)), 2);
+        assert_eq!(world.fluid_level(at(19, 1, 16)), 6);
+        assert_eq!(world.block(at(20, 1, 16)), Block::Air);
+    }
+
+    #[test]
+    fn a_falling_column_keeps_falling() {
+        let mut world = floor();
+        world.set_fluid(at(16, 10, 16), Block::Water, 0);
+        settle(&mut world);
+
+        for y in 1..10 {
+            assert_eq!(world.block(at(16, y, 16)), Block::Water, "at y = {y}");
+            assert_ne!(world.fluid_level(at(16, y, 16)) & FALLING, 0, "at y = {y}");
+        }
+        // The source spreads out one voxel, but the flowing water around it only falls
+        assert_ne!(world.fluid_level(at(17, 5, 16)) & FALLING, 0);
+        assert_eq!(world.block(at(17, 10, 17)), Block::Air);
+        assert_eq!(world.block(at(17, 5, 17)), Block::Air);
+        // And spreads out like a source once it lands
+        assert_eq!(world.fluid_level(at(18, 1, 16)), 1);
+        assert_eq!(world.fluid_level(at(16, 1, 24)), 7);
+        assert_eq!(world.block(at(16, 1, 25)), Block::Air);
+    }
+
+    #[test]
+    fn removing_the_source_drains_the_flow() {
+        let mut world = floor();
+        world.set_fluid(at(16, 3, 16), Block::Water, 0);
+        settle(&mut world);
+        assert_eq!(world.block(at(18, 1, 16)), Block::Water);
+
+        world.set_block(at(16, 3, 16), Block::Air);
+        settle(&mut world);
+
+        for world_pos in [at(16, 2, 16), at(16, 1, 16), at(18, 1, 16), at(16, 1, 22)] {
+            assert_eq!(world.block(world_pos), Block::Air, "at {world_pos}");
+        }
+    }
+
+    #[test]
+    fn water_between_two_sources_becomes_a_source() {
+        let mut world = floor();
+        world.set_fluid(at(15, 1, 16), Block::Water, 0);
+        world.set_fluid(at(17, 1, 16), Block::Water, 0);
+        settle(&mut world);
+
+        assert_eq!(world.fluid_level(at(16, 1, 16)), 0);
+    }
+
+    #[test]
+    fn lava_touching_water_turns_to_stone() {
+        let mut world = floor();
+        world.set_fluid(at(16, 1, 16), Block::Water, 0);
+        world.set_fluid(at(19, 1, 16), Block::Lava, 0);
+        settle(&mut world);
+
+        assert_eq!(world.block(at(19, 1, 16)), Block::Stone);
+        // Water doesn't flow into the stone, and no lava is left next to any water
+        assert_eq!(world.block(at(16, 1, 16)), Block::Water);
+        for x in 0..32 {
+            for z in 0..32 {
+                let world_pos = at(x, 1, z);
+                if world.block(world_pos) != Block::Lava {
+                    continue;
+                }
+                for offset in HORIZONTAL {
+                    assert_ne!(world.block(world_pos + offset), Block::Water, "lava at {world_pos}");
+                }
+            }
+        }
+    }
+}
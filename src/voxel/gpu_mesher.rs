@@ -0,0 +1,614 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+use crate::engine::resource::mesh_pool::{MeshPool, PoolMesh};
+use crate::engine::util::catch_validation_error;
+use crate::voxel::block::{Block, RenderLayer};
+use crate::voxel::chunk::Chunk;
+use crate::voxel::light::{self, LightChannel};
+use crate::voxel::util::{CHUNK_SIZE, CHUNK_VOL};
+use crate::voxel::vertex::ChunkVertex;
+
+/// Where Chunk Meshes get built.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeshingMode {
+    /// Everything gets meshed on the CPU.
+    #[default]
+    Cpu,
+    /// Opaque and cutout blocks at full detail get meshed by a compute shader.
+    /// Chunks with fluids in them, translucent blocks and lower levels of detail still use the CPU.
+    Gpu,
+    /// Same as `Gpu`, but every Mesh also gets built on the CPU, and any difference gets logged.
+    Validate,
+}
+
+/// The Chunk plus one voxel of its neighbours on every side, so faces along the border
+/// can be culled without looking at other Chunks.
+const PADDED_SIZE: i32 = CHUNK_SIZE + 2;
+/// Marks voxels in Chunks that aren't loaded. Faces towards them don't get drawn.
+const UNLOADED: u32 = 255;
+/// Every voxel showing all 6 faces. Can't happen with real blocks, but it's what the buffers
+/// have to fit so they never run out.
+const MAX_FACES: u32 = CHUNK_VOL as u32 * 6;
+/// Has to match `@workgroup_size` in `chunk_mesh.wgsl`.
+const WORKGROUP_SIZE: u32 = 4;
+
+/// Block flags, has to match the constants in `chunk_mesh.wgsl`.
+const VISIBLE: u32 = 1;
+const OPAQUE: u32 = 2;
+const TRANSLUCENT: u32 = 4;
+const FLUID: u32 = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    layer: u32,
+    max_faces: u32,
+    _padding: [u32; 2],
+}
+
+/// Builds Chunk Meshes with a compute shader.
+///
+/// A Chunk's voxels get uploaded once with `upload`, then `mesh` builds the Mesh for one
+/// RenderLayer at a time. Every visible face gets a spot in the output buffers from an atomic
+/// counter, so the faces come out in no particular order. The counter gets read back to find
+/// out how big the Mesh is, and the faces get copied into a MeshPool.
+pub struct GpuMesher {
+    pipeline: wgpu::ComputePipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    voxel_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    counter_buffer: wgpu::Buffer,
+    validate: bool,
+}
+
+impl GpuMesher {
+    /// * `validate` - Compare every Mesh with the one the CPU builds. See `MeshingMode::Validate`.
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule, validate: bool) -> Self {
+        let storage = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("chunk_mesh_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+                storage(5, false),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Chunk Mesh Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, shader);
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Mesh Params Buffer"),
+            size: std::mem::size_of::<Params>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let voxel_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Mesh Voxel Buffer"),
+            size: (PADDED_SIZE.pow(3) as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let block_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Mesh Block Buffer"),
+            contents: bytemuck::cast_slice(&Block::ALL.map(block_info)),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Mesh Vertex Buffer"),
+            size: (MAX_FACES as usize * 4 * std::mem::size_of::<ChunkVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Mesh Index Buffer"),
+            size: (MAX_FACES as usize * 6 * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let counter_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Mesh Counter Buffer"),
+            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("chunk_mesh_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: voxel_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: block_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: vertex_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: index_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: counter_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            pipeline,
+            pipeline_layout,
+            bind_group,
+            params_buffer,
+            voxel_buffer,
+            vertex_buffer,
+            index_buffer,
+            counter_buffer,
+            validate,
+        }
+    }
+
+    /// Rebuilds the pipeline with another version of the shader. Meshes built from now on use it.
+    /// If that doesn't work the old pipeline stays.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, shader: &wgpu::ShaderModule) -> Result<(), wgpu::Error> {
+        self.pipeline = catch_validation_error(device, || Self::create_pipeline(device, &self.pipeline_layout, shader))?;
+        Ok(())
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::ComputePipeline {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Chunk Mesh Pipeline"),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: "cs_main",
+        })
+    }
+
+    /// Whether Meshes should also be built on the CPU and handed to `mesh` to compare with.
+    pub fn validates(&self) -> bool {
+        self.validate
+    }
+
+    /// Whether a Chunk can be meshed on the GPU at all. Fluids need their neighbours' levels
+    /// to slope their surfaces, which the compute shader doesn't know about.
+    pub fn can_mesh(chunk: &Chunk) -> bool {
+        !chunk.has_fluid()
+    }
+
+    /// Whether the GPU builds the Mesh for a RenderLayer. Translucent faces get sorted on the CPU,
+    /// so it has to know where they are anyway.
+    pub fn meshes_layer(layer: RenderLayer) -> bool {
+        layer != RenderLayer::Translucent
+    }
+
+    /// Uploads the voxels of a Chunk and its direct neighbours, to be meshed by `mesh`.
+    pub fn upload(&self, queue: &wgpu::Queue, chunk: &Chunk, world_chunks: &HashMap<glam::IVec3, Chunk>) {
+        let mut voxels = Vec::with_capacity(PADDED_SIZE.pow(3) as usize);
+        for y in -1..=CHUNK_SIZE {
+            for z in -1..=CHUNK_SIZE {
+                for x in -1..=CHUNK_SIZE {
+                    voxels.push(pack_voxel(chunk, glam::IVec3::new(x, y, z), world_chunks));
+                }
+            }
+        }
+
+        queue.write_buffer(&self.voxel_buffer, 0, bytemuck::cast_slice(&voxels));
+    }
+
+    /// Builds the Mesh for one RenderLayer of the Chunk that was uploaded last.
+    ///
+    /// * `expected` - What the CPU made of the same voxels. Any difference gets logged.
+    /// * `pool` - Where the Mesh goes.
+    ///
+    /// Gives `None` if the results couldn't be read back, so the CPU can take over.
+    pub fn mesh(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layer: RenderLayer,
+        name: &str,
+        expected: Option<(&[ChunkVertex], &[u32])>,
+        pool: &mut MeshPool<ChunkVertex>,
+    ) -> Option<PoolMesh> {
+        let faces = self.dispatch(device, queue, layer, name)?;
+
+        if let Some(expected) = expected {
+            let actual = self.read_mesh(device, queue, faces)?;
+            if let Err(e) = compare_meshes(expected, (&actual.0, &actual.1)) {
+                log::warn!("GPU mesh of {name} doesn't match the CPU: {e}");
+            }
+        }
+
+        Some(pool.insert_from_buffers(&self.vertex_buffer, faces * 4, &self.index_buffer, faces * 6, device, queue))
+    }
+
+    /// Runs the compute shader over the Chunk that was uploaded last. Gives how many faces it made.
+    fn dispatch(&self, device: &wgpu::Device, queue: &wgpu::Queue, layer: RenderLayer, name: &str) -> Option<u32> {
+        let params = Params {
+            layer: layer as u32,
+            max_faces: MAX_FACES,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+        queue.write_buffer(&self.counter_buffer, 0, bytemuck::cast_slice(&[0u32]));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Chunk Mesh Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Chunk Mesh Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            let workgroups = CHUNK_SIZE as u32 / WORKGROUP_SIZE;
+            compute_pass.dispatch_workgroups(workgroups, workgroups, workgroups);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let faces: u32 = read_buffer(device, queue, &self.counter_buffer, 1)?[0];
+        if faces > MAX_FACES {
+            log::warn!("{name} has {faces} faces, only {MAX_FACES} fit");
+            return None;
+        }
+        Some(faces)
+    }
+
+    /// Copies the vertices and indices of the last `dispatch` back to the CPU.
+    fn read_mesh(&self, device: &wgpu::Device, queue: &wgpu::Queue, faces: u32) -> Option<(Vec<ChunkVertex>, Vec<u32>)> {
+        let vertices = read_buffer(device, queue, &self.vertex_buffer, faces as usize * 4)?;
+        let indices = read_buffer(device, queue, &self.index_buffer, faces as usize * 6)?;
+        Some((vertices, indices))
+    }
+}
+
+/// What the compute shader needs to know about a block.
+/// Flags, RenderLayer, and the top, side and bottom tiles packed 8 bits each.
+fn block_info(block: Block) -> [u32; 4] {
+    let mut flags = 0;
+    if block.is_visible() {
+        flags |= VISIBLE;
+    }
+    if block.is_opaque() {
+        flags |= OPAQUE;
+    }
+    if block.render_layer() == RenderLayer::Translucent {
+        flags |= TRANSLUCENT;
+    }
+    if block.is_fluid() {
+        flags |= FLUID;
+    }
+
+    let tiles = block.texture(glam::IVec3::Y)
+        | block.texture(glam::IVec3::X) << 8
+        | block.texture(glam::IVec3::NEG_Y) << 16;
+
+    [flags, block.render_layer() as u32, tiles, 0]
+}
+
+/// A voxel relative to `chunk` the way the compute shader wants it:
+/// the block in the lowest 8 bits, then sunlight and block light, 4 bits each.
+fn pack_voxel(chunk: &Chunk, voxel_pos: glam::IVec3, world_chunks: &HashMap<glam::IVec3, Chunk>) -> u32 {
+    let Some(block) = chunk.block_at(voxel_pos, world_chunks) else {
+        return UNLOADED;
+    };
+
+    let world_pos = chunk.position() * CHUNK_SIZE + voxel_pos;
+    let sun = light::get_light(world_chunks, world_pos, LightChannel::Sun).unwrap_or(0) as u32;
+    let block_light = light::get_light(world_chunks, world_pos, LightChannel::Block).unwrap_or(0) as u32;
+
+    block as u32 | sun << 8 | block_light << 12
+}
+
+/// Copies the first `count` elements of a buffer back to the CPU, waiting until they're there.
+fn read_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    count: usize,
+) -> Option<Vec<T>> {
+    let mut data = vec![T::zeroed(); count];
+    let size = std::mem::size_of_val(data.as_slice()) as wgpu::BufferAddress;
+    if size == 0 {
+        return Some(data);
+    }
+
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Chunk Mesh Staging Buffer"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Chunk Mesh Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = staging_buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+
+    match receiver.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            log::warn!("Couldn't read back a chunk mesh buffer: {e}");
+            return None;
+        }
+        Err(_) => return None,
+    }
+
+    bytemuck::cast_slice_mut(&mut data).copy_from_slice(&slice.get_mapped_range());
+    staging_buffer.unmap();
+    Some(data)
+}
+
+/// A face of a Mesh: its 4 vertices, and its indices counted from its first vertex.
+struct MeshFace {
+    vertices: [ChunkVertex; 4],
+    indices: [u32; 6],
+}
+
+impl MeshFace {
+    /// Both meshers always put a face's 4 vertices right after each other,
+    /// and start its indices with the first one.
+    fn collect(vertices: &[ChunkVertex], indices: &[u32]) -> Result<Vec<MeshFace>, String> {
+        indices
+            .chunks(6)
+            .map(|face_indices| {
+                let first = face_indices[0] as usize;
+                let face_vertices = vertices
+                    .get(first..first + 4)
+                    .ok_or_else(|| format!("index {first} is out of bounds"))?;
+
+                Ok(MeshFace {
+                    vertices: face_vertices.try_into().unwrap(),
+                    indices: std::array::from_fn(|i| face_indices[i].wrapping_sub(first as u32)),
+                })
+            })
+            .collect()
+    }
+
+    /// Tells faces apart no matter what order they're in: no two faces share a normal and a first corner.
+    /// The first word of a vertex has both.
+    fn key(&self) -> u32 {
+        bytemuck::cast::<_, [u32; 2]>(self.vertices[0])[0]
+    }
+
+    fn matches(&self, other: &MeshFace) -> bool {
+        self.indices == other.indices && self.vertices == other.vertices
+    }
+}
+
+/// Checks that two Meshes have the same faces, in any order.
+fn compare_meshes(
+    expected: (&[ChunkVertex], &[u32]),
+    actual: (&[ChunkVertex], &[u32]),
+) -> Result<(), String> {
+    let mut expected = MeshFace::collect(expected.0, expected.1)?;
+    let mut actual = MeshFace::collect(actual.0, actual.1)?;
+    if expected.len() != actual.len() {
+        return Err(format!("expected {} faces, got {}", expected.len(), actual.len()));
+    }
+
+    expected.sort_by_key(MeshFace::key);
+    actual.sort_by_key(MeshFace::key);
+    for (expected, actual) in expected.iter().zip(&actual) {
+        if !expected.matches(actual) {
+            return Err(format!(
+                "face at {:?} facing {:?} differs",
+                expected.vertices[0].position(),
+                expected.vertices[0].normal()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::util::{load_string, software_device};
+    use crate::voxel::chunk::ChunkModel;
+
+    /// A Chunk with nothing in it.
+    fn empty_chunk(chunk_pos: glam::IVec3) -> Chunk {
+        let mut chunk = Chunk::new(chunk_pos);
+        // Way up in the sky, so the terrain doesn't get there
+        chunk.generate(glam::IVec3::new(0, 1000, 0));
+        chunk
+    }
+
+    /// Stone up to `height`, with grass on top.
+    fn flat_chunk(chunk_pos: glam::IVec3, height: i32) -> Chunk {
+        let mut chunk = empty_chunk(chunk_pos);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..height {
+                    chunk.set_block(glam::IVec3::new(x, y, z), Block::Stone);
+                }
+                chunk.set_block(glam::IVec3::new(x, height, z), Block::Grass);
+            }
+        }
+        chunk
+    }
+
+    /// Meshes the Chunk at `chunk_pos` on both the CPU and the GPU, and checks they made the same faces.
+    fn assert_same_meshes(mut chunks: HashMap<glam::IVec3, Chunk>, chunk_pos: glam::IVec3) {
+        let (device, queue) = software_device();
+        let source = pollster::block_on(load_string("shaders/chunk_mesh.wgsl")).unwrap();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("chunk_mesh.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let mesher = GpuMesher::new(&device, &shader, true);
+
+        light::light_all(&mut chunks);
+        assert!(GpuMesher::can_mesh(&chunks[&chunk_pos]));
+        mesher.upload(&queue, &chunks[&chunk_pos], &chunks);
+
+        for layer in RenderLayer::ALL.into_iter().filter(|&layer| GpuMesher::meshes_layer(layer)) {
+            let (vertices, indices, _) = ChunkModel::mesh_data(&chunks, chunk_pos, 0, &[layer]);
+            let expected = (&vertices[layer as usize][..], &indices[layer as usize][..]);
+
+            let faces = mesher.dispatch(&device, &queue, layer, "test chunk").unwrap();
+            let actual = mesher.read_mesh(&device, &queue, faces).unwrap();
+            assert_eq!(compare_meshes(expected, (&actual.0, &actual.1)), Ok(()), "{layer:?}");
+        }
+    }
+
+    /// A Mesh of square faces, each one `(corner, face)`, with their vertices in the same order.
+    fn quads(faces: &[(glam::Vec3, usize)], light: u8) -> (Vec<ChunkVertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for &(corner, face) in faces {
+            let first = vertices.len() as u32;
+            for [u, v] in [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]] {
+                let position = corner + glam::Vec3::new(u, 0.0, v);
+                vertices.push(ChunkVertex::new(position, face, 1, [u, v], [light, 0], [0.0, 0.0]));
+            }
+            indices.extend([0, 1, 2, 0, 2, 3].map(|index| first + index));
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn faces_in_another_order_match() {
+        let faces = [(glam::Vec3::new(1.0, 2.0, 3.0), 2), (glam::Vec3::new(4.0, 5.0, 6.0), 3)];
+        let (vertices, indices) = quads(&faces, 15);
+        let (reordered, reordered_indices) = quads(&[faces[1], faces[0]], 15);
+
+        assert_eq!(compare_meshes((&vertices, &indices), (&reordered, &reordered_indices)), Ok(()));
+    }
+
+    #[test]
+    fn a_changed_vertex_is_reported() {
+        let faces = [(glam::Vec3::new(1.0, 2.0, 3.0), 2), (glam::Vec3::new(4.0, 5.0, 6.0), 3)];
+        let (vertices, indices) = quads(&faces, 15);
+        // The second face is a little darker
+        let (mut changed, changed_indices) = quads(&faces, 15);
+        changed[4..].copy_from_slice(&quads(&faces[1..], 14).0);
+
+        let error = compare_meshes((&vertices, &indices), (&changed, &changed_indices)).unwrap_err();
+        assert!(error.contains("differs"), "{error}");
+    }
+
+    #[test]
+    fn out_of_bounds_indices_are_reported() {
+        let (vertices, indices) = quads(&[(glam::Vec3::ZERO, 2)], 15);
+        let (mut broken, mut broken_indices) = quads(&[(glam::Vec3::ZERO, 2)], 15);
+        broken.truncate(3);
+        broken_indices[0] = 1;
+
+        let error = compare_meshes((&vertices, &indices), (&broken, &broken_indices)).unwrap_err();
+        assert!(error.contains("out of bounds"), "{error}");
+        assert!(MeshFace::collect(&broken, &broken_indices).is_err());
+    }
+
+    #[test]
+    fn missing_faces_are_reported() {
+        let (vertices, indices) = quads(&[(glam::Vec3::ZERO, 2), (glam::Vec3::ONE, 2)], 15);
+        let (fewer, fewer_indices) = quads(&[(glam::Vec3::ZERO, 2)], 15);
+
+        assert_eq!(
+            compare_meshes((&vertices, &indices), (&fewer, &fewer_indices)),
+            Err("expected 2 faces, got 1".to_string())
+        );
+    }
+
+    #[test]
+    fn flat_terrain() {
+        let chunk_pos = glam::IVec3::ZERO;
+        assert_same_meshes(HashMap::from([(chunk_pos, flat_chunk(chunk_pos, 8))]), chunk_pos);
+    }
+
+    #[test]
+    fn chunk_border() {
+        let chunk_pos = glam::IVec3::ZERO;
+        let mut chunk = flat_chunk(chunk_pos, 8);
+        let mut neighbour = flat_chunk(glam::IVec3::X, 12);
+        // A wall right along the border, next to a hole on the other side of it
+        for y in 9..16 {
+            for z in 4..12 {
+                chunk.set_block(glam::IVec3::new(CHUNK_SIZE - 1, y, z), Block::Stone);
+            }
+            neighbour.set_block(glam::IVec3::new(0, y, 6), Block::Air);
+        }
+        chunk.set_block(glam::IVec3::new(CHUNK_SIZE - 1, 16, 4), Block::Lamp);
+        chunk.set_block(glam::IVec3::new(CHUNK_SIZE - 1, 9, 0), Block::Leaves);
+
+        assert_same_meshes(HashMap::from([(chunk_pos, chunk), (glam::IVec3::X, neighbour)]), chunk_pos);
+    }
+
+    #[test]
+    fn fluid_and_translucent() {
+        let chunk_pos = glam::IVec3::ZERO;
+        let mut chunk = flat_chunk(chunk_pos, 8);
+        for x in 10..14 {
+            for z in 10..14 {
+                chunk.set_block(glam::IVec3::new(x, 9, z), Block::Glass);
+                chunk.set_block(glam::IVec3::new(x, 10, z), Block::Leaves);
+            }
+        }
+        // The Chunk itself can't have fluids in it, but its neighbour can
+        let mut neighbour = flat_chunk(glam::IVec3::X, 4);
+        for y in 5..12 {
+            for z in 0..CHUNK_SIZE {
+                neighbour.set_block(glam::IVec3::new(0, y, z), Block::Water);
+                neighbour.set_block(glam::IVec3::new(1, y, z), Block::Lava);
+            }
+        }
+
+        assert_same_meshes(HashMap::from([(chunk_pos, chunk), (glam::IVec3::X, neighbour)]), chunk_pos);
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::voxel::block::Block;
+use crate::voxel::chunk::Chunk;
+use crate::voxel::util::{world_to_chunk, CHUNK_SIZE};
+
+/// The brightest light can be. Light drops by one for every voxel it travels.
+pub const MAX_LIGHT: u8 = 15;
+
+const DIRECTIONS: [glam::IVec3; 6] = [
+    glam::IVec3::X,
+    glam::IVec3::NEG_X,
+    glam::IVec3::Y,
+    glam::IVec3::NEG_Y,
+    glam::IVec3::Z,
+    glam::IVec3::NEG_Z,
+];
+
+/// Voxels store two kinds of light.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LightChannel {
+    /// Light from the sky. Travels straight down through air without losing any strength.
+    /// Anything else it can pass through, like water or leaves, dims it.
+    Sun,
+    /// Light given off by blocks like lamps.
+    Block,
+}
+
+/// The light level at a world position, or None if that Chunk isn't loaded.
+pub fn get_light(
+    chunks: &HashMap<glam::IVec3, Chunk>,
+    world_pos: glam::IVec3,
+    channel: LightChannel,
+) -> Option<u8> {
+    let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+    chunks.get(&chunk_pos).map(|chunk| chunk.light(local_pos, channel))
+}
+
+fn set_light(
+    chunks: &mut HashMap<glam::IVec3, Chunk>,
+    world_pos: glam::IVec3,
+    channel: LightChannel,
+    level: u8,
+    dirty: &mut Option<&mut HashSet<glam::IVec3>>,
+) {
+    let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+    if let Some(chunk) = chunks.get_mut(&chunk_pos) {
+        chunk.set_light(local_pos, channel, level);
+        if let Some(dirty) = dirty {
+            mark_dirty(chunk_pos, local_pos, dirty);
+        }
+    }
+}
+
+fn get_block(chunks: &HashMap<glam::IVec3, Chunk>, world_pos: glam::IVec3) -> Option<Block> {
+    let (chunk_pos, local_pos) = world_to_chunk(world_pos);
+    chunks.get(&chunk_pos).map(|chunk| chunk.block(local_pos))
+}
+
+/// Marks the Chunk a voxel is in as needing a new mesh. Faces of neighbouring
+/// Chunks can use this voxel's light too, so those get marked if we're on a border.
+fn mark_dirty(chunk_pos: glam::IVec3, local_pos: glam::IVec3, dirty: &mut HashSet<glam::IVec3>) {
+    dirty.insert(chunk_pos);
+    for axis in 0..3 {
+        let mut offset = glam::IVec3::ZERO;
+        if local_pos[axis] == 0 {
+            offset[axis] = -1;
+        } else if local_pos[axis] == CHUNK_SIZE - 1 {
+            offset[axis] = 1;
+        } else {
+            continue;
+        }
+        dirty.insert(chunk_pos + offset);
+    }
+}
+
+/// Lights every loaded Chunk from scratch.
+///
+/// Sunlight is poured down every column until it hits something that isn't air,
+/// then both sunlight and block light are flood filled outwards.
+pub fn light_all(chunks: &mut HashMap<glam::IVec3, Chunk>) {
+    // Where sunlight stops in every column. Anything above this is fully lit.
+    let mut heights: HashMap<(i32, i32), i32> = HashMap::new();
+
+    let mut columns: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    for chunk_pos in chunks.keys() {
+        let column = columns
+            .entry((chunk_pos.x, chunk_pos.z))
+            .or_insert((chunk_pos.y, chunk_pos.y));
+        column.0 = column.0.min(chunk_pos.y);
+        column.1 = column.1.max(chunk_pos.y);
+    }
+
+    for ((cx, cz), (bottom, top)) in columns {
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                // Pour the light down through the column of chunks, stopping at the first
+                // block that isn't air or the first chunk that isn't loaded. Whatever is
+                // below that only gets the dimmed light the flood fill brings it.
+                let mut height = bottom * CHUNK_SIZE - 1;
+                'column: for cy in (bottom..=top).rev() {
+                    let Some(chunk) = chunks.get_mut(&glam::IVec3::new(cx, cy, cz)) else {
+                        height = (cy + 1) * CHUNK_SIZE - 1;
+                        break;
+                    };
+
+                    for y in (0..CHUNK_SIZE).rev() {
+                        let local_pos = glam::IVec3::new(x, y, z);
+                        if chunk.block(local_pos) != Block::Air {
+                            height = cy * CHUNK_SIZE + y;
+                            break 'column;
+                        }
+                        chunk.set_light(local_pos, LightChannel::Sun, MAX_LIGHT);
+                    }
+                }
+                heights.insert((cx * CHUNK_SIZE + x, cz * CHUNK_SIZE + z), height);
+            }
+        }
+    }
+
+    // Only the sunlit voxels next to a shadowed one need to spread any further,
+    // that includes the one right above where the column stopped.
+    let mut sun_queue = VecDeque::new();
+    for (&(x, z), &height) in &heights {
+        let highest_neighbour = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .iter()
+            .filter_map(|(dx, dz)| heights.get(&(x + dx, z + dz)))
+            .copied()
+            .max()
+            .unwrap_or(height);
+
+        for y in height + 1..=highest_neighbour.max(height + 1) {
+            sun_queue.push_back(glam::IVec3::new(x, y, z));
+        }
+    }
+    propagate(chunks, LightChannel::Sun, sun_queue, &mut None);
+
+    let mut block_queue = VecDeque::new();
+    for (chunk_pos, chunk) in chunks.iter_mut() {
+        for local_pos in chunk.emitters() {
+            let emission = chunk.block(local_pos).light_emission();
+            chunk.set_light(local_pos, LightChannel::Block, emission);
+            block_queue.push_back(*chunk_pos * CHUNK_SIZE + local_pos);
+        }
+    }
+    propagate(chunks, LightChannel::Block, block_queue, &mut None);
+}
+
+/// Fixes up the light around a voxel after its block changed from `old` to `new`.
+///
+/// Every Chunk whose mesh needs to be rebuilt because of it gets added to `dirty`.
+pub fn update_light(
+    chunks: &mut HashMap<glam::IVec3, Chunk>,
+    world_pos: glam::IVec3,
+    new: Block,
+    dirty: &mut HashSet<glam::IVec3>,
+) {
+    let dirty = &mut Some(dirty);
+    for channel in [LightChannel::Block, LightChannel::Sun] {
+        let mut queue = remove_light(chunks, world_pos, channel, dirty);
+
+        if channel == LightChannel::Block && new.light_emission() > 0 {
+            set_light(chunks, world_pos, channel, new.light_emission(), dirty);
+            queue.push_back(world_pos);
+        }
+
+        // Let the light around us flow back in if it can now pass through.
+        if !new.is_opaque() {
+            for dir in DIRECTIONS {
+                if get_light(chunks, world_pos + dir, channel).is_some_and(|level| level > 0) {
+                    queue.push_back(world_pos + dir);
+                }
+            }
+        }
+
+        propagate(chunks, channel, queue, dirty);
+    }
+}
+
+/// Flood fills light outwards from every position in the queue.
+fn propagate(
+    chunks: &mut HashMap<glam::IVec3, Chunk>,
+    channel: LightChannel,
+    mut queue: VecDeque<glam::IVec3>,
+    dirty: &mut Option<&mut HashSet<glam::IVec3>>,
+) {
+    while let Some(pos) = queue.pop_front() {
+        let level = get_light(chunks, pos, channel).unwrap_or(0);
+        if level == 0 {
+            continue;
+        }
+
+        for dir in DIRECTIONS {
+            let neighbour = pos + dir;
+            let block = match get_block(chunks, neighbour) {
+                Some(block) if !block.is_opaque() => block,
+                _ => continue,
+            };
+
+            // Only open sky keeps sunlight at full strength, water and leaves dim it like anything else.
+            let new_level = if channel == LightChannel::Sun
+                && dir == glam::IVec3::NEG_Y
+                && level == MAX_LIGHT
+                && block == Block::Air
+            {
+                MAX_LIGHT
+            } else {
+                level - 1
+            };
+
+            if get_light(chunks, neighbour, channel).unwrap_or(MAX_LIGHT) < new_level {
+                set_light(chunks, neighbour, channel, new_level, dirty);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+}
+
+/// Removes the light at a position along with all the light that came from it.
+///
+/// Returns the positions lit by something else that bordered the removed area.
+/// Those need to be propagated again to fill the hole back in.
+fn remove_light(
+    chunks: &mut HashMap<glam::IVec3, Chunk>,
+    world_pos: glam::IVec3,
+    channel: LightChannel,
+    dirty: &mut Option<&mut HashSet<glam::IVec3>>,
+) -> VecDeque<glam::IVec3> {
+    let mut relight = VecDeque::new();
+    let Some(level) = get_light(chunks, world_pos, channel) else {
+        return relight;
+    };
+
+    set_light(chunks, world_pos, channel, 0, dirty);
+    let mut queue = VecDeque::from([(world_pos, level)]);
+
+    while let Some((pos, level)) = queue.pop_front() {
+        for dir in DIRECTIONS {
+            let neighbour = pos + dir;
+            let Some(neighbour_level) = get_light(chunks, neighbour, channel) else {
+                continue;
+            };
+            if neighbour_level == 0 {
+                continue;
+            }
+
+            let lit_by_us = neighbour_level < level
+                || (channel == LightChannel::Sun
+                    && dir == glam::IVec3::NEG_Y
+                    && level == MAX_LIGHT
+                    && neighbour_level == MAX_LIGHT);
+
+            if lit_by_us {
+                set_light(chunks, neighbour, channel, 0, dirty);
+                queue.push_back((neighbour, neighbour_level));
+
+                // Light sources keep shining on their own.
+                let emission = get_block(chunks, neighbour).map_or(0, Block::light_emission);
+                if channel == LightChannel::Block && emission > 0 {
+                    set_light(chunks, neighbour, channel, emission, dirty);
+                    relight.push_back(neighbour);
+                }
+            } else {
+                relight.push_back(neighbour);
+            }
+        }
+    }
+
+    relight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::ChunkModel;
+
+    fn pos(x: i32, y: i32, z: i32) -> glam::IVec3 {
+        glam::IVec3::new(x, y, z)
+    }
+
+    /// Empty Chunks at every position, with layers of blocks stretching across all of them.
+    /// Lit from scratch once everything is in.
+    fn lit_world(chunk_positions: &[glam::IVec3], layers: &[(i32, Block)]) -> ChunkModel {
+        let mut world = ChunkModel::new();
+        for &chunk_pos in chunk_positions {
+            world.add_empty_chunk(chunk_pos);
+        }
+        let max_x = chunk_positions.iter().map(|chunk_pos| chunk_pos.x + 1).max().unwrap() * CHUNK_SIZE - 1;
+        for &(y, block) in layers {
+            world.fill(pos(0, y, 0), pos(max_x, y, CHUNK_SIZE - 1), block);
+        }
+        world.light_all();
+        world
+    }
+
+    #[test]
+    fn sunlight_is_dimmed_by_water_and_leaves() {
+        let layers: Vec<(i32, Block)> = (10..15)
+            .map(|y| (y, Block::Water))
+            .chain([(20, Block::Leaves)])
+            .collect();
+        let world = lit_world(&[glam::IVec3::ZERO], &layers);
+
+        let sun = |y| world.light(pos(16, y, 16), LightChannel::Sun);
+        assert_eq!(sun(21), MAX_LIGHT);
+        assert_eq!(sun(20), MAX_LIGHT - 1, "leaves");
+        assert_eq!(sun(19), MAX_LIGHT - 2, "under the leaves");
+        assert_eq!(sun(15), MAX_LIGHT - 6);
+        assert_eq!(sun(14), MAX_LIGHT - 7, "top of the water");
+        assert_eq!(sun(10), MAX_LIGHT - 11, "bottom of the water");
+        assert_eq!(sun(9), MAX_LIGHT - 12, "under the water");
+    }
+
+    #[test]
+    fn placing_blocks_matches_lighting_from_scratch() {
+        let mut world = lit_world(&[glam::IVec3::ZERO], &[(20, Block::Leaves)]);
+        for x in 4..12 {
+            world.set_block(pos(x, 12, 8), Block::Water);
+        }
+        world.set_block(pos(8, 5, 8), Block::Stone);
+        world.set_block(pos(8, 20, 8), Block::Air);
+
+        let lights = |world: &ChunkModel| -> Vec<u8> {
+            (0..CHUNK_SIZE)
+                .flat_map(|y| (0..CHUNK_SIZE).map(move |x| pos(x, y, 8)))
+                .map(|world_pos| world.light(world_pos, LightChannel::Sun))
+                .collect()
+        };
+        let placed = lights(&world);
+        world.light_all();
+
+        assert_eq!(placed, lights(&world));
+    }
+
+    #[test]
+    fn placing_and_removing_a_lamp() {
+        let mut world = lit_world(&[glam::IVec3::ZERO], &[]);
+        let lamp = pos(16, 16, 16);
+
+        world.set_block(lamp, Block::Lamp);
+        let block_light = |world: &ChunkModel, world_pos| world.light(world_pos, LightChannel::Block);
+        assert_eq!(block_light(&world, lamp), MAX_LIGHT);
+        assert_eq!(block_light(&world, lamp + glam::IVec3::X), MAX_LIGHT - 1);
+        assert_eq!(block_light(&world, lamp + pos(3, -2, 1)), MAX_LIGHT - 6);
+        assert_eq!(block_light(&world, lamp + pos(0, 15, 0)), 0);
+
+        world.set_block(lamp, Block::Air);
+        for offset in [pos(0, 0, 0), pos(1, 0, 0), pos(3, -2, 1), pos(0, 14, 0)] {
+            assert_eq!(block_light(&world, lamp + offset), 0, "at {offset}");
+        }
+    }
+
+    #[test]
+    fn a_lamp_behind_a_wall() {
+        let mut world = lit_world(&[glam::IVec3::ZERO], &[]);
+        let lamp = pos(16, 16, 16);
+        world.set_block(lamp, Block::Lamp);
+
+        // The light has to go around the wall now
+        world.set_block(lamp + glam::IVec3::X, Block::Stone);
+        assert_eq!(world.light(lamp + pos(2, 0, 0), LightChannel::Block), MAX_LIGHT - 4);
+
+        world.set_block(lamp + glam::IVec3::X, Block::Air);
+        assert_eq!(world.light(lamp + pos(2, 0, 0), LightChannel::Block), MAX_LIGHT - 2);
+    }
+
+    #[test]
+    fn a_roof_shades_the_cave_under_it() {
+        let mut world = lit_world(&[glam::IVec3::ZERO], &[(20, Block::Stone)]);
+        let sun = |world: &ChunkModel, world_pos| world.light(world_pos, LightChannel::Sun);
+        assert_eq!(sun(&world, pos(8, 21, 8)), MAX_LIGHT);
+        assert_eq!(sun(&world, pos(8, 19, 8)), 0);
+        assert_eq!(sun(&world, pos(8, 0, 8)), 0);
+
+        // A hole lets a shaft of full sunlight all the way down, spreading out from there
+        world.set_block(pos(8, 20, 8), Block::Air);
+        assert_eq!(sun(&world, pos(8, 20, 8)), MAX_LIGHT);
+        assert_eq!(sun(&world, pos(8, 0, 8)), MAX_LIGHT);
+        assert_eq!(sun(&world, pos(10, 0, 8)), MAX_LIGHT - 2);
+        assert_eq!(sun(&world, pos(9, 19, 8)), MAX_LIGHT - 1);
+
+        world.set_block(pos(8, 20, 8), Block::Stone);
+        for world_pos in [pos(8, 19, 8), pos(8, 0, 8), pos(10, 0, 8), pos(9, 19, 8)] {
+            assert_eq!(sun(&world, world_pos), 0, "at {world_pos}");
+        }
+    }
+
+    #[test]
+    fn light_crosses_chunk_borders() {
+        let mut world = lit_world(&[glam::IVec3::ZERO, glam::IVec3::X], &[(20, Block::Stone)]);
+
+        // Sunlight through a hole right at the edge of the first Chunk
+        world.set_block(pos(CHUNK_SIZE - 1, 20, 8), Block::Air);
+        assert_eq!(world.light(pos(CHUNK_SIZE, 10, 8), LightChannel::Sun), MAX_LIGHT - 1);
+        assert_eq!(world.light(pos(CHUNK_SIZE + 3, 10, 8), LightChannel::Sun), MAX_LIGHT - 4);
+
+        // And a lamp right at the edge of the second one
+        world.set_block(pos(CHUNK_SIZE, 5, 20), Block::Lamp);
+        assert_eq!(world.light(pos(CHUNK_SIZE - 1, 5, 20), LightChannel::Block), MAX_LIGHT - 1);
+        assert_eq!(world.light(pos(CHUNK_SIZE - 5, 5, 20), LightChannel::Block), MAX_LIGHT - 5);
+
+        // Both survive lighting everything from scratch
+        world.light_all();
+        assert_eq!(world.light(pos(CHUNK_SIZE + 3, 10, 8), LightChannel::Sun), MAX_LIGHT - 4);
+        assert_eq!(world.light(pos(CHUNK_SIZE - 5, 5, 20), LightChannel::Block), MAX_LIGHT - 5);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::voxel::block::Block;
+use crate::voxel::chunk::Chunk;
+use crate::voxel::util::CHUNK_SIZE;
+
+/// How many levels of detail a Chunk can be meshed at. Level 0 is every voxel,
+/// every level after that merges twice as many voxels on each side into one cell.
+pub const LODS: usize = 4;
+
+/// How many voxels there are on each side of a cell at a level of detail.
+pub fn lod_scale(lod: usize) -> i32 {
+    1 << lod
+}
+
+/// Which level of detail to draw something at, `distance` away from the camera.
+/// Everything closer than `lod_distance` gets full detail, and every time the distance
+/// doubles after that, the detail halves.
+pub fn select_lod(distance: f32, lod_distance: f32) -> usize {
+    if distance < lod_distance || lod_distance <= 0.0 {
+        return 0;
+    }
+
+    let lod = (distance / lod_distance).log2().floor() as usize + 1;
+    lod.min(LODS - 1)
+}
+
+/// A Chunk's voxels at a lower resolution, to mesh far away Chunks with fewer faces.
+///
+/// A cell is filled as soon as any of its voxels is, so distant terrain is never lower than
+/// the real thing. That's what keeps the seams between levels closed: the coarser side
+/// of a seam always reaches at least as high, and its walls along the Chunk border
+/// (see `create_lod_mesh_data`) cover the step.
+pub struct Downsampled {
+    scale: i32,
+    size: i32,
+    blocks: Vec<Block>,
+}
+
+impl Downsampled {
+    pub fn new(chunk: &Chunk, lod: usize) -> Self {
+        let scale = lod_scale(lod);
+        let size = CHUNK_SIZE / scale;
+        let mut blocks = Vec::with_capacity((size * size * size) as usize);
+
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    blocks.push(Self::cell_block(chunk, glam::IVec3::new(x, y, z) * scale, scale));
+                }
+            }
+        }
+
+        Self {
+            scale,
+            size,
+            blocks,
+        }
+    }
+
+    /// Voxels on each side of a cell.
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
+    /// Cells on each side of the Chunk.
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    /// The block a cell is drawn as. Anything outside of the Chunk is air.
+    pub fn block(&self, cell: glam::IVec3) -> Block {
+        if cell.cmplt(glam::IVec3::ZERO).any() || cell.cmpge(glam::IVec3::splat(self.size)).any() {
+            return Block::Air;
+        }
+        self.blocks[(cell.x + self.size * cell.z + self.size * self.size * cell.y) as usize]
+    }
+
+    /// What the topmost voxels in a cell mostly are, since that's what you see from far away.
+    fn cell_block(chunk: &Chunk, origin: glam::IVec3, scale: i32) -> Block {
+        for y in (0..scale).rev() {
+            let mut counts: Vec<(Block, u32)> = Vec::new();
+            for x in 0..scale {
+                for z in 0..scale {
+                    let block = chunk.block(origin + glam::IVec3::new(x, y, z));
+                    if !block.is_visible() {
+                        continue;
+                    }
+                    match counts.iter_mut().find(|(counted, _)| *counted == block) {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((block, 1)),
+                    }
+                }
+            }
+
+            if let Some(&(block, _)) = counts.iter().max_by_key(|(_, count)| *count) {
+                return block;
+            }
+        }
+
+        Block::Air
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Chunk with its bottom corner cell at scale 2 filled in with `layers`, bottom layer first.
+    /// Each layer is the four voxels at (0, 0), (1, 0), (0, 1) and (1, 1) in x and z.
+    fn corner_cell(layers: [[Block; 4]; 2]) -> Chunk {
+        let mut chunk = Chunk::empty(glam::IVec3::ZERO);
+        for (y, layer) in layers.iter().enumerate() {
+            for (i, &block) in layer.iter().enumerate() {
+                chunk.set_block(glam::IVec3::new(i as i32 % 2, y as i32, i as i32 / 2), block);
+            }
+        }
+        chunk
+    }
+
+    fn cell_block(layers: [[Block; 4]; 2]) -> Block {
+        Downsampled::cell_block(&corner_cell(layers), glam::IVec3::ZERO, 2)
+    }
+
+    #[test]
+    fn cells_are_what_most_of_their_top_layer_is() {
+        use Block::*;
+
+        assert_eq!(cell_block([[Stone; 4], [Grass, Grass, Dirt, Grass]]), Grass);
+        // Air doesn't count, so a single block is enough
+        assert_eq!(cell_block([[Stone; 4], [Air, Sand, Air, Air]]), Sand);
+    }
+
+    #[test]
+    fn empty_top_layers_are_skipped() {
+        use Block::*;
+
+        assert_eq!(cell_block([[Dirt, Dirt, Stone, Dirt], [Air; 4]]), Dirt);
+        assert_eq!(cell_block([[Air; 4], [Air; 4]]), Air);
+    }
+
+    #[test]
+    fn downsampled_chunks_have_fewer_cells() {
+        let chunk = corner_cell([[Block::Stone; 4], [Block::Grass; 4]]);
+
+        let downsampled = Downsampled::new(&chunk, 1);
+        assert_eq!(downsampled.scale(), 2);
+        assert_eq!(downsampled.size(), CHUNK_SIZE / 2);
+        assert_eq!(downsampled.block(glam::IVec3::ZERO), Block::Grass);
+        assert_eq!(downsampled.block(glam::IVec3::X), Block::Air);
+        assert_eq!(downsampled.block(glam::IVec3::NEG_Y), Block::Air);
+        assert_eq!(downsampled.block(glam::IVec3::splat(downsampled.size())), Block::Air);
+
+        // The cell at scale 4 has the grass in its bottom layer, but nothing above that
+        assert_eq!(Downsampled::new(&chunk, 2).block(glam::IVec3::ZERO), Block::Grass);
+    }
+
+    #[test]
+    fn lods_halve_every_time_the_distance_doubles() {
+        let lod_distance = 100.0;
+
+        assert_eq!(select_lod(0.0, lod_distance), 0);
+        assert_eq!(select_lod(99.9, lod_distance), 0);
+        assert_eq!(select_lod(100.0, lod_distance), 1);
+        assert_eq!(select_lod(199.9, lod_distance), 1);
+        assert_eq!(select_lod(200.0, lod_distance), 2);
+        assert_eq!(select_lod(399.9, lod_distance), 2);
+        assert_eq!(select_lod(400.0, lod_distance), 3);
+        // There's no level past the last one
+        assert_eq!(select_lod(800.0, lod_distance), LODS - 1);
+        assert_eq!(select_lod(1.0e6, lod_distance), LODS - 1);
+    }
+
+    #[test]
+    fn no_lod_distance_means_full_detail() {
+        assert_eq!(select_lod(1000.0, 0.0), 0);
+        assert_eq!(select_lod(1000.0, -1.0), 0);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::voxel::chunk::ChunkModel;
+use crate::voxel::util::CHUNK_SIZE;
+
+/// Chunks are split into cubes this big for random ticks, so every part of the world
+/// gets picked about as often no matter how big Chunks are.
+const SECTION_SIZE: i32 = 16;
+/// How many voxels get a random tick in every section, every tick.
+const RANDOM_TICKS_PER_SECTION: u32 = 1;
+
+const NEIGHBOURS: [glam::IVec3; 7] = [
+    glam::IVec3::ZERO,
+    glam::IVec3::X,
+    glam::IVec3::NEG_X,
+    glam::IVec3::Y,
+    glam::IVec3::NEG_Y,
+    glam::IVec3::Z,
+    glam::IVec3::NEG_Z,
+];
+
+/// Runs the `BlockBehaviour` of every block that needs it.
+///
+/// Every tick, blocks hear about three things:
+/// * A voxel right next to them changed. They usually react by scheduling a tick.
+/// * A tick they scheduled earlier came up.
+/// * They got picked at random. A few voxels in every section of every Chunk are, every tick.
+///
+/// Nothing else in the world gets looked at, so a world where nothing happens costs next to nothing.
+#[derive(Debug)]
+pub struct BlockUpdates {
+    tick: u64,
+    /// Voxels to tick, by the tick they're due on.
+    scheduled: BTreeMap<u64, Vec<glam::IVec3>>,
+    /// When every scheduled voxel is due, so nothing gets scheduled twice.
+    due: HashMap<glam::IVec3, u64>,
+    random: Random,
+}
+
+impl BlockUpdates {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            tick: 0,
+            scheduled: BTreeMap::new(),
+            due: HashMap::new(),
+            random: Random::new(seed),
+        }
+    }
+
+    /// Ticks the block at a world position `delay` ticks from now.
+    /// Does nothing if it already has a tick coming up.
+    pub fn schedule(&mut self, world_pos: glam::IVec3, delay: u64) {
+        if self.due.contains_key(&world_pos) {
+            return;
+        }
+
+        let tick = self.tick + delay.max(1);
+        self.due.insert(world_pos, tick);
+        self.scheduled.entry(tick).or_default().push(world_pos);
+    }
+
+    /// A random number from 0 up to, but not including, `max`.
+    pub fn random(&mut self, max: u32) -> u32 {
+        self.random.below(max)
+    }
+
+    /// Runs one tick. Picks up whatever changed in the world since the last one.
+    pub fn tick(&mut self, world: &mut ChunkModel) {
+        self.tick += 1;
+
+        for world_pos in world.take_changes() {
+            for offset in NEIGHBOURS {
+                let neighbour = world_pos + offset;
+                world
+                    .block(neighbour)
+                    .behaviour()
+                    .neighbour_changed(world, self, neighbour);
+            }
+        }
+
+        let due: Vec<u64> = self.scheduled.range(..=self.tick).map(|(&tick, _)| tick).collect();
+        for tick in due {
+            for world_pos in self.scheduled.remove(&tick).unwrap_or_default() {
+                self.due.remove(&world_pos);
+                world
+                    .block(world_pos)
+                    .behaviour()
+                    .scheduled_tick(world, self, world_pos);
+            }
+        }
+
+        self.random_ticks(world);
+    }
+
+    fn random_ticks(&mut self, world: &mut ChunkModel) {
+        for world_pos in self.random_positions(world) {
+            world
+                .block(world_pos)
+                .behaviour()
+                .random_tick(world, self, world_pos);
+        }
+    }
+
+    /// Picks `RANDOM_TICKS_PER_SECTION` voxels in every section of every loaded Chunk.
+    fn random_positions(&mut self, world: &ChunkModel) -> Vec<glam::IVec3> {
+        let sections = CHUNK_SIZE / SECTION_SIZE;
+        let mut positions = Vec::new();
+
+        for chunk_pos in world.chunk_positions() {
+            for x in 0..sections {
+                for y in 0..sections {
+                    for z in 0..sections {
+                        let section = chunk_pos * CHUNK_SIZE + glam::IVec3::new(x, y, z) * SECTION_SIZE;
+
+                        for _ in 0..RANDOM_TICKS_PER_SECTION {
+                            let offset = glam::IVec3::new(
+                                self.random(SECTION_SIZE as u32) as i32,
+                                self.random(SECTION_SIZE as u32) as i32,
+                                self.random(SECTION_SIZE as u32) as i32,
+                            );
+                            positions.push(section + offset);
+                        }
+                    }
+                }
+            }
+        }
+        positions
+    }
+}
+
+/// A small, fast and good enough random number generator (xorshift64*).
+#[derive(Debug)]
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // splitmix64, so seeds that are close together still start far apart
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        // The state can never be zero
+        Self {
+            state: if state == 0 { 0x2545_F491_4F6C_DD1D } else { state },
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, max: u32) -> u32 {
+        ((self.next() >> 32) % max.max(1) as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::block::Block;
+
+    fn at(x: i32, y: i32, z: i32) -> glam::IVec3 {
+        glam::IVec3::new(x, y, z)
+    }
+
+    /// An empty Chunk with a stone floor at y = 0, and some blocks already in it.
+    fn floor_with(blocks: &[(glam::IVec3, Block)]) -> ChunkModel {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        world.fill(at(0, 0, 0), at(31, 0, 31), Block::Stone);
+        for &(world_pos, block) in blocks {
+            world.fill(world_pos, world_pos, block);
+        }
+        world
+    }
+
+    #[test]
+    fn scheduling_twice_keeps_the_first_tick() {
+        let mut updates = BlockUpdates::new(1);
+        updates.schedule(at(1, 2, 3), 5);
+        updates.schedule(at(1, 2, 3), 1);
+
+        assert_eq!(updates.due[&at(1, 2, 3)], 5);
+        assert_eq!(updates.scheduled.values().flatten().count(), 1);
+    }
+
+    #[test]
+    fn scheduled_ticks_run_once_due() {
+        let mut world = floor_with(&[(at(5, 10, 5), Block::Sand), (at(8, 10, 8), Block::Sand)]);
+        let mut updates = BlockUpdates::new(1);
+        updates.schedule(at(5, 10, 5), 1);
+        updates.schedule(at(8, 10, 8), 3);
+
+        updates.tick(&mut world);
+        assert_eq!(world.block(at(5, 9, 5)), Block::Sand);
+        assert_eq!(world.block(at(8, 10, 8)), Block::Sand);
+
+        updates.tick(&mut world);
+        assert_eq!(world.block(at(8, 10, 8)), Block::Sand);
+
+        updates.tick(&mut world);
+        assert_eq!(world.block(at(8, 9, 8)), Block::Sand);
+        assert!(!updates.due.contains_key(&at(8, 10, 8)));
+    }
+
+    #[test]
+    fn neighbours_hear_about_changes() {
+        let mut world = floor_with(&[(at(5, 10, 5), Block::Sand), (at(5, 9, 5), Block::Stone)]);
+        let mut updates = BlockUpdates::new(1);
+
+        // Nothing changed, so the sand has no reason to look around
+        updates.tick(&mut world);
+        assert!(updates.due.is_empty());
+
+        world.set_block(at(5, 9, 5), Block::Air);
+        updates.tick(&mut world);
+        assert!(updates.due.contains_key(&at(5, 10, 5)));
+
+        // Every voxel it drops is a change again, so it keeps falling until it lands
+        for _ in 0..100 {
+            updates.tick(&mut world);
+        }
+        assert_eq!(world.block(at(5, 1, 5)), Block::Sand);
+        assert_eq!(world.block(at(5, 10, 5)), Block::Air);
+        assert!(updates.due.is_empty());
+    }
+
+    #[test]
+    fn water_waits_for_its_delay() {
+        let mut world = floor_with(&[]);
+        let mut updates = BlockUpdates::new(1);
+        world.set_fluid(at(5, 1, 5), Block::Water, 0);
+
+        // The first tick notices the change, the water flows `fluid_delay` ticks after that
+        for _ in 0..Block::Water.fluid_delay() {
+            updates.tick(&mut world);
+        }
+        assert_eq!(world.block(at(6, 1, 5)), Block::Air);
+
+        updates.tick(&mut world);
+        assert_eq!(world.block(at(6, 1, 5)), Block::Water);
+    }
+
+    #[test]
+    fn one_random_tick_per_section() {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+        world.add_empty_chunk(glam::IVec3::new(-1, 0, 0));
+        let mut updates = BlockUpdates::new(1);
+
+        let positions = updates.random_positions(&world);
+
+        let sections_per_chunk = (CHUNK_SIZE / SECTION_SIZE).pow(3) as usize;
+        assert_eq!(positions.len(), 2 * sections_per_chunk * RANDOM_TICKS_PER_SECTION as usize);
+        let mut sections: Vec<glam::IVec3> = positions
+            .iter()
+            .map(|world_pos| world_pos.div_euclid(glam::IVec3::splat(SECTION_SIZE)))
+            .collect();
+        sections.sort_by_key(|section| section.to_array());
+        sections.dedup();
+        assert_eq!(sections.len(), positions.len());
+        assert!(positions.iter().all(|&world_pos| world.is_loaded(world_pos)));
+    }
+
+    #[test]
+    fn random_ticks_repeat_with_the_same_seed() {
+        let mut world = ChunkModel::new();
+        world.add_empty_chunk(glam::IVec3::ZERO);
+
+        let run = |seed| {
+            let mut updates = BlockUpdates::new(seed);
+            (0..10).map(|_| updates.random_positions(&world)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        // Seeds that only differ in their lowest bit
+        assert_ne!(run(6), run(7));
+        assert_ne!(run(0), run(1));
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
-use crate::engine::resource::model::ModelVertex;
+use crate::voxel::block::Block;
 use crate::voxel::chunk::Chunk;
+use crate::voxel::fluid;
+use crate::voxel::lod::Downsampled;
+use crate::voxel::vertex::ChunkVertex;
 
 pub const CHUNK_SIZE: i32 = 32;
 pub const CHUNK_SIZE_F32: f32 = 32.0;
@@ -10,132 +13,315 @@ pub const MAX_HEIGHT: f64 = CHUNK_SIZE_F64 / 2.0;
 pub const CHUNK_AREA: i32 = CHUNK_SIZE * CHUNK_SIZE;
 pub const CHUNK_VOL: i32 = CHUNK_AREA * CHUNK_SIZE;
 
-/// Creates the ModelVertex vector as well as the index vector for our current Voxel.
-/// 
+/// Splits a world voxel position into the position of the Chunk it's in
+/// and its local position within that Chunk.
+pub fn world_to_chunk(world_pos: glam::IVec3) -> (glam::IVec3, glam::IVec3) {
+    (
+        world_pos.div_euclid(glam::IVec3::splat(CHUNK_SIZE)),
+        world_pos.rem_euclid(glam::IVec3::splat(CHUNK_SIZE)),
+    )
+}
+
+/// The index of a local voxel position in a Chunk's voxel list.
+pub fn voxel_index(local_pos: glam::IVec3) -> usize {
+    (local_pos.x + CHUNK_SIZE * local_pos.z + CHUNK_AREA * local_pos.y) as usize
+}
+
+/// How fast water scrolls down the sides of a voxel and down slopes, in tiles per second.
+/// Slower fluids scroll slower.
+pub const FLUID_FLOW_SPEED: f32 = 1.0;
+/// Still fluids drift ever so slightly, so they don't look frozen.
+const FLUID_DRIFT: [f32; 2] = [0.03, 0.02];
+
+/// One side of a Voxel.
+struct Face {
+    normal: glam::IVec3,
+    /// Where the corners are compared to the center of the Voxel.
+    corners: [[f32; 3]; 4],
+    /// The two triangles, wound so they face outwards.
+    indices: [u32; 6],
+}
+
+const FACES: [Face; 6] = [
+    // Above
+    Face {
+        normal: glam::IVec3::Y,
+        corners: [[-0.5, 0.5, -0.5], [0.5, 0.5, -0.5], [0.5, 0.5, 0.5], [-0.5, 0.5, 0.5]],
+        indices: [0, 3, 1, 1, 3, 2],
+    },
+    // Under
+    Face {
+        normal: glam::IVec3::NEG_Y,
+        corners: [[-0.5, -0.5, -0.5], [0.5, -0.5, -0.5], [0.5, -0.5, 0.5], [-0.5, -0.5, 0.5]],
+        indices: [0, 1, 3, 1, 2, 3],
+    },
+    // Right
+    Face {
+        normal: glam::IVec3::X,
+        corners: [[0.5, -0.5, -0.5], [0.5, -0.5, 0.5], [0.5, 0.5, 0.5], [0.5, 0.5, -0.5]],
+        indices: [0, 3, 1, 1, 3, 2],
+    },
+    // Left
+    Face {
+        normal: glam::IVec3::NEG_X,
+        corners: [[-0.5, -0.5, -0.5], [-0.5, -0.5, 0.5], [-0.5, 0.5, 0.5], [-0.5, 0.5, -0.5]],
+        indices: [0, 1, 3, 1, 2, 3],
+    },
+    // Behind
+    Face {
+        normal: glam::IVec3::Z,
+        corners: [[-0.5, -0.5, 0.5], [-0.5, 0.5, 0.5], [0.5, 0.5, 0.5], [0.5, -0.5, 0.5]],
+        indices: [0, 3, 1, 1, 3, 2],
+    },
+    // In front
+    Face {
+        normal: glam::IVec3::NEG_Z,
+        corners: [[-0.5, -0.5, -0.5], [-0.5, 0.5, -0.5], [0.5, 0.5, -0.5], [0.5, -0.5, -0.5]],
+        indices: [0, 1, 3, 1, 2, 3],
+    },
+];
+
+/// The direction a side of a Voxel points in, by its index in `FACES`.
+pub fn face_normal(face: usize) -> glam::IVec3 {
+    FACES[face].normal
+}
+
+/// Where a face corner is within its tile of the blocks texture, 0.0 to 1.0.
+/// The sides have the top of the tile facing up.
+fn face_uv(normal: glam::IVec3, corner: [f32; 3]) -> [f32; 2] {
+    match normal.y {
+        0 if normal.x != 0 => [corner[2] + 0.5, 0.5 - corner[1]],
+        0 => [corner[0] + 0.5, 0.5 - corner[1]],
+        _ => [corner[0] + 0.5, corner[2] + 0.5],
+    }
+}
+
+/// Creates the ChunkVertex vector as well as the index vector for our current Voxel.
+///
+/// Only faces that can be seen are added, the ones covered by a neighbouring Voxel are left out.
+///
 /// * `chunk` - The Chunk this Voxel resides within.
+/// * `block` - What this Voxel is.
 /// * `local_pos` - This Voxel's local position within this Chunk.
-/// * `world_pos` - This Voxel's *world position*. Necessary to correctly draw the vertices.
+/// * `center` - The middle of this Voxel, relative to the lowest corner of the Chunk.
 /// * `start_index` - The current amount of Vertices. Used to set the indices correctly.
 /// * `world_chunks` - All of the chunks inside our world. Used so we can access another Chunk's
-/// Voxels while we draw in case the neighboring Voxel isn't local to our current Chunk.
+///   Voxels while we draw in case the neighboring Voxel isn't local to our current Chunk.
 pub fn create_chunk_mesh_data(
     chunk: &Chunk,
-    local_pos: glam::Vec3,
-    world_pos: glam::Vec3,
+    block: Block,
+    local_pos: glam::IVec3,
+    center: glam::Vec3,
     start_index: u32,
     world_chunks: &HashMap<glam::IVec3, Chunk>
-) -> (Vec<ModelVertex>, Vec<u32>) {
-    // Local position of Voxel within this Chunk
-    let lx = local_pos.x as i32;
-    let ly = local_pos.y as i32;
-    let lz = local_pos.z as i32;
-    
-    // World position of Voxel
-    let wx = world_pos.x;
-    let wy = world_pos.y;
-    let wz = world_pos.z;
-    
-    let mut model_verts: Vec<ModelVertex> = Vec::new();
+) -> (Vec<ChunkVertex>, Vec<u32>) {
+    let mut model_verts: Vec<ChunkVertex> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
-    
+
     let mut unique_verts: u32 = start_index;
 
-    // Check if there's a visible voxel above above
-    if chunk.is_void(glam::IVec3::new(lx, ly + 1, lz), world_chunks) {
-        model_verts.extend(
-            vec!(
-                ModelVertex { position: [wx + -0.5, wy + 0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, 1.0, 0.0]},
-                ModelVertex { position: [wx + 0.5, wy + 0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, 1.0, 0.0]},
-                ModelVertex { position: [wx + 0.5, wy + 0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, 1.0, 0.0]},
-                ModelVertex { position: [wx + -0.5, wy + 0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, 1.0, 0.0]},
+    for (face_index, face) in FACES.iter().enumerate() {
+        let neighbour_pos = local_pos + face.normal;
+        // Don't draw faces towards Chunks that aren't loaded, nobody can see them anyway
+        let Some(neighbour) = chunk.block_at(neighbour_pos, world_chunks) else {
+            continue;
+        };
+        if neighbour.hides_face_of(block) {
+            continue;
+        }
+
+        let light = chunk.light_at(neighbour_pos, world_chunks);
+        let tile = block.texture(face.normal);
+
+        model_verts.extend(face.corners.map(|corner| {
+            ChunkVertex::new(
+                center + glam::Vec3::from(corner),
+                face_index,
+                tile,
+                face_uv(face.normal, corner),
+                light,
+                [0.0, 0.0],
             )
-        );
-        indices.extend(voxel_indices_extender(vec![0, 3, 1, 1, 3, 2], unique_verts));
+        }));
+        indices.extend(face.indices.map(|i| i + unique_verts));
         unique_verts += 4;
     }
 
-    // Check under...
-    if chunk.is_void(glam::IVec3::new(lx, ly - 1, lz), world_chunks) {
-        model_verts.extend(
-            vec!(
-                ModelVertex { position: [wx + -0.5, wy + -0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, -1.0, 0.0]},
-                ModelVertex { position: [wx + 0.5, wy + -0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, -1.0, 0.0]},
-                ModelVertex { position: [wx + 0.5, wy + -0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, -1.0, 0.0]},
-                ModelVertex { position: [wx + -0.5, wy + -0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, -1.0, 0.0]},
-            )
-        );
+    (model_verts, indices)
+}
 
-        indices.extend(voxel_indices_extender(vec![0, 1, 3, 1, 2, 3], unique_verts));
-        unique_verts += 4;
+/// How high the surface of the fluid `block` is at a position relative to `chunk`, 0.0 to 1.0.
+/// None if there's some other block there.
+fn fluid_height(
+    chunk: &Chunk,
+    block: Block,
+    voxel_pos: glam::IVec3,
+    world_chunks: &HashMap<glam::IVec3, Chunk>
+) -> Option<f32> {
+    if chunk.block_at(voxel_pos, world_chunks) != Some(block) {
+        return None;
     }
+    // Fluid with more of it on top fills the voxel all the way up
+    if chunk.block_at(voxel_pos + glam::IVec3::Y, world_chunks) == Some(block) {
+        return Some(1.0);
+    }
+    Some(fluid::surface_height(chunk.level_at(voxel_pos, world_chunks)))
+}
 
-    // Right
-    if chunk.is_void(glam::IVec3::new(lx + 1, ly, lz), world_chunks) {
-        model_verts.extend(
-            vec!(
-                ModelVertex { position: [wx + 0.5, wy + -0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[1.0, 0.0, 0.0]},
-                ModelVertex { position: [wx + 0.5, wy + -0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[1.0, 0.0, 0.0]},
-                ModelVertex { position: [wx + 0.5, wy + 0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[1.0, 0.0, 0.0]},
-                ModelVertex { position: [wx + 0.5, wy + 0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[1.0, 0.0, 0.0]},
-            )
-        );
+/// Creates the ChunkVertex vector as well as the index vector for a fluid Voxel.
+///
+/// Works like `create_chunk_mesh_data`, except the surface sits lower the further the fluid
+/// is from its source. Every corner of the surface is the average height of the fluid around it,
+/// so it slopes down the way the fluid flows, and the texture scrolls down that slope.
+pub fn create_fluid_mesh_data(
+    chunk: &Chunk,
+    block: Block,
+    local_pos: glam::IVec3,
+    center: glam::Vec3,
+    start_index: u32,
+    world_chunks: &HashMap<glam::IVec3, Chunk>
+) -> (Vec<ChunkVertex>, Vec<u32>) {
+    let mut model_verts: Vec<ChunkVertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
 
-        indices.extend(voxel_indices_extender(vec![0, 3, 1, 1, 3, 2], unique_verts));
-        unique_verts += 4;
-    }
+    let mut unique_verts: u32 = start_index;
 
-    // Left
-    if chunk.is_void(glam::IVec3::new(lx - 1, ly, lz), world_chunks) {
-        model_verts.extend(
-            vec!(
-                ModelVertex { position: [wx + -0.5, wy + -0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[-1.0, 0.0, 0.0]},
-                ModelVertex { position: [wx + -0.5, wy + -0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[-1.0, 0.0, 0.0]},
-                ModelVertex { position: [wx + -0.5, wy + 0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[-1.0, 0.0, 0.0]},
-                ModelVertex { position: [wx + -0.5, wy + 0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[-1.0, 0.0, 0.0]},
-            )
-        );
+    let corner_height = |x: f32, z: f32| -> f32 {
+        let step_x = glam::IVec3::new(x.signum() as i32, 0, 0);
+        let step_z = glam::IVec3::new(0, 0, z.signum() as i32);
+        let heights: Vec<f32> = [glam::IVec3::ZERO, step_x, step_z, step_x + step_z]
+            .into_iter()
+            .filter_map(|offset| fluid_height(chunk, block, local_pos + offset, world_chunks))
+            .collect();
 
-        indices.extend(voxel_indices_extender(vec![0, 1, 3, 1, 2, 3], unique_verts));
-        unique_verts += 4;
-    }
+        if heights.contains(&1.0) {
+            1.0
+        } else {
+            heights.iter().sum::<f32>() / heights.len() as f32
+        }
+    };
 
-    // Behind
-    if chunk.is_void(glam::IVec3::new(lx, ly, lz + 1), world_chunks) {
-        model_verts.extend(
-            vec!(
-                ModelVertex { position: [wx + -0.5, wy + -0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, 1.0]},
-                ModelVertex { position: [wx + -0.5, wy + 0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, 1.0]},
-                ModelVertex { position: [wx + 0.5, wy + 0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, 1.0]},
-                ModelVertex { position: [wx + 0.5, wy + -0.5, wz + 0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, 1.0]},
-            )
-        );
+    let speed = FLUID_FLOW_SPEED * Block::Water.fluid_delay() as f32 / block.fluid_delay() as f32;
+    let surface = [-0.5, 0.5].map(|x| [-0.5, 0.5].map(|z| corner_height(x, z)));
+    // Downhill on the surface, in texture coordinates
+    let slope = glam::Vec2::new(
+        surface[1][0] + surface[1][1] - surface[0][0] - surface[0][1],
+        surface[0][1] + surface[1][1] - surface[0][0] - surface[1][0],
+    );
+    let surface_flow = if slope.length() > 0.01 {
+        (-slope.normalize() * speed).to_array()
+    } else {
+        FLUID_DRIFT
+    };
+
+    for (face_index, face) in FACES.iter().enumerate() {
+        let neighbour_pos = local_pos + face.normal;
+        let Some(neighbour) = chunk.block_at(neighbour_pos, world_chunks) else {
+            continue;
+        };
+        if neighbour.hides_face_of(block) {
+            continue;
+        }
 
-        indices.extend(voxel_indices_extender(vec![0, 3, 1, 1, 3, 2], unique_verts));
+        // The fluid next to us covers the bottom part of our side
+        let covered = fluid_height(chunk, block, neighbour_pos, world_chunks);
+        let flow = match face.normal.y {
+            1 if covered.is_some() => continue,
+            -1 if covered.is_some() => continue,
+            1 => surface_flow,
+            -1 => FLUID_DRIFT,
+            _ => [0.0, speed],
+        };
+
+        let corners = face.corners.map(|[x, y, z]| {
+            let top = -0.5 + surface[(x > 0.0) as usize][(z > 0.0) as usize];
+            if y > 0.0 {
+                [x, top, z]
+            } else if face.normal.y == 0 {
+                [x, (-0.5 + covered.unwrap_or(0.0)).min(top), z]
+            } else {
+                [x, y, z]
+            }
+        });
+        // Nothing of this side sticks out above the fluid next to it
+        if face.normal.y == 0 && corners.iter().all(|corner| {
+            corner[1] <= -0.5 + covered.unwrap_or(0.0) + f32::EPSILON
+        }) {
+            continue;
+        }
+
+        let light = chunk.light_at(neighbour_pos, world_chunks);
+        let tile = block.texture(face.normal);
+
+        model_verts.extend(corners.map(|corner| {
+            ChunkVertex::new(
+                center + glam::Vec3::from(corner),
+                face_index,
+                tile,
+                face_uv(face.normal, corner),
+                light,
+                flow,
+            )
+        }));
+        indices.extend(face.indices.map(|i| i + unique_verts));
         unique_verts += 4;
     }
 
-    // In front
-    if chunk.is_void(glam::IVec3::new(lx, ly, lz - 1), world_chunks) {
-        model_verts.extend(
-            vec!(
-                ModelVertex { position: [wx + -0.5, wy + -0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, -1.0]},
-                ModelVertex { position: [wx + -0.5, wy + 0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, -1.0]},
-                ModelVertex { position: [wx + 0.5, wy + 0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, -1.0]},
-                ModelVertex { position: [wx + 0.5, wy + -0.5, wz + -0.5], tex_coords: [0.0, 0.0], normal:[0.0, 0.0, -1.0]},
-            )
-        );
+    (model_verts, indices)
+}
+
+/// Creates the ChunkVertex vector as well as the index vector for a cell of a Downsampled Chunk.
+///
+/// Like `create_chunk_mesh_data`, except every face covers a whole cell and fluids are full cubes.
+/// Faces on the border of the Chunk are always drawn. They work as skirts,
+/// hiding the seams with neighbouring Chunks drawn at a different level of detail.
+///
+/// * `center` - The middle of the cell, relative to the lowest corner of the Chunk.
+pub fn create_lod_mesh_data(
+    chunk: &Chunk,
+    cells: &Downsampled,
+    cell: glam::IVec3,
+    block: Block,
+    center: glam::Vec3,
+    start_index: u32,
+    world_chunks: &HashMap<glam::IVec3, Chunk>
+) -> (Vec<ChunkVertex>, Vec<u32>) {
+    let mut model_verts: Vec<ChunkVertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    let mut unique_verts: u32 = start_index;
+    let scale = cells.scale();
+
+    for (face_index, face) in FACES.iter().enumerate() {
+        let neighbour_cell = cell + face.normal;
+        let on_border = neighbour_cell.cmplt(glam::IVec3::ZERO).any()
+            || neighbour_cell.cmpge(glam::IVec3::splat(cells.size())).any();
+        let neighbour = cells.block(neighbour_cell);
+        if !on_border && (neighbour.hides_face_of(block) || (neighbour == block && block.is_fluid())) {
+            continue;
+        }
 
-        indices.extend(voxel_indices_extender(vec![0, 1, 3, 1, 2, 3], unique_verts));
+        // Light from the voxel right outside the middle of the face
+        let light_pos = cell * scale
+            + glam::IVec3::splat(scale / 2)
+            + face.normal.max(glam::IVec3::ZERO) * (scale - scale / 2)
+            + face.normal.min(glam::IVec3::ZERO) * (scale / 2 + 1);
+        let light = chunk.light_at(light_pos, world_chunks);
+        let tile = block.texture(face.normal);
+
+        model_verts.extend(face.corners.map(|corner| {
+            ChunkVertex::new(
+                center + glam::Vec3::from(corner) * scale as f32,
+                face_index,
+                tile,
+                face_uv(face.normal, corner),
+                light,
+                [0.0, 0.0],
+            )
+        }));
+        indices.extend(face.indices.map(|i| i + unique_verts));
         unique_verts += 4;
     }
 
-    //println!("{:?}",indices);
-
     (model_verts, indices)
 }
-
-fn voxel_indices_extender(vec: Vec<u32>, extend_amount: u32) -> Vec<u32> {
-    vec.into_iter()
-        .map(|i| i + extend_amount)
-        .collect()
-}
\ No newline at end of file
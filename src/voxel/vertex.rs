@@ -0,0 +1,193 @@
+use crate::engine::util::Vertex;
+use crate::voxel::util::{face_normal, FLUID_FLOW_SPEED};
+
+/// Steps per voxel positions are stored in. Fine enough for the sloped surfaces of fluids,
+/// everything else sits on whole voxels anyway.
+const POSITION_STEPS: f32 = 64.0;
+/// Steps from one side of a tile to the other that texture coordinates are stored in.
+const UV_STEPS: f32 = 64.0;
+/// How many directions a fluid can flow in.
+const FLOW_DIRECTIONS: f32 = 64.0;
+/// Flow speeds go from `FLUID_FLOW_SPEED` at this level down, halving every two levels.
+/// Level 0 doesn't flow at all.
+const FLOW_SPEED_LEVELS: f32 = 15.0;
+
+/// The Vertex our Chunks are built out of, packed into two u32s.
+///
+/// Voxel faces only need a handful of bits for everything, so there's no point in spending
+/// a float on every part of every vertex like a ModelVertex does. The shaders unpack it
+/// again, see `unpack_chunk_vertex` in `shader.wgsl`.
+///
+/// ```text
+///      0      6      12           24     27       32
+/// 0:   | x    | z    | y          | face | tile   |
+///      0       7       14    18    22       28      32
+/// 1:   | u     | v     | sun | blk | flow ∠ | speed |
+/// ```
+///
+/// Positions are relative to the lowest corner of the Chunk. Where the Chunk is comes from
+/// the Instance it gets drawn with, see `ChunkModel`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkVertex {
+    packed: [u32; 2],
+}
+
+// The shaders and `desc` read it as exactly two u32s
+const _: () = assert!(std::mem::size_of::<ChunkVertex>() == 8);
+
+impl ChunkVertex {
+    /// * `position` - Relative to the lowest corner of the Chunk, 0.0 to `CHUNK_SIZE` on every axis.
+    ///   Only `y` can be between whole voxels.
+    /// * `face` - Which side of a voxel this is on, in `FACES` order.
+    /// * `tile` - The tile in `blocks.png`.
+    /// * `uv` - Where in the tile this vertex is, 0.0 to 1.0.
+    /// * `light` - Sunlight and block light of the voxel the face looks at, 0 to `MAX_LIGHT`.
+    /// * `flow` - How fast the texture scrolls across the face, in tiles per second. Makes fluids flow.
+    pub fn new(position: glam::Vec3, face: usize, tile: u32, uv: [f32; 2], light: [u8; 2], flow: [f32; 2]) -> Self {
+        let x = position.x.round() as u32;
+        let z = position.z.round() as u32;
+        let y = (position.y * POSITION_STEPS).round() as u32;
+        let u = (uv[0] * UV_STEPS).round() as u32;
+        let v = (uv[1] * UV_STEPS).round() as u32;
+        let (direction, speed) = Self::pack_flow(flow);
+
+        Self {
+            packed: [
+                x | z << 6 | y << 12 | (face as u32) << 24 | tile << 27,
+                u | v << 7 | (light[0] as u32) << 14 | (light[1] as u32) << 18 | direction << 22 | speed << 28,
+            ],
+        }
+    }
+
+    /// The direction as an angle and the speed as a level, like `FLOW_DIRECTIONS` and `FLOW_SPEED_LEVELS` say.
+    fn pack_flow(flow: [f32; 2]) -> (u32, u32) {
+        let flow = glam::Vec2::from(flow);
+        if flow == glam::Vec2::ZERO {
+            return (0, 0);
+        }
+
+        let angle = flow.y.atan2(flow.x).rem_euclid(std::f32::consts::TAU);
+        let direction = (angle / std::f32::consts::TAU * FLOW_DIRECTIONS).round() as u32 % FLOW_DIRECTIONS as u32;
+        let speed = (FLOW_SPEED_LEVELS + 2.0 * (flow.length() / FLUID_FLOW_SPEED).log2())
+            .round()
+            .clamp(1.0, FLOW_SPEED_LEVELS);
+
+        (direction, speed as u32)
+    }
+
+    /// Where the vertex is, relative to the lowest corner of the Chunk.
+    pub fn position(&self) -> glam::Vec3 {
+        let word = self.packed[0];
+        glam::Vec3::new(
+            (word & 0x3F) as f32,
+            ((word >> 12) & 0xFFF) as f32 / POSITION_STEPS,
+            ((word >> 6) & 0x3F) as f32,
+        )
+    }
+
+    /// The direction the face this vertex is on points in.
+    pub fn normal(&self) -> glam::IVec3 {
+        face_normal(((self.packed[0] >> 24) & 0x7) as usize)
+    }
+
+    /// The tile in `blocks.png`.
+    pub fn tile(&self) -> u32 {
+        self.packed[0] >> 27
+    }
+
+    /// Where in the tile this vertex is, 0.0 to 1.0.
+    pub fn uv(&self) -> [f32; 2] {
+        let word = self.packed[1];
+        [(word & 0x7F) as f32 / UV_STEPS, ((word >> 7) & 0x7F) as f32 / UV_STEPS]
+    }
+
+    /// Sunlight and block light, 0 to `MAX_LIGHT`.
+    pub fn light(&self) -> [u8; 2] {
+        let word = self.packed[1];
+        [((word >> 14) & 0xF) as u8, ((word >> 18) & 0xF) as u8]
+    }
+}
+
+impl Vertex for ChunkVertex {
+    /// Retrieve the VertexBufferLayout for the ChunkVertex. Maps out the information for our shader.
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ChunkVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Uint32x2,
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::light::MAX_LIGHT;
+    use crate::voxel::util::CHUNK_SIZE;
+
+    #[test]
+    fn fields_round_trip() {
+        let corners = [
+            glam::Vec3::ZERO,
+            glam::Vec3::new(CHUNK_SIZE as f32, CHUNK_SIZE as f32, CHUNK_SIZE as f32),
+            glam::Vec3::new(3.0, 17.0 + 40.0 / 64.0, 29.0),
+        ];
+        let surfaces = [
+            (0, [0.0, 0.0], [0, 0]),
+            (31, [1.0, 1.0], [MAX_LIGHT, MAX_LIGHT]),
+            (9, [0.25, 0.75], [12, 3]),
+        ];
+        for position in corners {
+            for face in 0..6 {
+                for (tile, uv, light) in surfaces {
+                    let vertex = ChunkVertex::new(position, face, tile, uv, light, [0.0, 0.0]);
+
+                    assert_eq!(vertex.position(), position);
+                    assert_eq!(vertex.normal(), face_normal(face));
+                    assert_eq!(vertex.tile(), tile);
+                    assert_eq!(vertex.uv(), uv);
+                    assert_eq!(vertex.light(), light);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fields_dont_overlap() {
+        let lit = ChunkVertex::new(glam::Vec3::ZERO, 0, 0, [0.0, 0.0], [MAX_LIGHT, 0], [0.0, 0.0]);
+        assert_eq!(lit.light(), [MAX_LIGHT, 0]);
+        assert_eq!(lit.uv(), [0.0, 0.0]);
+        assert_eq!(lit.packed[0], 0);
+
+        let far = ChunkVertex::new(glam::Vec3::splat(CHUNK_SIZE as f32), 0, 0, [1.0, 1.0], [0, 0], [0.0, 0.0]);
+        assert_eq!(far.normal(), face_normal(0));
+        assert_eq!(far.tile(), 0);
+        assert_eq!(far.light(), [0, 0]);
+    }
+
+    #[test]
+    fn heights_keep_their_steps() {
+        for step in 0..POSITION_STEPS as u32 {
+            let y = 5.0 + step as f32 / POSITION_STEPS;
+            let vertex = ChunkVertex::new(glam::Vec3::new(0.0, y, 0.0), 2, 0, [0.0, 0.0], [0, 0], [0.0, 0.0]);
+            assert_eq!(vertex.position().y, y);
+        }
+    }
+
+    #[test]
+    fn flow_packs_into_direction_and_speed() {
+        assert_eq!(ChunkVertex::pack_flow([0.0, 0.0]), (0, 0));
+
+        // A quarter turn, at full speed
+        let (direction, speed) = ChunkVertex::pack_flow([0.0, FLUID_FLOW_SPEED]);
+        assert_eq!(direction, FLOW_DIRECTIONS as u32 / 4);
+        assert_eq!(speed, FLOW_SPEED_LEVELS as u32);
+    }
+}